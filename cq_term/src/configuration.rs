@@ -1,4 +1,5 @@
 use crossterm::style::{Attribute, Attributes, Color, ContentStyle};
+use game_core::node::TeamColor;
 use getset::{CopyGetters, Getters};
 
 use crate::prelude::*;
@@ -27,6 +28,7 @@ pub struct ColorScheme {
     grid_border_hover: ContentStyle,
     possible_movement: ContentStyle,
     possible_movement_hover: ContentStyle,
+    previewed_damage: ContentStyle,
     immediate_movement: ContentStyle,
     selected_square: ContentStyle,
     selected_square_border: ContentStyle,
@@ -37,6 +39,7 @@ pub struct ColorScheme {
     shop_ui_listing_item_selected: ContentStyle,
     player_team_active: ContentStyle,
     player_team_tapped: ContentStyle,
+    menu_disabled: ContentStyle,
     menu_hover: ContentStyle,
     menu_title: ContentStyle,
     menu_title_hover: ContentStyle,
@@ -83,6 +86,11 @@ impl Default for ColorScheme {
             grid_border_hover: style(Some(Color::Blue), None, None),
             possible_movement: style(Some(Color::White), Some(Color::DarkGrey), None),
             possible_movement_hover: style(Some(Color::Blue), Some(Color::DarkGrey), None),
+            previewed_damage: style(
+                Some(Color::White),
+                Some(Color::DarkRed),
+                Some(Attribute::CrossedOut),
+            ),
             immediate_movement: style(Some(Color::Yellow), Some(Color::DarkGrey), None),
             player_team_active: style(
                 Some(Color::Black),
@@ -90,6 +98,7 @@ impl Default for ColorScheme {
                 Some(Attribute::Bold),
             ),
             player_team_tapped: style(Some(Color::Grey), None, None),
+            menu_disabled: style(Some(Color::DarkGrey), None, None),
             menu_hover: style(Some(Color::Blue), None, None),
             menu_title: style(None, None, None),
             menu_title_hover: style(None, None, Some(Attribute::Reverse)),
@@ -97,6 +106,20 @@ impl Default for ColorScheme {
     }
 }
 
+impl ColorScheme {
+    /// Color of the curios on a team
+    pub fn team(&self, team_color: TeamColor) -> Color {
+        match team_color {
+            TeamColor::Red => Color::Red,
+            TeamColor::Blue => Color::Blue,
+            TeamColor::Green => Color::Green,
+            TeamColor::Yellow => Color::Yellow,
+            TeamColor::Magenta => Color::Magenta,
+            TeamColor::Cyan => Color::Cyan,
+        }
+    }
+}
+
 pub fn style(fg: Option<Color>, bg: Option<Color>, attr: Option<Attribute>) -> ContentStyle {
     let attributes = attr
         .map(|attr| Attributes::default() | attr)
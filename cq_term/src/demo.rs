@@ -12,16 +12,16 @@ use game_core::board::{Board, BoardPiece, BoardPosition, BoardScreen, BoardSize,
 use game_core::card::{CardDefinition, CardHandle, Deck, Nickname};
 use game_core::configuration::{NodeConfiguration, PlayerConfiguration};
 use game_core::dialog::Dialog;
-use game_core::item::{Item, ItemOp, Wallet};
+use game_core::item::{Inventory, Item, ItemOp, Wallet};
 use game_core::node::{
     ForNode, InNode, Node, NodeId, NodeOp, OnTeam, PlayedCards, Team, TeamStatus,
 };
 use game_core::op::{CoreOps, OpResult};
 use game_core::player::{ForPlayer, Ncp, Player, PlayerBundle};
 use game_core::prelude::*;
-use game_core::quest::QuestStatus;
+use game_core::quest::{QuestLog, QuestStatus};
 use game_core::saving::SaveOp;
-use game_core::shop::{ShopId, ShopInventory, ShopListing, ShopOp};
+use game_core::shop::{ShopId, ShopInventory, ShopListing, ShopOp, ShopSellRate};
 
 use crate::animation::AnimationPlayer;
 use crate::base_ui::context_menu::ContextActions;
@@ -35,7 +35,9 @@ use crate::input_event::{KeyCode, MouseEventListener, MouseEventTty};
 use crate::layout::{CalculatedSizeTty, StyleTty, VisibilityTty};
 use crate::main_ui::{
     self, HudContextActions, ItemDetailsUiActions, ItemDetailsUiDescription, ItemDetailsUiStats,
-    MainUiOp, MonDisplay, SaveButton, ShopListingUi, ShopNotification, ShopUi, ShopUiBuyButton,
+    LoadUi, LoadUiCancelButton, LoadUiContextActions, LoadUiSlotList, MainUiOp, MonDisplay,
+    QuestUi, QuestUiCloseButton, QuestUiContextActions, QuestUiQuestList, SaveButton,
+    SaveStatusDisplay, ShopListingUi, ShopNotification, ShopSellListUi, ShopUi, ShopUiBuyButton,
     ShopUiFinishShoppingButton, ShopUiSelectedItem, UiOps,
 };
 use crate::nf::{NFNode, NFShop, NfPlugin, RequiredNodes, VictoryDialogue};
@@ -217,6 +219,8 @@ fn demo_startup(
     res_draw_config: Res<DrawConfiguration>,
     res_dialog_context_actions: Res<DialogUiContextActions>,
     res_hud_context_actions: Res<HudContextActions>,
+    res_load_ui_context_actions: Res<LoadUiContextActions>,
+    res_quest_ui_context_actions: Res<QuestUiContextActions>,
     asset_server: Res<AssetServer>,
     mut res_demo_state: ResMut<DemoState>,
     mut commands: Commands,
@@ -304,11 +308,15 @@ fn demo_startup(
                 SimplePieceInfo("Warez Node\nLeo's Shop\nA quality shop of basic programs at low prices. Come and see what we've got to offer".to_string()),
                 ShopInventory(vec![
                     ShopListing::new(500, Item::Card(asset_server.load("nightfall/lvl1.cards.json#Hack"))),
-                    ShopListing::new(750, Item::Card(asset_server.load("nightfall/lvl1.cards.json#Bug"))),
+                    ShopListing::new(750, Item::Card(asset_server.load("nightfall/lvl1.cards.json#Bug"))).with_stock(2),
                     ShopListing::new(750, Item::Card(asset_server.load("nightfall/lvl1.cards.json#Slingshot"))),
-                    ShopListing::new(500, Item::Card(asset_server.load("nightfall/lvl1.cards.json#Data Doctor"))),
+                    ShopListing::new(500, Item::Card(asset_server.load("nightfall/lvl1.cards.json#Data Doctor"))).with_stock(1),
                     ShopListing::new(250, Item::Card(asset_server.load("nightfall/lvl1.cards.json#Bit Man"))),
                 ]),
+                ShopSellRate {
+                    percent: 40,
+                    ..default()
+                },
             ));
             board.spawn((
                 BoardPiece("Pharmhaus".to_owned()),
@@ -339,6 +347,7 @@ fn demo_startup(
         .spawn((
             Deck::new().with_card(stabby_boi),
             Dialog::default(),
+            Inventory::default(),
             KeyMap::default(),
             Name::new("Steve"),
             Ncp,
@@ -349,6 +358,7 @@ fn demo_startup(
                     end_turn_after_all_pieces_tap: true,
                 }),
             },
+            QuestLog::default(),
             QuestStatus::default(),
             SelectedBoardPieceUi::default(),
             Wallet::new().with_mon(10_000), // Just for demo
@@ -478,6 +488,38 @@ fn demo_startup(
                             }),*/
                             Tooltip::new("Save"),
                         ));
+                        title_bar.spawn((
+                            Name::new("Save status display"),
+                            SaveStatusDisplay,
+                            ForPlayer(player),
+                            StyleTty(taffy::style::Style {
+                                size: Size {
+                                    width: length(12.0),
+                                    height: length(1.0),
+                                },
+                                flex_shrink: 1.0,
+                                ..Default::default()
+                            }),
+                            TerminalRendering::default(),
+                        ));
+                        title_bar.spawn((
+                            ForPlayer(player),
+                            ButtonUiBundle::new("Load", ContentStyle::new().green()),
+                            ContextActions::new(
+                                player,
+                                &[res_load_ui_context_actions.open_load_menu()],
+                            ),
+                            Tooltip::new("Load a saved game"),
+                        ));
+                        title_bar.spawn((
+                            ForPlayer(player),
+                            ButtonUiBundle::new("Quests", ContentStyle::new().green()),
+                            ContextActions::new(
+                                player,
+                                &[res_quest_ui_context_actions.open_quest_log()],
+                            ),
+                            Tooltip::new("Show the quest log"),
+                        ));
                     }
                 });
             board_ui_root
@@ -560,6 +602,8 @@ fn demo_startup(
                                 res_draw_config,
                                 player,
                                 res_dialog_context_actions.say_this(),
+                                res_load_ui_context_actions.close_load_menu(),
+                                res_quest_ui_context_actions.close_quest_log(),
                                 popup_menu_pane,
                             );
                         });
@@ -603,6 +647,8 @@ pub fn build_popup_menu(
     res_draw_config: Res<DrawConfiguration>,
     player: Entity,
     say_this_ca: Entity,
+    close_load_menu_ca: Entity,
+    close_quest_log_ca: Entity,
     popup_menu_pane: &mut ChildBuilder,
 ) {
     use taffy::prelude::*;
@@ -730,6 +776,19 @@ pub fn build_popup_menu(
                         ContextActions::new(player, &[say_this_ca]),
                         ForPlayer(player),
                     ));
+                    shop_ui.spawn((
+                        StyleTty(taffy::prelude::Style {
+                            flex_direction: FlexDirection::Column,
+                            margin: Rect {
+                                top: length(1.0),
+                                ..zero()
+                            },
+                            ..default()
+                        }),
+                        ShopSellListUi,
+                        Name::new("Shop UI/Sell List"),
+                        ForPlayer(player),
+                    ));
                     shop_ui
                         .spawn((
                             StyleTty(taffy::prelude::Style {
@@ -830,5 +889,99 @@ pub fn build_popup_menu(
                         ForPlayer(player),
                     ));
                 });
+            popup_menu
+                .spawn((
+                    StyleTty(taffy::prelude::Style {
+                        max_size: Size {
+                            width: length(60.0),
+                            height: length(30.0),
+                        },
+                        flex_direction: FlexDirection::Column,
+                        ..default()
+                    }),
+                    LoadUi,
+                    ForPlayer(player),
+                    Name::new("Load UI"),
+                    VisibilityTty(false),
+                ))
+                .with_children(|load_ui| {
+                    load_ui.spawn((
+                        StyleTty(Style {
+                            size: Size {
+                                width: auto(),
+                                height: length(2.0),
+                            },
+                            flex_shrink: 0.0,
+                            ..default()
+                        }),
+                        TerminalRendering::new(vec!["Load Game".to_owned()]),
+                        Name::new("Load UI/Title"),
+                    ));
+                    load_ui.spawn((
+                        StyleTty(taffy::prelude::Style {
+                            flex_direction: FlexDirection::Column,
+                            ..default()
+                        }),
+                        LoadUiSlotList,
+                        Name::new("Load UI/Slot List"),
+                        ForPlayer(player),
+                    ));
+                    load_ui.spawn((
+                        LoadUiCancelButton,
+                        ForPlayer(player),
+                        ButtonUiBundle::new(
+                            "Cancel",
+                            res_draw_config.color_scheme().shop_ui_done_button(),
+                        ),
+                        ContextActions::new(player, &[close_load_menu_ca]),
+                    ));
+                });
+            popup_menu
+                .spawn((
+                    StyleTty(taffy::prelude::Style {
+                        max_size: Size {
+                            width: length(60.0),
+                            height: length(30.0),
+                        },
+                        flex_direction: FlexDirection::Column,
+                        ..default()
+                    }),
+                    QuestUi,
+                    ForPlayer(player),
+                    Name::new("Quest UI"),
+                    VisibilityTty(false),
+                ))
+                .with_children(|quest_ui| {
+                    quest_ui.spawn((
+                        StyleTty(Style {
+                            size: Size {
+                                width: auto(),
+                                height: length(2.0),
+                            },
+                            flex_shrink: 0.0,
+                            ..default()
+                        }),
+                        TerminalRendering::new(vec!["Quest Log".to_owned()]),
+                        Name::new("Quest UI/Title"),
+                    ));
+                    quest_ui.spawn((
+                        StyleTty(taffy::prelude::Style {
+                            flex_direction: FlexDirection::Column,
+                            ..default()
+                        }),
+                        QuestUiQuestList,
+                        Name::new("Quest UI/Quest List"),
+                        ForPlayer(player),
+                    ));
+                    quest_ui.spawn((
+                        QuestUiCloseButton,
+                        ForPlayer(player),
+                        ButtonUiBundle::new(
+                            "Close",
+                            res_draw_config.color_scheme().shop_ui_done_button(),
+                        ),
+                        ContextActions::new(player, &[close_quest_log_ca]),
+                    ));
+                });
         });
 }
@@ -19,6 +19,7 @@ pub enum NamedInput {
     MenuFocusNext,
     MenuFocusPrev,
     Undo,
+    Redo,
     Help,
     NextMsg,
 }
@@ -127,6 +128,10 @@ impl Default for KeyMap {
                         KeyCombo(KeyCode::Backspace, KeyModifiers::NONE),
                         NamedInput::Undo,
                     ),
+                    (
+                        KeyCombo(KeyCode::Char('r'), KeyModifiers::CONTROL),
+                        NamedInput::Redo,
+                    ),
                     (
                         KeyCombo(KeyCode::Enter, KeyModifiers::NONE),
                         NamedInput::NextMsg,
@@ -1,6 +1,8 @@
 mod card_ui;
 mod hud;
+mod load_ui;
 mod main_ui_op;
+mod quest_ui;
 mod shop_ui;
 
 pub use card_ui::ShortName;
@@ -8,11 +10,15 @@ use game_core::op::{OpExecutor, OpExecutorPlugin, OpPlugin};
 use game_core::player::ForPlayer;
 use game_core::NDitCoreSet;
 pub use hud::*;
+pub use load_ui::{LoadUi, LoadUiCancelButton, LoadUiContextActions, LoadUiSlot, LoadUiSlotList};
 pub use main_ui_op::MainUiOp;
+pub use quest_ui::{
+    QuestUi, QuestUiCloseButton, QuestUiContextActions, QuestUiQuest, QuestUiQuestList,
+};
 pub use shop_ui::{
     ItemDetailsUi, ItemDetailsUiActions, ItemDetailsUiDescription, ItemDetailsUiStats,
-    ShopListingItemUi, ShopListingUi, ShopNotification, ShopUi, ShopUiBuyButton,
-    ShopUiFinishShoppingButton, ShopUiSelectedItem,
+    ShopListingItemUi, ShopListingUi, ShopNotification, ShopSellCardUi, ShopSellListUi, ShopUi,
+    ShopUiBuyButton, ShopUiFinishShoppingButton, ShopUiSelectedItem,
 };
 
 use self::shop_ui::ShopUiContextActions;
@@ -35,6 +41,8 @@ impl Plugin for MainUiPlugin {
             OpPlugin::<MainUiOp>::default(),
             card_ui::CardUiPlugin,
             shop_ui::ShopUiPlugin,
+            load_ui::LoadUiPlugin,
+            quest_ui::QuestUiPlugin,
             hud::HudPlugin::default(),
         ))
         .add_systems(
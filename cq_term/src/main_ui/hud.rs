@@ -1,9 +1,10 @@
 use charmi::CharacterMapImage;
 use crossterm::style::{ContentStyle, Stylize};
 use game_core::item::Wallet;
-use game_core::op::CoreOps;
+use game_core::op::{CoreOps, OpResult};
 use game_core::player::{ForPlayer, Player};
-use game_core::saving::SaveOp;
+use game_core::saving::{SaveOp, SaveWritten};
+use game_core::NDitCoreSet;
 use getset::CopyGetters;
 
 use crate::linkage::base_ui_game_core::context_action_from_op;
@@ -29,6 +30,10 @@ impl Plugin for HudPlugin {
                 sys_render_mon_display.in_set(RenderTtySet::PostCalculateLayout),
             );
         }
+        app.add_systems(
+            Update,
+            sys_update_save_status_display.in_set(NDitCoreSet::PostProcessCommands),
+        );
     }
 }
 
@@ -44,7 +49,12 @@ impl FromWorld for HudContextActions {
             save: world
                 .spawn((
                     Name::new("Save button CA"),
-                    context_action_from_op::<CoreOps, _>("Save button", SaveOp::Save),
+                    context_action_from_op::<CoreOps, _>(
+                        "Save button",
+                        SaveOp::Save {
+                            include_battle: false,
+                        },
+                    ),
                 ))
                 .id(),
         }
@@ -57,6 +67,46 @@ pub struct MonDisplay(u32);
 #[derive(Component, Debug, Default)]
 pub struct SaveButton;
 
+/// Shows whether the player's last save has been written yet
+#[derive(Component, Debug, Default)]
+pub struct SaveStatusDisplay;
+
+pub fn sys_update_save_status_display(
+    mut evr_save_op: EventReader<OpResult<SaveOp>>,
+    mut evr_save_written: EventReader<SaveWritten>,
+    mut q_save_status_display: Query<(&ForPlayer, &mut TerminalRendering), With<SaveStatusDisplay>>,
+) {
+    let save_started = evr_save_op.read().filter_map(|save_op_result| {
+        if !matches!(save_op_result.op(), SaveOp::Save { .. }) {
+            return None;
+        }
+        let status = match save_op_result.result() {
+            Ok(_) => ("Saving...".to_owned(), ContentStyle::new().yellow()),
+            Err(e) => (format!("Save failed: {e}"), ContentStyle::new().red()),
+        };
+        Some((save_op_result.source(), status))
+    });
+    let save_written = evr_save_written.read().map(|save_written| {
+        let status = match save_written.result() {
+            Ok(()) => ("Saved".to_owned(), ContentStyle::new().green()),
+            Err(e) => (format!("Save failed: {e}"), ContentStyle::new().red()),
+        };
+        (save_written.source(), status)
+    });
+    // Saves finish being written after they start, so these are in order
+    for (player_id, (text, style)) in save_started.chain(save_written) {
+        // The board and the node screen each have one
+        for (_, mut tr) in q_save_status_display
+            .iter_mut()
+            .filter(|(&ForPlayer(for_player), _)| for_player == player_id)
+        {
+            tr.update_charmie(
+                CharacterMapImage::new().with_row(|row| row.with_text(text.clone(), &style)),
+            );
+        }
+    }
+}
+
 // TODO slow down this system
 // TODO handle when size is too small for mon
 // TODO programatically handle all magnitudes
@@ -0,0 +1,281 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::hierarchy::{BuildWorldChildren, DespawnRecursiveExt};
+use crossterm::style::{ContentStyle, Stylize};
+use game_core::common::daddy::Daddy;
+use game_core::op::{CoreOps, OpResult};
+use game_core::player::ForPlayer;
+use game_core::saving::{self, SaveOp, SaveSlotSummary};
+use game_core::NDitCoreSet;
+use getset::CopyGetters;
+
+use crate::base_ui::context_menu::{ContextAction, ContextActions};
+use crate::base_ui::{ButtonUiBundle, FlexibleTextUi};
+use crate::configuration::DrawConfiguration;
+use crate::layout::{StyleTty, VisibilityTty};
+use crate::linkage::base_ui_game_core::context_action_from_op;
+use crate::prelude::*;
+use crate::render::TerminalRendering;
+
+#[derive(Debug)]
+pub struct LoadUiPlugin;
+
+impl Plugin for LoadUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadUiContextActions>().add_systems(
+            Update,
+            sys_update_load_ui.in_set(NDitCoreSet::PostProcessCommands),
+        );
+    }
+}
+
+#[derive(CopyGetters, Debug, Reflect, Resource)]
+#[get_copy = "pub"]
+pub struct LoadUiContextActions {
+    open_load_menu: Entity,
+    load_slot: Entity,
+    delete_slot: Entity,
+    confirm_delete_slot: Entity,
+    keep_slot: Entity,
+    close_load_menu: Entity,
+}
+
+impl FromWorld for LoadUiContextActions {
+    fn from_world(world: &mut World) -> Self {
+        world.init_resource::<Daddy<LoadUiContextActions>>();
+        let daddy = *world
+            .get_resource::<Daddy<LoadUiContextActions>>()
+            .expect("daddy should've just been initialized")
+            .deref();
+        let load_slot_sys = world.register_system(
+            |In(id): In<Entity>,
+             mut res_core_ops: ResMut<CoreOps>,
+             q_load_ui_slot: Query<(&ForPlayer, &LoadUiSlot)>| {
+                if let Ok((&ForPlayer(player_id), LoadUiSlot(slot))) = q_load_ui_slot.get(id) {
+                    res_core_ops.request(player_id, SaveOp::SetSlot(slot.clone()));
+                    res_core_ops.request(player_id, SaveOp::Load);
+                }
+            },
+        );
+        // Deleting can't be undone, so the slot has to be confirmed first
+        let delete_slot_sys = world.register_system(
+            |In(id): In<Entity>,
+             res_load_ui_ca: Res<LoadUiContextActions>,
+             mut q_load_ui_slot: Query<
+                (&LoadUiSlot, &mut FlexibleTextUi, &mut ContextActions),
+                Without<LoadUiPendingDelete>,
+            >,
+             mut commands: Commands| {
+                if let Ok((LoadUiSlot(slot), mut text_ui, mut context_actions)) =
+                    q_load_ui_slot.get_mut(id)
+                {
+                    text_ui.text = format!("Delete {slot}? This can't be undone");
+                    *context_actions.actions_mut() = vec![
+                        res_load_ui_ca.keep_slot(),
+                        res_load_ui_ca.confirm_delete_slot(),
+                    ];
+                    commands.entity(id).insert(LoadUiPendingDelete);
+                }
+            },
+        );
+        let confirm_delete_slot_sys = world.register_system(
+            |In(id): In<Entity>,
+             mut res_core_ops: ResMut<CoreOps>,
+             q_load_ui_slot: Query<(&ForPlayer, &LoadUiSlot), With<LoadUiPendingDelete>>| {
+                if let Ok((&ForPlayer(player_id), LoadUiSlot(slot))) = q_load_ui_slot.get(id) {
+                    res_core_ops.request(player_id, SaveOp::Delete(slot.clone()));
+                }
+            },
+        );
+        let keep_slot_sys = world.register_system(
+            |In(id): In<Entity>,
+             mut res_core_ops: ResMut<CoreOps>,
+             q_load_ui_slot: Query<&ForPlayer, With<LoadUiPendingDelete>>| {
+                if let Ok(&ForPlayer(player_id)) = q_load_ui_slot.get(id) {
+                    // Refresh the list to put the slot back the way it was
+                    res_core_ops.request(player_id, SaveOp::ListSlots);
+                }
+            },
+        );
+        let close_load_menu_sys = world.register_system(
+            |In(id): In<Entity>,
+             q_for_player: Query<&ForPlayer>,
+             mut q_load_ui: Query<(&ForPlayer, AsDerefMut<VisibilityTty>), With<LoadUi>>| {
+                if let Ok(&ForPlayer(player_id)) = q_for_player.get(id) {
+                    if let Some((_, mut is_visible)) =
+                        ForPlayer::get_mut(&mut q_load_ui, player_id)
+                    {
+                        is_visible.set_if_neq(false);
+                    }
+                }
+            },
+        );
+        let open_load_menu = world
+            .spawn((
+                Name::new("Open load menu CA"),
+                context_action_from_op::<CoreOps, _>("Load game", SaveOp::ListSlots),
+            ))
+            .set_parent(daddy)
+            .id();
+        let load_slot = world
+            .spawn((
+                Name::new("Load slot CA"),
+                ContextAction::from_system_id("Load save", load_slot_sys),
+            ))
+            .set_parent(daddy)
+            .id();
+        let delete_slot = world
+            .spawn((
+                Name::new("Delete slot CA"),
+                ContextAction::from_system_id("Delete save", delete_slot_sys),
+            ))
+            .set_parent(daddy)
+            .id();
+        let confirm_delete_slot = world
+            .spawn((
+                Name::new("Confirm delete slot CA"),
+                ContextAction::from_system_id("Confirm delete", confirm_delete_slot_sys),
+            ))
+            .set_parent(daddy)
+            .id();
+        let keep_slot = world
+            .spawn((
+                Name::new("Keep slot CA"),
+                ContextAction::from_system_id("Keep save", keep_slot_sys),
+            ))
+            .set_parent(daddy)
+            .id();
+        let close_load_menu = world
+            .spawn((
+                Name::new("Close load menu CA"),
+                ContextAction::from_system_id("Cancel", close_load_menu_sys),
+            ))
+            .set_parent(daddy)
+            .id();
+        Self {
+            open_load_menu,
+            load_slot,
+            delete_slot,
+            confirm_delete_slot,
+            keep_slot,
+            close_load_menu,
+        }
+    }
+}
+
+/// Screen listing the save slots that can be loaded
+#[derive(Component, Debug)]
+pub struct LoadUi;
+
+#[derive(Component, Debug)]
+pub struct LoadUiSlotList;
+
+#[derive(Component, Debug)]
+pub struct LoadUiSlot(String);
+
+/// Marks a slot that is waiting for the player to confirm deleting it
+#[derive(Component, Debug)]
+pub struct LoadUiPendingDelete;
+
+#[derive(Component, Debug)]
+pub struct LoadUiCancelButton;
+
+fn sys_update_load_ui(
+    mut commands: Commands,
+    mut evr_save_op: EventReader<OpResult<SaveOp>>,
+    mut res_core_ops: ResMut<CoreOps>,
+    res_draw_config: Res<DrawConfiguration>,
+    res_load_ui_ca: Res<LoadUiContextActions>,
+    q_slot_list: Query<(&ForPlayer, Entity), With<LoadUiSlotList>>,
+    mut q_load_ui: Query<(&ForPlayer, AsDerefMut<VisibilityTty>), With<LoadUi>>,
+) {
+    for save_op_result in evr_save_op.read() {
+        let player_id = save_op_result.source();
+        match (save_op_result.op(), save_op_result.result()) {
+            (SaveOp::ListSlots, Ok(metadata)) => {
+                let slots = match metadata.get_required(saving::key::SLOTS) {
+                    Ok(slots) => slots,
+                    Err(e) => {
+                        log::error!("Unable to get save slots: {e}");
+                        continue;
+                    },
+                };
+                if let Some((_, mut is_visible)) = ForPlayer::get_mut(&mut q_load_ui, player_id) {
+                    is_visible.set_if_neq(true);
+                }
+                let Some((_, slot_list_id)) = q_slot_list
+                    .iter()
+                    .find(|(&ForPlayer(for_player), _)| for_player == player_id)
+                else {
+                    continue;
+                };
+                commands
+                    .entity(slot_list_id)
+                    .despawn_descendants()
+                    .with_children(|slot_list| {
+                        if slots.is_empty() {
+                            slot_list.spawn((
+                                StyleTty(taffy::prelude::Style {
+                                    size: taffy::prelude::Size {
+                                        width: taffy::prelude::auto(),
+                                        height: taffy::prelude::length(1.0),
+                                    },
+                                    ..default()
+                                }),
+                                FlexibleTextUi {
+                                    style: ContentStyle::new().dark_grey(),
+                                    text: "No saves yet".to_owned(),
+                                },
+                                TerminalRendering::default(),
+                            ));
+                        }
+                        for summary in slots.iter() {
+                            slot_list.spawn((
+                                LoadUiSlot(summary.slot.clone()),
+                                ButtonUiBundle::new(
+                                    slot_text(summary),
+                                    res_draw_config.color_scheme().shop_ui_listing_item(),
+                                ),
+                                ContextActions::new(
+                                    player_id,
+                                    &[res_load_ui_ca.load_slot(), res_load_ui_ca.delete_slot()],
+                                ),
+                                ForPlayer(player_id),
+                            ));
+                        }
+                    });
+            },
+            (SaveOp::Delete(_), Ok(_)) => {
+                // Refresh the list
+                res_core_ops.request(player_id, SaveOp::ListSlots);
+            },
+            (SaveOp::Load, Ok(_)) => {
+                for (_, mut is_visible) in q_load_ui.iter_mut() {
+                    is_visible.set_if_neq(false);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+fn slot_text(summary: &SaveSlotSummary) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or_default();
+    let ago = now.saturating_sub(summary.saved_at);
+    let saved = if ago < 60 {
+        "just now".to_owned()
+    } else if ago < 60 * 60 {
+        format!("{}m ago", ago / 60)
+    } else if ago < 60 * 60 * 24 {
+        format!("{}h ago", ago / (60 * 60))
+    } else {
+        format!("{}d ago", ago / (60 * 60 * 24))
+    };
+    format!(
+        "{} ({saved}) - {} nodes / ${} / {} cards",
+        summary.slot, summary.completed_nodes, summary.mon, summary.deck_size
+    )
+}
@@ -0,0 +1,216 @@
+use bevy::hierarchy::{BuildWorldChildren, DespawnRecursiveExt};
+use crossterm::style::{ContentStyle, Stylize};
+use game_core::common::daddy::Daddy;
+use game_core::op::CoreOps;
+use game_core::player::{ForPlayer, Player};
+use game_core::quest::{QuestLog, QuestOp, QuestStatus, Quests};
+use game_core::registry::Reg;
+use game_core::NDitCoreSet;
+use getset::CopyGetters;
+
+use crate::base_ui::context_menu::{ContextAction, ContextActions};
+use crate::base_ui::{ButtonUiBundle, FlexibleTextUi};
+use crate::configuration::DrawConfiguration;
+use crate::layout::{StyleTty, VisibilityTty};
+use crate::prelude::*;
+use crate::render::TerminalRendering;
+
+#[derive(Debug)]
+pub struct QuestUiPlugin;
+
+impl Plugin for QuestUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<QuestUiContextActions>().add_systems(
+            Update,
+            sys_update_quest_ui.in_set(NDitCoreSet::PostProcessCommands),
+        );
+    }
+}
+
+#[derive(CopyGetters, Debug, Reflect, Resource)]
+#[get_copy = "pub"]
+pub struct QuestUiContextActions {
+    open_quest_log: Entity,
+    abandon_quest: Entity,
+    close_quest_log: Entity,
+}
+
+impl FromWorld for QuestUiContextActions {
+    fn from_world(world: &mut World) -> Self {
+        world.init_resource::<Daddy<QuestUiContextActions>>();
+        let daddy = *world
+            .get_resource::<Daddy<QuestUiContextActions>>()
+            .expect("daddy should've just been initialized")
+            .deref();
+        let open_quest_log_sys = world.register_system(sys_set_quest_ui_visibility::<true>);
+        let close_quest_log_sys = world.register_system(sys_set_quest_ui_visibility::<false>);
+        let abandon_quest_sys = world.register_system(
+            |In(id): In<Entity>,
+             mut res_core_ops: ResMut<CoreOps>,
+             q_quest_ui_quest: Query<(&ForPlayer, &QuestUiQuest)>| {
+                if let Ok((&ForPlayer(player_id), QuestUiQuest(quest_id))) =
+                    q_quest_ui_quest.get(id)
+                {
+                    res_core_ops.request(player_id, QuestOp::Abandon(quest_id.clone()));
+                }
+            },
+        );
+        let open_quest_log = world
+            .spawn((
+                Name::new("Open quest log CA"),
+                ContextAction::from_system_id("Quest log", open_quest_log_sys),
+            ))
+            .set_parent(daddy)
+            .id();
+        let abandon_quest = world
+            .spawn((
+                Name::new("Abandon quest CA"),
+                ContextAction::from_system_id("Abandon quest", abandon_quest_sys),
+            ))
+            .set_parent(daddy)
+            .id();
+        let close_quest_log = world
+            .spawn((
+                Name::new("Close quest log CA"),
+                ContextAction::from_system_id("Close", close_quest_log_sys),
+            ))
+            .set_parent(daddy)
+            .id();
+        Self {
+            open_quest_log,
+            abandon_quest,
+            close_quest_log,
+        }
+    }
+}
+
+/// Screen listing the quests a player has started and finished
+#[derive(Component, Debug)]
+pub struct QuestUi;
+
+#[derive(Component, Debug)]
+pub struct QuestUiQuestList;
+
+#[derive(Component, Debug)]
+pub struct QuestUiQuest(String);
+
+#[derive(Component, Debug)]
+pub struct QuestUiCloseButton;
+
+fn sys_set_quest_ui_visibility<const IS_VISIBLE: bool>(
+    In(id): In<Entity>,
+    q_for_player: Query<&ForPlayer>,
+    mut q_quest_ui: Query<(&ForPlayer, AsDerefMut<VisibilityTty>), With<QuestUi>>,
+) {
+    if let Ok(&ForPlayer(player_id)) = q_for_player.get(id) {
+        if let Some((_, mut is_visible)) = ForPlayer::get_mut(&mut q_quest_ui, player_id) {
+            is_visible.set_if_neq(IS_VISIBLE);
+        }
+    }
+}
+
+/// Rebuilds the quest list when the quest log is opened or the player makes
+/// progress while it is open
+fn sys_update_quest_ui(
+    mut commands: Commands,
+    res_draw_config: Res<DrawConfiguration>,
+    res_quests: Res<Reg<Quests>>,
+    res_quest_ui_ca: Res<QuestUiContextActions>,
+    q_player: Query<(Entity, Ref<QuestLog>, Ref<QuestStatus>), With<Player>>,
+    q_quest_ui: Query<(&ForPlayer, Ref<VisibilityTty>), With<QuestUi>>,
+    q_quest_list: Query<(&ForPlayer, Entity), With<QuestUiQuestList>>,
+) {
+    for (player_id, quest_log, quest_status) in q_player.iter() {
+        let Some((_, visibility)) = q_quest_ui
+            .iter()
+            .find(|(&ForPlayer(for_player), _)| for_player == player_id)
+        else {
+            continue;
+        };
+        let needs_refresh =
+            visibility.is_changed() || quest_log.is_changed() || quest_status.is_changed();
+        if !**visibility || !needs_refresh {
+            continue;
+        }
+        let Some((_, quest_list_id)) = q_quest_list
+            .iter()
+            .find(|(&ForPlayer(for_player), _)| for_player == player_id)
+        else {
+            continue;
+        };
+        let quest_name = |quest_id: &str| {
+            res_quests
+                .get(quest_id)
+                .map(|quest| quest.name.clone())
+                .unwrap_or_else(|| quest_id.to_owned())
+        };
+        commands
+            .entity(quest_list_id)
+            .despawn_descendants()
+            .with_children(|quest_list| {
+                if quest_log.active().is_empty() && quest_log.finished().is_empty() {
+                    quest_list.spawn(text_row(
+                        "No quests yet".to_owned(),
+                        ContentStyle::new().dark_grey(),
+                    ));
+                }
+                for (quest_id, progress) in quest_log.active() {
+                    let Some(quest) = res_quests.get(quest_id) else {
+                        log::warn!("Quest log has unknown quest [{quest_id}]");
+                        continue;
+                    };
+                    quest_list.spawn((
+                        QuestUiQuest(quest_id.to_owned()),
+                        ButtonUiBundle::new(
+                            format!(
+                                "{} ({}/{})",
+                                quest.name,
+                                progress.stage() + 1,
+                                quest.stages.len()
+                            ),
+                            res_draw_config.color_scheme().shop_ui_listing_item(),
+                        ),
+                        ContextActions::new(player_id, &[res_quest_ui_ca.abandon_quest()]),
+                        ForPlayer(player_id),
+                    ));
+                    let Some(stage) = quest.stages.get(progress.stage()) else {
+                        continue;
+                    };
+                    if !stage.description.is_empty() {
+                        quest_list.spawn(text_row(
+                            format!(" {}", stage.description),
+                            ContentStyle::new().cyan(),
+                        ));
+                    }
+                    for objective in stage.objectives.iter() {
+                        let (mark, style) = if progress.is_objective_met(objective, &quest_status) {
+                            ("x", ContentStyle::new().green())
+                        } else {
+                            (" ", ContentStyle::new())
+                        };
+                        quest_list.spawn(text_row(format!(" [{mark}] {objective}"), style));
+                    }
+                }
+                for quest_id in quest_log.finished().iter() {
+                    quest_list.spawn(text_row(
+                        format!("{} - done", quest_name(quest_id)),
+                        ContentStyle::new().dark_grey(),
+                    ));
+                }
+            });
+    }
+}
+
+fn text_row(text: String, style: ContentStyle) -> impl Bundle {
+    (
+        StyleTty(taffy::prelude::Style {
+            size: taffy::prelude::Size {
+                width: taffy::prelude::auto(),
+                height: taffy::prelude::length(1.0),
+            },
+            ..default()
+        }),
+        FlexibleTextUi { style, text },
+        TerminalRendering::default(),
+    )
+}
@@ -3,11 +3,11 @@ use std::borrow::Cow;
 use bevy::hierarchy::{BuildWorldChildren, DespawnRecursiveExt};
 use charmi::{CharacterMapImage, CharmieAnimation};
 use crossterm::style::{Color, ContentStyle, Stylize};
-use game_core::card::{Action, CardDefinition};
+use game_core::card::{Action, CardDefinition, CardQuery, Deck};
 use game_core::common::daddy::Daddy;
 use game_core::op::OpResult;
 use game_core::player::{ForPlayer, Player};
-use game_core::shop::{self, InShop, ShopId, ShopInventory, ShopOp};
+use game_core::shop::{self, InShop, ShopId, ShopInventory, ShopListing, ShopOp, ShopSellRate};
 use game_core::NDitCoreSet;
 use getset::CopyGetters;
 
@@ -16,7 +16,7 @@ use crate::animation::AnimationPlayer;
 use crate::base_ui::context_menu::{ContextAction, ContextActions};
 use crate::base_ui::{ButtonUiBundle, FlexibleTextUi, FlexibleTextUiMultiline};
 use crate::configuration::DrawConfiguration;
-use crate::layout::VisibilityTty;
+use crate::layout::{StyleTty, VisibilityTty};
 use crate::linkage;
 use crate::prelude::*;
 
@@ -34,9 +34,11 @@ impl Plugin for ShopUiPlugin {
                     sys_update_item_details_description,
                     sys_update_item_details_actions,
                     sys_update_item_details_stats,
+                    sys_update_shop_listing_text,
+                    sys_update_sell_list_ui,
                 )
                     .in_set(NDitCoreSet::PostProcessUiOps),
-                sys_buy_notification_ui.in_set(NDitCoreSet::PostProcessCommands),
+                sys_shop_notification_ui.in_set(NDitCoreSet::PostProcessCommands),
             ),
         );
     }
@@ -49,6 +51,7 @@ pub struct ShopUiContextActions {
     finish_shopping: Entity,
     select_item: Entity,
     select_action: Entity,
+    sell_card: Entity,
 }
 
 impl FromWorld for ShopUiContextActions {
@@ -187,6 +190,19 @@ impl FromWorld for ShopUiContextActions {
                     }
                 },
             );
+        let sell_card_sys = world.register_system(
+            |In(id): In<Entity>,
+             mut res_ui_ops: ResMut<UiOps>,
+             q_sell_card_ui: Query<(&ForPlayer, &ShopSellCardUi)>| {
+                if let Ok((&ForPlayer(player_id), &ShopSellCardUi(card_id))) =
+                    q_sell_card_ui.get(id)
+                {
+                    res_ui_ops.request(player_id, ShopOp::Sell(card_id));
+                } else {
+                    log::warn!("Trying to sell card, but no card selected")
+                }
+            },
+        );
         let buy_item = world
             .spawn((
                 Name::new("Buy item CA"),
@@ -218,11 +234,19 @@ impl FromWorld for ShopUiContextActions {
                 ContextAction::from_system_id("See action details", select_action_sys),
             ))
             .id();
+        let sell_card = world
+            .spawn((
+                Name::new("Sell card CA"),
+                ContextAction::from_system_id("Sell card", sell_card_sys),
+            ))
+            .set_parent(daddy)
+            .id();
         Self {
             buy_item,
             finish_shopping,
             select_item,
             select_action,
+            sell_card,
         }
     }
 }
@@ -242,6 +266,13 @@ pub struct ShopListingUi;
 #[derive(Component, Debug)]
 pub struct ShopListingItemUi(usize);
 
+/// Lists the cards in the player's deck that they could sell to the shop
+#[derive(Component, Debug)]
+pub struct ShopSellListUi;
+
+#[derive(Component, Debug)]
+pub struct ShopSellCardUi(Entity);
+
 #[derive(Component, Debug)]
 pub struct ItemDetailsUi;
 
@@ -307,11 +338,7 @@ pub fn sys_open_shop_ui(
                                 listing_ui.spawn((
                                     ShopListingItemUi(i),
                                     ButtonUiBundle::new(
-                                        format!(
-                                            "{} - ${}",
-                                            listing.item().name(&ast_card),
-                                            listing.price()
-                                        ),
+                                        listing_text(listing, &ast_card),
                                         res_draw_config.color_scheme().shop_ui_listing_item(),
                                     ),
                                     ContextActions::new(
@@ -328,10 +355,103 @@ pub fn sys_open_shop_ui(
     }
 }
 
+fn listing_text(listing: &ShopListing, ast_card: &Assets<CardDefinition>) -> String {
+    let name = listing.item().name(ast_card);
+    match listing.stock() {
+        Some(0) => format!("{name} - SOLD OUT"),
+        Some(stock) => format!("{name} - ${} ({stock} left)", listing.price()),
+        None => format!("{name} - ${}", listing.price()),
+    }
+}
+
+/// Keeps listings up to date as items are bought and restocked
+fn sys_update_shop_listing_text(
+    ast_card: Res<Assets<CardDefinition>>,
+    q_player: Query<&InShop, With<Player>>,
+    q_shop: Query<AsDeref<ShopInventory>, With<ShopId>>,
+    mut q_shop_listing_ui: Query<(
+        &ForPlayer,
+        &ShopListingItemUi,
+        &mut FlexibleTextUi,
+        &mut StyleTty,
+    )>,
+) {
+    for (&ForPlayer(player_id), &ShopListingItemUi(listing_idx), mut text_ui, mut style) in
+        q_shop_listing_ui.iter_mut()
+    {
+        let Some(listing) = q_player
+            .get(player_id)
+            .ok()
+            .and_then(|&InShop(shop_id)| q_shop.get(shop_id).ok())
+            .and_then(|shop_inventory| shop_inventory.get(listing_idx))
+        else {
+            continue;
+        };
+        let text = listing_text(listing, &ast_card);
+        if text_ui.text != text {
+            let width = taffy::prelude::length(text.len() as f32 + 2.0);
+            style.size.width = width;
+            style.max_size.width = width;
+            text_ui.text = text;
+        }
+    }
+}
+
+/// Lists the player's cards with what the shop would pay for them, whenever
+/// they enter a shop or their deck changes while they're in one
+fn sys_update_sell_list_ui(
+    mut commands: Commands,
+    res_shop_ui_ca: Res<ShopUiContextActions>,
+    res_draw_config: Res<DrawConfiguration>,
+    q_player: Query<(Entity, Ref<Deck>, Ref<InShop>), With<Player>>,
+    q_shop: Query<(&ShopInventory, Option<&ShopSellRate>), With<ShopId>>,
+    q_card: Query<(&Handle<CardDefinition>, CardQuery)>,
+    q_sell_list_ui: Query<(&ForPlayer, Entity), With<ShopSellListUi>>,
+) {
+    for (player_id, deck, in_shop) in q_player.iter() {
+        if !deck.is_changed() && !in_shop.is_changed() {
+            continue;
+        }
+        let Some((_, ui_id)) = q_sell_list_ui
+            .iter()
+            .find(|(&ForPlayer(for_player), _)| for_player == player_id)
+        else {
+            continue;
+        };
+        let Ok((shop_inventory, sell_rate)) = q_shop.get(**in_shop) else {
+            continue;
+        };
+        let sell_rate = sell_rate.copied().unwrap_or_default();
+        commands
+            .entity(ui_id)
+            .despawn_descendants()
+            .with_children(|sell_list_ui| {
+                for (card_id, count) in deck.cards_with_count() {
+                    let Ok((card_handle, card)) = q_card.get(card_id) else {
+                        continue;
+                    };
+                    sell_list_ui.spawn((
+                        ShopSellCardUi(card_id),
+                        ButtonUiBundle::new(
+                            format!(
+                                "{} x{count} - sell ${}",
+                                card.nickname_or_name(),
+                                sell_rate.sell_price(shop_inventory, card_handle)
+                            ),
+                            res_draw_config.color_scheme().shop_ui_listing_item(),
+                        ),
+                        ContextActions::new(player_id, &[res_shop_ui_ca.sell_card()]),
+                        ForPlayer(player_id),
+                    ));
+                }
+            });
+    }
+}
+
 fn sys_leave_shop_ui(
     mut commands: Commands,
     mut evr_shop_op: EventReader<OpResult<ShopOp>>,
-    q_shop_listing_ui: Query<(&ForPlayer, Entity), With<ShopListingUi>>,
+    q_shop_listing_ui: Query<(&ForPlayer, Entity), Or<(With<ShopListingUi>, With<ShopSellListUi>)>>,
     q_player_not_in_shop: Query<(), (With<Player>, Without<InShop>)>,
     mut q_shop_ui: Query<
         (
@@ -360,9 +480,9 @@ fn sys_leave_shop_ui(
             // Not technically necessary since the game despawns children when a
             // player enters a shop, and the ShopUi is invisible, but it's good
             // to clean up after yourself.
-            if let Some((_, ui_id)) = q_shop_listing_ui
+            for (_, ui_id) in q_shop_listing_ui
                 .iter()
-                .find(|(&ForPlayer(for_player), _)| for_player == player_id)
+                .filter(|(&ForPlayer(for_player), _)| for_player == player_id)
             {
                 commands.entity(ui_id).despawn_descendants();
             }
@@ -520,27 +640,32 @@ fn sys_update_item_details_description(
     }
 }
 
-fn sys_buy_notification_ui(
+fn sys_shop_notification_ui(
     mut evr_shop_op: EventReader<OpResult<ShopOp>>,
     mut ast_animation: ResMut<Assets<CharmieAnimation>>,
     mut q_shop_notification: Query<(&ForPlayer, &mut AnimationPlayer), With<ShopNotification>>,
 ) {
     for shop_op_result in evr_shop_op.read() {
-        if let (ShopOp::BuyItem(_), Ok(metadata)) = (shop_op_result.op(), shop_op_result.result()) {
+        let verb = match shop_op_result.op() {
+            ShopOp::BuyItem(_) | ShopOp::BuyItemByName(_) => "Bought ",
+            ShopOp::Sell(_) => "Sold ",
+            _ => continue,
+        };
+        if let Ok(metadata) = shop_op_result.result() {
             for (&ForPlayer(player_id), mut animation_player) in q_shop_notification.iter_mut() {
                 if shop_op_result.source() != player_id {
                     continue;
                 }
                 // TODO Sound effect!
                 if let Ok(item_name) = metadata.get_required(shop::key::ITEM_NAME) {
-                    let animation = generate_buy_notification_animation(item_name.as_str());
+                    let animation = generate_shop_notification_animation(verb, item_name.as_str());
                     let animation_handle = ast_animation.add(animation);
                     animation_player
                         .load(animation_handle.clone())
                         .play_once()
                         .unload_when_finished();
                 } else {
-                    log::error!("Unable to get name of item purchased");
+                    log::error!("Unable to get name of item bought or sold");
                 }
                 break;
             }
@@ -548,7 +673,7 @@ fn sys_buy_notification_ui(
     }
 }
 
-pub fn generate_buy_notification_animation(name: &str) -> CharmieAnimation {
+pub fn generate_shop_notification_animation(verb: &str, name: &str) -> CharmieAnimation {
     let frame_timing = [1000.0, 130.0, 130.0, 130.0, 1000.0];
     let shade_timing = [255, 191, 127, 63, 0];
     frame_timing
@@ -568,7 +693,7 @@ pub fn generate_buy_notification_animation(name: &str) -> CharmieAnimation {
             });
             frame_img
                 .new_row()
-                .add_text("Bought ", &basic_color)
+                .add_text(verb, &basic_color)
                 .add_text(name, &emphasis_color);
             // .add_text("", &basic_color);
             (timing, frame_img)
@@ -25,7 +25,7 @@ use game_core::NDitCoreSet;
 
 use self::grid_ui::GridUi;
 use self::menu_ui::{
-    MenuUiActions, MenuUiCardSelection, MenuUiDescription, MenuUiLabel, MenuUiStats,
+    MenuUiActions, MenuUiCardSelection, MenuUiDescription, MenuUiItems, MenuUiLabel, MenuUiStats,
 };
 pub use self::messagebar_ui::MessageBarUi;
 pub use self::node_glyph::NodeGlyph;
@@ -71,6 +71,7 @@ impl Plugin for NodeUiPlugin {
                 MenuUiStats::plugin(),
                 MenuUiLabel::plugin(),
                 MenuUiActions::plugin(),
+                MenuUiItems::plugin(),
                 MenuUiDescription::plugin(),
                 NodePopupsPlugin,
                 GridUi::plugin(),
@@ -95,6 +96,11 @@ pub struct SelectedNodePiece(pub Option<Entity>);
 #[derive(Component, Debug, Deref, DerefMut, PartialEq)]
 pub struct SelectedAction(Option<usize>);
 
+/// Item from the player's inventory that will be used on the node cursor,
+/// by item id
+#[derive(Component, Debug, Deref, DerefMut, PartialEq)]
+pub struct SelectedItem(Option<String>);
+
 #[derive(Component, Debug, Deref, DerefMut)]
 pub struct TelegraphedAction(Option<Handle<Action>>);
 
@@ -230,7 +236,7 @@ fn sys_react_to_node_op(
                                 }
                             }
                         },
-                        NodeOp::TelegraphAction { action_id } => {
+                        NodeOp::TelegraphAction { action_id, .. } => {
                             let (_, _, active_curio) = get_assert!(node, q_node)?;
                             let actions = q_curio.get(active_curio?).ok()?;
                             let action_handle = actions.iter().find_map(|action_handle| {
@@ -290,6 +296,7 @@ fn sys_react_to_node_op(
                             .request(player, NodeUiOp::MoveNodeCursor(grid.head(curio)?.into()));
                         res_ui_ops.request(player, NodeUiOp::ChangeFocus(FocusTarget::Grid));
                         res_ui_ops.request(player, NodeUiOp::SetSelectedAction(None));
+                        res_ui_ops.request(player, NodeUiOp::SetSelectedItem(None));
                         Some(())
                     });
                 },
@@ -297,12 +304,17 @@ fn sys_react_to_node_op(
                     res_ui_ops.request(player, NodeUiOp::ChangeFocus(FocusTarget::Grid));
                     res_ui_ops.request(player, NodeUiOp::SetSelectedAction(None));
                 },
+                NodeOp::UseItem { .. } => {
+                    res_ui_ops.request(player, NodeUiOp::ChangeFocus(FocusTarget::Grid));
+                    res_ui_ops.request(player, NodeUiOp::SetSelectedItem(None));
+                },
                 NodeOp::EndTurn => {
                     res_ui_ops.request(player, NodeUiOp::ChangeFocus(FocusTarget::Grid));
                     res_ui_ops.request(player, NodeUiOp::SetCursorHidden(true));
                 },
                 NodeOp::QuitNode(_) => {
                     res_ui_ops.request(player, NodeUiOp::SetSelectedAction(None));
+                    res_ui_ops.request(player, NodeUiOp::SetSelectedItem(None));
                     if let Some((_, board_screen_id)) = q_board_screen
                         .iter()
                         .find(|&(i_player_id, _)| i_player_id == player)
@@ -26,6 +26,9 @@ pub struct HelpButton;
 #[derive(Clone, Copy, Component, Reflect)]
 pub struct UndoButton;
 
+#[derive(Clone, Copy, Component, Reflect)]
+pub struct RedoButton;
+
 #[derive(Clone, Copy, Component, Reflect)]
 pub struct QuitButton;
 
@@ -136,8 +139,9 @@ pub fn sys_undo_button_state(
             &ForPlayer,
             AsDerefMut<VisibilityTty>,
             Has<MouseEventTtyDisabled>,
+            Has<RedoButton>,
         ),
-        With<UndoButton>,
+        Or<(With<UndoButton>, With<RedoButton>)>,
     >,
 ) {
     let updated_teams: EntityHashSet = evr_node_op
@@ -149,7 +153,7 @@ pub fn sys_undo_button_state(
     if updated_teams.is_empty() {
         return;
     }
-    for (ui_id, &ForPlayer(player_id), mut is_visible, has_disable_component) in
+    for (ui_id, &ForPlayer(player_id), mut is_visible, has_disable_component, is_redo_button) in
         q_undo_button.iter_mut()
     {
         q_player.get(player_id).ok().and_then(|team_id| {
@@ -159,9 +163,14 @@ pub fn sys_undo_button_state(
             let (team_phase, undo_stack) = q_team.get(*team_id).ok()?;
             let should_be_visible = *team_phase != TeamPhase::Setup;
             is_visible.set_if_neq(should_be_visible);
-            // If the undo stack is empty, it should have disable component
+            // If there's nothing to undo (or redo), it should have disable component
             // If not visible, doesn't matter
-            if should_be_visible && (undo_stack.is_empty() != has_disable_component) {
+            let is_empty = if is_redo_button {
+                !undo_stack.can_redo()
+            } else {
+                !undo_stack.can_undo()
+            };
+            if should_be_visible && (is_empty != has_disable_component) {
                 if has_disable_component {
                     commands.entity(ui_id).remove::<MouseEventTtyDisabled>();
                 } else {
@@ -13,13 +13,13 @@ use game_core::node::{
     AccessPoint, Curio, InNode, IsTapped, MovesTaken, Node, NodePiece, OnTeam, Pickup,
 };
 use game_core::player::{ForPlayer, Player};
-use game_core::NDitCoreSet;
+use game_core::{card, node, NDitCoreSet};
 pub use grid_animation::GridUiAnimation;
 
 use self::grid_inputs::GridContextActions;
 use super::{
     AvailableActionTargets, AvailableMoves, CursorIsHidden, NodeCursor, NodeUi, NodeUiQItem,
-    SelectedAction, SelectedNodePiece, TelegraphedAction,
+    SelectedAction, SelectedItem, SelectedNodePiece, TelegraphedAction,
 };
 use crate::base_ui::{HoverPoint, Scroll2d, Tooltip};
 use crate::input_event::MouseEventListener;
@@ -53,6 +53,8 @@ impl Plugin for GridUi {
                                 .after(calculate_ui_components::sys_hover_grid_point),
                             (
                                 calculate_ui_components::sys_get_range_of_action,
+                                calculate_ui_components::sys_preview_hovered_action
+                                    .after(calculate_ui_components::sys_hover_grid_point),
                                 grid_tooltip::sys_grid_ui_tooltip,
                             )
                                 .chain(),
@@ -85,6 +87,7 @@ impl NodeUi for GridUi {
         GridHoverPoint,
         LastGridHoverPoint,
         PathToGridPoint,
+        HoveredActionPreview,
         Tooltip,
     );
     type UiPlugin = Self;
@@ -119,6 +122,7 @@ impl NodeUi for GridUi {
             GridHoverPoint::default(),
             LastGridHoverPoint::default(),
             PathToGridPoint::default(),
+            HoveredActionPreview::default(),
             Tooltip::default(),
         )
     }
@@ -155,6 +159,42 @@ pub struct LastGridHoverPoint(UVec2);
 #[derive(Component, Default, Deref, DerefMut)]
 pub struct PathToGridPoint(Vec<(UVec2, Compass)>);
 
+/// What the selected action would do to the square under the cursor, from a
+/// preview of the op
+#[derive(Component, Debug, Default, PartialEq)]
+pub struct HoveredActionPreview {
+    /// Squares the action would delete
+    damaged_squares: HashSet<UVec2>,
+    /// Whether the action would remove a curio from the grid
+    is_fatal: bool,
+}
+
+impl HoveredActionPreview {
+    fn from_metadata(metadata: &Metadata) -> Self {
+        let mut preview = HoveredActionPreview::default();
+        let effects = metadata
+            .get_or_default(node::key::AREA_EFFECTS)
+            .unwrap_or_default()
+            .into_iter()
+            .chain(metadata.get_optional(node::key::EFFECTS).ok().flatten())
+            .chain(
+                metadata
+                    .get_optional(node::key::SELF_EFFECTS)
+                    .ok()
+                    .flatten(),
+            );
+        for effects_metadata in effects {
+            if let Ok(damages) = effects_metadata.get_or_default(card::key::DAMAGES) {
+                preview.damaged_squares.extend(damages);
+            }
+            preview.is_fatal |= effects_metadata
+                .get_or_default(card::key::FATAL)
+                .unwrap_or_default();
+        }
+        preview
+    }
+}
+
 #[derive(QueryData)]
 pub struct NodePieceQ {
     piece: &'static NodePiece,
@@ -174,6 +214,7 @@ pub struct PlayerUiQ {
     entity: Entity,
     selected_entity: &'static SelectedNodePiece,
     selected_action: &'static SelectedAction,
+    selected_item: &'static SelectedItem,
     telegraphed_action: &'static TelegraphedAction,
     node_cursor: &'static NodeCursor,
     cursor_is_hidden: AsDerefCopied<CursorIsHidden>,
@@ -1,15 +1,20 @@
-use game_core::card::{Action, Actions, MovementSpeed};
+use game_core::card::{Action, Actions, MovementSpeed, StatusEffects};
+use game_core::item::ItemActions;
 use game_core::node::{
-    AccessPoint, ActiveCurio, CurrentTurn, InNode, IsTapped, MovesTaken, Node, NodePiece, OnTeam,
-    Pickup, Team, TeamPhase,
+    AccessPoint, ActiveCurio, Alliances, CurrentTurn, InNode, IsTapped, MovesTaken, Node, NodeOp,
+    NodeOpPreview, NodePiece, OnTeam, Pickup, Team, TeamPhase,
 };
 use game_core::player::{ForPlayer, Player};
 
 use super::super::{AvailableMoves, SelectedNodePiece};
-use super::{GridHoverPoint, GridUi, LastGridHoverPoint, PathToGridPoint, PlayerUiQ};
+use super::{
+    GridHoverPoint, GridUi, HoveredActionPreview, LastGridHoverPoint, PathToGridPoint, PlayerUiQ,
+};
 use crate::base_ui::{HoverPoint, Scroll2d};
 use crate::layout::UiFocus;
-use crate::node_ui::{AvailableActionTargets, CursorIsHidden, SelectedAction, TelegraphedAction};
+use crate::node_ui::{
+    AvailableActionTargets, CursorIsHidden, SelectedAction, SelectedItem, TelegraphedAction,
+};
 use crate::prelude::*;
 
 pub fn sys_adjust_available_moves(
@@ -17,6 +22,7 @@ pub fn sys_adjust_available_moves(
         (
             Ref<UiFocus>,
             &SelectedAction,
+            &SelectedItem,
             AsDerefCopied<SelectedNodePiece>,
             &InNode,
             AsDerefCopiedOrDefault<CursorIsHidden>,
@@ -33,6 +39,7 @@ pub fn sys_adjust_available_moves(
             &MovementSpeed,
             Option<&MovesTaken>,
             Option<&IsTapped>,
+            Option<&StatusEffects>,
         ),
         With<NodePiece>,
     >,
@@ -41,6 +48,7 @@ pub fn sys_adjust_available_moves(
     for (
         ui_focus,
         selected_action,
+        selected_item,
         selected_entity,
         node_id,
         cursor_is_hidden,
@@ -60,13 +68,14 @@ pub fn sys_adjust_available_moves(
                     .and(selected_entity)
                     .or(active_curio)?;
 
-                let (entity, speed, moves_taken, tapped) = node_pieces.get(curio_id).ok()?;
+                let (entity, speed, moves_taken, tapped, status_effects) =
+                    node_pieces.get(curio_id).ok()?;
                 if matches!(tapped, Some(IsTapped(true))) {
                     return None;
                 }
                 let head = grid.head(entity)?;
 
-                if selected_action.is_some()
+                if (selected_action.is_some() || selected_item.is_some())
                     && ui_focus
                         .into_inner()
                         .map(|focused_entity| grid_uis.contains(focused_entity))
@@ -74,9 +83,17 @@ pub fn sys_adjust_available_moves(
                 {
                     return Some(std::iter::once((head, None)).collect()); // TODO better pattern for this ?
                 }
-                let moves =
-                    (**speed).saturating_sub(moves_taken.map(|mt| **mt).unwrap_or_default());
-                Some(possible_moves(head, &pickups, moves, entity, grid))
+                let movement_penalty = status_effects
+                    .map(StatusEffects::movement_penalty)
+                    .unwrap_or(0);
+                let moves = (**speed)
+                    .saturating_sub(movement_penalty)
+                    .saturating_sub(moves_taken.map(|mt| **mt).unwrap_or_default());
+                // TODO only let certain entities get pickups.
+                Some(
+                    grid.distance_map(entity, Some(moves), |id| pickups.contains(id))
+                        .directions(),
+                )
             })
             .unwrap_or_default();
 
@@ -86,40 +103,6 @@ pub fn sys_adjust_available_moves(
     }
 }
 
-fn possible_moves(
-    head: UVec2,
-    pickup_query: &Query<(), With<Pickup>>,
-    moves: u32,
-    id: Entity,
-    grid: &EntityGrid,
-) -> HashMap<UVec2, Option<Compass>> {
-    let mut points_map: HashMap<UVec2, _> = [(head, None)].into_iter().collect();
-    let mut last_edge_set: HashSet<_> = [head].into_iter().collect();
-    for _ in 0..moves {
-        let mut next_edge_set = HashSet::new();
-        for pt in last_edge_set {
-            for dir in Compass::ALL_DIRECTIONS.iter() {
-                let next_pt = (pt + *dir).min(grid.index_bounds());
-                if points_map.contains_key(&next_pt) {
-                    continue;
-                }
-                // TODO only let certain entities get pickups.
-                let can_move_to_pt = grid.square_is_free(next_pt)
-                    || grid
-                        .item_at(next_pt)
-                        .map(|pt_id| id == pt_id || pickup_query.contains(pt_id))
-                        .unwrap_or(false);
-                if can_move_to_pt {
-                    points_map.insert(next_pt, Some(*dir));
-                    next_edge_set.insert(next_pt);
-                }
-            }
-        }
-        last_edge_set = next_edge_set;
-    }
-    points_map
-}
-
 // TODO might need to reorganize these methods
 // Update Hover Grid Point.
 
@@ -214,17 +197,20 @@ pub fn sys_path_under_hover(
 
 pub fn sys_get_range_of_action(
     ast_actions: Res<Assets<Action>>,
+    res_item_actions: Res<ItemActions>,
     mut players: ParamSet<(
         Query<PlayerUiQ>,
         Query<(Entity, &mut AvailableActionTargets)>,
     )>,
     q_team: Query<AsDerefCopied<OnTeam>, With<NodePiece>>,
+    q_alliances: Query<&Alliances, With<Team>>,
     changed_player: Query<
         (),
         (
             With<Player>,
             Or<(
                 Changed<SelectedAction>,
+                Changed<SelectedItem>,
                 Changed<SelectedNodePiece>,
                 Changed<AvailableMoves>,
                 Changed<TelegraphedAction>,
@@ -253,23 +239,30 @@ pub fn sys_get_range_of_action(
         .p0()
         .iter_many(&players_to_update)
         .filter_map(|player_q| {
-            // Note: Will probably have to change this logic so that when the player is
-            // actually trying to perform the action, it only shows up
-            let selected_piece = (*player_q.selected_entity.deref())?;
-
-            let (curio_actions, is_tapped) = player_q.selected_entity.of(&node_pieces)?;
-            if is_tapped.map(|is_tapped| **is_tapped).unwrap_or(false) {
-                return None;
-            }
             let (grid, active_curio) = node_grids.get(**player_q.in_node).ok()?;
+            // Items are used by the active curio, whichever piece is selected
+            let (action_id, entity, selected_piece) =
+                if let Some(item_id) = player_q.selected_item.as_deref() {
+                    let active_curio = active_curio?;
+                    (res_item_actions.get(item_id)?, active_curio, active_curio)
+                } else {
+                    // Note: Will probably have to change this logic so that when the player is
+                    // actually trying to perform the action, it only shows up
+                    let selected_piece = (*player_q.selected_entity.deref())?;
 
-            let (action_id, entity) = match player_q.telegraphed_action.as_ref() {
-                Some(action_id) => (action_id, active_curio?),
-                None => (
-                    &curio_actions[(**player_q.selected_action)?],
-                    (**player_q.selected_entity)?,
-                ),
-            };
+                    let (curio_actions, is_tapped) = player_q.selected_entity.of(&node_pieces)?;
+                    if is_tapped.map(|is_tapped| **is_tapped).unwrap_or(false) {
+                        return None;
+                    }
+                    match player_q.telegraphed_action.as_ref() {
+                        Some(action_id) => (action_id, active_curio?, selected_piece),
+                        None => (
+                            &curio_actions[(**player_q.selected_action)?],
+                            (**player_q.selected_entity)?,
+                            selected_piece,
+                        ),
+                    }
+                };
             let action_def = ast_actions.get(action_id)?;
             let target = action_def.target();
             let range = action_def.range()?; // Not all actions have a range
@@ -280,13 +273,14 @@ pub fn sys_get_range_of_action(
             } = grid.bounds();
 
             let team_check = |id| q_team.get(id).ok();
+            let alliances = team_check(selected_piece).and_then(|team| q_alliances.get(team).ok());
 
             let pts: HashMap<UVec2, bool> = (0..width)
                 .flat_map(|x| {
                     (0..height).filter_map(move |y| {
                         let pt = UVec2 { x, y };
                         let valid_target =
-                            target.valid_target(grid, selected_piece, pt, team_check);
+                            target.valid_target(grid, selected_piece, pt, team_check, alliances);
                         // Will need to change this logic for Packman moves
                         if !valid_target && grid.square_is_closed(pt) {
                             return None;
@@ -304,7 +298,7 @@ pub fn sys_get_range_of_action(
                         }
 
                         // TODO only run this if the player has selected to perform an action
-                        if range.in_range_of_pts(available_moves.keys(), pt) {
+                        if range.in_range_of_pts(grid, entity, available_moves.keys(), pt) {
                             return Some((pt, false));
                         }
 
@@ -325,3 +319,53 @@ pub fn sys_get_range_of_action(
         }
     }
 }
+
+/// Previews the selected action or item on the hovered square, if it can
+/// target it
+pub fn sys_preview_hovered_action(
+    ast_actions: Res<Assets<Action>>,
+    node_op_preview: NodeOpPreview,
+    players: Query<PlayerUiQ, With<Player>>,
+    node_pieces: Query<&Actions, With<NodePiece>>,
+    mut grid_uis: Query<
+        (
+            AsDerefCopied<ForPlayer>,
+            AsDerefCopied<GridHoverPoint>,
+            &mut HoveredActionPreview,
+        ),
+        With<GridUi>,
+    >,
+) {
+    for (player_id, hover_point, mut hovered_action_preview) in grid_uis.iter_mut() {
+        let new_preview = hover_point
+            .and_then(|target| {
+                let player_q = players.get(player_id).ok()?;
+                if !player_q
+                    .available_action_targets
+                    .get(&target)
+                    .copied()
+                    .unwrap_or_default()
+                {
+                    return None;
+                }
+                let op = if let Some(item_id) = player_q.selected_item.as_deref() {
+                    NodeOp::UseItem {
+                        item_id: item_id.to_owned(),
+                        target,
+                    }
+                } else {
+                    let actions = player_q.selected_entity.of(&node_pieces)?;
+                    let action = ast_actions.get(actions.get((**player_q.selected_action)?)?)?;
+                    NodeOp::PerformCurioAction {
+                        action_id: action.id_cow(),
+                        curio: **player_q.selected_entity,
+                        target,
+                    }
+                };
+                node_op_preview.preview(player_id, &op).ok()
+            })
+            .map(|metadata| HoveredActionPreview::from_metadata(&metadata))
+            .unwrap_or_default();
+        hovered_action_preview.set_if_neq(new_preview);
+    }
+}
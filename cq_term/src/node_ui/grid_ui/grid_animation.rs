@@ -32,10 +32,20 @@ pub fn sys_grid_animations(
 ) {
     for node_op_result in ev_node_op.read() {
         if let Some((node_id, animation_handle)) = match node_op_result.op() {
-            NodeOp::PerformCurioAction { target, .. } => {
+            NodeOp::PerformCurioAction { target, .. } | NodeOp::UseItem { target, .. } => {
                 node_op_result.result().as_ref().ok().and_then(|metadata| {
                     let effects_metadata = metadata.get_or_default(node::key::EFFECTS).ok()?;
-                    let damages = effects_metadata.get_or_default(card::key::DAMAGES).ok()?;
+                    // Damage to the area around the target animates first, so
+                    // that a fatal hit on the target still ends on its head
+                    let damages: Vec<UVec2> = metadata
+                        .get_or_default(node::key::AREA_EFFECTS)
+                        .ok()?
+                        .iter()
+                        .chain(std::iter::once(&effects_metadata))
+                        .map(|effects| effects.get_or_default(card::key::DAMAGES))
+                        .collect::<Result<Vec<_>, _>>()
+                        .ok()?
+                        .concat();
                     if damages.is_empty() {
                         return None;
                     }
@@ -15,7 +15,8 @@ use crate::layout::UiFocus;
 use crate::main_ui::UiOps;
 use crate::node_ui::node_ui_op::FocusTarget;
 use crate::node_ui::{
-    AvailableActionTargets, AvailableMoves, NodeCursor, NodeUiOp, SelectedAction, SelectedNodePiece,
+    AvailableActionTargets, AvailableMoves, NodeCursor, NodeUiOp, SelectedAction, SelectedItem,
+    SelectedNodePiece,
 };
 use crate::prelude::*;
 use crate::{KeyMap, Submap};
@@ -70,22 +71,33 @@ impl FromWorld for GridContextActions {
                 );
             })
         )).id();
-        let perform_action =
-            world
-                .spawn((
-                    Name::new("Perform action CA"),
-                    ContextAction::new("Perform action", |grid_id, world| {
-                        // Once we can run systems with input we make this a bit easier
-                        world.run_system_once(
+        let perform_action = world
+            .spawn((
+                Name::new("Perform action CA"),
+                ContextAction::new("Perform action", |grid_id, world| {
+                    // Once we can run systems with input we make this a bit easier
+                    world.run_system_once(
                         move |ast_action: Res<Assets<Action>>,
                               mut res_core_ops: ResMut<CoreOps>,
                               q_grid_ui: Query<(&ForPlayer, &LastGridHoverPoint), With<GridUi>>,
-                              q_player: Query<(&SelectedAction, &SelectedNodePiece), With<Player>>,
+                              q_player: Query<
+                            (&SelectedAction, &SelectedItem, &SelectedNodePiece),
+                            With<Player>,
+                        >,
                               q_curio: Query<&Actions, With<Curio>>| {
                             get_assert!(grid_id, q_grid_ui).and_then(
                                 |(&ForPlayer(player_id), &LastGridHoverPoint(target))| {
-                                    let (&SelectedAction(action_index), &SelectedNodePiece(curio)) =
-                                        get_assert!(player_id, q_player)?;
+                                    let (
+                                        &SelectedAction(action_index),
+                                        SelectedItem(item_id),
+                                        &SelectedNodePiece(curio),
+                                    ) = get_assert!(player_id, q_player)?;
+                                    // A selected item is used instead of the selected action
+                                    if let Some(item_id) = item_id.clone() {
+                                        let op = NodeOp::UseItem { item_id, target };
+                                        res_core_ops.request(player_id, op);
+                                        return Some(());
+                                    }
                                     let actions = q_curio.get(curio?).ok()?;
                                     let action_handle = actions.get(action_index?)?;
                                     let action_id = ast_action.get(action_handle)?.id_cow();
@@ -101,9 +113,9 @@ impl FromWorld for GridContextActions {
                             );
                         },
                     )
-                    }),
-                ))
-                .id();
+                }),
+            ))
+            .id();
         let select_piece = world
             .spawn((
                 Name::new("Select piece CA"),
@@ -267,6 +279,7 @@ pub fn kb_grid(
             &NodeCursor,
             &SelectedNodePiece,
             &SelectedAction,
+            &SelectedItem,
         ),
         With<Player>,
     >,
@@ -284,6 +297,7 @@ pub fn kb_grid(
             cursor,
             selected_entity,
             selected_action,
+            selected_item,
         ) in players.iter()
         {
             if focus_opt
@@ -303,14 +317,27 @@ pub fn kb_grid(
 
                     match named_input {
                         NamedInput::Direction(dir) => {
-                            if is_controlling_active_curio && selected_action.is_none() {
+                            if is_controlling_active_curio
+                                && selected_action.is_none()
+                                && selected_item.is_none()
+                            {
                                 res_core_ops.request(player, NodeOp::MoveActiveCurio { dir })
                             } else {
                                 res_ui_ops.request(player, NodeUiOp::MoveNodeCursor(dir.into()));
                             }
                         },
                         NamedInput::Activate => {
-                            if let Some(selected_action_index) = **selected_action {
+                            if let Some(item_id) = selected_item.as_deref() {
+                                if is_controlling_active_curio && *team_phase == TeamPhase::Play {
+                                    res_core_ops.request(
+                                        player,
+                                        NodeOp::UseItem {
+                                            item_id: item_id.to_owned(),
+                                            target: **cursor,
+                                        },
+                                    );
+                                }
+                            } else if let Some(selected_action_index) = **selected_action {
                                 selected_entity.of(&node_pieces).and_then(
                                     |(actions, is_tapped)| {
                                         if **is_tapped || *team_phase == TeamPhase::Setup {
@@ -372,6 +399,9 @@ pub fn kb_grid(
                             }
                         },
                         NamedInput::Undo => {
+                            if selected_item.is_some() {
+                                res_ui_ops.request(player, NodeUiOp::SetSelectedItem(None));
+                            }
                             if selected_action.is_some() {
                                 res_ui_ops.request(player, NodeUiOp::SetSelectedAction(None));
                                 if is_controlling_active_curio {
@@ -2,11 +2,11 @@ use std::borrow::Cow;
 
 use game_core::card::Action;
 use game_core::node::{
-    ActiveCurio, CurrentTurn, InNode, Node, NodePiece, OnTeam, Pickup, Team, TeamPhase,
+    ActiveCurio, Alliances, CurrentTurn, InNode, Node, NodePiece, OnTeam, Pickup, Team, TeamPhase,
 };
 use game_core::player::{ForPlayer, Player};
 
-use super::{GridUi, NodePieceQ, NodePieceQItem};
+use super::{GridUi, HoveredActionPreview, NodePieceQ, NodePieceQItem};
 use crate::base_ui::{HoverPoint, Scroll2d, Tooltip};
 use crate::node_ui::{AvailableActionTargets, AvailableMoves, SelectedAction, SelectedNodePiece};
 use crate::prelude::*;
@@ -21,6 +21,7 @@ pub fn sys_grid_ui_tooltip(
             AsDerefCopied<ForPlayer>,
             AsDerefCopied<HoverPoint>,
             AsDeref<Scroll2d>,
+            &HoveredActionPreview,
         ),
         With<GridUi>,
     >,
@@ -47,8 +48,10 @@ pub fn sys_grid_ui_tooltip(
     pickups: Query<(), (With<Pickup>, With<NodePiece>)>,
     teamcheck: Query<AsDerefCopied<OnTeam>>,
     teams: Query<&TeamPhase, With<Team>>,
+    team_alliances: Query<&Alliances, With<Team>>,
 ) {
-    for (mut tooltip, player_id, hover_point, scroll) in grid_uis.iter_mut() {
+    for (mut tooltip, player_id, hover_point, scroll, hovered_action_preview) in grid_uis.iter_mut()
+    {
         let tooltip_text: Option<Cow<'static, str>> = hover_point.and_then(|hover_point| {
             let hover_point = UVec2::new(
                 (scroll.x + hover_point.x) / 3,
@@ -119,15 +122,22 @@ pub fn sys_grid_ui_tooltip(
                     let action = ast_actions
                         .get(action_handle)
                         .expect("action should be loaded");
+                    let alliances = teamcheck
+                        .get(active_curio_id)
+                        .ok()
+                        .and_then(|team| team_alliances.get(team).ok());
                     let valid = action.target().valid_target(
                         grid,
                         active_curio_id,
                         hover_point,
                         |node_piece_id| teamcheck.get(node_piece_id).ok(),
+                        alliances,
                     );
                     let action_name = action.id();
                     let name = hover_name.unwrap_or("space".to_string());
-                    if valid {
+                    if valid && hovered_action_preview.is_fatal {
+                        Some(format!("[RightMb] use {action_name} on {name} (Fatal!)"))
+                    } else if valid {
                         Some(format!("[RightMb] use {action_name} on {name}"))
                     } else {
                         Some(format!("[Cannot apply {action_name} to {name}]"))
@@ -2,7 +2,7 @@ use std::cmp;
 
 use charmi::{CharacterMapImage, CharmieString};
 use crossterm::style::{ContentStyle, Stylize};
-use game_core::node::{ActiveCurio, Node};
+use game_core::node::{ActiveCurio, Node, Team, TeamColor};
 use game_core::player::{ForPlayer, Player};
 use game_core::registry::Reg;
 use itertools::Itertools;
@@ -11,7 +11,8 @@ use super::borders::{arrow_border, border_style_for, intersection_for_pivot, Bor
 use super::grid_animation::GridUiAnimation;
 use super::render_square::render_square;
 use super::{
-    GridHoverPoint, GridUi, NodePieceQ, PathToGridPoint, PlayerUiQ, PlayerUiQItem, Scroll2d,
+    GridHoverPoint, GridUi, HoveredActionPreview, NodePieceQ, PathToGridPoint, PlayerUiQ,
+    PlayerUiQItem, Scroll2d,
 };
 use crate::animation::AnimationPlayer;
 use crate::configuration::DrawConfiguration;
@@ -26,6 +27,7 @@ const OPEN_SQUARE: &str = "░░";
 pub fn render_grid_system(
     node_data: Query<(&EntityGrid, &ActiveCurio), With<Node>>,
     node_pieces: Query<NodePieceQ>,
+    team_colors: Query<&TeamColor, With<Team>>,
     players: Query<PlayerUiQ, With<Player>>,
     reg_glyph: Res<Reg<NodeGlyph>>,
     draw_config: Res<DrawConfiguration>,
@@ -36,6 +38,7 @@ pub fn render_grid_system(
             &ForPlayer,
             &GridHoverPoint,
             Ref<PathToGridPoint>,
+            &HoveredActionPreview,
             &mut TerminalRendering,
         ),
         With<GridUi>,
@@ -45,8 +48,15 @@ pub fn render_grid_system(
         (With<GridUiAnimation>, Without<GridUi>),
     >,
 ) {
-    for (size, scroll, ForPlayer(player), hover_point, path_to_grid_point, mut rendering) in
-        render_grid_q.iter_mut()
+    for (
+        size,
+        scroll,
+        ForPlayer(player),
+        hover_point,
+        path_to_grid_point,
+        hovered_action_preview,
+        mut rendering,
+    ) in render_grid_q.iter_mut()
     {
         if let Ok(player_ui_q) = players.get(*player) {
             if let Ok((grid, active_curio)) = node_data.get(**player_ui_q.in_node) {
@@ -62,10 +72,12 @@ pub fn render_grid_system(
                     scroll,
                     hover_point,
                     path_to_grid_point,
+                    hovered_action_preview,
                     &player_ui_q,
                     grid,
                     active_curio,
                     &node_pieces,
+                    &team_colors,
                     &reg_glyph,
                     &draw_config,
                     grid_animation.unwrap(),
@@ -82,10 +94,12 @@ fn render_grid(
     scroll: &Scroll2d,
     hover_point: &GridHoverPoint,
     path_to_grid_point: Ref<PathToGridPoint>,
+    hovered_action_preview: &HoveredActionPreview,
     player_q: &PlayerUiQItem,
     grid: &EntityGrid,
     active_curio: &ActiveCurio,
     node_pieces: &Query<NodePieceQ>,
+    team_colors: &Query<&TeamColor, With<Team>>,
     reg_glyph: &Reg<NodeGlyph>,
     draw_config: &DrawConfiguration,
     grid_animation: (&AnimationPlayer, &TerminalRendering, &ForPlayer),
@@ -100,9 +114,23 @@ fn render_grid(
 
     // Use Cow instead of String?
     let mut sprite_map = grid.point_map(|i, sprite| {
-        render_square(i, sprite, active_curio, node_pieces, reg_glyph, draw_config)
+        render_square(
+            i,
+            sprite,
+            active_curio,
+            node_pieces,
+            team_colors,
+            reg_glyph,
+            draw_config,
+        )
     });
 
+    for damaged_square in hovered_action_preview.damaged_squares.iter() {
+        sprite_map
+            .entry(*damaged_square)
+            .and_modify(|(style, _)| *style = draw_config.color_scheme().previewed_damage());
+    }
+
     let x_start = (scroll.x / 3) as usize;
     // The highest x value to be on screen, in character columns
     let x2 = cmp::min(width * 3 + 1, scroll.x as usize + size.width());
@@ -1,5 +1,5 @@
-use crossterm::style::ContentStyle;
-use game_core::node::ActiveCurio;
+use crossterm::style::{ContentStyle, Stylize};
+use game_core::node::{ActiveCurio, Team, TeamColor};
 use game_core::prelude::*;
 use game_core::registry::Reg;
 
@@ -13,6 +13,7 @@ pub fn render_square(
     entity: Entity,
     active_curio: &ActiveCurio,
     node_pieces: &Query<super::NodePieceQ>,
+    team_colors: &Query<&TeamColor, With<Team>>,
     reg_glyph: &Reg<NodeGlyph>,
     configuration: &DrawConfiguration,
 ) -> (ContentStyle, String) {
@@ -35,6 +36,12 @@ pub fn render_square(
         .unwrap_or_default()
     {
         configuration.color_scheme().player_team_active()
+    } else if let Some(&team_color) = node_piece
+        .team
+        .filter(|_| node_piece.has_curio)
+        .and_then(|team| team_colors.get(team).ok())
+    {
+        glyph_style.with(configuration.color_scheme().team(team_color))
     } else {
         glyph_style
     };
@@ -6,7 +6,7 @@ use game_core::op::CoreOps;
 use game_core::player::Player;
 
 use super::grid_ui::GridUi;
-use super::menu_ui::{MenuUiActions, MenuUiCardSelection};
+use super::menu_ui::{MenuUiActions, MenuUiCardSelection, MenuUiItems};
 use super::node_ui_op::FocusTarget;
 use super::{NodeUiOp, SelectedAction, SelectedNodePiece};
 use crate::key_map::NamedInput;
@@ -30,6 +30,9 @@ pub fn kb_ready(
                 Some(NamedInput::Undo) => {
                     res_prime_op.request(player, NodeOp::Undo);
                 },
+                Some(NamedInput::Redo) => {
+                    res_prime_op.request(player, NodeOp::Redo);
+                },
                 _ => {},
             }
         }
@@ -54,10 +57,18 @@ pub fn kb_skirm_focus(
     nodes: Query<(&ActiveCurio, &CurrentTurn), With<Node>>,
     action_pieces: Query<&Actions, With<NodePiece>>,
     access_points: Query<(), (With<AccessPoint>, With<NodePiece>)>,
-    skirm_uis: Query<(), Or<(With<GridUi>, With<MenuUiCardSelection>, With<MenuUiActions>)>>,
+    skirm_uis: Query<
+        (),
+        Or<(
+            With<GridUi>,
+            With<MenuUiCardSelection>,
+            With<MenuUiActions>,
+            With<MenuUiItems>,
+        )>,
+    >,
     grid_uis: Query<(), With<GridUi>>,
     card_menus: Query<(), With<MenuUiCardSelection>>,
-    action_menus: Query<(), With<MenuUiActions>>,
+    action_menus: Query<(), Or<(With<MenuUiActions>, With<MenuUiItems>)>>,
 ) {
     for KeyEvent { code, modifiers } in ev_keys.read() {
         for (player, in_node, team, focus, key_map, selected_entity, selected_action) in
@@ -88,6 +99,7 @@ pub fn kb_skirm_focus(
                                 && selected_entity.of(&access_points).is_some()
                             {
                                 Some(FocusTarget::CardMenu)
+                            // Activate on the actions or items menu => Use it on the grid
                             } else if focus
                                 .map(|focus| action_menus.contains(focus))
                                 .unwrap_or_default()
@@ -117,7 +129,7 @@ pub fn kb_skirm_focus(
                         },
                         NamedInput::MenuFocusNext => Some(FocusTarget::Next),
                         NamedInput::MenuFocusPrev => Some(FocusTarget::Prev),
-                        NamedInput::Undo => Some(FocusTarget::Grid),
+                        NamedInput::Undo | NamedInput::Redo => Some(FocusTarget::Grid),
                         _ => None,
                     }
                 })
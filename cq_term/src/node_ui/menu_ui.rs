@@ -1,6 +1,7 @@
 mod actions;
 mod card_selection;
 mod description;
+mod items;
 mod label;
 mod simple_submenu;
 mod stats;
@@ -11,9 +12,10 @@ use bevy::ecs::system::SystemParam;
 pub use card_selection::MenuUiCardSelection;
 use charmi::CharacterMapImage;
 pub use description::MenuUiDescription;
-use game_core::card::{Actions, Description, MaximumSize, MovementSpeed};
+use game_core::card::{Actions, Description, MaximumSize, MovementSpeed, StatusEffects};
 use game_core::node::{AccessPoint, Curio, IsTapped, MovesTaken, NodePiece, Pickup, Team};
 use game_core::prelude::*;
+pub use items::MenuUiItems;
 pub use label::MenuUiLabel;
 pub use stats::MenuUiStats;
 
@@ -63,5 +65,6 @@ pub struct NodePieceQ {
     max_size: Option<&'static MaximumSize>,
     moves_taken: Option<&'static MovesTaken>,
     is_tapped: Option<&'static IsTapped>,
+    status_effects: Option<&'static StatusEffects>,
     access_point: Option<&'static AccessPoint>,
 }
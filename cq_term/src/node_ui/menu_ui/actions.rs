@@ -2,9 +2,9 @@ use std::borrow::Cow;
 use std::ops::Deref;
 
 use charmi::CharacterMapImage;
-use game_core::card::{Action, ActionTarget, Actions};
+use game_core::card::{Action, ActionTarget, Actions, PrereqQ};
 use game_core::common::daddy::Daddy;
-use game_core::node::{IsTapped, NodeOp, NodePiece, OnTeam, Team, TeamPhase};
+use game_core::node::{InNode, IsTapped, Node, NodeOp, NodePiece, OnTeam, Team, TeamPhase};
 use game_core::op::CoreOps;
 use game_core::player::{ForPlayer, Player};
 use game_core::NDitCoreSet;
@@ -188,7 +188,17 @@ impl MenuUiActions {
         res_draw_config: Res<DrawConfiguration>,
         ast_actions: Res<Assets<Action>>,
         node_pieces: Query<&Actions, With<NodePiece>>,
-        players: Query<(&SelectedNodePiece, &SelectedAction, &UiFocus), With<Player>>,
+        players: Query<
+            (
+                &SelectedNodePiece,
+                &SelectedAction,
+                &UiFocus,
+                Option<&InNode>,
+            ),
+            With<Player>,
+        >,
+        grids: Query<&EntityGrid, With<Node>>,
+        prereq_curios: Query<PrereqQ>,
         mut ui: Query<
             (
                 Entity,
@@ -202,7 +212,8 @@ impl MenuUiActions {
     ) {
         // let render_param = render_param.into_inner();
         for (id, size, ForPlayer(player), hover_point, mut tr) in ui.iter_mut() {
-            if let Ok((selected_entity, selected_action, focus)) = players.get(*player) {
+            if let Ok((selected_entity, selected_action, focus, in_node)) = players.get(*player) {
+                let grid = in_node.and_then(|InNode(node)| grids.get(*node).ok());
                 let rendering = selected_entity
                     .of(&node_pieces)
                     .map(|piece_actions| {
@@ -220,14 +231,25 @@ impl MenuUiActions {
 
                         for (idx, action) in piece_actions.iter().enumerate() {
                             if let Some(action) = ast_actions.get(action) {
-                                let style = (Some(idx) == hover_index)
-                                    .then(|| res_draw_config.color_scheme().menu_hover())
-                                    .unwrap_or_default();
-                                let action_text = if Some(idx) == **selected_action {
+                                let unsatisfied_prereq =
+                                    grid.zip(**selected_entity).and_then(|(grid, piece)| {
+                                        action.unsatisfied_prereq(grid, piece, None, &prereq_curios)
+                                    });
+                                let style = if Some(idx) == hover_index {
+                                    res_draw_config.color_scheme().menu_hover()
+                                } else if unsatisfied_prereq.is_some() {
+                                    res_draw_config.color_scheme().menu_disabled()
+                                } else {
+                                    default()
+                                };
+                                let mut action_text = if Some(idx) == **selected_action {
                                     format!("▶{}", action.id())
                                 } else {
                                     action.id().to_string()
                                 };
+                                if let Some(prereq) = unsatisfied_prereq {
+                                    action_text = format!("{action_text} ({prereq})");
+                                }
                                 menu.new_row().add_text(action_text, &style);
                             }
                         }
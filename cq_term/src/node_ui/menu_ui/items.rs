@@ -0,0 +1,266 @@
+use charmi::CharacterMapImage;
+use game_core::item::{Inventory, ItemActions};
+use game_core::node::InNode;
+use game_core::player::{ForPlayer, Player};
+use game_core::NDitCoreSet;
+
+use crate::base_ui::{HoverPoint, Tooltip};
+use crate::configuration::DrawConfiguration;
+use crate::input_event::{MouseEventListener, MouseEventTty, MouseEventTtyKind};
+use crate::key_map::NamedInput;
+use crate::layout::{CalculatedSizeTty, StyleTty, UiFocus};
+use crate::main_ui::UiOps;
+use crate::node_ui::node_ui_op::FocusTarget;
+use crate::node_ui::{NodeUi, NodeUiOp, NodeUiQItem, SelectedItem};
+use crate::prelude::*;
+use crate::render::{RenderTtySet, TerminalRendering, RENDER_TTY_SCHEDULE};
+use crate::{KeyMap, Submap};
+
+/// Menu of the items in the player's inventory that can be used in the node
+#[derive(Component, Default, Debug)]
+pub struct MenuUiItems;
+
+impl Plugin for MenuUiItems {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (
+                Self::kb_item_menu.in_set(NDitCoreSet::ProcessInputs),
+                Self::mouse_item_menu.in_set(NDitCoreSet::ProcessInputs),
+            ),
+        )
+        .add_systems(
+            Update,
+            Self::sys_adjust_selected_item.in_set(NDitCoreSet::PostProcessUiOps),
+        )
+        .add_systems(
+            RENDER_TTY_SCHEDULE,
+            (
+                Self::sys_adjust_style_item_menu.in_set(RenderTtySet::AdjustLayoutStyle),
+                Self::sys_render_item_menu.in_set(RenderTtySet::PostCalculateLayout),
+            ),
+        );
+    }
+}
+
+/// Ids and counts of the items in the inventory that have an action
+fn usable_items<'a>(inventory: &'a Inventory, item_actions: &ItemActions) -> Vec<(&'a str, u32)> {
+    inventory
+        .items()
+        .into_iter()
+        .filter(|(item_id, _)| item_actions.contains_key(*item_id))
+        .collect()
+}
+
+impl MenuUiItems {
+    pub fn kb_item_menu(
+        mut ev_keys: EventReader<KeyEvent>,
+        res_item_actions: Res<ItemActions>,
+        mut res_ui_ops: ResMut<UiOps>,
+        players: Query<(Entity, &UiFocus, &KeyMap, &Inventory, &SelectedItem), With<Player>>,
+        item_menu_uis: Query<(), With<MenuUiItems>>,
+    ) {
+        for KeyEvent { code, modifiers } in ev_keys.read() {
+            for (player_id, focus, key_map, inventory, selected_item) in players.iter() {
+                if (**focus)
+                    .map(|focused_ui| !item_menu_uis.contains(focused_ui))
+                    .unwrap_or(true)
+                {
+                    continue;
+                }
+                let items = usable_items(inventory, &res_item_actions);
+                if items.is_empty() {
+                    continue;
+                }
+
+                match key_map.named_input_for_key(Submap::Node, *code, *modifiers) {
+                    Some(NamedInput::Direction(dir)) => {
+                        let items_bound = items.len();
+                        let current_item = selected_item
+                            .as_deref()
+                            .and_then(|selected_item| {
+                                items
+                                    .iter()
+                                    .position(|&(item_id, _)| item_id == selected_item)
+                            })
+                            .unwrap_or(0);
+                        let next_item = (current_item
+                            + match dir {
+                                Compass::North => items_bound - 1,
+                                Compass::South => 1,
+                                _ => 0,
+                            })
+                            % items_bound;
+                        let next_item = Some(items[next_item].0.to_owned());
+                        if **selected_item != next_item {
+                            res_ui_ops.request(player_id, NodeUiOp::SetSelectedItem(next_item));
+                        }
+                    },
+                    Some(NamedInput::MenuFocusNext | NamedInput::MenuFocusPrev) => {
+                        res_ui_ops.request(player_id, NodeUiOp::SetSelectedItem(None));
+                    },
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    pub fn mouse_item_menu(
+        mut res_ui_ops: ResMut<UiOps>,
+        res_item_actions: Res<ItemActions>,
+        mut ev_mouse: EventReader<MouseEventTty>,
+        players: Query<&Inventory, With<Player>>,
+        ui_items: Query<(&ForPlayer, AsDerefCopied<HoverPoint>), With<MenuUiItems>>,
+    ) {
+        for layout_event in ev_mouse.read() {
+            if !matches!(layout_event.event_kind(), MouseEventTtyKind::Down(_)) {
+                continue;
+            }
+            let Some((&ForPlayer(player_id), hover_point)) = layout_event
+                .top_entity()
+                .and_then(|top_entity| ui_items.get(top_entity).ok())
+            else {
+                continue;
+            };
+            res_ui_ops.request(player_id, NodeUiOp::ChangeFocus(FocusTarget::ItemMenu));
+            let hovered_item = hover_point
+                .and_then(|pt| pt.y.checked_sub(1))
+                .zip(players.get(player_id).ok())
+                .and_then(|(index, inventory)| {
+                    let items = usable_items(inventory, &res_item_actions);
+                    Some(items.get(index as usize)?.0.to_owned())
+                });
+            if hovered_item.is_some() {
+                res_ui_ops.request(player_id, NodeUiOp::SetSelectedItem(hovered_item));
+            }
+        }
+    }
+
+    /// Selects the first item when the menu is focused, like the actions menu
+    fn sys_adjust_selected_item(
+        res_item_actions: Res<ItemActions>,
+        mut players: Query<
+            (&UiFocus, &Inventory, &mut SelectedItem),
+            (Changed<UiFocus>, With<Player>),
+        >,
+        item_menus: Query<(), With<MenuUiItems>>,
+    ) {
+        for (ui_focus, inventory, mut selected_item) in players.iter_mut() {
+            if ui_focus
+                .map(|focus| item_menus.contains(focus))
+                .unwrap_or(false)
+                && selected_item.is_none()
+            {
+                **selected_item = usable_items(inventory, &res_item_actions)
+                    .first()
+                    .map(|(item_id, _)| (*item_id).to_owned());
+            }
+        }
+    }
+
+    fn sys_adjust_style_item_menu(
+        res_item_actions: Res<ItemActions>,
+        players: Query<&Inventory, (With<Player>, With<InNode>)>,
+        mut ui: Query<(&mut StyleTty, &ForPlayer), With<MenuUiItems>>,
+    ) {
+        use taffy::prelude::*;
+        for (mut style, ForPlayer(player)) in ui.iter_mut() {
+            let new_height = players
+                .get(*player)
+                .ok()
+                .map(|inventory| usable_items(inventory, &res_item_actions).len())
+                .filter(|&item_count| item_count > 0)
+                .map(|item_count| (item_count + 1) as f32)
+                .unwrap_or(0.0);
+
+            if Dimension::Length(new_height) != style.min_size.height {
+                style.min_size.height = length(new_height);
+                style.display = if new_height == 0.0 {
+                    style.size.height = length(new_height);
+                    taffy::style::Display::None
+                } else {
+                    // Give a little extra for padding if we can
+                    style.size.height = length(new_height + 1.0);
+                    taffy::style::Display::Flex
+                };
+            }
+        }
+    }
+
+    fn sys_render_item_menu(
+        res_draw_config: Res<DrawConfiguration>,
+        res_item_actions: Res<ItemActions>,
+        players: Query<(&Inventory, &SelectedItem, &UiFocus), With<Player>>,
+        mut ui: Query<
+            (
+                Entity,
+                &CalculatedSizeTty,
+                &ForPlayer,
+                AsDeref<HoverPoint>,
+                &mut TerminalRendering,
+            ),
+            With<MenuUiItems>,
+        >,
+    ) {
+        for (id, size, ForPlayer(player), hover_point, mut tr) in ui.iter_mut() {
+            let Ok((inventory, selected_item, focus)) = players.get(*player) else {
+                continue;
+            };
+            let title_style = if Some(id) == **focus {
+                res_draw_config.color_scheme().menu_title_hover()
+            } else {
+                res_draw_config.color_scheme().menu_title()
+            };
+            let hover_index = hover_point
+                .as_ref()
+                .and_then(|pt| (pt.y as usize).checked_sub(1));
+            let mut menu = CharacterMapImage::new();
+            let menu_title = format!("{0:─<1$}", "─Items", size.width());
+            menu.new_row().add_text(menu_title, &title_style);
+            for (idx, (item_id, count)) in usable_items(inventory, &res_item_actions)
+                .into_iter()
+                .enumerate()
+            {
+                let style = if Some(idx) == hover_index {
+                    res_draw_config.color_scheme().menu_hover()
+                } else {
+                    default()
+                };
+                let item_text = if selected_item.as_deref() == Some(item_id) {
+                    format!("▶{item_id} x{count}")
+                } else {
+                    format!("{item_id} x{count}")
+                };
+                menu.new_row().add_text(item_text, &style);
+            }
+            tr.update_charmie(menu);
+        }
+    }
+}
+
+impl NodeUi for MenuUiItems {
+    const NAME: &'static str = "Items Menu";
+    type UiBundleExtras = (MouseEventListener, HoverPoint, Tooltip);
+    type UiPlugin = Self;
+
+    fn initial_style(_: &NodeUiQItem) -> StyleTty {
+        use taffy::prelude::*;
+
+        StyleTty(taffy::prelude::Style {
+            display: Display::None,
+            min_size: Size {
+                width: Dimension::Auto,
+                height: length(0.0),
+            },
+            ..default()
+        })
+    }
+
+    fn ui_bundle_extras() -> Self::UiBundleExtras {
+        (
+            MouseEventListener,
+            HoverPoint::default(),
+            Tooltip::new("Select item (Use it on a square in the grid)"),
+        )
+    }
+}
@@ -1,5 +1,5 @@
 use charmi::CharacterMapImage;
-use game_core::node::{Curio, Pickup};
+use game_core::node::Pickup;
 use game_core::prelude::*;
 use game_core::registry::Reg;
 
@@ -49,16 +49,15 @@ impl SimpleSubmenu for MenuUiLabel {
                     row.with_plain_text("Access Point")
                 } else if let Some(name) = selected
                     .curio
-                    .map(Curio::name)
+                    .map(|curio| curio.name().to_owned())
                     .or_else(|| {
                         selected.pickup.map(|pickup| match pickup {
-                            Pickup::Mon(_) => "Mon",
-                            Pickup::Card(_) => "Card: ??",
-                            Pickup::Item(_) => "Item: ??",
-                            Pickup::MacGuffin => "Intelligence", // TODO Need to configure these labels
+                            Pickup::Mon(_) => "Mon".to_owned(),
+                            Pickup::Card(_) => "Card: ??".to_owned(),
+                            Pickup::Item(item_id) => format!("Item: {item_id}"),
+                            Pickup::MacGuffin => "Intelligence".to_owned(), // TODO Need to configure these labels
                         })
                     })
-                    .map(str::to_owned)
                 {
                     row.with_text(name, &glyph.style())
                 } else {
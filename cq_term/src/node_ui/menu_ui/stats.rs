@@ -23,7 +23,11 @@ impl SimpleSubmenu for MenuUiStats {
 
     fn height(selected: &NodePieceQItem<'_>) -> Option<usize> {
         let stats_to_display = if selected.max_size.is_some() { 1 } else { 0 }
-            + if selected.speed.is_some() { 1 } else { 0 };
+            + if selected.speed.is_some() { 1 } else { 0 }
+            + selected
+                .status_effects
+                .map(|status_effects| status_effects.len())
+                .unwrap_or(0);
         if stats_to_display > 0 {
             Some(stats_to_display + 1)
         } else {
@@ -52,6 +56,9 @@ impl SimpleSubmenu for MenuUiStats {
                     .unwrap_or(0);
                 stats.push(format!("Moves: {}/{}", moves_taken, **speed));
             }
+            for status_effect in selected.status_effects.iter().flat_map(|se| se.iter()) {
+                stats.push(status_effect.to_string());
+            }
             Some(stats.into_iter().collect())
         } else {
             None
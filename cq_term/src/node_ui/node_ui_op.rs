@@ -3,8 +3,8 @@ use game_core::op::{Op, OpError, OpErrorUtils, OpImplResult};
 use game_core::player::{ForPlayer, Player};
 
 use super::grid_ui::GridUi;
-use super::menu_ui::{MenuUiActions, MenuUiCardSelection};
-use super::{CursorIsHidden, NodeCursor, SelectedAction, SelectedNodePiece};
+use super::menu_ui::{MenuUiActions, MenuUiCardSelection, MenuUiItems};
+use super::{CursorIsHidden, NodeCursor, SelectedAction, SelectedItem, SelectedNodePiece};
 use crate::layout::{
     ui_focus_cycle_next, ui_focus_cycle_prev, StyleTty, UiFocus, UiFocusCycleOrder,
 };
@@ -16,6 +16,7 @@ pub enum NodeUiOp {
     MoveNodeCursor(CompassOrPoint),
     SetCursorHidden(bool),
     SetSelectedAction(Option<usize>),
+    SetSelectedItem(Option<String>),
 }
 
 #[derive(Clone, Copy, Debug, Reflect)]
@@ -25,6 +26,7 @@ pub enum FocusTarget {
     Grid,
     CardMenu,
     ActionMenu,
+    ItemMenu,
 }
 
 impl Op for NodeUiOp {
@@ -36,7 +38,8 @@ impl Op for NodeUiOp {
             .register_op(opsys_nodeui_focus)
             .register_op(opsys_nodeui_move_cursor)
             .register_op(opsys_nodeui_hide_cursor)
-            .register_op(opsys_nodeui_selected_action);
+            .register_op(opsys_nodeui_selected_action)
+            .register_op(opsys_nodeui_selected_item);
     }
 
     fn system_index(&self) -> usize {
@@ -46,6 +49,7 @@ impl Op for NodeUiOp {
             Self::MoveNodeCursor(_) => 1,
             Self::SetCursorHidden(_) => 2,
             Self::SetSelectedAction(_) => 3,
+            Self::SetSelectedItem(_) => 4,
         }
     }
 }
@@ -56,6 +60,7 @@ pub fn opsys_nodeui_focus(
     action_menus: IndexedQuery<ForPlayer, Entity, With<MenuUiActions>>,
     grid_uis: IndexedQuery<ForPlayer, Entity, With<GridUi>>,
     card_selection_menus: IndexedQuery<ForPlayer, Entity, With<MenuUiCardSelection>>,
+    item_menus: IndexedQuery<ForPlayer, Entity, With<MenuUiItems>>,
     mut players: Query<(AsDerefMut<UiFocus>, AsDerefMut<CursorIsHidden>), With<Player>>,
 ) -> OpImplResult {
     if let NodeUiOp::ChangeFocus(focus_target) = op {
@@ -66,6 +71,7 @@ pub fn opsys_nodeui_focus(
             FocusTarget::ActionMenu => action_menus.get_for(player).ok(),
             FocusTarget::Grid => grid_uis.get_for(player).ok(),
             FocusTarget::CardMenu => card_selection_menus.get_for(player).ok(),
+            FocusTarget::ItemMenu => item_menus.get_for(player).ok(),
         };
         focus.set_if_neq(next_focus);
         cursor_is_hidden.set_if_neq(false);
@@ -77,10 +83,13 @@ pub fn opsys_nodeui_focus(
 
 pub fn opsys_nodeui_selected_action(
     In((player, op)): In<(Entity, NodeUiOp)>,
-    mut players: Query<(AsDerefMut<SelectedAction>,), With<Player>>,
+    mut players: Query<(AsDerefMut<SelectedAction>, AsDerefMut<SelectedItem>), With<Player>>,
 ) -> OpImplResult {
     if let NodeUiOp::SetSelectedAction(next_selected_action) = op {
-        let (mut selected_action,) = players.get_mut(player).critical()?;
+        let (mut selected_action, mut selected_item) = players.get_mut(player).critical()?;
+        if next_selected_action.is_some() {
+            selected_item.set_if_neq(None);
+        }
         selected_action.set_if_neq(next_selected_action);
         Ok(default())
     } else {
@@ -88,6 +97,24 @@ pub fn opsys_nodeui_selected_action(
     }
 }
 
+/// Selecting an item deselects the selected action, since only one of them
+/// can be used on the node cursor
+pub fn opsys_nodeui_selected_item(
+    In((player, op)): In<(Entity, NodeUiOp)>,
+    mut players: Query<(AsDerefMut<SelectedItem>, AsDerefMut<SelectedAction>), With<Player>>,
+) -> OpImplResult {
+    if let NodeUiOp::SetSelectedItem(next_selected_item) = op {
+        let (mut selected_item, mut selected_action) = players.get_mut(player).critical()?;
+        if next_selected_item.is_some() {
+            selected_action.set_if_neq(None);
+        }
+        selected_item.set_if_neq(next_selected_item);
+        Ok(default())
+    } else {
+        Err(OpError::MismatchedOpSystem)
+    }
+}
+
 pub fn opsys_nodeui_move_cursor(
     In((player, op)): In<(Entity, NodeUiOp)>,
     mut players: Query<(&InNode, AsDerefMut<NodeCursor>, AsDerefMut<CursorIsHidden>), With<Player>>,
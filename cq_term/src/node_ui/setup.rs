@@ -5,6 +5,7 @@ use game_core::card::NO_OP_ACTION_ID;
 use game_core::node::{InNode, Node, NodeBattleIntelligence, NodeOp};
 use game_core::op::CoreOps;
 use game_core::player::{ForPlayer, Player};
+use game_core::saving::SaveOp;
 use getset::CopyGetters;
 use unicode_width::UnicodeWidthStr;
 
@@ -16,18 +17,18 @@ use crate::dialog_ui::{DialogLineUi, DialogOptionUi, DialogUiContextActions};
 use crate::input_event::{MouseEventListener, MouseEventTtyDisabled};
 use crate::layout::{StyleTty, UiFocusBundle, UiFocusCycleOrder, VisibilityTty};
 use crate::linkage::base_ui_game_core;
-use crate::main_ui::{MainUiOp, UiOps};
+use crate::main_ui::{MainUiOp, SaveStatusDisplay, UiOps};
 use crate::node_ui::button_ui::{
-    EndTurnButton, HelpButton, OptionsButton, QuitButton, ReadyButton, UndoButton,
+    EndTurnButton, HelpButton, OptionsButton, QuitButton, ReadyButton, RedoButton, UndoButton,
 };
 use crate::node_ui::grid_ui::{GridUi, GridUiAnimation};
 use crate::node_ui::menu_ui::{
-    MenuUiActions, MenuUiCardSelection, MenuUiDescription, MenuUiLabel, MenuUiStats,
+    MenuUiActions, MenuUiCardSelection, MenuUiDescription, MenuUiItems, MenuUiLabel, MenuUiStats,
 };
 use crate::node_ui::node_popups::{help_msg, HelpMenu, OptionsMenu, StatusScreen};
 use crate::node_ui::{
     AvailableActionTargets, AvailableMoves, CursorIsHidden, HasNodeUi, NodeUi, NodeUiScreen,
-    SelectedAction, SelectedNodePiece, TelegraphedAction,
+    SelectedAction, SelectedItem, SelectedNodePiece, TelegraphedAction,
 };
 use crate::prelude::*;
 use crate::render::TerminalRendering;
@@ -39,10 +40,12 @@ pub struct ButtonContextActions {
     end_turn: Entity,
     quit_battle: Entity,
     quit_game: Entity,
+    save_battle: Entity,
     start: Entity,
     toggle_help: Entity,
     toggle_options: Entity,
     undo: Entity,
+    redo: Entity,
 }
 
 impl FromWorld for ButtonContextActions {
@@ -155,20 +158,36 @@ impl FromWorld for ButtonContextActions {
                 },
             ))
             .id();
+        let save_battle = world
+            .spawn(base_ui_game_core::context_action_from_op::<CoreOps, _>(
+                "Save battle",
+                SaveOp::Save {
+                    include_battle: true,
+                },
+            ))
+            .id();
         let undo = world
             .spawn(base_ui_game_core::context_action_from_op::<CoreOps, _>(
                 "Undo",
                 NodeOp::Undo,
             ))
             .id();
+        let redo = world
+            .spawn(base_ui_game_core::context_action_from_op::<CoreOps, _>(
+                "Redo",
+                NodeOp::Redo,
+            ))
+            .id();
         ButtonContextActions {
             end_turn,
             quit_battle,
             quit_game,
+            save_battle,
             start,
             toggle_help,
             toggle_options,
             undo,
+            redo,
         }
     }
 }
@@ -218,6 +237,9 @@ pub fn create_node_ui(
                                     ..default()
                                 }),
                                 Name::new("Title Bar Left"),
+                                ForPlayer(player),
+                                SaveStatusDisplay,
+                                TerminalRendering::default(),
                             ));
                             title_bar.spawn((
                                 StyleTty(taffy::prelude::Style {
@@ -260,6 +282,15 @@ pub fn create_node_ui(
                                         Tooltip::new("[⌫] Undo")
                                     ));
 
+                                    title_bar_right.spawn((
+                                        ForPlayer(player),
+                                        RedoButton,
+                                        ButtonUiBundle::new("Redo", ContentStyle::new().cyan()),
+                                        ContextActions::new(player, &[res_button_context_actions.redo()]),
+                                        VisibilityTty::invisible(),
+                                        Tooltip::new("[^R] Redo")
+                                    ));
+
                                     title_bar_right.spawn((
                                         StyleTty(taffy::prelude::Style {
                                             size: Size {
@@ -340,7 +371,7 @@ pub fn create_node_ui(
 
                                     title_bar_right.spawn((
                                         ButtonUiBundle::new("Quit", ContentStyle::new().red()),
-                                        ContextActions::new(player, &[res_button_context_actions.quit_battle(), res_button_context_actions.quit_game()]),
+                                        ContextActions::new(player, &[res_button_context_actions.save_battle(), res_button_context_actions.quit_battle(), res_button_context_actions.quit_game()]),
                                         ForPlayer(player),
                                         QuitButton,
                                         Tooltip::new("[q] Click to exit")
@@ -408,6 +439,11 @@ pub fn create_node_ui(
                                         UiFocusCycleOrder(1),
                                         ContextActions::new(player, &[]),
                                     ));
+                                menu_bar
+                                    .spawn((
+                                        MenuUiItems::bundle(player, &node_q),
+                                        UiFocusCycleOrder(3),
+                                    ));
                                 menu_bar.spawn(MenuUiDescription::bundle(player, &node_q));
                             });
                         content_pane
@@ -572,6 +608,7 @@ pub fn create_node_ui(
                 CursorIsHidden::default(),
                 SelectedNodePiece(node_q.grid.item_at(default())),
                 SelectedAction(None),
+                SelectedItem(None),
                 TelegraphedAction(None),
                 AvailableActionTargets::default(),
                 UiFocusBundle::default(),
@@ -1,3 +1,7 @@
+use std::time::Duration;
+
+use game_core::node::{CurrentTurn, InNode, Node, Team, TeamClock};
+use game_core::player::{ForPlayer, Player};
 use game_core::prelude::*;
 
 use super::{NodeUi, NodeUiQItem};
@@ -8,14 +12,38 @@ use crate::render::{RenderTtySet, TerminalRendering, RENDER_TTY_SCHEDULE};
 pub struct TitleBarUi;
 
 pub fn render_title_bar_system(
-    mut render_title_bar: Query<&mut TerminalRendering, With<TitleBarUi>>,
+    mut render_title_bar: Query<
+        (&mut TerminalRendering, AsDerefCopied<ForPlayer>),
+        With<TitleBarUi>,
+    >,
+    players: Query<AsDerefCopied<InNode>, With<Player>>,
+    nodes: Query<AsDerefCopied<CurrentTurn>, With<Node>>,
+    team_clocks: Query<&TeamClock, With<Team>>,
 ) {
-    let rendered_text = vec!["Common Quest".to_owned()];
-    for mut tr in render_title_bar.iter_mut() {
-        tr.update(rendered_text.clone());
+    for (mut tr, player_id) in render_title_bar.iter_mut() {
+        let team_clock = players
+            .get(player_id)
+            .ok()
+            .and_then(|node_id| nodes.get(node_id).ok())
+            .and_then(|current_turn| team_clocks.get(current_turn).ok());
+        let mut title = "Common Quest".to_owned();
+        if let Some(team_clock) = team_clock {
+            if let Some(turn_remaining) = team_clock.turn_remaining {
+                title.push_str(&format!("  Turn: {}", format_clock(turn_remaining)));
+            }
+            if let Some(total_remaining) = team_clock.total_remaining {
+                title.push_str(&format!("  Total: {}", format_clock(total_remaining)));
+            }
+        }
+        tr.update(vec![title]);
     }
 }
 
+fn format_clock(remaining: Duration) -> String {
+    let secs = remaining.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
 impl Plugin for TitleBarUi {
     fn build(&self, app: &mut App) {
         app.add_systems(
@@ -9,15 +9,18 @@ use crate::NDitCoreSet;
 
 mod card_action;
 mod card_as_asset;
+mod status_effect;
 
 use bevy::ecs::entity::MapEntities;
 use bevy::ecs::query::QueryData;
 pub use card_action::{
-    key, Action, ActionEffect, ActionRange, ActionTarget, Actions, Prereqs, Prerequisite,
-    RangeShape,
+    key, Action, ActionEffect, ActionHistory, ActionRange, ActionTarget, Actions,
+    CurioEffectAccess, CurioEffectProps, CurioEffectQ, CurioPrereqAccess, CurioPrereqProps,
+    EffectMut, LineOfSight, PrereqQ, Prereqs, Prerequisite, RangeShape,
 };
 pub use card_as_asset::{CardDefinition, NO_OP_ACTION_ID};
 use serde::{Deserialize, Serialize};
+pub use status_effect::{Status, StatusEffect, StatusEffects};
 
 // TODO better key architecture
 pub mod save_key {
@@ -35,6 +38,7 @@ impl Plugin for CardPlugin {
         app.init_asset::<CardDefinition>()
             .init_asset::<Action>()
             .register_type::<Action>()
+            .register_type::<ActionHistory>()
             .register_type::<Actions>()
             .register_type::<BaseName>()
             .register_type::<Card>()
@@ -44,8 +48,11 @@ impl Plugin for CardPlugin {
             .register_type::<MaximumSize>()
             .register_type::<MovementSpeed>()
             .register_type::<Nickname>()
-            .register_type::<Tag>()
+            .register_type::<Status>()
+            .register_type::<StatusEffect>()
+            .register_type::<StatusEffects>()
             .register_type::<Tags>()
+            .register_type::<LineOfSight>()
             .register_type::<HashMap<Entity, NonZeroU32>>()
             .register_type::<NonZeroU32>()
             .register_type::<Vec<Entity>>()
@@ -67,6 +74,12 @@ impl Plugin for CardPlugin {
 #[reflect(Component)]
 pub struct BaseName(String);
 
+impl BaseName {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self(name.into())
+    }
+}
+
 #[derive(Component, Debug, Default, Reflect)]
 #[reflect(Component)]
 pub struct Card;
@@ -175,6 +188,11 @@ impl Deck {
         self.ordering.iter().map(|card| (*card, self.cards[card]))
     }
 
+    /// The number of cards in the deck, counting every copy
+    pub fn total_cards(&self) -> u32 {
+        self.cards.values().map(|count| count.get()).sum()
+    }
+
     pub fn cards_iter(&self) -> impl Iterator<Item = Entity> + '_ {
         self.ordering.iter().copied()
     }
@@ -275,18 +293,31 @@ impl Nickname {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Reflect, Serialize)]
-pub enum Tag {
-    Damage,
-    Healing,
-    Fire,
-    Flying,
+/// Tags added to a curio at runtime, such as by [`ActionEffect::AddTag`]
+#[derive(Clone, Component, Debug, Default, Deref, Reflect)]
+#[reflect(Component)]
+pub struct Tags {
+    tags: Vec<String>,
 }
 
-#[derive(Component, Default, Deref, Reflect)]
-#[reflect(Component)]
-struct Tags {
-    tags: Vec<Tag>,
+impl Tags {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Returns false if the tag was already present
+    pub(crate) fn add_tag(&mut self, tag: String) -> bool {
+        if self.has_tag(&tag) {
+            false
+        } else {
+            self.tags.push(tag);
+            true
+        }
+    }
+
+    pub(crate) fn remove_tag(&mut self, tag: &str) {
+        self.tags.retain(|t| t != tag);
+    }
 }
 
 pub fn sys_sort_decks(cards: Query<CardQuery>, mut decks: Query<&mut Deck, Changed<Deck>>) {
@@ -342,6 +373,8 @@ pub fn sys_save_deck(res_save_data: Res<SaveData>, q_player: Query<&Deck, With<P
             .expect("Should be able to save!");
         let card_entities: Vec<Entity> = deck.cards_iter().collect();
         res_save_data.add_entities(&card_entities);
+        let deck_size = deck.total_cards();
+        res_save_data.summarize(move |summary| summary.deck_size = deck_size);
     }
 }
 
@@ -1,12 +1,13 @@
 use std::borrow::Borrow;
+use std::ops::{Deref, DerefMut};
 
-use bevy::ecs::system::QueryLens;
+use bevy::ecs::query::{QueryData, QueryFilter};
 use getset::Getters;
 use serde::{Deserialize, Serialize};
 
-use super::{MaximumSize, MovementSpeed};
+use super::{MaximumSize, MovementSpeed, Status, StatusEffect, StatusEffects, Tags};
 use crate::common::metadata::MetadataErr;
-use crate::node::Curio;
+use crate::node::{Alliances, LostSquares, MovesTaken};
 // TODO figure out how to handle these Node imports to decrease coupling
 use crate::prelude::*;
 
@@ -26,11 +27,110 @@ pub mod key {
     pub const OLD_SQUARE_STATUS: Key<bool> = typed_key!("old_square_status");
     pub const OLD_TARGET_CAPACITY: Key<u32> = typed_key!("old_capacity");
     pub const OLD_TARGET_MOVEMENT: Key<u32> = typed_key!("old_movement");
+    pub const HEALS: Key<Vec<UVec2>> = typed_key!("heals");
+    pub const OLD_LOST_SQUARES: Key<Vec<UVec2>> = typed_key!("old_lost_squares");
+    pub const ADDED_TAG: Key<String> = typed_key!("added_tag");
+    pub const SHIELDED: Key<bool> = typed_key!("shielded");
+    pub const OLD_STATUS_EFFECTS: Key<Vec<StatusEffect>> = typed_key!("old_status_effects");
+}
+
+/// Curio components that action effects can modify
+#[derive(Debug, QueryData)]
+#[query_data(mutable)]
+pub struct CurioEffectQ {
+    max_size: &'static mut MaximumSize,
+    movement_speed: &'static mut MovementSpeed,
+    lost_squares: Option<&'static mut LostSquares>,
+    tags: Option<&'static mut Tags>,
+    status_effects: Option<&'static mut StatusEffects>,
+}
+
+/// The curio state that action effects can modify, borrowed from the world or
+/// from a simulation of it
+#[derive(Debug)]
+pub struct CurioEffectProps<'a> {
+    pub max_size: EffectMut<'a, u32>,
+    pub movement_speed: EffectMut<'a, u32>,
+    pub lost_squares: Option<EffectMut<'a, Vec<UVec2>>>,
+    pub tags: Option<EffectMut<'a, Tags>>,
+    pub status_effects: Option<EffectMut<'a, StatusEffects>>,
+}
+
+/// A mutable borrow of curio state for [`CurioEffectProps`]. Components
+/// borrowed from the world are only marked as changed when they are written
+/// to, not whenever an effect looks at them.
+#[derive(Debug)]
+pub enum EffectMut<'a, T> {
+    Plain(&'a mut T),
+    Tracked(Mut<'a, T>),
+}
+
+impl<T> Deref for EffectMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            Self::Plain(value) => value,
+            Self::Tracked(value) => value,
+        }
+    }
+}
+
+impl<T> DerefMut for EffectMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        match self {
+            Self::Plain(value) => value,
+            Self::Tracked(value) => value,
+        }
+    }
+}
+
+impl<'a, T> From<&'a mut T> for EffectMut<'a, T> {
+    fn from(value: &'a mut T) -> Self {
+        Self::Plain(value)
+    }
+}
+
+impl<'a, T> From<Mut<'a, T>> for EffectMut<'a, T> {
+    fn from(value: Mut<'a, T>) -> Self {
+        Self::Tracked(value)
+    }
+}
+
+/// Lets [`ActionEffect::apply_effect`] modify curios without caring where
+/// they are stored, so AI simulations follow the same rules as node ops
+pub trait CurioEffectAccess {
+    fn effect_props(&mut self, curio: Entity) -> Option<CurioEffectProps<'_>>;
+}
+
+impl<F: QueryFilter> CurioEffectAccess for Query<'_, '_, CurioEffectQ, F> {
+    fn effect_props(&mut self, curio: Entity) -> Option<CurioEffectProps<'_>> {
+        let curio_q = self.get_mut(curio).ok()?;
+        Some(CurioEffectProps {
+            max_size: curio_q
+                .max_size
+                .map_unchanged(|max_size| &mut max_size.0)
+                .into(),
+            movement_speed: curio_q
+                .movement_speed
+                .map_unchanged(|movement_speed| &mut movement_speed.0)
+                .into(),
+            lost_squares: curio_q.lost_squares.map(|lost_squares| {
+                lost_squares
+                    .map_unchanged(|lost_squares| &mut lost_squares.0)
+                    .into()
+            }),
+            tags: curio_q.tags.map(Into::into),
+            status_effects: curio_q.status_effects.map(Into::into),
+        })
+    }
 }
 
 #[derive(Asset, Clone, Debug, Getters, Reflect)]
 pub struct Action {
     pub(crate) range: Option<ActionRange>,
+    /// Area around the target that effects also apply to
+    pub(crate) area: Option<ActionRange>,
     pub(crate) id: String,
     #[getset(get = "pub")]
     pub(crate) effects: Vec<ActionEffect>,
@@ -49,12 +149,81 @@ pub struct Action {
 #[reflect(Component)]
 pub struct Actions(pub Vec<Handle<Action>>);
 
+/// Records which actions a curio has used this battle, for prerequisites that
+/// limit how often an action can be performed
+#[derive(Clone, Component, Debug, Default, Deserialize, Reflect, Serialize)]
+#[reflect(Component)]
+pub struct ActionHistory {
+    used: Vec<String>,
+    cooldowns: HashMap<String, u32>,
+}
+
+/// Curio components that prerequisites are checked against
+#[derive(Debug, QueryData)]
+pub struct PrereqQ {
+    max_size: Option<&'static MaximumSize>,
+    movement_speed: Option<&'static MovementSpeed>,
+    moves_taken: Option<&'static MovesTaken>,
+    status_effects: Option<&'static StatusEffects>,
+    tags: Option<&'static Tags>,
+    action_history: Option<&'static ActionHistory>,
+}
+
+/// The curio state that prerequisites are checked against, from the world or
+/// from a simulation of it
+#[derive(Debug)]
+pub struct CurioPrereqProps<'a> {
+    pub max_size: Option<u32>,
+    /// Moves the curio can still make this turn, accounting for status effects
+    pub moves_left: u32,
+    pub tags: Option<&'a Tags>,
+    pub action_history: Option<&'a ActionHistory>,
+}
+
+/// Lets [`Prerequisite::satisfied`] check curios without caring where they
+/// are stored, so AI simulations follow the same rules as node ops
+pub trait CurioPrereqAccess {
+    fn prereq_props(&self, curio: Entity) -> Option<CurioPrereqProps<'_>>;
+}
+
+impl<F: QueryFilter> CurioPrereqAccess for Query<'_, '_, PrereqQ, F> {
+    fn prereq_props(&self, curio: Entity) -> Option<CurioPrereqProps<'_>> {
+        let curio_q = self.get(curio).ok()?;
+        let movement_speed = curio_q.movement_speed.map(|ms| **ms).unwrap_or(0);
+        let moves_taken = curio_q.moves_taken.map(|mt| **mt).unwrap_or(0);
+        let movement_penalty = curio_q
+            .status_effects
+            .map(StatusEffects::movement_penalty)
+            .unwrap_or(0);
+        Some(CurioPrereqProps {
+            max_size: curio_q.max_size.map(|max_size| **max_size),
+            moves_left: movement_speed
+                .saturating_sub(movement_penalty)
+                .saturating_sub(moves_taken),
+            tags: curio_q.tags,
+            action_history: curio_q.action_history,
+        })
+    }
+}
+
 #[derive(Copy, Clone, Component, Debug, Reflect)]
 pub struct ActionRange {
     shape: RangeShape,
     max_range: u32,
     min_range: u32,
     headless: bool,
+    line_of_sight: Option<LineOfSight>,
+}
+
+/// What can block a straight line between the source of an action and its
+/// target
+#[derive(Clone, Copy, Debug, Deserialize, Default, PartialEq, Eq, Reflect, Serialize)]
+pub enum LineOfSight {
+    /// Only closed squares block the line
+    #[default]
+    Walls,
+    /// Closed squares and pieces other than the source block the line
+    WallsAndPieces,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Default, Reflect, Serialize)]
@@ -89,6 +258,7 @@ pub enum ActionEffect {
     ModifyMovement(i32),
     ModifyCapacity(i32),
     AddTag(String),
+    ApplyStatus(StatusEffect),
 }
 
 #[derive(Clone, Component, Debug, Deref, DerefMut, Reflect)]
@@ -97,7 +267,14 @@ pub struct Prereqs(pub Vec<Prerequisite>);
 #[derive(Clone, Debug, Deserialize, Reflect, Serialize)]
 pub enum Prerequisite {
     MinSize(u32),
-    // TargetMaxSize (Limit how much you can expand a curio)
+    /// Limit how much you can expand a curio
+    TargetMaxSize(u32),
+    MinMovesLeft(u32),
+    HasTag(String),
+    TargetHasTag(String),
+    OncePerBattle,
+    /// Once used, the action can be used again this many turns later
+    Cooldown(u32),
 }
 
 impl ActionEffect {
@@ -112,12 +289,12 @@ impl ActionEffect {
         }
     }
 
-    pub fn apply_effect(
+    pub fn apply_effect<C: CurioEffectAccess>(
         &self,
-        grid: &mut Mut<EntityGrid>,
+        grid: &mut EntityGrid,
         _source: Entity,
         target: UVec2,
-        entity_props: &mut Query<(AsDerefMut<MaximumSize>, AsDerefMut<MovementSpeed>), With<Curio>>,
+        entity_props: &mut C,
     ) -> Result<Metadata, MetadataErr> {
         let mut action_metadata = Metadata::default();
 
@@ -125,7 +302,28 @@ impl ActionEffect {
         Ok(match self {
             ActionEffect::Damage(dmg) => {
                 if let Some(key) = grid.item_at(target) {
+                    if let Some(CurioEffectProps {
+                        status_effects: Some(mut status_effects),
+                        ..
+                    }) = entity_props.effect_props(key)
+                    {
+                        let old_status_effects = status_effects.0.clone();
+                        if status_effects.has_status(Status::Shielded) {
+                            status_effects.use_shield();
+                            action_metadata.put(key::TARGET_ENTITY, key)?;
+                            action_metadata.put(key::SHIELDED, true)?;
+                            action_metadata.put(key::OLD_STATUS_EFFECTS, old_status_effects)?;
+                            return Ok(action_metadata);
+                        }
+                    }
                     let damages = grid.list_back_n(key, *dmg);
+                    if let Some(CurioEffectProps {
+                        lost_squares: Some(mut lost_squares),
+                        ..
+                    }) = entity_props.effect_props(key)
+                    {
+                        lost_squares.extend(damages.iter().copied());
+                    }
                     action_metadata.put(key::TARGET_ENTITY, key)?;
                     action_metadata.put(key::DAMAGES, damages)?;
                     action_metadata.put(key::FATAL, grid.len_of(key) <= *dmg)?;
@@ -149,7 +347,11 @@ impl ActionEffect {
             },
             ActionEffect::ModifyCapacity(capacity_change) => {
                 if let Some(target_id) = grid.item_at(target) {
-                    if let Ok((mut capacity, _)) = entity_props.get_mut(target_id) {
+                    if let Some(CurioEffectProps {
+                        max_size: mut capacity,
+                        ..
+                    }) = entity_props.effect_props(target_id)
+                    {
                         action_metadata.put(key::TARGET_ENTITY, target_id)?;
                         action_metadata.put(key::OLD_TARGET_CAPACITY, *capacity)?;
                         if *capacity_change > 0 {
@@ -163,7 +365,10 @@ impl ActionEffect {
             },
             ActionEffect::ModifyMovement(movement_change) => {
                 if let Some(target_id) = grid.item_at(target) {
-                    if let Ok((_, mut movement_speed)) = entity_props.get_mut(target_id) {
+                    if let Some(CurioEffectProps {
+                        mut movement_speed, ..
+                    }) = entity_props.effect_props(target_id)
+                    {
                         action_metadata.put(key::TARGET_ENTITY, target_id)?;
                         action_metadata.put(key::OLD_TARGET_MOVEMENT, *movement_speed)?;
                         if *movement_change > 0 {
@@ -177,21 +382,99 @@ impl ActionEffect {
                 }
                 action_metadata
             },
-            ActionEffect::Heal(_healing) => todo!("Healing not implemented yet"),
-            _ => todo!("Not implemented yet"),
+            ActionEffect::Heal(healing) => {
+                if let Some(target_id) = grid.item_at(target) {
+                    if let Some(mut curio_props) = entity_props.effect_props(target_id) {
+                        let max_size = *curio_props.max_size as usize;
+                        let mut heals = Vec::new();
+                        let mut old_lost_squares = Vec::new();
+                        while heals.len() < *healing && grid.len_of(target_id) < max_size {
+                            let Some(back) = grid.back(target_id) else {
+                                break;
+                            };
+                            let lost_square = curio_props
+                                .lost_squares
+                                .as_deref()
+                                .and_then(|lost_squares| lost_squares.last().copied());
+                            // Grow back the way the curio was damaged if possible,
+                            // otherwise any free square next to the back will do
+                            let heal_pt = lost_square
+                                .filter(|pt| {
+                                    grid.square_is_free(*pt) && back.manhattan_distance(pt) == 1
+                                })
+                                .or_else(|| {
+                                    Compass::ALL_DIRECTIONS
+                                        .into_iter()
+                                        .map(|dir| back + dir)
+                                        .find(|pt| grid.square_is_free(*pt))
+                                });
+                            let Some(heal_pt) = heal_pt else {
+                                break;
+                            };
+                            if let Some(lost_squares) = curio_props.lost_squares.as_mut() {
+                                old_lost_squares.extend(lost_squares.pop());
+                            }
+                            grid.push_back(heal_pt, target_id);
+                            heals.push(heal_pt);
+                        }
+                        action_metadata.put(key::TARGET_ENTITY, target_id)?;
+                        action_metadata.put(key::HEALS, heals)?;
+                        action_metadata.put(key::OLD_LOST_SQUARES, old_lost_squares)?;
+                    }
+                }
+                action_metadata
+            },
+            ActionEffect::AddTag(tag) => {
+                if let Some(target_id) = grid.item_at(target) {
+                    if let Some(CurioEffectProps {
+                        tags: Some(mut tags),
+                        ..
+                    }) = entity_props.effect_props(target_id)
+                    {
+                        if tags.add_tag(tag.clone()) {
+                            action_metadata.put(key::TARGET_ENTITY, target_id)?;
+                            action_metadata.put(key::ADDED_TAG, tag)?;
+                        }
+                    }
+                }
+                action_metadata
+            },
+            ActionEffect::ApplyStatus(status_effect) => {
+                if let Some(target_id) = grid.item_at(target) {
+                    if let Some(CurioEffectProps {
+                        status_effects: Some(mut status_effects),
+                        ..
+                    }) = entity_props.effect_props(target_id)
+                    {
+                        action_metadata.put(key::TARGET_ENTITY, target_id)?;
+                        action_metadata.put(key::OLD_STATUS_EFFECTS, &status_effects.0)?;
+                        status_effects.push(*status_effect);
+                    }
+                }
+                action_metadata
+            },
         })
     }
 
-    pub fn revert_effects(
+    pub fn revert_effects<C: CurioEffectAccess>(
         metadata: Metadata,
-        grid: &mut Mut<EntityGrid>,
-        mut curio_props: QueryLens<(AsDerefMut<MaximumSize>, AsDerefMut<MovementSpeed>)>,
+        grid: &mut EntityGrid,
+        curio_props: &mut C,
     ) -> Result<(), MetadataErr> {
         let target_pt = metadata.get_required(key::TARGET_POINT)?;
-        let mut curio_props = curio_props.query();
         if let Some(mut damages) = metadata.get_optional(key::DAMAGES)? {
             let target_entity = metadata.get_required(key::TARGET_ENTITY)?;
             let was_fatal = metadata.get_required(key::FATAL)?;
+            if let Some(CurioEffectProps {
+                lost_squares: Some(mut lost_squares),
+                ..
+            }) = curio_props.effect_props(target_entity)
+            {
+                if lost_squares.ends_with(&damages) {
+                    let len = lost_squares.len() - damages.len();
+                    lost_squares.truncate(len);
+                }
+            }
             if was_fatal {
                 let head = damages
                     .pop()
@@ -211,14 +494,51 @@ impl ActionEffect {
         }
         if let Some(old_capacity) = metadata.get_optional(key::OLD_TARGET_CAPACITY)? {
             let target_entity = metadata.get_required(key::TARGET_ENTITY)?;
-            if let Ok((mut max_size, _)) = curio_props.get_mut(target_entity) {
+            if let Some(CurioEffectProps { mut max_size, .. }) =
+                curio_props.effect_props(target_entity)
+            {
                 *max_size = old_capacity;
             }
         }
         if let Some(old_movement) = metadata.get_optional(key::OLD_TARGET_MOVEMENT)? {
             let target_entity = metadata.get_required(key::TARGET_ENTITY)?;
-            if let Ok((_, mut movement)) = curio_props.get_mut(target_entity) {
-                *movement = old_movement;
+            if let Some(CurioEffectProps {
+                mut movement_speed, ..
+            }) = curio_props.effect_props(target_entity)
+            {
+                *movement_speed = old_movement;
+            }
+        }
+        if let Some(heals) = metadata.get_optional(key::HEALS)? {
+            let target_entity = metadata.get_required(key::TARGET_ENTITY)?;
+            let old_lost_squares = metadata.get_required(key::OLD_LOST_SQUARES)?;
+            grid.pop_back_n(target_entity, heals.len());
+            if let Some(CurioEffectProps {
+                lost_squares: Some(mut lost_squares),
+                ..
+            }) = curio_props.effect_props(target_entity)
+            {
+                lost_squares.extend(old_lost_squares.into_iter().rev());
+            }
+        }
+        if let Some(added_tag) = metadata.get_optional(key::ADDED_TAG)? {
+            let target_entity = metadata.get_required(key::TARGET_ENTITY)?;
+            if let Some(CurioEffectProps {
+                tags: Some(mut tags),
+                ..
+            }) = curio_props.effect_props(target_entity)
+            {
+                tags.remove_tag(&added_tag);
+            }
+        }
+        if let Some(old_status_effects) = metadata.get_optional(key::OLD_STATUS_EFFECTS)? {
+            let target_entity = metadata.get_required(key::TARGET_ENTITY)?;
+            if let Some(CurioEffectProps {
+                status_effects: Some(mut status_effects),
+                ..
+            }) = curio_props.effect_props(target_entity)
+            {
+                status_effects.0 = old_status_effects;
             }
         }
 
@@ -233,6 +553,7 @@ impl ActionRange {
             min_range: 0,
             shape: RangeShape::Diamond,
             headless: false,
+            line_of_sight: None,
         }
     }
 
@@ -250,6 +571,15 @@ impl ActionRange {
         self.headless
     }
 
+    pub fn with_line_of_sight(mut self, line_of_sight: Option<LineOfSight>) -> Self {
+        self.line_of_sight = line_of_sight;
+        self
+    }
+
+    pub fn line_of_sight(&self) -> Option<LineOfSight> {
+        self.line_of_sight
+    }
+
     pub fn max_range(&self) -> u32 {
         self.max_range
     }
@@ -279,63 +609,143 @@ impl ActionRange {
     pub fn in_range_of(&self, grid: &EntityGrid, source: Entity, target: UVec2) -> bool {
         if self.headless {
             grid.square_iter(source)
-                .any(|sqr| self.in_range_of_pt(sqr.location(), target))
+                .any(|sqr| self.in_range_of_pt(grid, Some(source), sqr.location(), target))
         } else {
             grid.head(source)
-                .map(|head| self.in_range_of_pt(head, target))
+                .map(|head| self.in_range_of_pt(grid, Some(source), head, target))
                 .unwrap_or_default()
         }
     }
 
-    fn in_range_of_pt(&self, from_pt: UVec2, target: UVec2) -> bool {
+    fn in_range_of_pt(
+        &self,
+        grid: &EntityGrid,
+        source: Option<Entity>,
+        from_pt: UVec2,
+        target: UVec2,
+    ) -> bool {
         let dist = self.shape.dist(from_pt, target);
-        self.min_range <= dist && dist <= self.max_range
+        self.min_range <= dist
+            && dist <= self.max_range
+            && self.has_line_of_sight(grid, source, from_pt, target)
+    }
+
+    /// Whether anything blocks the line from `from_pt` to `target`. Squares of
+    /// `source` never block it, and neither does whatever is at either end.
+    pub fn has_line_of_sight(
+        &self,
+        grid: &EntityGrid,
+        source: Option<Entity>,
+        from_pt: UVec2,
+        target: UVec2,
+    ) -> bool {
+        let Some(line_of_sight) = self.line_of_sight else {
+            return true;
+        };
+        squares_between(from_pt, target).into_iter().all(|pt| {
+            !grid.square_is_closed(pt)
+                && (line_of_sight == LineOfSight::Walls
+                    || grid.item_at(pt).is_none()
+                    || grid.item_at(pt) == source)
+        })
+    }
+
+    /// Every point in the grid within this range of the center point
+    pub fn pts_in_range_of(&self, grid: &EntityGrid, center: UVec2) -> Vec<UVec2> {
+        let source = grid.item_at(center);
+        (0..grid.height())
+            .flat_map(|y| (0..grid.width()).map(move |x| UVec2 { x, y }))
+            .filter(|pt| self.in_range_of_pt(grid, source, center, *pt))
+            .collect()
     }
 
     pub fn in_range_of_pts<P: Borrow<UVec2>, I: IntoIterator<Item = P>>(
         &self,
+        grid: &EntityGrid,
+        source: Entity,
         from_pts: I,
         target: UVec2,
     ) -> bool {
         from_pts
             .into_iter()
-            .any(|pt| self.in_range_of_pt(*pt.borrow(), target))
+            .any(|pt| self.in_range_of_pt(grid, Some(source), *pt.borrow(), target))
     }
     pub fn pt_in_range<P: Borrow<UVec2>, I: IntoIterator<Item = P>>(
         &self,
+        grid: &EntityGrid,
+        source: Entity,
         from_pts: I,
         target: UVec2,
     ) -> Option<UVec2> {
         from_pts.into_iter().find_map(|pt| {
-            self.in_range_of_pt(*pt.borrow(), target)
+            self.in_range_of_pt(grid, Some(source), *pt.borrow(), target)
                 .then(|| *pt.borrow())
         })
     }
 }
 
+/// The squares a straight line from `from` to `to` passes through, traced
+/// like Bresenham's line algorithm, not including either end
+fn squares_between(from: UVec2, to: UVec2) -> Vec<UVec2> {
+    let (x1, y1) = (to.x as i64, to.y as i64);
+    let (mut x, mut y) = (from.x as i64, from.y as i64);
+    let dx = (x1 - x).abs();
+    let dy = -(y1 - y).abs();
+    let step_x = if x < x1 { 1 } else { -1 };
+    let step_y = if y < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let mut squares = Vec::new();
+    while (x, y) != (x1, y1) {
+        let doubled_err = 2 * err;
+        if doubled_err >= dy {
+            err += dy;
+            x += step_x;
+        }
+        if doubled_err <= dx {
+            err += dx;
+            y += step_y;
+        }
+        if (x, y) != (x1, y1) {
+            squares.push(UVec2 {
+                x: x as u32,
+                y: y as u32,
+            });
+        }
+    }
+    squares
+}
+
 impl ActionTarget {
+    /// `alliances` are those of the source's team, so that pieces of allied
+    /// teams count as allies rather than enemies
     pub fn valid_target<F: Fn(Entity) -> Option<Entity>>(
         &self,
         grid: &EntityGrid,
         source: Entity,
         target: UVec2,
         team_check: F,
+        alliances: Option<&Alliances>,
     ) -> bool {
+        let is_allied = |target_team: Entity| {
+            let source_team = team_check(source)?;
+            Some(
+                target_team == source_team
+                    || alliances.is_some_and(|alliances| alliances.is_allied_with(target_team)),
+            )
+        };
         match self {
             Self::None => true,
-            Self::Curios => grid.item_at(target).and_then(team_check).is_some(),
+            Self::Curios => grid.item_at(target).and_then(&team_check).is_some(),
             Self::Enemies => grid
                 .item_at(target)
                 .and_then(&team_check)
-                .and_then(|target_team| Some(target_team != team_check(source)?))
+                .and_then(|target_team| Some(!is_allied(target_team)?))
+                .unwrap_or(false),
+            Self::Allies => grid
+                .item_at(target)
+                .and_then(&team_check)
+                .and_then(is_allied)
                 .unwrap_or(false),
-            Self::Allies => {
-                if let Some(target_entity) = grid.item_at(target) {
-                    team_check(target_entity) == team_check(source)
-                } else {
-                    false
-                }
-            },
             Self::FreeSquare => grid.square_is_free(target),
             Self::ClosedSquare => grid.square_is_closed(target),
             Self::Point => true,
@@ -344,11 +754,101 @@ impl ActionTarget {
 }
 
 impl Prerequisite {
-    pub fn satisfied(&self, grid: &Mut<EntityGrid>, source: Entity, _target: UVec2) -> bool {
+    /// Checks if this prerequisite holds for `source` performing `action_id`.
+    ///
+    /// Prerequisites about the target are considered satisfied when there is
+    /// no target, so this can also be used to check if an action is usable
+    /// at all before a target is chosen.
+    pub fn satisfied<P: CurioPrereqAccess>(
+        &self,
+        action_id: &str,
+        grid: &EntityGrid,
+        source: Entity,
+        target: Option<UVec2>,
+        curios: &P,
+    ) -> bool {
+        let source_q = curios.prereq_props(source);
+        let target_q = target
+            .and_then(|target| grid.item_at(target))
+            .and_then(|target_id| curios.prereq_props(target_id));
         match self {
             Prerequisite::MinSize(min_size) => (*min_size as usize) <= grid.len_of(source),
+            Prerequisite::TargetMaxSize(max_size) => target_q
+                .and_then(|target_q| target_q.max_size)
+                .map(|target_max_size| target_max_size <= *max_size)
+                .unwrap_or(true),
+            Prerequisite::MinMovesLeft(min_moves) => source_q
+                .map(|source_q| source_q.moves_left >= *min_moves)
+                .unwrap_or(false),
+            Prerequisite::HasTag(tag) => source_q
+                .and_then(|source_q| source_q.tags)
+                .map(|tags| tags.has_tag(tag))
+                .unwrap_or(false),
+            Prerequisite::TargetHasTag(tag) => {
+                target.is_none()
+                    || target_q
+                        .and_then(|target_q| target_q.tags)
+                        .map(|tags| tags.has_tag(tag))
+                        .unwrap_or(false)
+            },
+            Prerequisite::OncePerBattle => source_q
+                .and_then(|source_q| source_q.action_history)
+                .map(|history| !history.has_used(action_id))
+                .unwrap_or(true),
+            Prerequisite::Cooldown(_) => source_q
+                .and_then(|source_q| source_q.action_history)
+                .map(|history| history.cooldown(action_id) == 0)
+                .unwrap_or(true),
+        }
+    }
+}
+
+impl std::fmt::Display for Prerequisite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Prerequisite::MinSize(min_size) => write!(f, "Requires size {min_size}"),
+            Prerequisite::TargetMaxSize(max_size) => {
+                write!(f, "Target max size must be at most {max_size}")
+            },
+            Prerequisite::MinMovesLeft(min_moves) => {
+                write!(f, "Requires {min_moves} moves left")
+            },
+            Prerequisite::HasTag(tag) => write!(f, "Requires tag [{tag}]"),
+            Prerequisite::TargetHasTag(tag) => write!(f, "Target requires tag [{tag}]"),
+            Prerequisite::OncePerBattle => write!(f, "Once per battle"),
+            Prerequisite::Cooldown(turns) => write!(f, "Cooldown of {turns} turns"),
+        }
+    }
+}
+
+impl ActionHistory {
+    pub fn has_used(&self, action_id: &str) -> bool {
+        self.used.iter().any(|used| used == action_id)
+    }
+
+    /// Turns until the action can be used again
+    pub fn cooldown(&self, action_id: &str) -> u32 {
+        self.cooldowns.get(action_id).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn record_use(&mut self, action: &Action) {
+        if !self.has_used(action.id()) {
+            self.used.push(action.id().to_owned());
+        }
+        for prereq in action.prereqs() {
+            if let Prerequisite::Cooldown(turns) = prereq {
+                self.cooldowns.insert(action.id().to_owned(), *turns);
+            }
         }
     }
+
+    /// Counts down cooldowns at the end of the curio's team's turn
+    pub(crate) fn tick(&mut self) {
+        for cooldown in self.cooldowns.values_mut() {
+            *cooldown = cooldown.saturating_sub(1);
+        }
+        self.cooldowns.retain(|_, cooldown| *cooldown > 0);
+    }
 }
 
 impl RangeShape {
@@ -364,3 +864,364 @@ impl RangeShape {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::component::Tick;
+
+    use super::*;
+    use crate::node::node_testing::TestBattle;
+    use crate::node::NodeOp;
+
+    fn action(id: &str, effect: ActionEffect, target: ActionTarget) -> Action {
+        Action {
+            id: id.to_owned(),
+            range: Some(ActionRange::new(1)),
+            effects: vec![effect],
+            target,
+            ..default()
+        }
+    }
+
+    fn perform_action(battle: &mut TestBattle, curio: Entity, action_id: &str, target: UVec2) {
+        for op in [
+            NodeOp::ActivateCurio { curio_id: curio },
+            NodeOp::PerformCurioAction {
+                action_id: action_id.to_owned().into(),
+                curio: None,
+                target,
+            },
+        ] {
+            let results = battle.perform(0, op);
+            assert!(
+                results.iter().all(|result| result.result().is_ok()),
+                "{results:?}"
+            );
+        }
+    }
+
+    /// A battle where one curio can hit an ally of length 3, and another can
+    /// heal it
+    fn heal_battle() -> (TestBattle, [Entity; 3]) {
+        let mut battle = TestBattle::new(6, 6).with_undo();
+        let hit = battle.add_action(action("hit", ActionEffect::Damage(2), ActionTarget::Curios));
+        let heal = battle.add_action(action("heal", ActionEffect::Heal(2), ActionTarget::Allies));
+        let hitter = battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[hit]);
+        let healer = battle.spawn_curio(0, &[UVec2::new(1, 1)], 0, &[heal]);
+        let ally = battle.spawn_curio(
+            0,
+            &[UVec2::new(1, 0), UVec2::new(2, 0), UVec2::new(3, 0)],
+            0,
+            &[],
+        );
+        (battle, [hitter, healer, ally])
+    }
+
+    #[test]
+    fn test_heal_grows_back_lost_squares() {
+        let (mut battle, [hitter, healer, ally]) = heal_battle();
+        let original_points = battle.grid().points(ally);
+        perform_action(&mut battle, hitter, "hit", UVec2::new(1, 0));
+        assert_eq!(battle.grid().points(ally), vec![UVec2::new(1, 0)]);
+
+        perform_action(&mut battle, healer, "heal", UVec2::new(1, 0));
+        assert_eq!(battle.grid().points(ally), original_points);
+        assert!(battle.get::<LostSquares>(ally).is_empty());
+    }
+
+    #[test]
+    fn test_blocked_heal_keeps_lost_squares() {
+        let (mut battle, [hitter, healer, ally]) = heal_battle();
+        perform_action(&mut battle, hitter, "hit", UVec2::new(1, 0));
+        let damaged_lost_squares = battle.get::<LostSquares>(ally).clone();
+        battle.spawn_curio(1, &[UVec2::new(2, 0)], 0, &[]);
+
+        perform_action(&mut battle, healer, "heal", UVec2::new(1, 0));
+        assert_eq!(battle.grid().points(ally), vec![UVec2::new(1, 0)]);
+        assert_eq!(**battle.get::<LostSquares>(ally), *damaged_lost_squares);
+    }
+
+    #[test]
+    fn test_heal_stops_at_maximum_size() {
+        let (mut battle, [_, healer, ally]) = heal_battle();
+        perform_action(&mut battle, healer, "heal", UVec2::new(1, 0));
+        assert_eq!(
+            battle.grid().len_of(ally),
+            **battle.get::<MaximumSize>(ally) as usize
+        );
+    }
+
+    #[test]
+    fn test_undo_heal() {
+        let (mut battle, [hitter, healer, ally]) = heal_battle();
+        perform_action(&mut battle, hitter, "hit", UVec2::new(1, 0));
+        let damaged_lost_squares = battle.get::<LostSquares>(ally).clone();
+        perform_action(&mut battle, healer, "heal", UVec2::new(1, 0));
+
+        let results = battle.perform(0, NodeOp::Undo);
+        assert!(results[0].result().is_ok(), "{results:?}");
+        assert_eq!(battle.grid().points(ally), vec![UVec2::new(1, 0)]);
+        assert_eq!(**battle.get::<LostSquares>(ally), *damaged_lost_squares);
+    }
+
+    #[test]
+    fn test_add_tag_and_undo() {
+        let mut battle = TestBattle::new(4, 4).with_undo();
+        let bless = battle.add_action(action(
+            "bless",
+            ActionEffect::AddTag("blessed".to_owned()),
+            ActionTarget::Allies,
+        ));
+        let blesser = battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[bless]);
+        let ally = battle.spawn_curio(0, &[UVec2::new(1, 0)], 0, &[]);
+
+        perform_action(&mut battle, blesser, "bless", UVec2::new(1, 0));
+        assert!(battle.get::<Tags>(ally).has_tag("blessed"));
+
+        let results = battle.perform(0, NodeOp::Undo);
+        assert!(results[0].result().is_ok(), "{results:?}");
+        assert!(!battle.get::<Tags>(ally).has_tag("blessed"));
+    }
+
+    #[test]
+    fn test_area_hits_each_enemy_once() {
+        let mut battle = TestBattle::new(6, 6).with_undo();
+        let blast = battle.add_action(Action {
+            range: Some(ActionRange::new(2)),
+            area: Some(ActionRange::new(1)),
+            ..action("blast", ActionEffect::Damage(1), ActionTarget::Enemies)
+        });
+        let blaster = battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[blast]);
+        let ally = battle.spawn_curio(0, &[UVec2::new(1, 0)], 0, &[]);
+        let target = battle.spawn_curio(1, &[UVec2::new(2, 0), UVec2::new(3, 0)], 0, &[]);
+        let neighbor = battle.spawn_curio(1, &[UVec2::new(2, 1), UVec2::new(2, 2)], 0, &[]);
+        let original_grid = battle.grid().clone();
+
+        perform_action(&mut battle, blaster, "blast", UVec2::new(2, 0));
+        assert_eq!(battle.grid().len_of(ally), 1);
+        assert_eq!(battle.grid().points(target), vec![UVec2::new(2, 0)]);
+        assert_eq!(battle.grid().points(neighbor), vec![UVec2::new(2, 1)]);
+
+        let results = battle.perform(0, NodeOp::Undo);
+        assert!(results[0].result().is_ok(), "{results:?}");
+        assert_eq!(battle.grid(), &original_grid);
+    }
+
+    #[test]
+    fn test_area_applies_after_fatal_hit() {
+        let mut battle = TestBattle::new(6, 6);
+        let blast = battle.add_action(Action {
+            range: Some(ActionRange::new(2)),
+            area: Some(ActionRange::new(1)),
+            ..action("blast", ActionEffect::Damage(1), ActionTarget::Enemies)
+        });
+        let blaster = battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[blast]);
+        let target = battle.spawn_curio(1, &[UVec2::new(2, 0)], 0, &[]);
+        let neighbor = battle.spawn_curio(1, &[UVec2::new(2, 1)], 0, &[]);
+
+        let results = battle.perform(0, NodeOp::ActivateCurio { curio_id: blaster });
+        assert!(results[0].result().is_ok(), "{results:?}");
+        let results = battle.perform(
+            0,
+            NodeOp::PerformCurioAction {
+                action_id: "blast".into(),
+                curio: None,
+                target: UVec2::new(2, 0),
+            },
+        );
+        let metadata = results[0].result().as_ref().unwrap();
+        let area_effects = metadata
+            .get_required(crate::node::key::AREA_EFFECTS)
+            .unwrap();
+        assert_eq!(area_effects.len(), 1);
+        assert_eq!(
+            area_effects[0].get_required(key::TARGET_ENTITY).unwrap(),
+            neighbor
+        );
+        assert!(!battle.grid().contains_key(target));
+        assert!(!battle.grid().contains_key(neighbor));
+    }
+
+    /// Tries an action with a curio, returning whether it was performed
+    fn try_action(battle: &mut TestBattle, curio: Entity, action_id: &str, target: UVec2) -> bool {
+        let results = battle.perform(
+            0,
+            NodeOp::PerformCurioAction {
+                action_id: action_id.to_owned().into(),
+                curio: Some(curio),
+                target,
+            },
+        );
+        results[0].result().is_ok()
+    }
+
+    fn end_round(battle: &mut TestBattle) {
+        for player in [0, 1] {
+            let results = battle.perform(player, NodeOp::EndTurn);
+            assert!(results[0].result().is_ok(), "{results:?}");
+        }
+    }
+
+    fn prereq_battle(prereqs: Vec<Prerequisite>) -> (TestBattle, Entity) {
+        let mut battle = TestBattle::new(6, 6);
+        let zap = battle.add_action(Action {
+            prereqs,
+            ..action("zap", ActionEffect::Damage(1), ActionTarget::Enemies)
+        });
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 1, &[zap]);
+        let enemy_pts: Vec<UVec2> = (0..6).map(|y| UVec2::new(1, y)).collect();
+        battle.spawn_curio(1, &enemy_pts, 0, &[]);
+        (battle, curio)
+    }
+
+    #[test]
+    fn test_cooldown_blocks_until_expired() {
+        let (mut battle, curio) = prereq_battle(vec![Prerequisite::Cooldown(2)]);
+        assert!(try_action(&mut battle, curio, "zap", UVec2::new(1, 0)));
+        end_round(&mut battle);
+        assert!(!try_action(&mut battle, curio, "zap", UVec2::new(1, 0)));
+        end_round(&mut battle);
+        assert!(try_action(&mut battle, curio, "zap", UVec2::new(1, 0)));
+    }
+
+    #[test]
+    fn test_once_per_battle() {
+        let (mut battle, curio) = prereq_battle(vec![Prerequisite::OncePerBattle]);
+        assert!(try_action(&mut battle, curio, "zap", UVec2::new(1, 0)));
+        end_round(&mut battle);
+        assert!(!try_action(&mut battle, curio, "zap", UVec2::new(1, 0)));
+    }
+
+    #[test]
+    fn test_min_moves_left() {
+        let (mut battle, curio) = prereq_battle(vec![Prerequisite::MinMovesLeft(1)]);
+        let results = battle.perform(0, NodeOp::ActivateCurio { curio_id: curio });
+        assert!(results[0].result().is_ok(), "{results:?}");
+        let results = battle.perform(
+            0,
+            NodeOp::MoveActiveCurio {
+                dir: Compass::South,
+            },
+        );
+        assert!(results[0].result().is_ok(), "{results:?}");
+        assert!(!try_action(&mut battle, curio, "zap", UVec2::new(1, 1)));
+        end_round(&mut battle);
+        assert!(try_action(&mut battle, curio, "zap", UVec2::new(1, 1)));
+    }
+
+    #[test]
+    fn test_target_has_tag() {
+        let (mut battle, curio) =
+            prereq_battle(vec![Prerequisite::TargetHasTag("marked".to_owned())]);
+        assert!(!try_action(&mut battle, curio, "zap", UVec2::new(1, 0)));
+
+        let enemy = battle.grid().item_at(UVec2::new(1, 0)).unwrap();
+        battle
+            .app
+            .world_mut()
+            .get_mut::<Tags>(enemy)
+            .unwrap()
+            .add_tag("marked".to_owned());
+        assert!(try_action(&mut battle, curio, "zap", UVec2::new(1, 0)));
+    }
+
+    #[test]
+    fn test_effects_only_change_what_they_modify() {
+        let (mut battle, curio) = prereq_battle(Vec::new());
+        let enemy = battle.grid().item_at(UVec2::new(1, 0)).unwrap();
+        let before = battle.app.world_mut().change_tick();
+        assert!(try_action(&mut battle, curio, "zap", UVec2::new(1, 0)));
+
+        let world = battle.app.world();
+        let this_run = world.read_change_tick();
+        let changed = |last_changed: Tick| last_changed.is_newer_than(before, this_run);
+        let enemy = world.entity(enemy);
+        assert!(changed(
+            enemy.get_ref::<LostSquares>().unwrap().last_changed()
+        ));
+        for last_changed in [
+            enemy.get_ref::<MaximumSize>().unwrap().last_changed(),
+            enemy.get_ref::<MovementSpeed>().unwrap().last_changed(),
+            enemy.get_ref::<StatusEffects>().unwrap().last_changed(),
+            enemy.get_ref::<Tags>().unwrap().last_changed(),
+        ] {
+            assert!(!changed(last_changed));
+        }
+    }
+
+    #[test]
+    fn test_squares_between() {
+        assert_eq!(
+            squares_between(UVec2::new(0, 1), UVec2::new(3, 1)),
+            vec![UVec2::new(1, 1), UVec2::new(2, 1)]
+        );
+        assert_eq!(
+            squares_between(UVec2::new(0, 0), UVec2::new(2, 2)),
+            vec![UVec2::new(1, 1)]
+        );
+        assert!(squares_between(UVec2::new(0, 0), UVec2::new(0, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let mut grid = EntityGrid::from(vec![vec![true; 3]; 5]);
+        let source = Entity::from_raw(1);
+        let blocker = Entity::from_raw(2);
+        grid.put_entries([
+            (source, vec![UVec2::new(0, 1), UVec2::new(1, 1)]),
+            (blocker, vec![UVec2::new(3, 0)]),
+        ]);
+        let range = ActionRange::new(4).shaped(RangeShape::Square);
+        let walls = range.with_line_of_sight(Some(LineOfSight::Walls));
+        let walls_and_pieces = range.with_line_of_sight(Some(LineOfSight::WallsAndPieces));
+
+        // Pieces only block the line when they are meant to
+        let behind_blocker = UVec2::new(4, 0);
+        assert!(walls.in_range_of(&grid, source, behind_blocker));
+        assert!(!walls_and_pieces.in_range_of(&grid, source, behind_blocker));
+
+        let behind_wall = UVec2::new(4, 1);
+        assert!(walls.in_range_of(&grid, source, behind_wall));
+        grid.close_square(UVec2::new(3, 1));
+        assert!(range.in_range_of(&grid, source, behind_wall));
+        assert!(!walls.in_range_of(&grid, source, behind_wall));
+        assert!(!walls_and_pieces.in_range_of(&grid, source, behind_wall));
+
+        // The source's own squares never block it
+        assert!(walls_and_pieces.in_range_of(&grid, source, UVec2::new(2, 1)));
+    }
+
+    #[test]
+    fn test_enemies_exclude_allied_teams() {
+        let mut battle = TestBattle::with_team_count(3, 4, 4);
+        battle.ally(0, 2);
+        let hit = battle.add_action(action(
+            "hit",
+            ActionEffect::Damage(1),
+            ActionTarget::Enemies,
+        ));
+        let hitter = battle.spawn_curio(0, &[UVec2::new(1, 1)], 0, &[hit]);
+        battle.spawn_curio(1, &[UVec2::new(1, 0)], 0, &[]);
+        battle.spawn_curio(2, &[UVec2::new(0, 1)], 0, &[]);
+
+        assert!(!try_action(&mut battle, hitter, "hit", UVec2::new(0, 1)));
+        assert!(try_action(&mut battle, hitter, "hit", UVec2::new(1, 0)));
+    }
+
+    #[test]
+    fn test_allies_include_allied_teams() {
+        let mut battle = TestBattle::with_team_count(3, 4, 4);
+        battle.ally(0, 2);
+        let bless = battle.add_action(action(
+            "bless",
+            ActionEffect::AddTag("blessed".to_owned()),
+            ActionTarget::Allies,
+        ));
+        let blesser = battle.spawn_curio(0, &[UVec2::new(1, 1)], 0, &[bless]);
+        battle.spawn_curio(1, &[UVec2::new(1, 0)], 0, &[]);
+        battle.spawn_curio(2, &[UVec2::new(0, 1)], 0, &[]);
+
+        assert!(!try_action(&mut battle, blesser, "bless", UVec2::new(1, 0)));
+        assert!(try_action(&mut battle, blesser, "bless", UVec2::new(0, 1)));
+    }
+}
@@ -5,7 +5,11 @@ use bevy::asset::{AssetLoader, LoadContext};
 use getset::{CopyGetters, Getters};
 use serde::{Deserialize, Serialize};
 
-use super::{Action, ActionEffect, ActionRange, ActionTarget, Prerequisite, RangeShape};
+use super::{
+    Action, ActionEffect, ActionRange, ActionTarget, CurioPrereqAccess, LineOfSight, Prerequisite,
+    RangeShape,
+};
+use crate::node::Alliances;
 use crate::prelude::*;
 
 pub const NO_OP_ACTION_ID: Cow<'static, str> = Cow::Borrowed("No action");
@@ -28,6 +32,8 @@ pub struct CardAssetDef {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ActionAssetDef {
+    #[serde(default)]
+    area: Option<ActionRangeRepr>,
     effects: Vec<ActionEffect>,
     #[serde(default)]
     prereqs: Vec<Prerequisite>,
@@ -49,6 +55,7 @@ pub enum ActionRangeRepr {
         max_range: u32,
         shape: Option<RangeShape>,
         min_range: Option<u32>,
+        line_of_sight: Option<LineOfSight>,
     },
 }
 
@@ -89,6 +96,7 @@ impl AssetLoader for ActionAssetLoader {
         let asset_map: HashMap<String, ActionAssetDef> = serde_json::from_slice(&bytes[..])?;
         for (id, def) in asset_map.into_iter() {
             let ActionAssetDef {
+                area,
                 effects,
                 prereqs,
                 range,
@@ -100,6 +108,7 @@ impl AssetLoader for ActionAssetLoader {
             let def = Action {
                 id: id.clone(),
                 range: Some(range.into()),
+                area: area.map(ActionRangeRepr::into),
                 tags,
                 effects,
                 target,
@@ -174,10 +183,12 @@ impl ActionRangeRepr {
                 max_range,
                 shape,
                 min_range,
+                line_of_sight,
             } => ActionRange::new(max_range)
                 .shaped(shape.unwrap_or_default())
                 .min_range(min_range.unwrap_or_default())
-                .headless(headless.unwrap_or_default()),
+                .headless(headless.unwrap_or_default())
+                .with_line_of_sight(line_of_sight),
         }
     }
 }
@@ -189,6 +200,7 @@ impl From<ActionRange> for ActionRangeRepr {
             max_range: value.max_range(),
             shape: Some(value.shape()),
             min_range: Some(value.minimum_range()),
+            line_of_sight: value.line_of_sight(),
         }
     }
 }
@@ -198,6 +210,7 @@ impl Default for Action {
         Action {
             id: NO_OP_ACTION_ID.into_owned(),
             range: None,
+            area: None,
             effects: Vec::new(),
             self_effects: Vec::new(),
             target: ActionTarget::None,
@@ -223,6 +236,54 @@ impl Action {
     pub fn range(&self) -> Option<ActionRange> {
         self.range
     }
+
+    pub fn area(&self) -> Option<ActionRange> {
+        self.area
+    }
+
+    /// The first prerequisite of this action that isn't satisfied, if any
+    pub fn unsatisfied_prereq<P: CurioPrereqAccess>(
+        &self,
+        grid: &EntityGrid,
+        source: Entity,
+        target: Option<UVec2>,
+        curios: &P,
+    ) -> Option<&Prerequisite> {
+        self.prereqs
+            .iter()
+            .find(|prereq| !prereq.satisfied(self.id(), grid, source, target, curios))
+    }
+
+    /// Points other than the main target that the effects of this action
+    /// apply to. Each curio in the area is only included once, and only if it
+    /// is a valid target for this action.
+    pub fn area_targets<F: Fn(Entity) -> Option<Entity>>(
+        &self,
+        grid: &EntityGrid,
+        source: Entity,
+        target: UVec2,
+        team_check: F,
+        alliances: Option<&Alliances>,
+    ) -> Vec<UVec2> {
+        let Some(area) = self.area else {
+            return Vec::new();
+        };
+        let mut hit_curios: HashSet<Entity> = grid.item_at(target).into_iter().collect();
+        area.pts_in_range_of(grid, target)
+            .into_iter()
+            .filter(|pt| {
+                *pt != target
+                    && self
+                        .target
+                        .valid_target(grid, source, *pt, &team_check, alliances)
+            })
+            .filter(|pt| {
+                grid.item_at(*pt)
+                    .map(|id| hit_curios.insert(id))
+                    .unwrap_or(true)
+            })
+            .collect()
+    }
 }
 
 impl CardDefinition {
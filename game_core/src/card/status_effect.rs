@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Reflect, Serialize)]
+pub enum Status {
+    /// Reduces how many moves the curio can make each turn
+    Slowed(u32),
+    /// The curio starts its team's turn tapped
+    Stunned,
+    /// The next damage the curio takes is ignored, removing the shield
+    Shielded,
+}
+
+/// A status applied to a curio for a number of its team's turns. Turns are
+/// counted down at the start of each turn of the curio's team, and the status
+/// is removed at the start of the turn after its last one.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Reflect, Serialize)]
+pub struct StatusEffect {
+    pub status: Status,
+    pub turns: u32,
+}
+
+#[derive(Clone, Component, Debug, Default, Deref, DerefMut, Reflect)]
+#[reflect(Component)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+impl StatusEffects {
+    pub fn has_status(&self, status: Status) -> bool {
+        self.iter().any(|effect| effect.status == status)
+    }
+
+    pub fn movement_penalty(&self) -> u32 {
+        self.iter()
+            .map(|effect| match effect.status {
+                Status::Slowed(penalty) => penalty,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Removes one shield if there is one, returning true if one was removed
+    pub(crate) fn use_shield(&mut self) -> bool {
+        if let Some(index) = self
+            .iter()
+            .position(|effect| effect.status == Status::Shielded)
+        {
+            self.remove(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes statuses that have run out, and counts down a turn on the rest.
+    /// A status with no turns left is in its last turn.
+    pub(crate) fn tick(&mut self) {
+        self.retain(|effect| effect.turns > 0);
+        for effect in self.iter_mut() {
+            effect.turns -= 1;
+        }
+    }
+}
+
+impl std::fmt::Display for StatusEffect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.status {
+            Status::Slowed(penalty) => write!(f, "Slowed -{penalty}"),
+            Status::Stunned => write!(f, "Stunned"),
+            Status::Shielded => write!(f, "Shielded"),
+        }?;
+        match self.turns {
+            0 => write!(f, " (last turn)"),
+            1 => write!(f, " (1 turn)"),
+            turns => write!(f, " ({turns} turns)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::card::{Action, ActionEffect, ActionRange, ActionTarget};
+    use crate::node::node_testing::TestBattle;
+    use crate::node::{IsTapped, NodeOp};
+
+    fn status_action(id: &str, status: Status, turns: u32) -> Action {
+        Action {
+            id: id.to_owned(),
+            range: Some(ActionRange::new(1)),
+            effects: vec![ActionEffect::ApplyStatus(StatusEffect { status, turns })],
+            target: ActionTarget::Curios,
+            ..default()
+        }
+    }
+
+    fn perform(battle: &mut TestBattle, player: usize, op: NodeOp) {
+        let results = battle.perform(player, op);
+        assert!(
+            results.iter().all(|result| result.result().is_ok()),
+            "{results:?}"
+        );
+    }
+
+    fn apply_status(battle: &mut TestBattle, player: usize, curio: Entity, target: UVec2) {
+        perform(battle, player, NodeOp::ActivateCurio { curio_id: curio });
+        perform(
+            battle,
+            player,
+            NodeOp::PerformCurioAction {
+                action_id: "status".into(),
+                curio: None,
+                target,
+            },
+        );
+    }
+
+    fn end_round(battle: &mut TestBattle) {
+        perform(battle, 0, NodeOp::EndTurn);
+        perform(battle, 1, NodeOp::EndTurn);
+    }
+
+    #[test]
+    fn test_tick_keeps_last_turn() {
+        let mut status_effects = StatusEffects(vec![
+            StatusEffect {
+                status: Status::Stunned,
+                turns: 1,
+            },
+            StatusEffect {
+                status: Status::Shielded,
+                turns: 0,
+            },
+        ]);
+        status_effects.tick();
+        assert_eq!(
+            *status_effects,
+            vec![StatusEffect {
+                status: Status::Stunned,
+                turns: 0,
+            }]
+        );
+        status_effects.tick();
+        assert!(status_effects.is_empty());
+    }
+
+    #[test]
+    fn test_stun_from_enemy_taps_next_turn() {
+        let mut battle = TestBattle::new(4, 4);
+        let stun = battle.add_action(status_action("status", Status::Stunned, 1));
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 1, &[]);
+        let enemy = battle.spawn_curio(1, &[UVec2::new(1, 0)], 1, &[stun]);
+
+        perform(&mut battle, 0, NodeOp::EndTurn);
+        apply_status(&mut battle, 1, enemy, UVec2::new(0, 0));
+        perform(&mut battle, 1, NodeOp::EndTurn);
+        assert!(**battle.get::<IsTapped>(curio));
+
+        end_round(&mut battle);
+        assert!(!**battle.get::<IsTapped>(curio));
+        assert!(battle.get::<StatusEffects>(curio).is_empty());
+    }
+
+    #[test]
+    fn test_stun_during_own_turn_taps_next_turn() {
+        let mut battle = TestBattle::new(4, 4);
+        let stun = battle.add_action(status_action("status", Status::Stunned, 1));
+        let stunner = battle.spawn_curio(0, &[UVec2::new(0, 0)], 1, &[stun]);
+        let ally = battle.spawn_curio(0, &[UVec2::new(1, 0)], 1, &[]);
+
+        apply_status(&mut battle, 0, stunner, UVec2::new(1, 0));
+        end_round(&mut battle);
+        assert!(**battle.get::<IsTapped>(ally));
+        assert!(battle
+            .get::<StatusEffects>(ally)
+            .has_status(Status::Stunned));
+
+        end_round(&mut battle);
+        assert!(!**battle.get::<IsTapped>(ally));
+        assert!(battle.get::<StatusEffects>(ally).is_empty());
+    }
+
+    #[test]
+    fn test_slowed_reduces_movement() {
+        let mut battle = TestBattle::new(4, 4);
+        let slow = battle.add_action(status_action("status", Status::Slowed(1), 1));
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 2, &[]);
+        let enemy = battle.spawn_curio(1, &[UVec2::new(1, 0)], 1, &[slow]);
+
+        perform(&mut battle, 0, NodeOp::EndTurn);
+        apply_status(&mut battle, 1, enemy, UVec2::new(0, 0));
+        perform(&mut battle, 1, NodeOp::EndTurn);
+
+        perform(&mut battle, 0, NodeOp::ActivateCurio { curio_id: curio });
+        let south = NodeOp::MoveActiveCurio {
+            dir: Compass::South,
+        };
+        perform(&mut battle, 0, south.clone());
+        let results = battle.perform(0, south);
+        assert!(results[0].result().is_err());
+    }
+
+    #[test]
+    fn test_shield_blocks_damage() {
+        let mut battle = TestBattle::new(4, 4);
+        let hit = battle.add_action(Action {
+            id: "hit".to_owned(),
+            range: Some(ActionRange::new(1)),
+            effects: vec![ActionEffect::Damage(1)],
+            target: ActionTarget::Enemies,
+            ..default()
+        });
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 1, &[hit]);
+        let enemy = battle.spawn_curio(1, &[UVec2::new(1, 0), UVec2::new(2, 0)], 1, &[]);
+        battle
+            .app
+            .world_mut()
+            .get_mut::<StatusEffects>(enemy)
+            .unwrap()
+            .push(StatusEffect {
+                status: Status::Shielded,
+                turns: 2,
+            });
+
+        perform(&mut battle, 0, NodeOp::ActivateCurio { curio_id: curio });
+        perform(
+            &mut battle,
+            0,
+            NodeOp::PerformCurioAction {
+                action_id: "hit".into(),
+                curio: None,
+                target: UVec2::new(1, 0),
+            },
+        );
+        assert_eq!(battle.grid().len_of(enemy), 2);
+        assert!(battle.get::<StatusEffects>(enemy).is_empty());
+    }
+}
@@ -97,6 +97,22 @@ impl Metadata {
         Ok(())
     }
 
+    /// Removes a field, returning its JSON if it was present
+    pub fn remove_field(&mut self, field: &str) -> Option<String> {
+        self.0.remove(field)
+    }
+
+    /// Moves the data in one field to another, replacing anything already there.
+    /// Returns false if there was nothing to move.
+    pub fn rename_field(&mut self, from: &str, to: &str) -> bool {
+        if let Some(data_str) = self.0.remove(from) {
+            self.0.insert(to.to_string(), data_str);
+            true
+        } else {
+            false
+        }
+    }
+
     ///
     /// # Safety
     ///
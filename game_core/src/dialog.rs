@@ -7,6 +7,7 @@ use getset::Getters;
 
 use crate::op::CoreOps;
 use crate::prelude::*;
+use crate::quest::QuestOp;
 use crate::shop::ShopOp;
 
 #[derive(Debug)]
@@ -115,6 +116,13 @@ fn sys_yarn_commands(
                     log::error!("open_shop requires a parameter")
                 }
             },
+            "start_quest" => {
+                if let Some(quest_id) = command.parameters.first() {
+                    res_core_ops.request(*source, QuestOp::Start(quest_id.to_string()));
+                } else {
+                    log::error!("start_quest requires a parameter")
+                }
+            },
             _ => {},
         }
     }
@@ -14,6 +14,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::prelude::*;
 pub mod commands;
+pub mod pathing;
 
 // Potential future developments:
 // * removing squares from the middle of an Item
@@ -221,16 +222,14 @@ impl From<Square> for Option<Entity> {
 impl EntityGrid {
     /// A representation of closed and open squares, though no width/height information encoded.
     /// Does not support maps with width/height greater u16::MAX
-    pub fn shape_bitvec(&self) -> BitVec<u8> {
-        let height: [u8; 2] = (self.height() as u16).to_le_bytes();
-        let width: [u8; 2] = (self.width() as u16).to_le_bytes();
-        let squarebits: BitVec<u8> = self
-            .grid
-            .iter()
-            .flat_map(|col| col.iter().map(|sqr| sqr.is_some()))
-            .collect();
-
-        let mut bitvec = BitVec::<u8>::new();
+    pub fn shape_bitvec(&self) -> BitVec<u8, Msb0> {
+        let height: [u8; 2] = (self.height() as u16).to_be_bytes();
+        let width: [u8; 2] = (self.width() as u16).to_be_bytes();
+        // Row by row, the way [`EntityGrid::from_shape_bitslice`] reads them
+        let squarebits = (0..self.height() as usize)
+            .flat_map(|y| (0..self.width() as usize).map(move |x| self.grid[x][y].is_some()));
+
+        let mut bitvec = BitVec::<u8, Msb0>::new();
         bitvec.extend_from_raw_slice(&width[..]);
         bitvec.extend_from_raw_slice(&height);
         bitvec.extend(squarebits);
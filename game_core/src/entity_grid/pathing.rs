@@ -0,0 +1,231 @@
+//! Pathfinding for items moving their heads around an [`EntityGrid`].
+//!
+//! Paths are found on the grid as it is: other items are obstacles for the
+//! whole path, even if they would move out of the way in the meantime.
+
+use std::collections::VecDeque;
+
+use super::EntityGrid;
+use crate::prelude::*;
+
+/// How many moves it takes an item's head to reach each square it can reach,
+/// found by [`EntityGrid::distance_map`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DistanceMap {
+    start: UVec2,
+    /// Moves to reach each point, and the direction of the last move
+    steps: HashMap<UVec2, (u32, Option<Compass>)>,
+}
+
+impl DistanceMap {
+    /// Where the item's head started
+    pub fn start(&self) -> UVec2 {
+        self.start
+    }
+
+    pub fn contains(&self, pt: UVec2) -> bool {
+        self.steps.contains_key(&pt)
+    }
+
+    /// The fewest moves it takes to reach `pt`, if it can be reached
+    pub fn distance_to(&self, pt: UVec2) -> Option<u32> {
+        self.steps.get(&pt).map(|(distance, _)| *distance)
+    }
+
+    /// The direction of the last move on the shortest path to `pt`. None if
+    /// `pt` is the start or cannot be reached.
+    pub fn direction_to(&self, pt: UVec2) -> Option<Compass> {
+        self.steps.get(&pt).and_then(|(_, dir)| *dir)
+    }
+
+    /// A shortest path from the start to `pt`, if it can be reached
+    pub fn path_to(&self, pt: UVec2) -> Option<Vec<Compass>> {
+        let mut path = Vec::with_capacity(self.distance_to(pt)? as usize);
+        let mut current = pt;
+        while let Some(dir) = self.direction_to(current) {
+            path.push(dir);
+            current = current - dir;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Every point that can be reached, in no particular order
+    pub fn points(&self) -> impl Iterator<Item = UVec2> + '_ {
+        self.steps.keys().copied()
+    }
+
+    /// Every point that can be reached, mapped to the direction of the last
+    /// move on the shortest path to it
+    pub fn directions(&self) -> HashMap<UVec2, Option<Compass>> {
+        self.steps
+            .iter()
+            .map(|(pt, (_, dir))| (*pt, *dir))
+            .collect()
+    }
+}
+
+impl EntityGrid {
+    /// Whether the item can move its head onto `pt`.
+    ///
+    /// Items can move onto open squares that are empty, hold one of their own
+    /// squares, or hold an item that `can_pick_up` returns true for. This
+    /// matches what the node movement op allows.
+    pub fn can_move_to<P: Fn(Entity) -> bool>(
+        &self,
+        item_key: Entity,
+        pt: UVec2,
+        can_pick_up: P,
+    ) -> bool {
+        self.square_is_open(pt)
+            && self
+                .item_at(pt)
+                .map(|pt_item| pt_item == item_key || can_pick_up(pt_item))
+                .unwrap_or(true)
+    }
+
+    /// Finds how many moves it takes the item's head to reach each square,
+    /// up to `max_moves` if given.
+    ///
+    /// Empty if the item is not in the grid.
+    pub fn distance_map<P: Fn(Entity) -> bool>(
+        &self,
+        item_key: Entity,
+        max_moves: Option<u32>,
+        can_pick_up: P,
+    ) -> DistanceMap {
+        self.search_paths(item_key, max_moves, can_pick_up, |_| false)
+            .0
+    }
+
+    /// The squares the item's head can reach with `moves` moves, including
+    /// where it is now
+    pub fn reachable_squares<P: Fn(Entity) -> bool>(
+        &self,
+        item_key: Entity,
+        moves: u32,
+        can_pick_up: P,
+    ) -> HashSet<UVec2> {
+        self.distance_map(item_key, Some(moves), can_pick_up)
+            .points()
+            .collect()
+    }
+
+    /// A shortest path for the item's head to reach `to`, if there is one
+    /// within `max_moves`
+    pub fn shortest_path<P: Fn(Entity) -> bool>(
+        &self,
+        item_key: Entity,
+        to: UVec2,
+        max_moves: Option<u32>,
+        can_pick_up: P,
+    ) -> Option<Vec<Compass>> {
+        self.path_to_nearest(item_key, max_moves, can_pick_up, |pt| pt == to)
+    }
+
+    /// A shortest path for the item's head to reach any point that `is_goal`
+    /// returns true for, if there is one within `max_moves`
+    pub fn path_to_nearest<P: Fn(Entity) -> bool, G: Fn(UVec2) -> bool>(
+        &self,
+        item_key: Entity,
+        max_moves: Option<u32>,
+        can_pick_up: P,
+        is_goal: G,
+    ) -> Option<Vec<Compass>> {
+        let (distance_map, goal) = self.search_paths(item_key, max_moves, can_pick_up, is_goal);
+        distance_map.path_to(goal?)
+    }
+
+    /// Breadth first search from the item's head, stopping early at the first
+    /// point that satisfies `is_goal`
+    fn search_paths<P: Fn(Entity) -> bool, G: Fn(UVec2) -> bool>(
+        &self,
+        item_key: Entity,
+        max_moves: Option<u32>,
+        can_pick_up: P,
+        is_goal: G,
+    ) -> (DistanceMap, Option<UVec2>) {
+        let Some(start) = self.head(item_key) else {
+            return (DistanceMap::default(), None);
+        };
+        let mut distance_map = DistanceMap {
+            start,
+            steps: [(start, (0, None))].into_iter().collect(),
+        };
+        let mut queue = VecDeque::from([(start, 0)]);
+        while let Some((pt, distance)) = queue.pop_front() {
+            if is_goal(pt) {
+                return (distance_map, Some(pt));
+            }
+            if max_moves.is_some_and(|max_moves| distance >= max_moves) {
+                continue;
+            }
+            for dir in Compass::ALL_DIRECTIONS {
+                let next_pt = pt + dir;
+                // Adding a direction saturates at the edge of the grid
+                if next_pt == pt
+                    || distance_map.contains(next_pt)
+                    || !self.can_move_to(item_key, next_pt, &can_pick_up)
+                {
+                    continue;
+                }
+                distance_map
+                    .steps
+                    .insert(next_pt, (distance + 1, Some(dir)));
+                queue.push_back((next_pt, distance + 1));
+            }
+        }
+        (distance_map, None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn open_grid() -> EntityGrid {
+        EntityGrid::from(vec![vec![true; 5]; 5])
+    }
+
+    #[test]
+    fn distance_map_respects_max_moves() {
+        let mut grid = open_grid();
+        let item = Entity::from_raw(1);
+        grid.put_item(UVec2 { x: 2, y: 2 }, item);
+        let distance_map = grid.distance_map(item, Some(1), |_| false);
+        assert_eq!(distance_map.points().count(), 5);
+        assert_eq!(distance_map.distance_to(UVec2 { x: 2, y: 1 }), Some(1));
+        assert_eq!(distance_map.distance_to(UVec2 { x: 1, y: 1 }), None);
+    }
+
+    #[test]
+    fn paths_go_around_other_items_unless_they_can_be_picked_up() {
+        let mut grid = open_grid();
+        let item = Entity::from_raw(1);
+        let wall = Entity::from_raw(2);
+        grid.put_item(UVec2 { x: 0, y: 0 }, item);
+        grid.put_item(UVec2 { x: 1, y: 0 }, wall);
+        for y in 1..4 {
+            grid.push_back(UVec2 { x: 1, y }, wall);
+        }
+        let to = UVec2 { x: 2, y: 0 };
+        let around = grid.shortest_path(item, to, None, |_| false).unwrap();
+        assert_eq!(around.len(), 10);
+        let through = grid.shortest_path(item, to, None, |id| id == wall).unwrap();
+        assert_eq!(through, vec![Compass::East, Compass::East]);
+        assert_eq!(grid.shortest_path(item, to, Some(9), |_| false), None);
+    }
+
+    #[test]
+    fn closed_squares_block_movement() {
+        let mut grid = open_grid();
+        let item = Entity::from_raw(1);
+        grid.put_item(UVec2 { x: 0, y: 0 }, item);
+        grid.close_square(UVec2 { x: 1, y: 0 });
+        grid.close_square(UVec2 { x: 0, y: 1 });
+        assert_eq!(
+            grid.reachable_squares(item, 3, |_| true),
+            [UVec2 { x: 0, y: 0 }].into_iter().collect()
+        );
+    }
+}
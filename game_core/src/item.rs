@@ -1,24 +1,34 @@
 use std::borrow::Cow;
 
 use getset::CopyGetters;
+use serde::{Deserialize, Serialize};
 
 use self::daddy::Daddy;
-use crate::card::{Action, Card, CardDefinition, CardHandle, Deck, Nickname};
+use crate::card::{Action, Card, CardDefinition, CardHandle, Deck, Description, Nickname};
+use crate::node::{ActiveCurio, InNode, Node, NodePiece, OnTeam, Pickup};
 use crate::op::{Op, OpError, OpErrorUtils, OpImplResult, OpPlugin, OpRegistrar};
 use crate::player::Player;
 use crate::prelude::*;
+use crate::registry::{Reg, Registry, UpdatedRegistryKey};
 use crate::saving::{LoadData, LoadSchedule, SaveData, SaveSchedule};
 
 pub const MAX_MON: u32 = 100_000_000;
 
 pub mod key {
     use bevy::ecs::entity::Entity;
+    use bevy::math::UVec2;
     use typed_key::{typed_key, Key};
 
+    use super::Inventory;
+
     pub const CARD_ID: Key<Entity> = typed_key!("card_id");
+    pub const DROP_POINT: Key<UVec2> = typed_key!("drop_point");
+    pub const ITEM_COUNT: Key<u32> = typed_key!("item_count");
     pub const NEW_CARD: Key<bool> = typed_key!("new_card");
+    pub const PICKUP_ID: Key<Entity> = typed_key!("pickup_id");
     pub mod save {
         use super::*;
+        pub const INVENTORY: Key<Inventory> = typed_key!("inventory");
         pub const WALLET: Key<u32> = typed_key!("wallet");
     }
 }
@@ -29,10 +39,16 @@ pub struct ItemPlugin;
 impl Plugin for ItemPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Daddy<Card>>()
+            .init_resource::<ItemActions>()
+            .register_type::<Inventory>()
             .register_type::<Item>()
-            .add_plugins(OpPlugin::<ItemOp>::default())
-            .add_systems(SaveSchedule, sys_save_wallet)
-            .add_systems(LoadSchedule, sys_load_wallet);
+            .add_plugins((
+                OpPlugin::<ItemOp>::default(),
+                Reg::<ItemDefinitions>::default(),
+            ))
+            .add_systems(Update, sys_load_item_actions)
+            .add_systems(SaveSchedule, (sys_save_wallet, sys_save_inventory))
+            .add_systems(LoadSchedule, (sys_load_wallet, sys_load_inventory));
     }
 }
 
@@ -75,16 +91,111 @@ impl Wallet {
     }
 }
 
+/// Player component with the non-card items they are carrying, counted by
+/// item id. Items are defined in the [`ItemDefinitions`] registry.
+#[derive(Clone, Component, Debug, Default, Deserialize, Reflect, Serialize)]
+#[reflect(Component)]
+pub struct Inventory(HashMap<String, u32>);
+
+impl Inventory {
+    pub fn count(&self, item_id: &str) -> u32 {
+        self.0.get(item_id).copied().unwrap_or(0)
+    }
+
+    pub fn add(&mut self, item_id: &str, count: u32) {
+        let item_count = self.0.entry(item_id.to_owned()).or_default();
+        *item_count = item_count.saturating_add(count);
+    }
+
+    /// Removes items only if there are at least `count` of them
+    pub fn try_remove(&mut self, item_id: &str, count: u32) -> bool {
+        match self.0.get_mut(item_id) {
+            Some(item_count) if *item_count >= count => {
+                *item_count -= count;
+                if *item_count == 0 {
+                    self.0.remove(item_id);
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Items and their counts, ordered by item id
+    pub fn items(&self) -> Vec<(&str, u32)> {
+        let mut items: Vec<_> = self
+            .0
+            .iter()
+            .map(|(item_id, count)| (item_id.as_str(), *count))
+            .collect();
+        items.sort();
+        items
+    }
+}
+
+#[derive(Debug)]
+pub struct ItemDefinitions;
+
+impl Registry for ItemDefinitions {
+    const REGISTRY_NAME: &'static str = "core:items";
+    type Value = ItemDefinition;
+
+    fn detect_change(old_value: &Self::Value, new_value: &Self::Value) -> bool {
+        old_value != new_value
+    }
+
+    fn emit_change_events() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ItemDefinition {
+    pub kind: ItemKind,
+    #[serde(default)]
+    pub description: String,
+    /// Asset path of the action performed when the item is used in a node,
+    /// if it can be used there
+    #[serde(default)]
+    pub action: Option<String>,
+}
+
+/// Handles to the actions of items that can be used in nodes, by item id.
+/// Kept loaded so that items can be used as soon as a node starts.
+#[derive(Debug, Default, Deref, Resource)]
+pub struct ItemActions(HashMap<String, Handle<Action>>);
+
+impl ItemActions {
+    #[cfg(test)]
+    pub(crate) fn insert(&mut self, item_id: &str, action: Handle<Action>) {
+        self.0.insert(item_id.to_owned(), action);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Reflect, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    /// Opens up something, such as a locked node
+    Key,
+    /// Used up when it is used
+    Consumable,
+    /// Improves the player's programs
+    UpgradeChip,
+}
+
 #[derive(Clone, Debug, Reflect, PartialEq)]
 pub enum Item {
     Card(Handle<CardDefinition>),
-    Mon(u32), // Others?
+    Mon(u32),
+    /// A non-card item that goes in the player's [`Inventory`], by item id
+    Inventory(String),
 }
 
 impl Item {
-    pub fn name(&self, cards: &Assets<CardDefinition>) -> Cow<str> {
+    pub fn name(&self, cards: &Assets<CardDefinition>) -> Cow<'_, str> {
         match self {
             Self::Mon(_) => Cow::from("Mon"),
+            Self::Inventory(item_id) => Cow::from(item_id.as_str()),
             Self::Card(handle) => cards
                 .get(handle)
                 .map(|card_def| Cow::Owned(card_def.id().to_owned()))
@@ -95,9 +206,10 @@ impl Item {
         }
     }
 
-    pub fn description(&self, cards: &Assets<CardDefinition>) -> Cow<str> {
+    pub fn description(&self, cards: &Assets<CardDefinition>) -> Cow<'_, str> {
         match self {
             Self::Mon(_) => Cow::from("Makes the world go round"), // TODO Better money description
+            Self::Inventory(_) => Cow::from("???"), // See ItemDefinition::description
             Self::Card(handle) => cards
                 .get(handle)
                 .map(|card_def| Cow::Owned(card_def.description().to_owned()))
@@ -110,7 +222,7 @@ impl Item {
 
     pub fn actions(&self, cards: &Assets<CardDefinition>) -> Vec<Handle<Action>> {
         match self {
-            Self::Mon(_) => Vec::default(), // TODO Better money description
+            Self::Mon(_) | Self::Inventory(_) => Vec::default(), // TODO Better money description
             Self::Card(handle) => cards
                 .get(handle)
                 .map(|card_def| card_def.actions().clone())
@@ -123,14 +235,14 @@ impl Item {
 
     pub fn speed(&self, cards: &Assets<CardDefinition>) -> Option<u32> {
         match self {
-            Self::Mon(_) => None, // TODO Better money description
+            Self::Mon(_) | Self::Inventory(_) => None, // TODO Better money description
             Self::Card(handle) => cards.get(handle).map(|card_def| card_def.movement_speed()),
         }
     }
 
     pub fn max_size(&self, cards: &Assets<CardDefinition>) -> Option<u32> {
         match self {
-            Self::Mon(_) => None, // TODO Better money description
+            Self::Mon(_) | Self::Inventory(_) => None, // TODO Better money description
             Self::Card(handle) => cards.get(handle).map(|card_def| card_def.max_size()),
         }
     }
@@ -138,18 +250,42 @@ impl Item {
 
 #[derive(Debug, Reflect)]
 pub enum ItemOp {
-    AddItem { item: Item, refund: u32 },
-    GiveItem { item: Item, target: Entity }, // Remove #[non_exhaustive] when we add another enum
-                                             // Give, Drop, Trash?
+    AddItem {
+        item: Item,
+        refund: u32,
+    },
+    /// Gives an item to another player
+    GiveItem {
+        item: Item,
+        target: Entity,
+    },
+    /// Drops an item next to the active curio in the player's node, where it
+    /// can be picked up again
+    DropItem {
+        item: Item,
+    },
+    /// Gets rid of an item for good
+    TrashItem {
+        item: Item,
+    },
 }
 
 impl Op for ItemOp {
     fn register_systems(mut registrar: OpRegistrar<Self>) {
-        registrar.register_op(opsys_add_item);
+        registrar
+            .register_op(opsys_add_item)
+            .register_op(opsys_give_item)
+            .register_op(opsys_drop_item)
+            .register_op(opsys_trash_item);
     }
 
     fn system_index(&self) -> usize {
-        0
+        match self {
+            Self::AddItem { .. } => 0,
+            Self::GiveItem { .. } => 1,
+            Self::DropItem { .. } => 2,
+            Self::TrashItem { .. } => 3,
+        }
     }
 }
 
@@ -157,8 +293,10 @@ pub fn opsys_add_item(
     In((source_id, op)): In<(Entity, ItemOp)>,
     mut commands: Commands,
     res_daddy_card: Res<Daddy<Card>>,
+    res_item_definitions: Res<Reg<ItemDefinitions>>,
     mut q_deck: Query<&mut Deck>,
     mut q_wallet: Query<&mut Wallet>,
+    mut q_inventory: Query<&mut Inventory>,
     q_card: Query<&Handle<CardDefinition>, Without<Nickname>>,
 ) -> OpImplResult {
     if let ItemOp::AddItem { item, refund } = op {
@@ -207,17 +345,156 @@ pub fn opsys_add_item(
                 wallet.increase_mon(mon);
                 Ok(default())
             },
+            Item::Inventory(item_id) => {
+                let interim_result = (|| {
+                    if res_item_definitions.get(&item_id).is_none() {
+                        Err(format!("No such item: {item_id}"))?;
+                    }
+                    let mut inventory = q_inventory.get_mut(source_id).invalid()?;
+                    inventory.add(&item_id, 1);
+                    let mut metadata = Metadata::default();
+                    metadata
+                        .put(key::ITEM_COUNT, inventory.count(&item_id))
+                        .critical()?;
+                    Ok(metadata)
+                })();
+                // Refund them if problems occur
+                if interim_result.is_err() {
+                    let mut wallet = q_wallet.get_mut(source_id).critical()?;
+                    wallet.increase_mon(refund);
+                }
+                interim_result
+            },
         }
     } else {
         Err(OpError::MismatchedOpSystem)
     }
 }
 
+pub fn opsys_give_item(
+    In((source_id, op)): In<(Entity, ItemOp)>,
+    mut q_player: Query<(&mut Wallet, &mut Inventory), With<Player>>,
+) -> OpImplResult {
+    let ItemOp::GiveItem { item, target } = op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    if source_id == target {
+        Err("Can't give items to yourself".invalid())?;
+    }
+    let [(mut source_wallet, mut source_inventory), (mut target_wallet, mut target_inventory)] =
+        q_player
+            .get_many_mut([source_id, target])
+            .map_err(|_| "Can only give items to another player".invalid())?;
+    let mut metadata = Metadata::default();
+    match item {
+        Item::Mon(mon) => {
+            if !source_wallet.try_spend(mon) {
+                Err("Not enough mon".invalid())?;
+            }
+            target_wallet.increase_mon(mon);
+        },
+        Item::Inventory(item_id) => {
+            if !source_inventory.try_remove(&item_id, 1) {
+                Err("You don't have that item".invalid())?;
+            }
+            target_inventory.add(&item_id, 1);
+            metadata
+                .put(key::ITEM_COUNT, source_inventory.count(&item_id))
+                .critical()?;
+        },
+        Item::Card(_) => Err("Cards can't be given away".invalid())?,
+    }
+    Ok(metadata)
+}
+
+pub fn opsys_drop_item(
+    In((source_id, op)): In<(Entity, ItemOp)>,
+    mut commands: Commands,
+    res_item_definitions: Res<Reg<ItemDefinitions>>,
+    mut q_player: Query<
+        (&mut Inventory, AsDerefCopied<InNode>, AsDerefCopied<OnTeam>),
+        With<Player>,
+    >,
+    q_node: Query<(&EntityGrid, AsDerefCopied<ActiveCurio>), With<Node>>,
+    q_curio_team: Query<AsDerefCopied<OnTeam>>,
+) -> OpImplResult {
+    let ItemOp::DropItem { item } = op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    let Item::Inventory(item_id) = item else {
+        return Err("Only inventory items can be dropped".invalid());
+    };
+    let (mut inventory, node_id, team_id) = q_player
+        .get_mut(source_id)
+        .map_err(|_| "Items can only be dropped in a node".invalid())?;
+    let (grid, active_curio) = q_node.get(node_id).critical()?;
+    let curio_id = active_curio
+        .filter(|curio_id| q_curio_team.get(*curio_id).ok() == Some(team_id))
+        .ok_or("Need an active curio to drop items next to".invalid())?;
+    let head = grid
+        .head(curio_id)
+        .ok_or("Active curio not in grid".critical())?;
+    let drop_point = Compass::ALL_DIRECTIONS
+        .into_iter()
+        .map(|dir| head + dir)
+        .find(|&pt| pt != head && !grid.square_is_closed(pt) && grid.item_at(pt).is_none())
+        .ok_or("No room to drop that here".invalid())?;
+    if !inventory.try_remove(&item_id, 1) {
+        Err("You don't have that item".invalid())?;
+    }
+
+    let mut metadata = Metadata::default();
+    let pickup = Pickup::Item(item_id.clone());
+    let description = res_item_definitions
+        .get(&item_id)
+        .map(|item_definition| item_definition.description.clone())
+        .unwrap_or_default();
+    let pickup_id = commands
+        .spawn((
+            Name::new(item_id.clone()),
+            NodePiece::new(pickup.default_diplay_id()),
+            Description::new(description),
+            pickup,
+        ))
+        .set_parent(node_id)
+        .add_to_grid(node_id, vec![drop_point])
+        .id();
+    metadata.put(key::DROP_POINT, drop_point).critical()?;
+    metadata.put(key::PICKUP_ID, pickup_id).critical()?;
+    metadata
+        .put(key::ITEM_COUNT, inventory.count(&item_id))
+        .critical()?;
+    Ok(metadata)
+}
+
+pub fn opsys_trash_item(
+    In((source_id, op)): In<(Entity, ItemOp)>,
+    mut q_inventory: Query<&mut Inventory, With<Player>>,
+) -> OpImplResult {
+    let ItemOp::TrashItem { item } = op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    let Item::Inventory(item_id) = item else {
+        return Err("Only inventory items can be trashed".invalid());
+    };
+    let mut inventory = q_inventory.get_mut(source_id).invalid()?;
+    if !inventory.try_remove(&item_id, 1) {
+        Err("You don't have that item".invalid())?;
+    }
+    let mut metadata = Metadata::default();
+    metadata
+        .put(key::ITEM_COUNT, inventory.count(&item_id))
+        .critical()?;
+    Ok(metadata)
+}
+
 pub fn sys_save_wallet(res_save_data: Res<SaveData>, q_player: Query<&Wallet, With<Player>>) {
     for wallet in q_player.iter() {
         res_save_data
             .put(key::save::WALLET, wallet.mon())
             .expect("wallet should be pretty simple to serialize");
+        let mon = wallet.mon();
+        res_save_data.summarize(move |summary| summary.mon = mon);
     }
 }
 
@@ -231,3 +508,181 @@ pub fn sys_load_wallet(
         }
     }
 }
+
+pub fn sys_load_item_actions(
+    asset_server: Res<AssetServer>,
+    res_item_definitions: Res<Reg<ItemDefinitions>>,
+    mut res_item_actions: ResMut<ItemActions>,
+    mut evr_item_definitions: EventReader<UpdatedRegistryKey<ItemDefinitions>>,
+) {
+    for updated_key in evr_item_definitions.read() {
+        let item_id = updated_key.key();
+        // Only items that are used up can be used in nodes
+        match res_item_definitions
+            .get(item_id)
+            .filter(|item_definition| item_definition.kind == ItemKind::Consumable)
+            .and_then(|item_definition| item_definition.action.as_ref())
+        {
+            Some(action_path) => {
+                res_item_actions
+                    .0
+                    .insert(item_id.to_owned(), asset_server.load(action_path.clone()));
+            },
+            None => {
+                res_item_actions.0.remove(item_id);
+            },
+        }
+    }
+}
+
+pub fn sys_save_inventory(res_save_data: Res<SaveData>, q_player: Query<&Inventory, With<Player>>) {
+    for inventory in q_player.iter() {
+        res_save_data
+            .put(key::save::INVENTORY, inventory)
+            .expect("inventory should be pretty simple to serialize");
+    }
+}
+
+pub fn sys_load_inventory(
+    res_load_data: ResMut<LoadData>,
+    mut q_player: Query<&mut Inventory, With<Player>>,
+) {
+    for mut inventory in q_player.iter_mut() {
+        if let Ok(Some(load_inventory)) = res_load_data.get_optional(key::save::INVENTORY) {
+            *inventory = load_inventory;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::event::Events;
+
+    use super::*;
+    use crate::node::node_testing::TestBattle;
+    use crate::node::NodeOp;
+    use crate::op::{CoreOps, OpResult};
+
+    const POTION: &str = "item:potion";
+
+    fn item_battle() -> TestBattle {
+        let mut battle = TestBattle::new(4, 4);
+        let world = battle.app.world_mut();
+        world.resource_mut::<Reg<ItemDefinitions>>().insert(
+            POTION,
+            ItemDefinition {
+                kind: ItemKind::Consumable,
+                description: String::new(),
+                action: None,
+            },
+        );
+        for player in battle.players.iter() {
+            world.entity_mut(*player).insert(Wallet::new());
+        }
+        battle
+    }
+
+    fn give_potions(battle: &mut TestBattle, player: usize, count: u32) {
+        let player = battle.players[player];
+        battle
+            .app
+            .world_mut()
+            .get_mut::<Inventory>(player)
+            .unwrap()
+            .add(POTION, count);
+    }
+
+    fn potions(battle: &TestBattle, player: usize) -> u32 {
+        battle
+            .get::<Inventory>(battle.players[player])
+            .count(POTION)
+    }
+
+    fn perform_item_op(battle: &mut TestBattle, player: usize, op: ItemOp) -> OpImplResult {
+        let player = battle.players[player];
+        battle
+            .app
+            .world_mut()
+            .resource_mut::<CoreOps>()
+            .request(player, op);
+        battle.update();
+        let mut results: Vec<_> = battle
+            .app
+            .world_mut()
+            .resource_mut::<Events<OpResult<ItemOp>>>()
+            .drain()
+            .collect();
+        assert_eq!(results.len(), 1, "{results:?}");
+        results.remove(0).result
+    }
+
+    #[test]
+    fn test_add_inventory_item() {
+        let mut battle = item_battle();
+        let item = Item::Inventory(POTION.to_owned());
+        let metadata = perform_item_op(&mut battle, 0, ItemOp::AddItem { item, refund: 5 })
+            .expect("adding a defined item should work");
+        assert_eq!(metadata.get_required(key::ITEM_COUNT).unwrap(), 1);
+        assert_eq!(potions(&battle, 0), 1);
+        assert_eq!(battle.get::<Wallet>(battle.players[0]).mon(), 0);
+    }
+
+    #[test]
+    fn test_add_missing_item_refunds() {
+        let mut battle = item_battle();
+        let item = Item::Inventory("item:missing".to_owned());
+        assert!(perform_item_op(&mut battle, 0, ItemOp::AddItem { item, refund: 5 }).is_err());
+        assert_eq!(battle.get::<Wallet>(battle.players[0]).mon(), 5);
+    }
+
+    #[test]
+    fn test_give_item() {
+        let mut battle = item_battle();
+        give_potions(&mut battle, 0, 1);
+        let target = battle.players[1];
+        let give = || ItemOp::GiveItem {
+            item: Item::Inventory(POTION.to_owned()),
+            target,
+        };
+        assert!(perform_item_op(&mut battle, 0, give()).is_ok());
+        assert_eq!(potions(&battle, 0), 0);
+        assert_eq!(potions(&battle, 1), 1);
+        assert!(perform_item_op(&mut battle, 0, give()).is_err());
+        assert_eq!(potions(&battle, 1), 1);
+    }
+
+    #[test]
+    fn test_drop_item_beside_active_curio() {
+        let mut battle = item_battle();
+        give_potions(&mut battle, 0, 2);
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 1, &[]);
+        let drop = || ItemOp::DropItem {
+            item: Item::Inventory(POTION.to_owned()),
+        };
+        assert!(perform_item_op(&mut battle, 0, drop()).is_err());
+
+        battle.perform(0, NodeOp::ActivateCurio { curio_id: curio });
+        let metadata = perform_item_op(&mut battle, 0, drop()).expect("drop should work");
+        let drop_point = metadata.get_required(key::DROP_POINT).unwrap();
+        let pickup_id = metadata.get_required(key::PICKUP_ID).unwrap();
+        assert_eq!(drop_point.x + drop_point.y, 1);
+        assert_eq!(battle.grid().item_at(drop_point), Some(pickup_id));
+        assert!(matches!(
+            battle.get::<Pickup>(pickup_id),
+            Pickup::Item(item_id) if item_id == POTION
+        ));
+        assert_eq!(potions(&battle, 0), 1);
+    }
+
+    #[test]
+    fn test_trash_item() {
+        let mut battle = item_battle();
+        give_potions(&mut battle, 0, 1);
+        let trash = || ItemOp::TrashItem {
+            item: Item::Inventory(POTION.to_owned()),
+        };
+        assert!(perform_item_op(&mut battle, 0, trash()).is_ok());
+        assert_eq!(potions(&battle, 0), 0);
+        assert!(perform_item_op(&mut battle, 0, trash()).is_err());
+    }
+}
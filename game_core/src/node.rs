@@ -1,4 +1,4 @@
-use crate::card::{Action, CardDefinition, Deck};
+use crate::card::{Action, ActionHistory, CardDefinition, Deck};
 use crate::item::{Item, ItemOp};
 use crate::op::{CoreOps, OpPlugin, OpResult};
 use crate::prelude::*;
@@ -7,16 +7,30 @@ use crate::NDitCoreSet;
 mod ai;
 mod node_loading;
 mod node_op;
+mod node_replay;
+mod node_saving;
 mod rule;
 
-pub use ai::{AiThread, NodeBattleIntelligence, SimpleAiCurioOrder};
+#[cfg(test)]
+pub(crate) mod node_testing;
+
+pub use ai::{
+    AiOpResult, AiOpSender, AiPiece, AiThread, LookaheadAi, NodeAiSnapshot, NodeAiStrategies,
+    NodeAiStrategy, NodeBattleIntelligence, SimpleAiCurioOrder,
+};
 use bevy::ecs::entity::{EntityHashMap, EntityMapper, MapEntities};
 use bevy::ecs::reflect::ReflectMapEntities;
 use getset::CopyGetters;
 pub use node_loading::NodeScene;
+pub use node_op::node_op_preview::NodeOpPreview;
 pub use node_op::node_op_undo::NodeUndoStack;
 pub use node_op::NodeOp;
-pub use rule::AccessPointLoadingRule;
+pub use node_replay::{NodeOpRecorder, NodeReplay};
+pub use node_saving::BattleSaveFilter;
+pub use rule::{
+    AccessPointLoadingRule, TeamClock, TimeoutRule, TurnTimeLimit, TurnsEnded, VictoryRule,
+    VictoryRules,
+};
 use serde::{Deserialize, Serialize};
 
 use self::daddy::Daddy;
@@ -27,6 +41,7 @@ pub mod key {
     use super::*;
 
     pub const ALL_TEAM_MEMBERS_READY: Key<bool> = typed_key!("all_team_members_ready");
+    pub const AREA_EFFECTS: Key<Vec<Metadata>> = typed_key!("area_effects");
     pub const CARD: Key<Entity> = typed_key!("card");
     pub const CLOSING_NODE: Key<bool> = typed_key!("closing_node");
     pub const CURIO: Key<Entity> = typed_key!("curio");
@@ -38,6 +53,7 @@ pub mod key {
     pub const REPLACED_SQUARE: Key<bool> = typed_key!("replaced_square");
     pub const REPLACED_SQUARE_NEXT: Key<UVec2> = typed_key!("replaced_square_next");
     pub const NODE_ID: Key<Entity> = typed_key!("node_id");
+    pub const OLD_ACTION_HISTORY: Key<ActionHistory> = typed_key!("old_action_history");
     pub const PICKUP: Key<Pickup> = typed_key!("pickup");
     pub const PICKUPS: Key<Vec<Pickup>> = typed_key!("pickups");
     pub const PICKUP_ID: Key<Entity> = typed_key!("pickup_id");
@@ -49,6 +65,23 @@ pub mod key {
     pub const TARGET_POINT: Key<UVec2> = typed_key!("target_pt");
     pub const UNLOADED_CARD: Key<Entity> = typed_key!("unloaded_card");
     pub const VICTORY_STATUS: Key<VictoryStatus> = typed_key!("victory_status");
+
+    /// Whether a field in node op metadata holds entities, which aren't
+    /// stable between worlds
+    pub fn is_entity_field(field: &str) -> bool {
+        [
+            CARD.name(),
+            CURIO.name(),
+            DEACTIVATED_CURIO.name(),
+            MOVED_PIECES.name(),
+            NODE_ID.name(),
+            PICKUP_ID.name(),
+            RETURNED_CARDS.name(),
+            UNLOADED_CARD.name(),
+            crate::card::key::TARGET_ENTITY.name(),
+        ]
+        .contains(&field)
+    }
 }
 
 #[derive(Debug)]
@@ -70,11 +103,14 @@ impl Plugin for NodePlugin {
             .init_resource::<Daddy<Node>>()
             .register_type::<AccessPoint>()
             .register_type::<ActiveCurio>()
+            .register_type::<Alliances>()
             .register_type::<Curio>()
             .register_type::<CurrentTurn>()
+            .register_type::<EnteringTeam>()
             .register_type::<InNode>()
             .register_type::<IsReadyToGo>()
             .register_type::<IsTapped>()
+            .register_type::<LostSquares>()
             .register_type::<Mon>()
             .register_type::<MovesTaken>()
             .register_type::<Node>()
@@ -92,6 +128,12 @@ impl Plugin for NodePlugin {
             .register_type::<VictoryAward>()
             .register_type::<VictoryStatus>()
             .register_type::<rule::AccessPointLoadingRule>()
+            .register_type::<rule::TeamClock>()
+            .register_type::<rule::TimeoutRule>()
+            .register_type::<rule::TurnTimeLimit>()
+            .register_type::<rule::TurnsEnded>()
+            .register_type::<rule::VictoryRule>()
+            .register_type::<rule::VictoryRules>()
             // Internal collection types need to be registered too
             .register_type::<Vec<Entity>>()
             // .register_type::<HashMap<Entity, VictoryStatus>>()
@@ -100,9 +142,24 @@ impl Plugin for NodePlugin {
             .add_plugins((
                 ai::NodeAiPlugin,
                 node_loading::NodeLoadingPlugin,
+                node_saving::NodeSavingPlugin,
                 node_op::node_op_undo::NodeOpUndoPlugin::default(),
+                node_replay::NodeReplayPlugin,
                 OpPlugin::<NodeOp>::default(),
-            ));
+            ))
+            .add_systems(
+                PreUpdate,
+                rule::sys_tick_team_clocks.in_set(NDitCoreSet::ProcessInputs),
+            )
+            .add_systems(
+                Update,
+                (rule::sys_reset_turn_clocks, rule::sys_apply_victory_rules)
+                    .in_set(NDitCoreSet::PostProcessCommands),
+            )
+            .add_systems(
+                PostUpdate,
+                (rule::sys_setup_turns_ended, rule::sys_setup_team_clocks),
+            );
 
         if self.always_award_pickups {
             app.add_systems(
@@ -131,9 +188,40 @@ impl MapEntities for AccessPoint {
 
 /// Indicates the current curio performing moving and/or performing an action
 #[derive(Component, Debug, Default, Deref, DerefMut, Reflect)]
-#[reflect(Component)]
+#[reflect(Component, MapEntities)]
 pub struct ActiveCurio(pub Option<Entity>);
 
+impl MapEntities for ActiveCurio {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        if let Some(id) = self.0 {
+            self.0 = Some(entity_mapper.map_entity(id))
+        }
+    }
+}
+
+/// Team component listing the other teams in the node this team is allied
+/// with. Allied teams are treated as allies when targeting actions, and win
+/// together.
+#[derive(Clone, Component, Debug, Default, Deref, DerefMut, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct Alliances(pub Vec<Entity>);
+
+impl Alliances {
+    pub fn is_allied_with(&self, team: Entity) -> bool {
+        self.0.contains(&team)
+    }
+}
+
+impl MapEntities for Alliances {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        self.0 = self
+            .0
+            .iter()
+            .map(|id| entity_mapper.map_entity(*id))
+            .collect();
+    }
+}
+
 /// Indicates a pickup has been claimed by a player
 #[derive(Component, CopyGetters, Debug, Reflect)]
 #[reflect(Component, MapEntities)]
@@ -209,6 +297,14 @@ impl MapEntities for CurrentTurn {
 #[reflect(Component, Serialize, Deserialize)]
 pub struct EnteringNode(pub NodeId);
 
+/// Indicates which of the node's team slots this player wants to join when
+/// entering it. Players without it join the first team.
+#[derive(
+    Clone, Component, Copy, Debug, Default, Deref, DerefMut, Deserialize, Reflect, Serialize,
+)]
+#[reflect(Component, Serialize, Deserialize)]
+pub struct EnteringTeam(pub usize);
+
 /// Indicates this Player is in the specified node
 #[derive(Clone, Component, Debug, Default, Deref, DerefMut, Deserialize, Reflect, Serialize)]
 #[reflect(Component, Serialize, Deserialize)]
@@ -257,6 +353,12 @@ pub struct Mon(pub u32);
 #[reflect(Component)]
 pub struct MovesTaken(pub u32);
 
+/// Squares a curio has lost to damage, most recent last, so healing can grow
+/// it back along them
+#[derive(Clone, Component, Debug, Default, Deref, DerefMut, Reflect)]
+#[reflect(Component)]
+pub struct LostSquares(pub Vec<UVec2>);
+
 /// Indicates a Node entity
 /// Note that multiple Node instances at runtime can exist for the same [NodeId],
 /// so that multiple players can play different instances of the same node.
@@ -372,7 +474,7 @@ impl MapEntities for OnTeam {
 pub enum Pickup {
     Mon(Mon),     // Money
     Card(String), // A new card to play
-    Item(Entity), // A non-card item
+    Item(String), // A non-card item, by item id
     #[default]
     MacGuffin, // A token of some sort, usually a victory condition
 }
@@ -391,10 +493,28 @@ impl Pickup {
 /// Player Component, indicates which cards they've played already.
 ///
 /// Contains a list of cards and where they have been played to.
-#[derive(Component, Debug, Default, Reflect)]
+#[derive(Clone, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component)]
 pub struct PlayedCards(HashMap<Entity, Vec<Entity>>);
 
+impl MapEntities for PlayedCards {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        self.0 = self
+            .0
+            .drain()
+            .map(|(card_id, location_list)| {
+                (
+                    entity_mapper.map_entity(card_id),
+                    location_list
+                        .into_iter()
+                        .map(|location| entity_mapper.map_entity(location))
+                        .collect(),
+                )
+            })
+            .collect();
+    }
+}
+
 impl PlayedCards {
     fn num_played(&self, card_id: Entity) -> u32 {
         self.0
@@ -463,16 +583,39 @@ pub struct PreventNoOp;
 #[reflect(Component)]
 pub struct Team;
 
-/// Originally used to color curios by team color. Might
-/// change it later so that a team's pieces are outlined
-/// in a color. Might also change it to just use a color
-/// definition from Charmi.
-#[derive(Component, Debug, Default, Reflect)]
+/// Colors a team's curios. Might change it later so that a
+/// team's pieces are outlined in a color. Might also change
+/// it to just use a color definition from Charmi.
+///
+/// Teams without a color are given one from [`TeamColor::PALETTE`] by
+/// their position in the node's [`Teams`].
+#[derive(Clone, Component, Copy, Debug, Default, Eq, PartialEq, Reflect)]
 #[reflect(Component)]
 pub enum TeamColor {
     #[default]
     Red,
     Blue,
+    Green,
+    Yellow,
+    Magenta,
+    Cyan,
+}
+
+impl TeamColor {
+    pub const PALETTE: [TeamColor; 6] = [
+        TeamColor::Red,
+        TeamColor::Blue,
+        TeamColor::Green,
+        TeamColor::Yellow,
+        TeamColor::Magenta,
+        TeamColor::Cyan,
+    ];
+
+    /// The palette color for a team slot, wrapping around if there are more
+    /// teams than colors
+    pub fn for_slot(slot: usize) -> Self {
+        Self::PALETTE[slot % Self::PALETTE.len()]
+    }
 }
 
 /// Indicates what phase of the game the team is in.
@@ -503,7 +646,7 @@ impl MapEntities for TeamStatus {
 }
 
 impl TeamStatus {
-    fn is_decided(&self, team_id: Entity) -> bool {
+    pub fn is_decided(&self, team_id: Entity) -> bool {
         if let Some(status) = self.0.get(&team_id) {
             status.is_decided()
         } else {
@@ -517,6 +660,17 @@ impl TeamStatus {
 #[reflect(Component, MapEntities)]
 pub struct Teams(pub Vec<Entity>);
 
+impl Teams {
+    /// The team after this one in turn order that hasn't won or lost yet,
+    /// which may be this team itself
+    pub fn next_undecided(&self, team: Entity, team_status: &TeamStatus) -> Option<Entity> {
+        let team_index = self.0.iter().position(|team_id| *team_id == team)?;
+        (1..=self.0.len())
+            .map(|offset| self.0[(team_index + offset) % self.0.len()])
+            .find(|team_id| !team_status.is_decided(*team_id))
+    }
+}
+
 impl MapEntities for Teams {
     fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
         self.0 = self
@@ -585,9 +739,7 @@ pub fn sys_grant_pickups_on_node_exit(
                             Pickup::MacGuffin => {
                                 continue;
                             },
-                            Pickup::Item(_item_id) => {
-                                unimplemented!()
-                            },
+                            Pickup::Item(item_id) => Item::Inventory(item_id),
                         };
                         res_core_ops.request(*source, ItemOp::AddItem { item, refund: 0 });
                     }
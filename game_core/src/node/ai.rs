@@ -1,13 +1,22 @@
+mod lookahead;
+mod strategies;
+
 use std::sync::mpsc::{Receiver, SendError, Sender, TryRecvError};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use bevy::ecs::query::QueryData;
 use bevy::tasks::{AsyncComputeTaskPool, Task};
 use bevy::time::Time;
 
-use super::{Curio, CurrentTurn, Node, NodeOp, NodePiece, OnTeam};
-use crate::card::{Action, ActionEffect, ActionRange, Actions, MovementSpeed, NO_OP_ACTION_ID};
+pub use lookahead::LookaheadAi;
+
+use super::{
+    Alliances, Curio, CurrentTurn, LostSquares, Node, NodeOp, NodePiece, OnTeam, Team, Teams,
+};
+use crate::card::{
+    Action, ActionHistory, Actions, MaximumSize, MovementSpeed, PrereqQ, StatusEffects, Tags,
+};
 use crate::op::CoreOps;
 use crate::player::Player;
 use crate::prelude::*;
@@ -23,6 +32,13 @@ impl Plugin for NodeAiPlugin {
             .add_systems(PostUpdate, sys_ai_setup)
             .register_type::<NodeBattleIntelligence>()
             .register_type::<SimpleAiCurioOrder>();
+        app.world_mut()
+            .get_resource_or_insert_with(NodeAiStrategies::default)
+            .register("DoNothing", strategies::DoNothing)
+            .register("Lazy", strategies::LazyAi)
+            .register("Simple", strategies::SimpleAi)
+            .register("Nightfall", strategies::NightfallAi)
+            .register("Lookahead", LookaheadAi::default());
     }
 }
 
@@ -30,7 +46,7 @@ impl Plugin for NodeAiPlugin {
 #[reflect(Component)]
 pub struct SimpleAiCurioOrder(pub usize);
 
-#[derive(Clone, Component, Copy, Debug, Default, Reflect)]
+#[derive(Clone, Component, Debug, Default, Reflect)]
 #[reflect(Component)]
 pub enum NodeBattleIntelligence {
     #[default]
@@ -38,6 +54,20 @@ pub enum NodeBattleIntelligence {
     Lazy,
     Simple,
     Nightfall,
+    /// Any strategy registered in [`NodeAiStrategies`], by name
+    Strategy(String),
+}
+
+impl NodeBattleIntelligence {
+    pub fn strategy_name(&self) -> &str {
+        match self {
+            Self::DoNothing => "DoNothing",
+            Self::Lazy => "Lazy",
+            Self::Simple => "Simple",
+            Self::Nightfall => "Nightfall",
+            Self::Strategy(name) => name.as_str(),
+        }
+    }
 }
 
 #[derive(Component, Debug, Default, DerefMut, Deref)]
@@ -101,389 +131,192 @@ fn sys_ai_apply(
     }
 }
 
+/// A strategy an AI player uses to take its turn in a node battle.
+///
+/// Strategies run on a background task with a snapshot of the node taken at
+/// the start of the turn. They send the ops to perform in order, along with
+/// how long to pause after each one. The turn ends when the strategy returns.
+pub trait NodeAiStrategy: std::fmt::Debug + Send + Sync + 'static {
+    fn take_turn(&self, snapshot: &NodeAiSnapshot, sx: &AiOpSender) -> AiOpResult;
+}
+
+pub type AiOpSender = Sender<(NodeOp, Duration)>;
+pub type AiOpResult = Result<(), SendError<(NodeOp, Duration)>>;
+
+/// Strategies that `NodeBattleIntelligence` can refer to by name
+#[derive(Debug, Default, Resource)]
+pub struct NodeAiStrategies(HashMap<String, Arc<dyn NodeAiStrategy>>);
+
+impl NodeAiStrategies {
+    pub fn register<S: NodeAiStrategy>(&mut self, name: &str, strategy: S) -> &mut Self {
+        self.0.insert(name.to_owned(), Arc::new(strategy));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn NodeAiStrategy>> {
+        self.0.get(name).cloned()
+    }
+}
+
+/// Read-only view of a node at the start of an AI player's turn
+#[derive(Clone, Debug)]
+pub struct NodeAiSnapshot {
+    pub player: Entity,
+    pub team: Entity,
+    pub grid: EntityGrid,
+    pub my_pieces: Vec<AiPiece>,
+    /// Pieces of other teams allied with this one
+    pub ally_pieces: Vec<AiPiece>,
+    pub enemy_pieces: Vec<AiPiece>,
+    /// Alliances of every team in the node, by team
+    pub alliances: HashMap<Entity, Alliances>,
+    /// Definitions of every action available to pieces in the snapshot, by id
+    pub actions: HashMap<String, Action>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AiPiece {
+    pub id: Entity,
+    pub team: Entity,
+    pub actions: Vec<String>,
+    /// Moves the piece can make this turn, accounting for status effects
+    pub movement_speed: u32,
+    pub max_size: u32,
+    pub ai_order: usize,
+    pub lost_squares: Vec<UVec2>,
+    pub tags: Tags,
+    pub status_effects: StatusEffects,
+    pub action_history: ActionHistory,
+}
+
+impl NodeAiSnapshot {
+    pub fn action(&self, action_id: &str) -> Option<&Action> {
+        self.actions.get(action_id)
+    }
+
+    pub fn alliances_of(&self, team: Entity) -> Option<&Alliances> {
+        self.alliances.get(&team)
+    }
+}
+
 #[derive(QueryData)]
 struct PieceQ {
     id: Entity,
+    team: AsDerefCopied<OnTeam>,
     actions: AsDerefClonedOrDefault<Actions>,
     movement: Option<&'static MovementSpeed>,
+    max_size: Option<&'static MaximumSize>,
+    status_effects: Option<&'static StatusEffects>,
+    lost_squares: Option<&'static LostSquares>,
+    tags: Option<&'static Tags>,
+    action_history: Option<&'static ActionHistory>,
     ai_order: OrUsize<AsDerefCopied<SimpleAiCurioOrder>, 30>,
 }
 
-#[derive(QueryData)]
-struct ActionQ {
-    range: Copied<ActionRange>,
-    effect: Cloned<ActionEffect>,
-}
-
 fn sys_ai(
     ast_actions: Res<Assets<Action>>,
+    res_strategies: Res<NodeAiStrategies>,
     mut ai_players: IndexedQuery<
         OnTeam,
         (Entity, &NodeBattleIntelligence, AsDerefMut<AiThread>),
         With<Player>,
     >,
     changed_turn_nodes: Query<
-        (AsDerefCopied<CurrentTurn>, &EntityGrid),
+        (AsDerefCopied<CurrentTurn>, &EntityGrid, AsDeref<Teams>),
         (Changed<CurrentTurn>, With<Node>),
     >,
-    pieces: Query<(AsDerefCopied<OnTeam>, PieceQ), (With<NodePiece>, With<Curio>)>,
+    team_alliances: Query<&Alliances, With<Team>>,
+    pieces: Query<PieceQ, (With<NodePiece>, With<Curio>)>,
+    prereqs: Query<PrereqQ, With<Curio>>,
 ) {
     let task_pool = AsyncComputeTaskPool::get();
 
-    for (current_turn, grid) in changed_turn_nodes.iter() {
+    for (current_turn, grid, teams) in changed_turn_nodes.iter() {
         if let Ok((id, intelligence, mut ai_thread)) = ai_players.get_for_mut(current_turn) {
-            match intelligence {
-                NodeBattleIntelligence::DoNothing => {
-                    let (sx, rx) = std::sync::mpsc::channel();
-                    *ai_thread = Some(AiThreadInternal {
-                        handle: task_pool.spawn(async {
-                            std::thread::sleep(Duration::from_secs(3));
-                            let _ = sx;
-                        }),
-                        events: Mutex::new(rx),
-                        pause_until: default(),
-                    });
-                },
-                NodeBattleIntelligence::Lazy => {
-                    let (sx, rx) = std::sync::mpsc::channel();
-                    let mut actions = HashMap::new();
-                    let my_pieces: Vec<(Entity, Vec<String>)> = pieces
-                        .iter()
-                        .filter_map(|(team, piece)| {
-                            if team != current_turn {
+            let strategy_name = intelligence.strategy_name();
+            let strategy = res_strategies.get(strategy_name).unwrap_or_else(|| {
+                log::error!("No AI strategy named [{strategy_name}], doing nothing");
+                Arc::new(strategies::DoNothing)
+            });
+            let alliances: HashMap<Entity, Alliances> = teams
+                .iter()
+                .filter_map(|team| Some((*team, team_alliances.get(*team).ok()?.clone())))
+                .collect();
+            let my_alliances = alliances.get(&current_turn);
+            let mut actions: HashMap<Handle<Action>, Option<Action>> = HashMap::new();
+            let (my_pieces, other_pieces): (Vec<AiPiece>, Vec<AiPiece>) = pieces
+                .iter()
+                .filter(|piece| grid.contains_key(piece.id))
+                .map(|piece| {
+                    let piece_actions = piece
+                        .actions
+                        .into_iter()
+                        .filter_map(|action| {
+                            let definition = actions
+                                .entry(action)
+                                .or_insert_with_key(|action| ast_actions.get(action).cloned())
+                                .as_ref()?;
+                            // Actions that can't be used no matter the target are left out
+                            if definition
+                                .unsatisfied_prereq(grid, piece.id, None, &prereqs)
+                                .is_some()
+                            {
                                 return None;
                             }
-                            let piece_actions = piece
-                                .actions
-                                .clone()
-                                .into_iter()
-                                .filter_map(|action| {
-                                    let definition = actions
-                                        .entry(action)
-                                        .or_insert_with_key(|action| {
-                                            ast_actions.get(action).cloned()
-                                        })
-                                        .as_ref()?;
-                                    Some(definition.id().to_owned())
-                                })
-                                .collect();
-                            Some((piece.id, piece_actions))
+                            Some(definition.id().to_owned())
                         })
                         .collect();
-                    let enemy_pieces: Vec<Entity> = pieces
-                        .iter()
-                        .filter_map(|(team, piece)| (team != current_turn).then_some(piece.id))
-                        .collect();
-                    let grid = grid.clone();
-                    let actions: HashMap<String, Action> = actions
-                        .into_iter()
-                        .filter_map(|(_, v)| v.map(|v| (v.id().to_owned(), v)))
-                        .collect();
-
-                    *ai_thread = Some(AiThreadInternal {
-                        pause_until: default(),
-                        events: Mutex::new(rx),
-                        handle: task_pool.spawn(async move {
-                            lazy_ai_script(id, actions, sx, grid, my_pieces, enemy_pieces);
-                        }),
-                    });
-                },
-                NodeBattleIntelligence::Nightfall | NodeBattleIntelligence::Simple => {
-                    let (sx, rx) = std::sync::mpsc::channel();
-                    let mut actions = HashMap::new();
-                    let my_pieces: Vec<(Entity, Vec<String>, Option<MovementSpeed>, usize)> =
-                        pieces
-                            .iter()
-                            .filter_map(|(team, piece)| {
-                                if team != current_turn {
-                                    return None;
-                                }
-                                let piece_actions = piece
-                                    .actions
-                                    .clone()
-                                    .into_iter()
-                                    .filter_map(|action| {
-                                        let definition = actions
-                                            .entry(action)
-                                            .or_insert_with_key(|action| {
-                                                ast_actions.get(action).cloned()
-                                            })
-                                            .as_ref()?;
-                                        Some(definition.id().to_owned())
-                                    })
-                                    .collect();
-                                Some((
-                                    piece.id,
-                                    piece_actions,
-                                    piece.movement.cloned(),
-                                    piece.ai_order,
-                                ))
-                            })
-                            .collect();
-                    let enemy_pieces: Vec<Entity> = pieces
-                        .iter()
-                        .filter_map(|(team, piece)| (team != current_turn).then_some(piece.id))
-                        .collect();
-                    let actions: HashMap<String, Action> = actions
-                        .into_iter()
-                        .filter_map(|(_, v)| v.map(|v| (v.id().to_owned(), v)))
-                        .collect();
-                    let grid = grid.clone();
-
-                    *ai_thread = Some(AiThreadInternal {
-                        pause_until: default(),
-                        events: Mutex::new(rx),
-                        handle: task_pool.spawn(async move {
-                            simple_ai_script(id, actions, sx, grid, my_pieces, enemy_pieces);
-                        }),
-                    });
-                },
-            }
-        }
-    }
-}
-
-// Other scripts for the future:
-// Searches all possible movement spaces for the ability to attack
-// Pathfinds towards nearest piece
-// Search by all actions on all pieces, whichever does the most damage wins
-
-// No pathfinding, simply moves in the direction of the nearest piece until it is within attack distance.
-fn simple_ai_script(
-    id: Entity,
-    actions: HashMap<String, Action>,
-    sx: Sender<(NodeOp, Duration)>,
-    mut grid: EntityGrid,
-    mut my_pieces: Vec<(Entity, Vec<String>, Option<MovementSpeed>, usize)>,
-    enemy_pieces: Vec<Entity>,
-) {
-    if let Err(SendError(_)) = (move || {
-        std::thread::sleep(Duration::from_millis(350));
-        my_pieces.sort_by_key(|piece| piece.3);
-        for piece in my_pieces {
-            let mut grid_head = match grid.head(piece.0) {
-                Some(grid_head) => grid_head,
-                None => continue,
-            };
-            sx.send((
-                NodeOp::ActivateCurio { curio_id: piece.0 },
-                Duration::from_millis(500),
-            ))?;
-            if let Some(closest_enemy_pt) = enemy_pieces
-                .iter()
-                .flat_map(|id| grid.points(*id))
-                .min_by_key(|pt| pt.manhattan_distance(&grid_head))
-            {
-                log::trace!(
-                    "Closest enemy point for piece[{:?}] is {:?}",
-                    piece.1,
-                    closest_enemy_pt
-                );
-                let movement_speed = piece.2.as_ref().map(|ms| **ms).unwrap_or(0);
-                for _ in 0..movement_speed {
-                    match grid_head.dirs_to(&closest_enemy_pt) {
-                        [Some(dir1), Some(dir2)] => {
-                            // Choose which to prioritize, then try one, and if it fails, the other.
-                            let (dir1, dir2) = if grid_head
-                                .dist_to_pt_along_compass(&closest_enemy_pt, dir1)
-                                > grid_head.dist_to_pt_along_compass(&closest_enemy_pt, dir2)
-                            {
-                                (dir1, dir2)
-                            } else {
-                                (dir2, dir1)
-                            };
-                            if grid.square_is_free(grid_head + dir1)
-                                || grid.item_at(grid_head + dir1) == Some(piece.0)
-                            {
-                                log::trace!("{:?} Went {:?} from {:?}", piece.0, dir1, grid_head);
-                                grid_head = grid_head + dir1;
-                                sx.send((
-                                    NodeOp::MoveActiveCurio { dir: dir1 },
-                                    Duration::from_millis(400),
-                                ))?;
-                            } else if grid.square_is_free(grid_head + dir2)
-                                || grid.item_at(grid_head + dir2) == Some(piece.0)
-                            {
-                                log::trace!("{:?} Went {:?} from {:?}", piece.0, dir2, grid_head);
-                                grid_head = grid_head + dir2;
-                                sx.send((
-                                    NodeOp::MoveActiveCurio { dir: dir2 },
-                                    Duration::from_millis(400),
-                                ))?;
-                            } else {
-                                log::trace!(
-                                    "{:?} can't go {:?} OR {:?} from {:?}",
-                                    piece.0,
-                                    dir1,
-                                    dir2,
-                                    grid_head
-                                );
-                                break;
-                            }
-                        },
-                        [Some(dir), None] => {
-                            // if dir is not blocked ,go that way
-                            if grid.square_is_free(grid_head + dir)
-                                || grid.item_at(grid_head + dir) == Some(id)
-                            {
-                                log::trace!("{:?} Went {:?} from {:?}", piece.0, dir, grid_head);
-                                grid_head = grid_head + dir;
-                                sx.send((
-                                    NodeOp::MoveActiveCurio { dir },
-                                    Duration::from_millis(400),
-                                ))?;
-                            } else {
-                                log::trace!(
-                                    "{:?} can't go {:?} from {:?}",
-                                    piece.0,
-                                    dir,
-                                    grid_head
-                                );
-                                break;
-                            }
-                        },
-                        [None, None] => {
-                            panic!("Somehow the grid points became super imposed on each other")
-                        },
-                        [None, Some(_)] => unreachable!(),
+                    let movement_penalty = piece
+                        .status_effects
+                        .map(StatusEffects::movement_penalty)
+                        .unwrap_or(0);
+                    AiPiece {
+                        id: piece.id,
+                        team: piece.team,
+                        actions: piece_actions,
+                        movement_speed: piece
+                            .movement
+                            .map(|ms| ms.saturating_sub(movement_penalty))
+                            .unwrap_or(0),
+                        max_size: piece.max_size.map(|ms| **ms).unwrap_or(1),
+                        ai_order: piece.ai_order,
+                        lost_squares: piece
+                            .lost_squares
+                            .map(|lost_squares| lost_squares.0.clone())
+                            .unwrap_or_default(),
+                        tags: piece.tags.cloned().unwrap_or_default(),
+                        status_effects: piece.status_effects.cloned().unwrap_or_default(),
+                        action_history: piece.action_history.cloned().unwrap_or_default(),
                     }
-                }
-            }
-
-            if let Some((action_id, target, target_id, dmg)) =
-                piece.1.into_iter().find_map(|action| {
-                    let action = actions.get(&action)?;
-                    // TODO When ops improves, use op logic instead of recreating here
-                    for effect in action.effects() {
-                        match effect {
-                            // NOTE: This could be bugged behavior if action has other effect
-                            ActionEffect::Damage(dmg) => {
-                                for enemy_piece in enemy_pieces.iter() {
-                                    let enemy_squares = grid.points(*enemy_piece);
-                                    let range = action
-                                        .range()
-                                        .expect("damage effect should have range on action");
-                                    if let Some(point_in_range) =
-                                        range.pt_in_range(enemy_squares, grid_head)
-                                    {
-                                        return Some((
-                                            action.id_cow(),
-                                            point_in_range,
-                                            enemy_piece,
-                                            *dmg,
-                                        ));
-                                    }
-                                }
-                            },
-                            _ => {},
-                        }
-                    }
-                    None
                 })
-            {
-                sx.send((
-                    NodeOp::TelegraphAction {
-                        action_id: action_id.clone(),
-                    },
-                    Duration::from_millis(500),
-                ))?;
-                sx.send((
-                    NodeOp::PerformCurioAction {
-                        action_id,
-                        curio: None,
-                        target,
-                    },
-                    Duration::from_secs(2),
-                ))?;
-                grid.pop_back_n(*target_id, dmg);
-            } else {
-                sx.send((
-                    NodeOp::PerformCurioAction {
-                        action_id: NO_OP_ACTION_ID,
-                        curio: None,
-                        target: UVec2::default(),
-                    },
-                    Duration::from_millis(500),
-                ))?;
-            }
-        }
-        Ok(())
-    })() {
-        log::error!("AI thread unexpected closed!")
-    }
-}
-
-// Attacks whatever is nearby, no movement
-fn lazy_ai_script(
-    _id: Entity,
-    actions: HashMap<String, Action>,
-    sx: Sender<(NodeOp, Duration)>,
-    mut grid: EntityGrid,
-    my_pieces: Vec<(Entity, Vec<String>)>,
-    enemy_pieces: Vec<Entity>,
-) {
-    if let Err(SendError(_)) = (move || {
-        std::thread::sleep(Duration::from_millis(350));
-        for piece in my_pieces {
-            sx.send((
-                NodeOp::ActivateCurio { curio_id: piece.0 },
-                Duration::from_millis(500),
-            ))?;
-            if let Some((action, target)) = piece.1.into_iter().find_map(|action| {
-                let action = actions.get(&action)?;
-                for effect in action.effects() {
-                    match effect {
-                        ActionEffect::Damage(_) => {
-                            let head = grid.head(piece.0).expect("ai piece should have a head");
-                            for enemy_piece in enemy_pieces.iter() {
-                                let enemy_squares = grid.points(*enemy_piece);
-                                let range = action
-                                    .range()
-                                    .expect("damage effect should have range on action");
-                                if let Some(point_in_range) = range.pt_in_range(enemy_squares, head)
-                                {
-                                    let dmg = action
-                                        .effects()
-                                        .iter()
-                                        .map(|effect| {
-                                            if let ActionEffect::Damage(dmg) = effect {
-                                                *dmg
-                                            } else {
-                                                0
-                                            }
-                                        })
-                                        .sum();
-                                    grid.pop_back_n(*enemy_piece, dmg);
-                                    return Some((action.id_cow(), point_in_range));
-                                }
-                            }
-                        },
-                        _ => {},
+                .partition(|piece| piece.team == current_turn);
+            let (ally_pieces, enemy_pieces) = other_pieces.into_iter().partition(|piece| {
+                my_alliances.is_some_and(|alliances| alliances.is_allied_with(piece.team))
+            });
+            let snapshot = NodeAiSnapshot {
+                player: id,
+                team: current_turn,
+                grid: grid.clone(),
+                my_pieces,
+                ally_pieces,
+                enemy_pieces,
+                alliances,
+                actions: actions
+                    .into_values()
+                    .flatten()
+                    .map(|action| (action.id().to_owned(), action))
+                    .collect(),
+            };
+            let (sx, rx) = std::sync::mpsc::channel();
+            *ai_thread = Some(AiThreadInternal {
+                pause_until: default(),
+                events: Mutex::new(rx),
+                handle: task_pool.spawn(async move {
+                    if let Err(SendError(_)) = strategy.take_turn(&snapshot, &sx) {
+                        log::error!("AI thread unexpected closed!")
                     }
-                }
-                None
-            }) {
-                sx.send((
-                    NodeOp::PerformCurioAction {
-                        action_id: action,
-                        curio: None,
-                        target,
-                    },
-                    Duration::from_secs(2),
-                ))?;
-            } else {
-                sx.send((
-                    NodeOp::PerformCurioAction {
-                        action_id: NO_OP_ACTION_ID,
-                        curio: None,
-                        target: UVec2::default(),
-                    },
-                    Duration::from_millis(500),
-                ))?;
-            }
+                }),
+            });
         }
-        Ok(())
-    })() {
-        log::error!("AI thread unexpected closed!")
     }
 }
@@ -0,0 +1,574 @@
+use std::time::{Duration, Instant};
+
+use super::{AiOpResult, AiOpSender, AiPiece, NodeAiSnapshot, NodeAiStrategy};
+use crate::card::{
+    Action, ActionHistory, CurioEffectAccess, CurioEffectProps, CurioPrereqAccess,
+    CurioPrereqProps, StatusEffects, Tags, NO_OP_ACTION_ID,
+};
+use crate::node::node_op::{advance_curio, check_action_target};
+use crate::node::{Alliances, NodeOp};
+use crate::prelude::*;
+
+/// Score for each curio still in the battle, on top of its size
+const CURIO_VALUE: i64 = 50;
+const SQUARE_VALUE: i64 = 10;
+
+/// Plans each curio's move by simulating the plans of the curios that act
+/// after it, its allies and then its enemies, and picking the one that leads
+/// to the best position. Enemies are assumed to pick whatever is worst for
+/// this AI.
+///
+/// Searches one curio deeper at a time until it reaches `depth` or runs out
+/// of `time_budget`, keeping the result of the deepest finished search.
+#[derive(Debug)]
+pub struct LookaheadAi {
+    /// How many curio turns to look ahead, including the one being planned
+    pub depth: usize,
+    /// How long to spend planning the whole turn
+    pub time_budget: Duration,
+}
+
+impl LookaheadAi {
+    pub fn new(depth: usize, time_budget: Duration) -> Self {
+        LookaheadAi { depth, time_budget }
+    }
+}
+
+impl Default for LookaheadAi {
+    fn default() -> Self {
+        LookaheadAi::new(3, Duration::from_secs(2))
+    }
+}
+
+impl NodeAiStrategy for LookaheadAi {
+    fn take_turn(&self, snapshot: &NodeAiSnapshot, sx: &AiOpSender) -> AiOpResult {
+        let mut sim = SimNode::new(snapshot);
+        let mut my_pieces: Vec<&AiPiece> = snapshot.my_pieces.iter().collect();
+        my_pieces.sort_by_key(|piece| piece.ai_order);
+        let my_pieces: Vec<Entity> = my_pieces.into_iter().map(|piece| piece.id).collect();
+        let enemy_pieces: Vec<Entity> = snapshot.enemy_pieces.iter().map(|p| p.id).collect();
+        if my_pieces.is_empty() || enemy_pieces.is_empty() {
+            return Ok(());
+        }
+        let time_budget = self.time_budget / my_pieces.len() as u32;
+        for (i, &piece) in my_pieces.iter().enumerate() {
+            if !sim.grid.contains_key(piece) {
+                continue;
+            }
+            // Curios that act after this one, looping around into future turns
+            let mut order = my_pieces[i..].to_vec();
+            while order.len() < self.depth + my_pieces.len() + enemy_pieces.len() {
+                order.extend(enemy_pieces.iter().copied());
+                order.extend(my_pieces.iter().copied());
+            }
+            let search = Search {
+                actions: &snapshot.actions,
+                team: snapshot.team,
+                order,
+                deadline: Instant::now() + time_budget,
+            };
+            let (plan, next_sim) = search.best_plan(&sim, self.depth);
+            sx.send((
+                NodeOp::ActivateCurio { curio_id: piece },
+                Duration::from_millis(500),
+            ))?;
+            send_plan(sx, &plan)?;
+            sim = next_sim;
+        }
+        Ok(())
+    }
+}
+
+/// What a curio does with its turn: where it moves, and what it targets
+/// with which action once it gets there
+#[derive(Clone, Debug, Default)]
+struct Plan {
+    path: Vec<Compass>,
+    action: Option<(String, UVec2)>,
+}
+
+#[derive(Clone, Debug)]
+struct SimCurio {
+    team: Entity,
+    actions: Vec<String>,
+    /// Moves per turn, with status effects from the snapshot already applied
+    movement_speed: u32,
+    /// Moves taken by the plan being played out
+    moves_taken: u32,
+    max_size: u32,
+    lost_squares: Vec<UVec2>,
+    tags: Tags,
+    status_effects: StatusEffects,
+    action_history: ActionHistory,
+}
+
+/// A copy of a node battle that plans can be tried out on without ops
+#[derive(Clone, Debug)]
+struct SimNode {
+    grid: EntityGrid,
+    curios: HashMap<Entity, SimCurio>,
+    alliances: HashMap<Entity, Alliances>,
+}
+
+impl CurioEffectAccess for HashMap<Entity, SimCurio> {
+    fn effect_props(&mut self, curio: Entity) -> Option<CurioEffectProps<'_>> {
+        let sim_curio = self.get_mut(&curio)?;
+        Some(CurioEffectProps {
+            max_size: (&mut sim_curio.max_size).into(),
+            movement_speed: (&mut sim_curio.movement_speed).into(),
+            lost_squares: Some((&mut sim_curio.lost_squares).into()),
+            tags: Some((&mut sim_curio.tags).into()),
+            status_effects: Some((&mut sim_curio.status_effects).into()),
+        })
+    }
+}
+
+impl CurioPrereqAccess for HashMap<Entity, SimCurio> {
+    fn prereq_props(&self, curio: Entity) -> Option<CurioPrereqProps<'_>> {
+        let sim_curio = self.get(&curio)?;
+        Some(CurioPrereqProps {
+            max_size: Some(sim_curio.max_size),
+            moves_left: sim_curio
+                .movement_speed
+                .saturating_sub(sim_curio.moves_taken),
+            tags: Some(&sim_curio.tags),
+            action_history: Some(&sim_curio.action_history),
+        })
+    }
+}
+
+impl SimNode {
+    fn new(snapshot: &NodeAiSnapshot) -> Self {
+        let curios = snapshot
+            .my_pieces
+            .iter()
+            .chain(snapshot.ally_pieces.iter())
+            .chain(snapshot.enemy_pieces.iter())
+            .map(|piece| {
+                let sim_curio = SimCurio {
+                    team: piece.team,
+                    actions: piece.actions.clone(),
+                    movement_speed: piece.movement_speed,
+                    moves_taken: 0,
+                    max_size: piece.max_size,
+                    lost_squares: piece.lost_squares.clone(),
+                    tags: piece.tags.clone(),
+                    status_effects: piece.status_effects.clone(),
+                    action_history: piece.action_history.clone(),
+                };
+                (piece.id, sim_curio)
+            })
+            .collect();
+        SimNode {
+            grid: snapshot.grid.clone(),
+            curios,
+            alliances: snapshot.alliances.clone(),
+        }
+    }
+
+    fn team_of(&self, curio: Entity) -> Option<Entity> {
+        self.curios.get(&curio).map(|sim_curio| sim_curio.team)
+    }
+
+    fn alliances_of_curio(&self, curio: Entity) -> Option<&Alliances> {
+        self.alliances.get(&self.team_of(curio)?)
+    }
+
+    fn is_allied(&self, team: Entity, other_team: Entity) -> bool {
+        team == other_team
+            || self
+                .alliances
+                .get(&team)
+                .is_some_and(|alliances| alliances.is_allied_with(other_team))
+    }
+
+    /// Plays out a plan, returning false if any step of it isn't possible
+    fn apply_plan(
+        &mut self,
+        curio: Entity,
+        plan: &Plan,
+        actions: &HashMap<String, Action>,
+    ) -> bool {
+        let Some(sim_curio) = self.curios.get_mut(&curio) else {
+            return false;
+        };
+        // The moves are all made before the action, so prerequisites see them
+        sim_curio.moves_taken = plan.path.len() as u32;
+        let max_size = sim_curio.max_size;
+        for dir in plan.path.iter() {
+            let Some(head) = self.grid.head(curio) else {
+                return false;
+            };
+            let next_pt = head + *dir;
+            // Pickups aren't part of the simulation, so they are treated as obstacles
+            if next_pt == head || !self.grid.can_move_to(curio, next_pt, |_| false) {
+                return false;
+            }
+            advance_curio(&mut self.grid, curio, next_pt, max_size);
+        }
+        if let Some((action_id, target)) = plan.action.as_ref() {
+            let Some(action) = actions.get(action_id) else {
+                return false;
+            };
+            if !self.can_target(curio, action, *target) {
+                return false;
+            }
+            self.perform_action(curio, action, *target);
+        }
+        true
+    }
+
+    /// Uses the same checks as the action op, including prerequisites
+    fn can_target(&self, curio: Entity, action: &Action, target: UVec2) -> bool {
+        check_action_target(
+            action,
+            &self.grid,
+            curio,
+            target,
+            &self.curios,
+            |id| self.team_of(id),
+            self.alliances_of_curio(curio),
+        )
+        .is_ok()
+    }
+
+    /// Applies the effects of an action in the same order as the action op
+    fn perform_action(&mut self, curio: Entity, action: &Action, target: UVec2) {
+        for effect in action.effects() {
+            let _ = effect.apply_effect(&mut self.grid, curio, target, &mut self.curios);
+        }
+        let area_targets = action.area_targets(
+            &self.grid,
+            curio,
+            target,
+            |id| self.team_of(id),
+            self.alliances_of_curio(curio),
+        );
+        for target in area_targets {
+            for effect in action.effects() {
+                let _ = effect.apply_effect(&mut self.grid, curio, target, &mut self.curios);
+            }
+        }
+        for effect in action.self_effects() {
+            if let Some(head) = self.grid.head(curio) {
+                let _ = effect.apply_effect(&mut self.grid, curio, head, &mut self.curios);
+            }
+        }
+        if let Some(sim_curio) = self.curios.get_mut(&curio) {
+            sim_curio.action_history.record_use(action);
+        }
+    }
+
+    /// Every plan the curio could follow, along with where it leads. Doing
+    /// nothing is always first.
+    fn plans(&self, curio: Entity, actions: &HashMap<String, Action>) -> Vec<(Plan, SimNode)> {
+        let Some(sim_curio) = self.curios.get(&curio) else {
+            return Vec::new();
+        };
+        let distance_map = self
+            .grid
+            .distance_map(curio, Some(sim_curio.movement_speed), |_| false);
+        let mut destinations: Vec<UVec2> = distance_map.points().collect();
+        destinations.sort_by_key(|pt| distance_map.distance_to(*pt));
+        let mut plans = Vec::new();
+        for path in destinations
+            .into_iter()
+            .filter_map(|pt| distance_map.path_to(pt))
+        {
+            let move_plan = Plan { path, action: None };
+            let mut moved = self.clone();
+            if !moved.apply_plan(curio, &move_plan, actions) {
+                continue;
+            }
+            let Some(moved_head) = moved.grid.head(curio) else {
+                continue;
+            };
+            plans.push((move_plan.clone(), moved.clone()));
+            for action in sim_curio
+                .actions
+                .iter()
+                .filter(|action_id| action_id.as_str() != NO_OP_ACTION_ID)
+                .filter_map(|action_id| actions.get(action_id))
+            {
+                let candidates = action
+                    .range()
+                    .map(|range| range.pts_in_range_of(&moved.grid, moved_head))
+                    .unwrap_or_else(|| vec![moved_head]);
+                let mut targeted_curios = HashSet::new();
+                for target in candidates {
+                    if !moved.can_target(curio, action, target)
+                        || moved
+                            .grid
+                            .item_at(target)
+                            .is_some_and(|id| !targeted_curios.insert(id))
+                    {
+                        continue;
+                    }
+                    let mut acted = moved.clone();
+                    acted.perform_action(curio, action, target);
+                    let plan = Plan {
+                        path: move_plan.path.clone(),
+                        action: Some((action.id().to_owned(), target)),
+                    };
+                    plans.push((plan, acted));
+                }
+            }
+        }
+        plans
+    }
+
+    /// How good this position is for `team`: the more and the bigger its and
+    /// its allies' curios are compared to its enemies', the better, and the
+    /// closer its curios are to the enemy, the better
+    fn evaluate(&self, team: Entity) -> i64 {
+        let enemy_pts: Vec<UVec2> = self
+            .curios
+            .iter()
+            .filter(|(_, sim_curio)| !self.is_allied(team, sim_curio.team))
+            .flat_map(|(id, _)| self.grid.points(*id))
+            .collect();
+        self.curios
+            .iter()
+            .map(|(id, sim_curio)| {
+                let size = self.grid.len_of(*id) as i64;
+                if size == 0 {
+                    return 0;
+                }
+                let value = CURIO_VALUE + size * SQUARE_VALUE;
+                if !self.is_allied(team, sim_curio.team) {
+                    return -value;
+                }
+                if sim_curio.team != team {
+                    return value;
+                }
+                let distance = self
+                    .grid
+                    .head(*id)
+                    .and_then(|head| {
+                        enemy_pts
+                            .iter()
+                            .map(|pt| pt.manhattan_distance(&head))
+                            .min()
+                    })
+                    .unwrap_or(0);
+                value - distance as i64
+            })
+            .sum()
+    }
+}
+
+struct Search<'a> {
+    actions: &'a HashMap<String, Action>,
+    team: Entity,
+    /// Which curio acts at each step of the search
+    order: Vec<Entity>,
+    deadline: Instant,
+}
+
+impl Search<'_> {
+    /// Picks a plan for the first curio in `order`, deepening the search
+    /// until `max_depth` or the deadline
+    fn best_plan(&self, sim: &SimNode, max_depth: usize) -> (Plan, SimNode) {
+        let mut plans = sim.plans(self.order[0], self.actions);
+        let mut best_index = 0;
+        for depth in 1..=max_depth.max(1) {
+            let mut best_score = i64::MIN;
+            let mut depth_best_index = 0;
+            let finished = plans.iter().enumerate().all(|(i, (_, next_sim))| {
+                let Some(score) = self.search(next_sim, 1, depth - 1, best_score, i64::MAX) else {
+                    return false;
+                };
+                if score > best_score {
+                    best_score = score;
+                    depth_best_index = i;
+                }
+                true
+            });
+            if !finished {
+                log::debug!("Lookahead AI ran out of time at depth {depth}");
+                break;
+            }
+            best_index = depth_best_index;
+        }
+        if plans.is_empty() {
+            (Plan::default(), sim.clone())
+        } else {
+            plans.swap_remove(best_index)
+        }
+    }
+
+    /// Minimax with alpha-beta pruning. Returns None if the deadline passes.
+    fn search(
+        &self,
+        sim: &SimNode,
+        ply: usize,
+        depth: usize,
+        mut alpha: i64,
+        mut beta: i64,
+    ) -> Option<i64> {
+        if Instant::now() >= self.deadline {
+            return None;
+        }
+        let Some(&curio) = self.order.get(ply) else {
+            return Some(sim.evaluate(self.team));
+        };
+        if depth == 0 {
+            return Some(sim.evaluate(self.team));
+        }
+        if !sim.grid.contains_key(curio) {
+            return self.search(sim, ply + 1, depth, alpha, beta);
+        }
+        let maximizing = sim
+            .team_of(curio)
+            .is_some_and(|team| sim.is_allied(self.team, team));
+        let mut best = if maximizing { i64::MIN } else { i64::MAX };
+        for (_, next_sim) in sim.plans(curio, self.actions) {
+            let score = self.search(&next_sim, ply + 1, depth - 1, alpha, beta)?;
+            if maximizing {
+                best = best.max(score);
+                alpha = alpha.max(best);
+            } else {
+                best = best.min(score);
+                beta = beta.min(best);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        Some(best)
+    }
+}
+
+fn send_plan(sx: &AiOpSender, plan: &Plan) -> AiOpResult {
+    for dir in plan.path.iter() {
+        sx.send((
+            NodeOp::MoveActiveCurio { dir: *dir },
+            Duration::from_millis(400),
+        ))?;
+    }
+    if let Some((action_id, target)) = plan.action.as_ref() {
+        sx.send((
+            NodeOp::TelegraphAction {
+                action_id: action_id.clone().into(),
+                target: *target,
+            },
+            Duration::from_millis(500),
+        ))?;
+        sx.send((
+            NodeOp::PerformCurioAction {
+                action_id: action_id.clone().into(),
+                curio: None,
+                target: *target,
+            },
+            Duration::from_secs(2),
+        ))?;
+    } else {
+        sx.send((
+            NodeOp::PerformCurioAction {
+                action_id: NO_OP_ACTION_ID,
+                curio: None,
+                target: UVec2::default(),
+            },
+            Duration::from_millis(500),
+        ))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::card::{ActionEffect, ActionRange, ActionTarget, Prerequisite};
+
+    const AI_TEAM: Entity = Entity::from_raw(100);
+    const ENEMY_TEAM: Entity = Entity::from_raw(101);
+
+    fn piece(id: u32, team: Entity, actions: &[&str], tags: Tags) -> AiPiece {
+        AiPiece {
+            id: Entity::from_raw(id),
+            team,
+            actions: actions.iter().map(|action| (*action).to_owned()).collect(),
+            movement_speed: 1,
+            max_size: 3,
+            ai_order: 0,
+            lost_squares: Vec::new(),
+            tags,
+            status_effects: default(),
+            action_history: default(),
+        }
+    }
+
+    /// An AI curio next to an enemy, with an attack that only works on
+    /// marked targets
+    fn snapshot(enemy_tags: Tags) -> NodeAiSnapshot {
+        let ai_curio = piece(1, AI_TEAM, &["mark_hit"], default());
+        let enemy = piece(2, ENEMY_TEAM, &[], enemy_tags);
+        let mut grid = EntityGrid::from(vec![vec![true; 4]; 4]);
+        grid.put_entries([
+            (ai_curio.id, vec![UVec2::new(0, 0)]),
+            (enemy.id, vec![UVec2::new(1, 0)]),
+        ]);
+        let mark_hit = Action {
+            id: "mark_hit".to_owned(),
+            range: Some(ActionRange::new(2)),
+            effects: vec![ActionEffect::Damage(1)],
+            target: ActionTarget::Enemies,
+            prereqs: vec![Prerequisite::TargetHasTag("marked".to_owned())],
+            ..default()
+        };
+        NodeAiSnapshot {
+            player: Entity::from_raw(200),
+            team: AI_TEAM,
+            grid,
+            my_pieces: vec![ai_curio],
+            ally_pieces: Vec::new(),
+            enemy_pieces: vec![enemy],
+            alliances: default(),
+            actions: [("mark_hit".to_owned(), mark_hit)].into_iter().collect(),
+        }
+    }
+
+    fn planned_action(snapshot: &NodeAiSnapshot) -> Option<String> {
+        let (sx, rx) = std::sync::mpsc::channel();
+        LookaheadAi::new(1, Duration::from_millis(500))
+            .take_turn(snapshot, &sx)
+            .unwrap();
+        drop(sx);
+        rx.iter().find_map(|(op, _)| match op {
+            NodeOp::PerformCurioAction { action_id, .. } if action_id != NO_OP_ACTION_ID => {
+                Some(action_id.into_owned())
+            },
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_lookahead_attacks() {
+        let mut tags = Tags::default();
+        tags.add_tag("marked".to_owned());
+        assert_eq!(planned_action(&snapshot(tags)).as_deref(), Some("mark_hit"));
+    }
+
+    #[test]
+    fn test_lookahead_respects_target_prereqs() {
+        assert_eq!(planned_action(&snapshot(default())), None);
+    }
+
+    #[test]
+    fn test_sim_records_action_use() {
+        let snapshot = snapshot(default());
+        let mut sim = SimNode::new(&snapshot);
+        let ai_curio = snapshot.my_pieces[0].id;
+        let once = Action {
+            id: "once".to_owned(),
+            range: Some(ActionRange::new(1)),
+            effects: vec![ActionEffect::Damage(1)],
+            target: ActionTarget::Enemies,
+            prereqs: vec![Prerequisite::OncePerBattle],
+            ..default()
+        };
+        assert!(sim.can_target(ai_curio, &once, UVec2::new(1, 0)));
+        sim.perform_action(ai_curio, &once, UVec2::new(1, 0));
+        assert!(!sim.can_target(ai_curio, &once, UVec2::new(1, 0)));
+    }
+}
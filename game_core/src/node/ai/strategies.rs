@@ -0,0 +1,465 @@
+use std::time::Duration;
+
+use super::{AiOpResult, AiOpSender, AiPiece, NodeAiSnapshot, NodeAiStrategy};
+use crate::card::{Action, ActionEffect, NO_OP_ACTION_ID};
+use crate::node::NodeOp;
+use crate::prelude::*;
+
+/// Waits a moment, then ends the turn
+#[derive(Debug)]
+pub struct DoNothing;
+
+impl NodeAiStrategy for DoNothing {
+    fn take_turn(&self, _: &NodeAiSnapshot, _: &AiOpSender) -> AiOpResult {
+        std::thread::sleep(Duration::from_secs(3));
+        Ok(())
+    }
+}
+
+// Other scripts for the future:
+// Search by all actions on all pieces, whichever does the most damage wins
+
+/// No pathfinding, simply moves in the direction of the nearest piece until it is within attack distance.
+#[derive(Debug)]
+pub struct SimpleAi;
+
+impl NodeAiStrategy for SimpleAi {
+    fn take_turn(&self, snapshot: &NodeAiSnapshot, sx: &AiOpSender) -> AiOpResult {
+        let mut grid = snapshot.grid.clone();
+        let mut my_pieces = snapshot.my_pieces.clone();
+        let enemy_pieces: Vec<Entity> = snapshot.enemy_pieces.iter().map(|p| p.id).collect();
+        std::thread::sleep(Duration::from_millis(350));
+        my_pieces.sort_by_key(|piece| piece.ai_order);
+        for piece in my_pieces {
+            let mut grid_head = match grid.head(piece.id) {
+                Some(grid_head) => grid_head,
+                None => continue,
+            };
+            sx.send((
+                NodeOp::ActivateCurio { curio_id: piece.id },
+                Duration::from_millis(500),
+            ))?;
+            if let Some(closest_enemy_pt) = enemy_pieces
+                .iter()
+                .flat_map(|id| grid.points(*id))
+                .min_by_key(|pt| pt.manhattan_distance(&grid_head))
+            {
+                log::trace!(
+                    "Closest enemy point for piece[{:?}] is {:?}",
+                    piece.actions,
+                    closest_enemy_pt
+                );
+                for _ in 0..piece.movement_speed {
+                    match grid_head.dirs_to(&closest_enemy_pt) {
+                        [Some(dir1), Some(dir2)] => {
+                            // Choose which to prioritize, then try one, and if it fails, the other.
+                            let (dir1, dir2) = if grid_head
+                                .dist_to_pt_along_compass(&closest_enemy_pt, dir1)
+                                > grid_head.dist_to_pt_along_compass(&closest_enemy_pt, dir2)
+                            {
+                                (dir1, dir2)
+                            } else {
+                                (dir2, dir1)
+                            };
+                            if grid.can_move_to(piece.id, grid_head + dir1, |_| false) {
+                                log::trace!("{:?} Went {:?} from {:?}", piece.id, dir1, grid_head);
+                                grid_head = grid_head + dir1;
+                                sx.send((
+                                    NodeOp::MoveActiveCurio { dir: dir1 },
+                                    Duration::from_millis(400),
+                                ))?;
+                            } else if grid.can_move_to(piece.id, grid_head + dir2, |_| false) {
+                                log::trace!("{:?} Went {:?} from {:?}", piece.id, dir2, grid_head);
+                                grid_head = grid_head + dir2;
+                                sx.send((
+                                    NodeOp::MoveActiveCurio { dir: dir2 },
+                                    Duration::from_millis(400),
+                                ))?;
+                            } else {
+                                log::trace!(
+                                    "{:?} can't go {:?} OR {:?} from {:?}",
+                                    piece.id,
+                                    dir1,
+                                    dir2,
+                                    grid_head
+                                );
+                                break;
+                            }
+                        },
+                        [Some(dir), None] => {
+                            // if dir is not blocked ,go that way
+                            if grid.can_move_to(piece.id, grid_head + dir, |_| false) {
+                                log::trace!("{:?} Went {:?} from {:?}", piece.id, dir, grid_head);
+                                grid_head = grid_head + dir;
+                                sx.send((
+                                    NodeOp::MoveActiveCurio { dir },
+                                    Duration::from_millis(400),
+                                ))?;
+                            } else {
+                                log::trace!(
+                                    "{:?} can't go {:?} from {:?}",
+                                    piece.id,
+                                    dir,
+                                    grid_head
+                                );
+                                break;
+                            }
+                        },
+                        [None, None] => {
+                            log::warn!(
+                                "{:?} is already at {:?}, the closest enemy point",
+                                piece.id,
+                                grid_head
+                            );
+                            break;
+                        },
+                        [None, Some(_)] => unreachable!(),
+                    }
+                }
+            }
+
+            let attack = piece.actions.iter().find_map(|action| {
+                let action = snapshot.action(action)?;
+                let (target_id, target) = attack_target(
+                    &grid,
+                    action,
+                    piece.id,
+                    grid_head,
+                    enemy_pieces.iter().copied(),
+                )?;
+                Some((action, target_id, target))
+            });
+            perform_attack(sx, &mut grid, attack)?;
+        }
+        Ok(())
+    }
+}
+
+/// Attacks whatever is nearby, no movement
+#[derive(Debug)]
+pub struct LazyAi;
+
+impl NodeAiStrategy for LazyAi {
+    fn take_turn(&self, snapshot: &NodeAiSnapshot, sx: &AiOpSender) -> AiOpResult {
+        let mut grid = snapshot.grid.clone();
+        let enemy_pieces: Vec<Entity> = snapshot.enemy_pieces.iter().map(|p| p.id).collect();
+        std::thread::sleep(Duration::from_millis(350));
+        for piece in snapshot.my_pieces.iter() {
+            let Some(head) = grid.head(piece.id) else {
+                continue;
+            };
+            sx.send((
+                NodeOp::ActivateCurio { curio_id: piece.id },
+                Duration::from_millis(500),
+            ))?;
+            let attack = piece.actions.iter().find_map(|action| {
+                let action = snapshot.action(action)?;
+                let (target_id, target) =
+                    attack_target(&grid, action, piece.id, head, enemy_pieces.iter().copied())?;
+                Some((action, target_id, target))
+            });
+            perform_attack(sx, &mut grid, attack)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plays like the enemies of the original Nightfall Incident: each curio takes
+/// the shortest path towards the nearest enemy, stopping as soon as its
+/// strongest attack can reach one, then attacks the enemy it would leave the
+/// smallest.
+#[derive(Debug)]
+pub struct NightfallAi;
+
+impl NodeAiStrategy for NightfallAi {
+    fn take_turn(&self, snapshot: &NodeAiSnapshot, sx: &AiOpSender) -> AiOpResult {
+        let mut grid = snapshot.grid.clone();
+        let mut my_pieces = snapshot.my_pieces.clone();
+        let enemy_pieces: Vec<Entity> = snapshot.enemy_pieces.iter().map(|p| p.id).collect();
+        std::thread::sleep(Duration::from_millis(350));
+        my_pieces.sort_by_key(|piece| piece.ai_order);
+        for piece in my_pieces {
+            let Some(head) = grid.head(piece.id) else {
+                continue;
+            };
+            sx.send((
+                NodeOp::ActivateCurio { curio_id: piece.id },
+                Duration::from_millis(500),
+            ))?;
+            let mut attacks: Vec<&Action> = piece
+                .actions
+                .iter()
+                .filter_map(|action| snapshot.action(action))
+                .filter(|action| action_damage(action) > 0)
+                .collect();
+            attacks.sort_by_key(|action| std::cmp::Reverse(action_damage(action)));
+            let best_attack_from = |grid: &EntityGrid, pt: UVec2| {
+                attacks.iter().find_map(|action| {
+                    let dmg = action_damage(action);
+                    enemy_pieces
+                        .iter()
+                        .filter_map(|enemy| {
+                            let target = action.range()?.pt_in_range(
+                                grid,
+                                piece.id,
+                                grid.points(*enemy),
+                                pt,
+                            )?;
+                            Some((*enemy, target))
+                        })
+                        .min_by_key(|(enemy, _)| grid.len_of(*enemy).saturating_sub(dmg))
+                        .map(|(enemy, target)| (*action, enemy, target))
+                })
+            };
+            let path = grid
+                .path_to_nearest(
+                    piece.id,
+                    Some(piece.movement_speed),
+                    |_| false,
+                    |pt| best_attack_from(&grid, pt).is_some(),
+                )
+                .or_else(|| {
+                    // Nothing to attack this turn, so head towards the nearest enemy
+                    let enemy_squares: HashSet<UVec2> = enemy_pieces
+                        .iter()
+                        .flat_map(|enemy| grid.points(*enemy))
+                        .collect();
+                    let mut path = grid.path_to_nearest(
+                        piece.id,
+                        None,
+                        |_| false,
+                        |pt| {
+                            Compass::ALL_DIRECTIONS
+                                .into_iter()
+                                .any(|dir| enemy_squares.contains(&(pt + dir)))
+                        },
+                    )?;
+                    path.truncate(piece.movement_speed as usize);
+                    Some(path)
+                })
+                .unwrap_or_default();
+            let mut pt = head;
+            for dir in path {
+                pt = pt + dir;
+                move_piece(&mut grid, &piece, pt);
+                sx.send((NodeOp::MoveActiveCurio { dir }, Duration::from_millis(400)))?;
+            }
+            let attack = best_attack_from(&grid, pt);
+            perform_attack(sx, &mut grid, attack)?;
+        }
+        Ok(())
+    }
+}
+
+/// Updates the AI's copy of the grid to match what the move op will do
+fn move_piece(grid: &mut EntityGrid, piece: &AiPiece, pt: UVec2) {
+    grid.push_front(pt, piece.id);
+    if grid.len_of(piece.id) > piece.max_size as usize {
+        grid.pop_back(piece.id);
+    }
+}
+
+fn action_damage(action: &Action) -> usize {
+    action
+        .effects()
+        .iter()
+        .map(|effect| {
+            if let ActionEffect::Damage(dmg) = effect {
+                *dmg
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// The first enemy in range of a damaging action, and the point to target
+fn attack_target<I: IntoIterator<Item = Entity>>(
+    grid: &EntityGrid,
+    action: &Action,
+    piece: Entity,
+    head: UVec2,
+    enemy_pieces: I,
+) -> Option<(Entity, UVec2)> {
+    if action_damage(action) == 0 {
+        return None;
+    }
+    // TODO When ops improves, use op logic instead of recreating here
+    let range = action.range()?;
+    enemy_pieces.into_iter().find_map(|enemy_piece| {
+        let target = range.pt_in_range(grid, piece, grid.points(enemy_piece), head)?;
+        Some((enemy_piece, target))
+    })
+}
+
+/// Sends the ops to perform an attack, or to do nothing if there isn't one,
+/// and updates the AI's copy of the grid
+fn perform_attack(
+    sx: &AiOpSender,
+    grid: &mut EntityGrid,
+    attack: Option<(&Action, Entity, UVec2)>,
+) -> AiOpResult {
+    if let Some((action, target_id, target)) = attack {
+        sx.send((
+            NodeOp::TelegraphAction {
+                action_id: action.id_cow(),
+                target,
+            },
+            Duration::from_millis(500),
+        ))?;
+        sx.send((
+            NodeOp::PerformCurioAction {
+                action_id: action.id_cow(),
+                curio: None,
+                target,
+            },
+            Duration::from_secs(2),
+        ))?;
+        grid.pop_back_n(target_id, action_damage(action));
+    } else {
+        sx.send((
+            NodeOp::PerformCurioAction {
+                action_id: NO_OP_ACTION_ID,
+                curio: None,
+                target: UVec2::default(),
+            },
+            Duration::from_millis(500),
+        ))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::card::{ActionRange, ActionTarget};
+
+    const AI_TEAM: Entity = Entity::from_raw(100);
+    const ENEMY_TEAM: Entity = Entity::from_raw(101);
+
+    fn piece(id: u32, team: Entity, actions: &[&str], movement_speed: u32) -> AiPiece {
+        AiPiece {
+            id: Entity::from_raw(id),
+            team,
+            actions: actions.iter().map(|action| (*action).to_owned()).collect(),
+            movement_speed,
+            max_size: 3,
+            ai_order: 0,
+            lost_squares: Vec::new(),
+            tags: default(),
+            status_effects: default(),
+            action_history: default(),
+        }
+    }
+
+    fn snapshot(pieces: &[(AiPiece, &[UVec2])]) -> NodeAiSnapshot {
+        let mut grid = EntityGrid::from(vec![vec![true; 6]; 6]);
+        grid.put_entries(
+            pieces
+                .iter()
+                .map(|(piece, pts)| (piece.id, pts.iter().copied())),
+        );
+        let (my_pieces, enemy_pieces) = pieces
+            .iter()
+            .map(|(piece, _)| piece.clone())
+            .partition(|piece| piece.team == AI_TEAM);
+        let hit = Action {
+            id: "hit".to_owned(),
+            range: Some(ActionRange::new(1)),
+            effects: vec![ActionEffect::Damage(1)],
+            target: ActionTarget::Enemies,
+            ..default()
+        };
+        NodeAiSnapshot {
+            player: Entity::from_raw(200),
+            team: AI_TEAM,
+            grid,
+            my_pieces,
+            ally_pieces: Vec::new(),
+            enemy_pieces,
+            alliances: default(),
+            actions: [("hit".to_owned(), hit)].into_iter().collect(),
+        }
+    }
+
+    fn take_turn<S: NodeAiStrategy>(strategy: S, snapshot: &NodeAiSnapshot) -> Vec<NodeOp> {
+        let (sx, rx) = std::sync::mpsc::channel();
+        strategy.take_turn(snapshot, &sx).unwrap();
+        drop(sx);
+        rx.iter().map(|(op, _)| op).collect()
+    }
+
+    fn attack_target_of(ops: &[NodeOp]) -> Option<UVec2> {
+        match ops.last() {
+            Some(NodeOp::PerformCurioAction {
+                action_id, target, ..
+            }) if action_id == "hit" => Some(*target),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_simple_ai_approaches_and_attacks() {
+        let snapshot = snapshot(&[
+            (piece(1, AI_TEAM, &["hit"], 3), &[UVec2::new(0, 0)]),
+            (
+                piece(2, ENEMY_TEAM, &[], 0),
+                &[UVec2::new(0, 4), UVec2::new(1, 4)],
+            ),
+        ]);
+        let ops = take_turn(SimpleAi, &snapshot);
+        let moves = ops
+            .iter()
+            .filter(|op| {
+                matches!(
+                    op,
+                    NodeOp::MoveActiveCurio {
+                        dir: Compass::South
+                    }
+                )
+            })
+            .count();
+        assert_eq!(moves, 3);
+        assert_eq!(attack_target_of(&ops), Some(UVec2::new(0, 4)));
+    }
+
+    #[test]
+    fn test_simple_ai_stays_put_on_closest_point() {
+        // A snapshot that lists the AI's own piece as an enemy puts the
+        // closest enemy point under its head
+        let mut snapshot = snapshot(&[(piece(1, AI_TEAM, &[], 2), &[UVec2::new(2, 2)])]);
+        snapshot.enemy_pieces = snapshot.my_pieces.clone();
+        let ops = take_turn(SimpleAi, &snapshot);
+        assert!(!ops
+            .iter()
+            .any(|op| matches!(op, NodeOp::MoveActiveCurio { .. })));
+    }
+
+    #[test]
+    fn test_lazy_ai_attacks_without_moving() {
+        let snapshot = snapshot(&[
+            (piece(1, AI_TEAM, &["hit"], 3), &[UVec2::new(2, 2)]),
+            (piece(2, ENEMY_TEAM, &[], 0), &[UVec2::new(2, 3)]),
+            (piece(3, ENEMY_TEAM, &[], 0), &[UVec2::new(5, 5)]),
+        ]);
+        let ops = take_turn(LazyAi, &snapshot);
+        assert!(!ops
+            .iter()
+            .any(|op| matches!(op, NodeOp::MoveActiveCurio { .. })));
+        assert_eq!(attack_target_of(&ops), Some(UVec2::new(2, 3)));
+    }
+
+    #[test]
+    fn test_nightfall_ai_attacks_weakest_enemy() {
+        let snapshot = snapshot(&[
+            (piece(1, AI_TEAM, &["hit"], 0), &[UVec2::new(2, 2)]),
+            (
+                piece(2, ENEMY_TEAM, &[], 0),
+                &[UVec2::new(1, 2), UVec2::new(1, 3), UVec2::new(1, 4)],
+            ),
+            (piece(3, ENEMY_TEAM, &[], 0), &[UVec2::new(3, 2)]),
+        ]);
+        let ops = take_turn(NightfallAi, &snapshot);
+        assert_eq!(attack_target_of(&ops), Some(UVec2::new(3, 2)));
+    }
+}
@@ -3,10 +3,13 @@ use std::ops::Deref;
 use bevy::ecs::query::Has;
 
 use super::{
-    Curio, CurioFromCard, EnteringNode, InNode, IsReadyToGo, IsTapped, MovesTaken, Node, NodePiece,
-    OnTeam, Teams,
+    Curio, CurioFromCard, EnteringNode, EnteringTeam, InNode, IsReadyToGo, IsTapped, LostSquares,
+    MovesTaken, Node, NodePiece, OnTeam, Team, TeamColor, Teams,
+};
+use crate::card::{
+    ActionHistory, Actions, CardDefinition, Description, MaximumSize, MovementSpeed, StatusEffects,
+    Tags,
 };
-use crate::card::{Actions, CardDefinition, Description, MaximumSize, MovementSpeed};
 use crate::player::Player;
 use crate::prelude::*;
 use crate::registry::{Reg, Registry};
@@ -15,9 +18,12 @@ pub struct NodeLoadingPlugin;
 
 impl Plugin for NodeLoadingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PostUpdate, (sys_enter_node_when_ready, sys_load_curios))
-            .add_plugins(Reg::<NodeScene>::default())
-            .register_type::<CurioFromCard>();
+        app.add_systems(
+            PostUpdate,
+            (sys_enter_node_when_ready, sys_load_curios, sys_color_teams),
+        )
+        .add_plugins(Reg::<NodeScene>::default())
+        .register_type::<CurioFromCard>();
     }
 }
 
@@ -52,21 +58,48 @@ impl Default for CurioFromCard {
 
 fn sys_enter_node_when_ready(
     mut commands: Commands,
-    players_entering: Query<(Entity, AsDeref<EnteringNode>), With<Player>>,
+    players_entering: Query<
+        (
+            Entity,
+            AsDeref<EnteringNode>,
+            Option<AsDerefCopied<EnteringTeam>>,
+        ),
+        With<Player>,
+    >,
     nodes: Query<(&Node, Entity, AsDeref<Teams>, Has<EntityGrid>)>,
 ) {
     // Note: Node loading kickoff should either happen here or in an op
-    for (player_id, node_id) in players_entering.iter() {
+    for (player_id, node_id, team_slot) in players_entering.iter() {
         if let Some((_, node_entity, teams, node_is_ready)) =
             nodes.iter().find(|node_q| node_q.0 .0 == *node_id)
         {
             // TODO check that all curios are loaded first
             if node_is_ready {
-                commands.entity(player_id).remove::<EnteringNode>().insert((
-                    InNode(node_entity),
-                    OnTeam(teams[0]),
-                    IsReadyToGo(false),
-                ));
+                let team_slot = team_slot.unwrap_or(0);
+                let team_id = teams.get(team_slot).copied().unwrap_or_else(|| {
+                    log::warn!("Node {node_id} has no team slot {team_slot}, joining first team");
+                    teams[0]
+                });
+                commands
+                    .entity(player_id)
+                    .remove::<(EnteringNode, EnteringTeam)>()
+                    .insert((InNode(node_entity), OnTeam(team_id), IsReadyToGo(false)));
+            }
+        }
+    }
+}
+
+/// Gives teams that don't specify a color one from the palette, by their slot
+/// in the node
+fn sys_color_teams(
+    mut commands: Commands,
+    nodes: Query<AsDeref<Teams>, (With<Node>, Changed<Teams>)>,
+    uncolored_teams: Query<(), (With<Team>, Without<TeamColor>)>,
+) {
+    for teams in nodes.iter() {
+        for (slot, team_id) in teams.iter().enumerate() {
+            if uncolored_teams.contains(*team_id) {
+                commands.entity(*team_id).insert(TeamColor::for_slot(slot));
             }
         }
     }
@@ -84,14 +117,18 @@ fn sys_load_curios(
             commands
                 .entity(id)
                 .insert((
+                    ActionHistory::default(),
                     Actions(card_def.actions().clone()),
                     Curio::new(card_def.id()),
                     NodePiece::new(card_def.id()),
                     Description::new(card_def.description().to_owned()),
                     IsTapped::default(),
+                    LostSquares::default(),
                     MaximumSize(card_def.max_size()),
                     MovementSpeed(card_def.movement_speed()),
                     MovesTaken::default(),
+                    StatusEffects::default(),
+                    Tags::default(),
                 ))
                 .remove::<CurioFromCard>();
         }
@@ -1,27 +1,32 @@
+pub mod node_op_preview;
 pub mod node_op_undo;
 
 use std::borrow::Cow;
 
+use bevy::ecs::entity::{EntityMapper, MapEntities};
 use bevy::ecs::query::QueryData;
 use bevy::hierarchy::DespawnRecursiveExt;
 use bevy::reflect::TypePath;
 use bevy::scene::DynamicScene;
 
 use self::daddy::Daddy;
+use self::node_op_preview::{commit_curio_effects, NodeOpPreview};
 use self::node_op_undo::NodeUndoStack;
 use super::{Claimed, EnteringNode, NodeId, NodeScene, VictoryAward};
 use crate::card::{
-    Action, ActionEffect, Actions, CardQuery, Deck, Description, MaximumSize, MovementSpeed,
+    Action, ActionEffect, ActionHistory, Actions, CardQuery, CurioEffectAccess, CurioEffectQ,
+    CurioPrereqAccess, Description, MaximumSize, MovementSpeed, Status, StatusEffects, Tags,
     NO_OP_ACTION_ID,
 };
 use crate::configuration::PlayerConfiguration;
 use crate::entity_grid::Square;
+use crate::item::{Inventory, ItemActions, ItemDefinitions, ItemKind};
 use crate::node::{
-    key, AccessPoint, AccessPointLoadingRule, ActiveCurio, Curio, CurrentTurn, InNode, IsReadyToGo,
-    IsTapped, MovesTaken, NoOpAction, Node, NodePiece, OnTeam, Pickup, PlayedCards, Team,
-    TeamPhase, TeamStatus, Teams, VictoryStatus,
+    key, AccessPoint, ActiveCurio, Alliances, Curio, CurrentTurn, InNode, IsReadyToGo, IsTapped,
+    LostSquares, MovesTaken, NoOpAction, Node, NodePiece, OnTeam, Pickup, PlayedCards, Team,
+    TeamPhase, TeamStatus, Teams, TurnsEnded, VictoryStatus,
 };
-use crate::op::{CoreOps, Op, OpError, OpErrorUtils, OpImplResult, OpRegistrar};
+use crate::op::{CoreOps, Op, OpEntityMapper, OpError, OpErrorUtils, OpImplResult, OpRegistrar};
 use crate::player::{Ncp, Player};
 use crate::prelude::*;
 use crate::quest::QuestStatus;
@@ -49,12 +54,22 @@ pub enum NodeOp {
     },
     ReadyToGo,
     EndTurn,
+    /// Shows what the active curio is about to do, with the results that
+    /// performing the action on `target` would have
     TelegraphAction {
         action_id: Cow<'static, str>,
+        target: UVec2,
     },
     EnterNode(NodeId),
     QuitNode(NodeId),
     Undo,
+    Redo,
+    /// Uses an item from the player's inventory on a target, in range of the
+    /// active curio
+    UseItem {
+        item_id: String,
+        target: UVec2,
+    },
 }
 
 #[derive(Debug, QueryData)]
@@ -68,6 +83,30 @@ pub struct CurioQ {
     movement_speed: Option<&'static mut MovementSpeed>,
     max_size: Option<&'static mut MaximumSize>,
     actions: Option<&'static Actions>,
+    status_effects: Option<&'static StatusEffects>,
+    action_history: Option<&'static mut ActionHistory>,
+}
+
+impl MapEntities for NodeOp {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        match self {
+            Self::PerformCurioAction {
+                curio: Some(curio), ..
+            } => *curio = entity_mapper.map_entity(*curio),
+            Self::ActivateCurio { curio_id } => *curio_id = entity_mapper.map_entity(*curio_id),
+            Self::LoadAccessPoint {
+                access_point_id,
+                card_id,
+            } => {
+                *access_point_id = entity_mapper.map_entity(*access_point_id);
+                *card_id = entity_mapper.map_entity(*card_id);
+            },
+            Self::UnloadAccessPoint { access_point_id } => {
+                *access_point_id = entity_mapper.map_entity(*access_point_id)
+            },
+            _ => {},
+        }
+    }
 }
 
 const ACCESS_POINT_DISPLAY_ID: &str = "env:access_point";
@@ -87,7 +126,9 @@ impl Op for NodeOp {
             .register_op(opsys_telegraph_action)
             .register_op(opsys_node_enter_battle)
             .register_op(opsys_node_quit_battle)
-            .register_op(opsys_node_undo);
+            .register_op(opsys_node_undo)
+            .register_op(opsys_node_redo)
+            .register_op(opsys_node_use_item);
     }
 
     fn system_index(&self) -> usize {
@@ -103,8 +144,18 @@ impl Op for NodeOp {
             Self::EnterNode(_) => 7,
             Self::QuitNode(_) => 8,
             Self::Undo => 9,
+            Self::Redo => 10,
+            Self::UseItem { .. } => 11,
         }
     }
+
+    fn map_op_entities(&mut self, entity_mapper: &mut OpEntityMapper) {
+        self.map_entities(entity_mapper)
+    }
+
+    fn is_entity_field(field: &str) -> bool {
+        key::is_entity_field(field)
+    }
 }
 
 fn opsys_node_movement(
@@ -112,93 +163,27 @@ fn opsys_node_movement(
     mut commands: Commands,
     mut res_core_ops: ResMut<CoreOps>,
     res_no_op_action: Res<NoOpAction>,
-    mut nodes: Query<
-        (
-            &mut EntityGrid,
-            AsDerefCopied<CurrentTurn>,
-            AsDerefCopied<ActiveCurio>,
-            &TeamStatus,
-        ),
-        With<Node>,
-    >,
-    players: Query<(AsDerefCopied<OnTeam>, AsDerefCopied<InNode>), With<Player>>,
-    team_phases: Query<&TeamPhase, With<Team>>,
-    mut curios: Query<CurioQ, With<Curio>>,
-    pickups: Query<&Pickup>,
+    mut params: ParamSet<(
+        NodeOpPreview,
+        Query<&mut EntityGrid, With<Node>>,
+        Query<CurioQ, With<Curio>>,
+    )>,
 ) -> OpImplResult {
     if let NodeOp::MoveActiveCurio { dir } = node_op {
-        let mut metadata = Metadata::default();
-        let (player_team_id, node_id) = players.get(player).critical()?;
-        let (mut grid, current_turn, active_curio, team_status) =
-            nodes.get_mut(node_id).critical()?;
-
-        if team_status.is_decided(player_team_id) {
-            Err("Cannot do any more".invalid())?;
-        }
-        if player_team_id != current_turn {
-            Err("Not this player's turn".invalid())?;
-        }
-        if *team_phases.get(player_team_id).critical()? == TeamPhase::Setup {
-            Err("Can't move pieces during setup phase".invalid())?;
-        }
-        let active_curio_id = active_curio.ok_or("No active curio to move")?;
-
-        metadata.put(key::NODE_ID, node_id).critical()?;
-        metadata.put(key::CURIO, active_curio_id).critical()?;
-        let mut curio_q = curios.get_mut(active_curio_id).critical()?;
-        debug_assert!(!**curio_q.tapped, "a tapped curio was active");
-        let movement_speed = **curio_q.movement_speed.ok_or("Movement speed is 0")?;
-        if movement_speed == **curio_q.moves_taken {
-            return Err("No movement remains")?;
-        }
-        let head = grid
-            .head(active_curio_id)
-            .ok_or("Active curio not in grid".critical())?;
-        let next_pt = head + dir;
-        metadata.put(key::TARGET_POINT, next_pt).critical()?;
-        if grid.square_is_closed(next_pt) {
-            return Err("Cannot move into closed square")?;
-        }
-        if let Some(entity_at_pt) = grid.item_at(next_pt) {
-            if entity_at_pt == active_curio_id {
-                // Curios can move onto their own squares
-                // TODO Consider replacing the use case of replaced_square being the last square to just use DROPPED_SQUARE
-                metadata.put(key::REPLACED_SQUARE, true).critical()?;
-                // Will be None if the replaced square is the last square
-                let sqr_next = grid.square_ref(next_pt).and_then(Square::next);
-                metadata
-                    .put_optional(key::REPLACED_SQUARE_NEXT, sqr_next)
-                    .critical()?;
-            } else if let Ok(pickup) = pickups.get(entity_at_pt) {
-                grid.remove_entity(entity_at_pt);
-                metadata.put(key::PICKUP, pickup).critical()?;
-                metadata.put(key::PICKUP_ID, entity_at_pt).critical()?;
-                commands
-                    .entity(entity_at_pt)
-                    .insert(Claimed { player, node_id });
-                log::debug!("Picked up: {pickup:?} ({entity_at_pt:?}) at {next_pt:?}");
-            } else {
-                return Err("Invalid target")?;
-            }
+        let plan = params.p0().plan_movement(player, dir)?;
+        *params.p1().get_mut(plan.node_id).critical()? = plan.grid;
+        if let Some(pickup_id) = plan.metadata.get_optional(key::PICKUP_ID).critical()? {
+            commands.entity(pickup_id).insert(Claimed {
+                player,
+                node_id: plan.node_id,
+            });
+            log::debug!("Picked up: {pickup_id:?}");
         }
-        grid.push_front(next_pt, active_curio_id);
+        let mut curios = params.p2();
+        let mut curio_q = curios.get_mut(plan.curio_id).critical()?;
         **curio_q.moves_taken += 1;
-        if grid.len_of(active_curio_id) as u32 > curio_q.max_size.map(|ms| **ms).unwrap_or(1) {
-            metadata
-                .put(
-                    key::DROPPED_SQUARE,
-                    grid.back(active_curio_id)
-                        .expect("piece should be at least one square long"),
-                )
-                .critical()?;
-            grid.pop_back(active_curio_id);
-        }
-        let remaining_moves = movement_speed - **curio_q.moves_taken;
 
-        metadata
-            .put(key::REMAINING_MOVES, remaining_moves)
-            .critical()?;
-        if movement_speed == **curio_q.moves_taken
+        if plan.remaining_moves == 0
             && curio_q
                 .actions
                 .map(|curio_actions| {
@@ -217,40 +202,128 @@ fn opsys_node_movement(
                 },
             );
         }
-        Ok(metadata)
+        Ok(plan.metadata)
     } else {
         Err(OpError::MismatchedOpSystem)
     }
 }
 
+/// Checks that it is a team's turn to play its pieces
+fn check_team_can_play(
+    team_id: Entity,
+    current_turn: Entity,
+    team_status: &TeamStatus,
+    team_phase: &TeamPhase,
+    setup_phase_err: &str,
+) -> Result<(), OpError> {
+    if team_status.is_decided(team_id) {
+        Err("Cannot do any more".invalid())?;
+    }
+    if team_id != current_turn {
+        Err("Not this player's turn".invalid())?;
+    }
+    if *team_phase == TeamPhase::Setup {
+        Err(setup_phase_err.invalid())?;
+    }
+    Ok(())
+}
+
+/// How many more times a curio can move this turn, after status effects
+fn moves_left(
+    movement_speed: Option<u32>,
+    status_effects: Option<&StatusEffects>,
+    moves_taken: u32,
+) -> Result<u32, OpError> {
+    let movement_penalty = status_effects
+        .map(StatusEffects::movement_penalty)
+        .unwrap_or(0);
+    let movement_speed = movement_speed
+        .map(|speed| speed.saturating_sub(movement_penalty))
+        .ok_or("Movement speed is 0")?;
+    if movement_speed <= moves_taken {
+        Err("No movement remains")?;
+    }
+    Ok(movement_speed - moves_taken)
+}
+
+/// Moves a curio one square in `dir`, taking any pickup in the way off the
+/// grid. Only the grid is changed, so that moves can be previewed on a copy.
+fn move_curio_on_grid(
+    grid: &mut EntityGrid,
+    curio_id: Entity,
+    dir: Compass,
+    max_size: u32,
+    pickups: &Query<&Pickup>,
+) -> Result<Metadata, OpError> {
+    let mut metadata = Metadata::default();
+    let head = grid
+        .head(curio_id)
+        .ok_or("Active curio not in grid".critical())?;
+    let next_pt = head + dir;
+    metadata.put(key::TARGET_POINT, next_pt).critical()?;
+    if next_pt == head {
+        Err("Cannot move off the edge of the grid")?;
+    }
+    if grid.square_is_closed(next_pt) {
+        Err("Cannot move into closed square")?;
+    }
+    if !grid.can_move_to(curio_id, next_pt, |id| pickups.contains(id)) {
+        Err("Invalid target")?;
+    }
+    if let Some(entity_at_pt) = grid.item_at(next_pt) {
+        if entity_at_pt == curio_id {
+            // Curios can move onto their own squares
+            // TODO Consider replacing the use case of replaced_square being the last square to just use DROPPED_SQUARE
+            metadata.put(key::REPLACED_SQUARE, true).critical()?;
+            // Will be None if the replaced square is the last square
+            let sqr_next = grid.square_ref(next_pt).and_then(Square::next);
+            metadata
+                .put_optional(key::REPLACED_SQUARE_NEXT, sqr_next)
+                .critical()?;
+        } else if let Ok(pickup) = pickups.get(entity_at_pt) {
+            grid.remove_entity(entity_at_pt);
+            metadata.put(key::PICKUP, pickup).critical()?;
+            metadata.put(key::PICKUP_ID, entity_at_pt).critical()?;
+        }
+    }
+    if let Some(dropped_square) = advance_curio(grid, curio_id, next_pt, max_size) {
+        metadata
+            .put(key::DROPPED_SQUARE, dropped_square)
+            .critical()?;
+    }
+    Ok(metadata)
+}
+
+/// Moves a curio's head onto `next_pt`, dropping its back square if that
+/// makes it longer than `max_size`. Returns the dropped square, if any.
+///
+/// Whether the curio may move there at all is up to the caller.
+pub(crate) fn advance_curio(
+    grid: &mut EntityGrid,
+    curio: Entity,
+    next_pt: UVec2,
+    max_size: u32,
+) -> Option<UVec2> {
+    grid.push_front(next_pt, curio);
+    if grid.len_of(curio) as u32 > max_size {
+        let dropped_square = grid.back(curio);
+        grid.pop_back(curio);
+        dropped_square
+    } else {
+        None
+    }
+}
+
 fn opsys_node_action(
     In((player, node_op)): In<(Entity, NodeOp)>,
-    ast_action: Res<Assets<Action>>,
     mut res_core_ops: ResMut<CoreOps>,
-    mut nodes: Query<
-        (
-            &mut EntityGrid,
-            AsDerefCopied<CurrentTurn>,
-            AsDerefMut<ActiveCurio>,
-            &Teams,
-            &mut TeamStatus,
-        ),
-        With<Node>,
-    >,
-    players: Query<
-        (
-            AsDerefCopied<OnTeam>,
-            AsDerefCopied<InNode>,
-            Option<&PlayerConfiguration>,
-        ),
-        With<Player>,
-    >,
-    team_phases: Query<&TeamPhase, With<Team>>,
-    mut curios: ParamSet<(
+    players: Query<(AsDerefCopied<OnTeam>, Option<&PlayerConfiguration>), With<Player>>,
+    mut params: ParamSet<(
+        NodeOpPreview,
+        Query<(&mut EntityGrid, AsDerefMut<ActiveCurio>), With<Node>>,
         Query<CurioQ, With<Curio>>,
-        Query<(AsDerefMut<MaximumSize>, AsDerefMut<MovementSpeed>), With<Curio>>,
+        Query<CurioEffectQ, With<Curio>>,
     )>,
-    curio_teams: Query<AsDerefCopied<OnTeam>, With<Curio>>,
 ) -> OpImplResult {
     if let NodeOp::PerformCurioAction {
         action_id,
@@ -258,135 +331,25 @@ fn opsys_node_action(
         target,
     } = node_op
     {
-        let (player_team_id, node_id, player_config) = players.get(player).critical()?;
-        let (mut grid, current_turn, mut active_curio, teams, mut team_status) =
-            nodes.get_mut(node_id).critical()?;
-
-        if team_status.is_decided(player_team_id) {
-            Err("Cannot do any more".invalid())?;
-        }
-        if player_team_id != current_turn {
-            Err("Not this player's turn".invalid())?;
-        }
-        if *team_phases.get(player_team_id).critical()? == TeamPhase::Setup {
-            Err("Can't perform actions during setup phase".invalid())?;
-        }
-        if active_curio.is_some() && curio.is_some() && *active_curio != curio {
-            Err("There's already an active curio and it's not that one".invalid())?;
-        }
-        let curio_id = active_curio
-            .or(curio)
-            .ok_or("No curio to perform that action".invalid())?;
-
-        let curios_p0 = curios.p0();
-        let curio_q = get_assert!(curio_id, curios_p0)
-            .ok_or("Calling curio action on entity that is not a curio".critical())?;
-        if **curio_q.tapped {
-            Err("Curio is tapped".invalid())?;
-        }
-        let action_def = curio_q
-            .actions
-            .ok_or("Curio has no actions".invalid())?
-            .iter()
-            .find_map(|action_handle| {
-                let action_def = ast_action.get(action_handle)?;
-                (action_def.id() == action_id).then_some(action_def)
-            })
-            .ok_or("That action is not defined".invalid())?;
-
-        if let Some(range) = action_def.range() {
-            if !range.in_range_of(grid.as_ref(), curio_id, target) {
-                Err("Target out of range".invalid())?;
-            }
-        }
-        for prereq in action_def.prereqs() {
-            if !prereq.satisfied(&grid, curio_id, target) {
-                Err("Prerequisites not satisfied".invalid())?;
-            }
-        }
-        if !action_def
-            .target()
-            .valid_target(&grid, curio_id, target, |id| curio_teams.get(id).ok())
+        let plan = params.p0().plan_action(player, &action_id, curio, target)?;
+        let (player_team_id, player_config) = players.get(player).critical()?;
         {
-            Err("Invalid target".invalid())?;
+            let mut nodes = params.p1();
+            let (mut grid, mut active_curio) = nodes.get_mut(plan.node_id).critical()?;
+            *grid = plan.grid;
+            *active_curio = None;
         }
-        let mut metadata = Metadata::new();
-        metadata.put(key::CURIO, curio_id).critical()?;
-        if let Some(last_active_id) = *active_curio {
-            if last_active_id != curio_id {
-                metadata.put(key::SKIPPED_ACTIVATION, true).critical()?;
-                metadata
-                    .put(key::DEACTIVATED_CURIO, last_active_id)
-                    .critical()?; // Recoverable?
-            } else {
-                metadata.put(key::SKIPPED_ACTIVATION, false).critical()?;
-            }
-        } else {
-            metadata.put(key::SKIPPED_ACTIVATION, true).critical()?;
-        }
-        let effect_metadata = action_def
-            .effects()
-            .iter()
-            .map(|effect| effect.apply_effect(&mut grid, curio_id, target, &mut curios.p1()))
-            .collect::<Result<Vec<_>, _>>()
-            .critical()?;
-
-        metadata
-            .put_optional(key::EFFECTS, Metadata::aggregate(effect_metadata))
-            .critical()?;
+        commit_curio_effects(plan.curio_effects, &mut params.p3());
 
-        let self_effects = action_def
-            .self_effects()
-            .iter()
-            .filter_map(|effect| {
-                let head = grid.head(curio_id)?;
-                Some(effect.apply_effect(&mut grid, curio_id, head, &mut curios.p1()))
-            })
-            .collect::<Result<Vec<_>, _>>()
-            .critical()?;
-        metadata
-            .put_optional(key::SELF_EFFECTS, Metadata::aggregate(self_effects))
-            .critical()?;
-        metadata.put(key::NODE_ID, node_id).critical()?;
-
-        // Have to drop curios_p0 temporarily to apply actions, then we need to bring them back to tap the piece
-        let mut curios_p0 = curios.p0();
-        let mut curio_q = get_assert_mut!(curio_id, curios_p0)
-            .ok_or("Curio disappeared mid operation".critical())?;
+        let mut curios = params.p2();
+        let mut curio_q = curios
+            .get_mut(plan.curio_id)
+            .map_err(|_| "Curio disappeared mid operation".critical())?;
         **curio_q.tapped = true;
-        *active_curio = None;
-
-        // WIP Test victory conditions
-        // Need to rework for different victory conditions, such as obtaining key items, or time limits
-        for team in teams.iter() {
-            if team_status[team].is_undecided() {
-                let still_in_this = curios
-                    .p0()
-                    .iter()
-                    .any(|curio_q| **curio_q.team == *team && grid.contains_key(curio_q.id));
-                if !still_in_this {
-                    team_status.insert(*team, VictoryStatus::Loss);
-                }
-            }
-        }
-        let remaining_teams: Vec<Entity> = teams
-            .iter()
-            .filter(|team| team_status[*team].is_undecided())
-            .copied()
-            .collect();
-        if remaining_teams.len() == 1 {
-            let team = remaining_teams[0];
-            let is_victory_flawed = curios.p0().iter().any(|curio_q| {
-                curio_q.in_node == node_id
-                    && **curio_q.team == team
-                    && !grid.contains_key(curio_q.id)
-            });
-            let victory_status = if is_victory_flawed {
-                VictoryStatus::Victory
-            } else {
-                VictoryStatus::PerfectVictory
-            };
-            team_status.insert(team, victory_status);
+        if let (Some(mut action_history), Some(new_action_history)) =
+            (curio_q.action_history, plan.action_history)
+        {
+            *action_history = new_action_history;
         }
 
         // TODO probably don't bother if the game is over
@@ -396,7 +359,6 @@ fn opsys_node_action(
             .unwrap_or(false)
         {
             let all_curios_tapped = curios
-                .p0()
                 .iter()
                 .all(|curio_q| **curio_q.team != player_team_id || **curio_q.tapped);
             if all_curios_tapped {
@@ -404,73 +366,232 @@ fn opsys_node_action(
             }
         }
 
-        Ok(metadata)
+        Ok(plan.metadata)
     } else {
         Err(OpError::MismatchedOpSystem)
     }
 }
 
-fn opsys_node_activate(
+fn opsys_node_use_item(
     In((player, node_op)): In<(Entity, NodeOp)>,
-    mut nodes: Query<
-        (
-            AsDerefCopied<CurrentTurn>,
-            AsDerefMut<ActiveCurio>,
-            &TeamStatus,
-        ),
-        With<Node>,
-    >,
-    players: Query<(AsDerefCopied<OnTeam>, AsDerefCopied<InNode>), With<Player>>,
-    team_phases: Query<&TeamPhase, With<Team>>,
-    mut curios: Query<CurioQ, With<Curio>>,
+    mut params: ParamSet<(
+        NodeOpPreview,
+        Query<&mut EntityGrid, With<Node>>,
+        Query<CurioEffectQ, With<Curio>>,
+        Query<&mut Inventory, With<Player>>,
+    )>,
 ) -> OpImplResult {
-    if let NodeOp::ActivateCurio { curio_id } = node_op {
-        let mut metadata = Metadata::default();
-        let (player_team_id, node_id) = players.get(player).critical()?;
-        let (current_turn, mut active_curio, team_status) = nodes.get_mut(node_id).critical()?;
+    let NodeOp::UseItem { item_id, target } = node_op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    let plan = params.p0().plan_use_item(player, &item_id, target)?;
+    *params.p1().get_mut(plan.node_id).critical()? = plan.grid;
+    commit_curio_effects(plan.curio_effects, &mut params.p2());
+    params
+        .p3()
+        .get_mut(player)
+        .critical()?
+        .try_remove(&item_id, 1);
+    Ok(plan.metadata)
+}
 
-        if team_status.is_decided(player_team_id) {
-            Err("Cannot do any more".invalid())?;
-        }
+/// Finds the definition of one of a curio's actions
+fn find_action<'a>(
+    ast_action: &'a Assets<Action>,
+    actions: Option<&Actions>,
+    action_id: &str,
+) -> Result<&'a Action, OpError> {
+    actions
+        .ok_or("Curio has no actions".invalid())?
+        .iter()
+        .find_map(|action_handle| {
+            let action_def = ast_action.get(action_handle)?;
+            (action_def.id() == action_id).then_some(action_def)
+        })
+        .ok_or("That action is not defined".invalid())
+}
 
-        if player_team_id != current_turn {
-            Err("Not this player's turn".invalid())?;
-        }
-        if *team_phases.get(player_team_id).critical()? == TeamPhase::Setup {
-            Err("Can't activate pieces during setup phase".invalid())?;
-        }
+/// Finds the action performed by using an item, which has to be one that is
+/// used up
+fn find_item_action<'a>(
+    ast_action: &'a Assets<Action>,
+    item_definitions: &Reg<ItemDefinitions>,
+    item_actions: &ItemActions,
+    item_id: &str,
+) -> Result<&'a Action, OpError> {
+    let item_definition = item_definitions
+        .get(item_id)
+        .ok_or("No such item".invalid())?;
+    if item_definition.kind != ItemKind::Consumable {
+        Err("Only consumable items can be used".invalid())?;
+    }
+    item_actions
+        .get(item_id)
+        .and_then(|action_handle| ast_action.get(action_handle))
+        .ok_or("That item can't be used here".invalid())
+}
 
-        let target_curio = curios
-            .get(curio_id)
-            .map_err(|_| "Target curio not found".invalid())?;
-        if **target_curio.team != player_team_id {
-            Err("Cannot activate pieces on the other team".invalid())?;
-        }
-        if **target_curio.tapped {
-            Err("Cannot activate tapped curio".invalid())?
+/// Checks that a curio can use an action on `target` from where it is
+pub(crate) fn check_action_target<P: CurioPrereqAccess, T: Fn(Entity) -> Option<Entity>>(
+    action_def: &Action,
+    grid: &EntityGrid,
+    curio_id: Entity,
+    target: UVec2,
+    prereqs: &P,
+    team_check: T,
+    alliances: Option<&Alliances>,
+) -> Result<(), OpError> {
+    if let Some(range) = action_def.range() {
+        if !range.in_range_of(grid, curio_id, target) {
+            Err("Target out of range".invalid())?;
         }
-        if let Some(last_active) = *active_curio {
-            if last_active == curio_id {
-                Err("That curio is already active".invalid())?;
-            }
+    }
+    if let Some(prereq) = action_def.unsatisfied_prereq(grid, curio_id, Some(target), prereqs) {
+        Err(OpError::InvalidOp(format!(
+            "Prerequisite not satisfied: {prereq}"
+        )))?;
+    }
+    if !action_def
+        .target()
+        .valid_target(grid, curio_id, target, team_check, alliances)
+    {
+        Err("Invalid target".invalid())?;
+    }
+    Ok(())
+}
+
+/// Records whether performing an action skips activating the curio, and which
+/// curio it deactivates if so
+fn put_activation_metadata(
+    metadata: &mut Metadata,
+    active_curio: Option<Entity>,
+    curio_id: Entity,
+) -> Result<(), OpError> {
+    if let Some(last_active_id) = active_curio {
+        if last_active_id != curio_id {
+            metadata.put(key::SKIPPED_ACTIVATION, true).critical()?;
             metadata
-                .put(key::DEACTIVATED_CURIO, last_active)
+                .put(key::DEACTIVATED_CURIO, last_active_id)
                 .critical()?; // Recoverable?
-            **curios.get_mut(last_active).critical()?.tapped = true;
+        } else {
+            metadata.put(key::SKIPPED_ACTIVATION, false).critical()?;
         }
-        *active_curio = Some(curio_id);
+    } else {
+        metadata.put(key::SKIPPED_ACTIVATION, true).critical()?;
+    }
+    Ok(())
+}
+
+/// Applies an action's effects to its target, the area around it and its
+/// user. Only the grid and `curio_effects` are changed, so that actions can
+/// be previewed on copies of them.
+#[allow(clippy::too_many_arguments)]
+fn apply_action_effects<C: CurioEffectAccess, F: Fn(Entity) -> Option<Entity>>(
+    metadata: &mut Metadata,
+    grid: &mut EntityGrid,
+    curio_id: Entity,
+    action_def: &Action,
+    target: UVec2,
+    team_check: F,
+    alliances: Option<&Alliances>,
+    curio_effects: &mut C,
+) -> Result<(), OpError> {
+    let effect_metadata = action_def
+        .effects()
+        .iter()
+        .map(|effect| effect.apply_effect(grid, curio_id, target, curio_effects))
+        .collect::<Result<Vec<_>, _>>()
+        .critical()?;
+
+    metadata
+        .put_optional(key::EFFECTS, Metadata::aggregate(effect_metadata))
+        .critical()?;
+
+    // The area is found after the main effects apply, so that it only
+    // includes curios that are still there to be affected
+    let area_effects = action_def
+        .area_targets(grid, curio_id, target, team_check, alliances)
+        .into_iter()
+        .filter_map(|area_target| {
+            action_def
+                .effects()
+                .iter()
+                .map(|effect| effect.apply_effect(grid, curio_id, area_target, curio_effects))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Metadata::aggregate)
+                .transpose()
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .critical()?;
+    if !area_effects.is_empty() {
+        metadata.put(key::AREA_EFFECTS, area_effects).critical()?;
+    }
+
+    let self_effects = action_def
+        .self_effects()
+        .iter()
+        .filter_map(|effect| {
+            let head = grid.head(curio_id)?;
+            Some(effect.apply_effect(grid, curio_id, head, curio_effects))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .critical()?;
+    metadata
+        .put_optional(key::SELF_EFFECTS, Metadata::aggregate(self_effects))
+        .critical()?;
+    Ok(())
+}
+
+fn opsys_node_activate(
+    In((player, node_op)): In<(Entity, NodeOp)>,
+    players: Query<AsDerefCopied<InNode>, With<Player>>,
+    mut params: ParamSet<(
+        NodeOpPreview,
+        Query<AsDerefMut<ActiveCurio>, With<Node>>,
+        Query<AsDerefMut<IsTapped>, With<Curio>>,
+    )>,
+) -> OpImplResult {
+    if let NodeOp::ActivateCurio { curio_id } = node_op {
+        let metadata = params.p0().plan_activate(player, curio_id)?;
+        let node_id = players.get(player).critical()?;
+        if let Some(last_active) = metadata.get_optional(key::DEACTIVATED_CURIO).critical()? {
+            *params.p2().get_mut(last_active).critical()? = true;
+        }
+        *params.p1().get_mut(node_id).critical()? = Some(curio_id);
         Ok(metadata)
     } else {
         Err(OpError::MismatchedOpSystem)
     }
 }
 
+/// Checks that a team can make `curio_id` its active curio
+fn check_can_activate(
+    team_id: Entity,
+    curio_id: Entity,
+    curio_team: Entity,
+    curio_is_tapped: bool,
+    active_curio: Option<Entity>,
+) -> Result<(), OpError> {
+    if curio_team != team_id {
+        Err("Cannot activate pieces on the other team".invalid())?;
+    }
+    if curio_is_tapped {
+        Err("Cannot activate tapped curio".invalid())?
+    }
+    if active_curio == Some(curio_id) {
+        Err("That curio is already active".invalid())?;
+    }
+    Ok(())
+}
+
 fn opsys_node_access_point(
     In((player, node_op)): In<(Entity, NodeOp)>,
     mut commands: Commands,
-    cards: Query<CardQuery>,
-    mut access_points: Query<(&mut AccessPoint, &mut NodePiece)>,
-    mut players: Query<(&mut PlayedCards, &Deck, &InNode), With<Player>>,
+    mut params: ParamSet<(
+        NodeOpPreview,
+        Query<(&mut AccessPoint, &mut NodePiece)>,
+        Query<&mut PlayedCards, With<Player>>,
+    )>,
 ) -> OpImplResult {
     let (access_point_id, next_card_id) = match node_op {
         NodeOp::LoadAccessPoint {
@@ -480,63 +601,22 @@ fn opsys_node_access_point(
         NodeOp::UnloadAccessPoint { access_point_id } => (access_point_id, None),
         _ => return Err(OpError::MismatchedOpSystem),
     };
-    let mut metadata = Metadata::new();
-    let (mut access_point, mut node_piece) = access_points
-        .get_mut(access_point_id)
-        .map_err(|_| "No such access point".invalid())?;
-    let (mut played_cards, deck, &InNode(node_id)) = players.get_mut(player).critical()?;
-    metadata.put(key::NODE_ID, node_id).invalid()?;
-
-    if access_point.card.is_none() && next_card_id.is_none() {
-        Err("Access point is already unloaded".invalid())?;
-    }
-    if access_point.card == next_card_id {
-        Err("That is already loaded".invalid())?;
-    }
+    let plan = params
+        .p0()
+        .plan_access_point(player, access_point_id, next_card_id)?;
+    *params.p2().get_mut(player).critical()? = plan.played_cards;
+    let mut access_points = params.p1();
+    let (mut access_point, mut node_piece) = access_points.get_mut(access_point_id).critical()?;
+    node_piece.set_display_id(plan.display_id);
+    access_point.card = next_card_id;
 
     let mut access_point_commands = commands.entity(access_point_id);
-    if let Some(next_card_id) = next_card_id {
-        if !played_cards.can_be_played(deck, next_card_id) {
-            Err("Already played all of those".invalid())?;
-        }
-        let card_q = cards
-            .get(next_card_id)
-            .map_err(|_| "Cannot find that card or it is not loaded".invalid())?;
-        metadata.put(key::CARD, next_card_id).critical()?;
-
-        // VALIDATIONS COMPLETE, COMMENCE MUTATING STATE
-        if let Some(old_card_id) = access_point.card {
-            let withdrawn_successfully = played_cards.withdraw_card_from(old_card_id, node_id);
-            if !withdrawn_successfully {
-                Err("Attempting to unload card that wasn't played here".critical())?;
-            }
-        }
-
-        played_cards.play_card_to(deck, next_card_id, node_id);
-
-        node_piece.set_display_id(card_q.base_name.clone());
-
-        access_point_commands.insert((
-            Description::new(card_q.description.to_owned()),
-            MovementSpeed(card_q.movement_speed),
-            MaximumSize(card_q.max_size),
-            Actions(card_q.actions.clone()),
-        ));
+    if let Some(card_props) = plan.card_props {
+        access_point_commands.insert(card_props);
     } else {
-        // Unloading card
-        if let Some(old_card_id) = access_point.card {
-            let withdrawn_successfully = played_cards.withdraw_card_from(old_card_id, node_id);
-            if !withdrawn_successfully {
-                Err("Attempting to unload card that wasn't played here".critical())?;
-            }
-        }
-
-        node_piece.set_display_id(ACCESS_POINT_DISPLAY_ID.to_owned());
         access_point_commands.remove::<(Description, MovementSpeed, MaximumSize, Actions)>();
-    };
-    access_point.card = next_card_id;
-
-    Ok(metadata)
+    }
+    Ok(plan.metadata)
 }
 
 fn opsys_node_ready(
@@ -544,173 +624,179 @@ fn opsys_node_ready(
     no_op_action: Res<NoOpAction>,
     mut commands: Commands,
     cards: Query<CardQuery>,
-    mut players: Query<(Entity, &OnTeam, &InNode, AsDerefMut<IsReadyToGo>), With<Player>>,
-    mut team_phases: Query<&mut TeamPhase, With<Team>>,
-    access_points: Query<(Entity, &OnTeam, &AccessPoint), With<NodePiece>>,
-    mut nodes: Query<(&AccessPointLoadingRule, &mut EntityGrid, &Teams), With<Node>>,
+    players: Query<(Entity, AsDerefCopied<OnTeam>), (With<Player>, With<IsReadyToGo>)>,
+    mut params: ParamSet<(
+        NodeOpPreview,
+        Query<AsDerefMut<IsReadyToGo>, With<Player>>,
+        Query<&mut TeamPhase, With<Team>>,
+        Query<&mut EntityGrid, With<Node>>,
+    )>,
 ) -> OpImplResult {
     if !matches!(node_op, NodeOp::ReadyToGo) {
         Err(OpError::MismatchedOpSystem)?;
     }
 
-    let mut metadata = Metadata::new();
-
-    let (_, OnTeam(player_team), InNode(node_id), is_ready_to_go) =
-        players.get(player).critical()?;
-    if *is_ready_to_go {
-        Err("You are already marked as ready".invalid())?;
-    }
-    let (access_point_loading_rule, mut grid, teams) = nodes.get_mut(*node_id).critical()?;
-    let relevant_teams = match access_point_loading_rule {
-        AccessPointLoadingRule::Staggered => vec![*player_team],
-        AccessPointLoadingRule::Simultaneous => teams.0.clone(),
+    let plan = params.p0().plan_ready(player)?;
+    let Some(ready_teams) = plan.ready_teams else {
+        *params.p1().get_mut(player).critical()? = true;
+        return Ok(plan.metadata);
     };
-    let has_loaded_access_point = access_points
-        .iter()
-        .any(|(id, OnTeam(team), access_point)| {
-            grid.contains_key(id) && team == player_team && access_point.card.is_some()
-        });
-    if !has_loaded_access_point {
-        Err("Must load an access point first".invalid())?;
+    for (player_id, team) in players.iter() {
+        if ready_teams.contains(&team) {
+            commands.entity(player_id).remove::<IsReadyToGo>();
+        }
     }
+    let mut nodes = params.p3();
+    let mut grid = nodes.get_mut(plan.node_id).critical()?;
+    for (node_piece, card_id) in plan.access_points.into_iter() {
+        card_id
+            .and_then(|card_id| {
+                let card_q = get_assert!(card_id, cards)?;
+                let mut ap_commands = commands.entity(node_piece);
 
-    let relevant_teams_are_ready =
-        players
-            .iter()
-            .all(|(iter_player, OnTeam(team), _, ready_to_go)| {
-                !relevant_teams.contains(team) || *ready_to_go || iter_player == player
-            });
-    metadata
-        .put(key::ALL_TEAM_MEMBERS_READY, relevant_teams_are_ready)
-        .critical()?;
+                ap_commands
+                    .insert((
+                        ActionHistory::default(),
+                        Curio::new_with_card(card_q.nickname_or_name(), card_id),
+                        IsTapped::default(),
+                        LostSquares::default(),
+                        MovesTaken::default(),
+                        StatusEffects::default(),
+                        Tags::default(),
+                    ))
+                    .remove::<AccessPoint>();
 
-    if relevant_teams_are_ready {
-        let relevant_access_points: Vec<(Entity, Option<Entity>)> = access_points
-            .iter()
-            .filter(|(id, OnTeam(team), _)| player_team == team && grid.contains_key(*id))
-            .map(|(id, _, access_point)| (id, access_point.card))
-            .collect();
-        for (player_id, OnTeam(team), _, _) in players.iter() {
-            if relevant_teams.contains(team) {
-                commands.entity(player_id).remove::<IsReadyToGo>();
-            }
-        }
-        for (node_piece, card_id) in relevant_access_points.into_iter() {
-            card_id
-                .and_then(|card_id| {
-                    let card_q = get_assert!(card_id, cards)?;
-                    let mut ap_commands = commands.entity(node_piece);
-
-                    ap_commands
-                        .insert((
-                            Curio::new_with_card(card_q.nickname_or_name(), card_id),
-                            IsTapped::default(),
-                            MovesTaken::default(),
-                        ))
-                        .remove::<AccessPoint>();
-
-                    if !card_q.prevent_no_op() {
-                        // Add No Op action
-                        let mut new_actions = card_q.actions.clone();
-                        new_actions.push(no_op_action.0.clone());
-
-                        ap_commands.insert(Actions(new_actions));
-                    }
-                    Some(())
-                })
-                .unwrap_or_else(|| {
-                    grid.remove_entity(node_piece);
-                    // Leaving access points lying around seems bug prone, but so does despawning them?
-                    // TODO Use play phase checks in ops, then remove the following line
-                    commands.entity(node_piece).despawn()
-                });
-        }
-        for team in relevant_teams {
-            *team_phases
-                .get_mut(team)
-                .expect("Team should have team phase component") = TeamPhase::Play;
-        }
-    } else {
-        *players.get_mut(player).unwrap().3 = true;
-    };
+                if !card_q.prevent_no_op() {
+                    // Add No Op action
+                    let mut new_actions = card_q.actions.clone();
+                    new_actions.push(no_op_action.0.clone());
 
-    Ok(metadata)
+                    ap_commands.insert(Actions(new_actions));
+                }
+                Some(())
+            })
+            .unwrap_or_else(|| {
+                grid.remove_entity(node_piece);
+                // Leaving access points lying around seems bug prone, but so does despawning them?
+                // TODO Use play phase checks in ops, then remove the following line
+                commands.entity(node_piece).despawn()
+            });
+    }
+    let mut team_phases = params.p2();
+    for team in ready_teams {
+        *team_phases
+            .get_mut(team)
+            .expect("Team should have team phase component") = TeamPhase::Play;
+    }
+
+    Ok(plan.metadata)
 }
 
 fn opsys_node_end_turn(
     In((player, node_op)): In<(Entity, NodeOp)>,
-    mut nodes: Query<
-        (
-            AsDerefMut<CurrentTurn>,
-            AsDerefMut<ActiveCurio>,
-            AsDeref<Teams>,
-            &TeamStatus,
-        ),
-        With<Node>,
-    >,
-    players: Query<(AsDerefCopied<OnTeam>, AsDerefCopied<InNode>), With<Player>>,
-    mut pieces: Query<
-        (
-            Entity,
-            AsDerefCopied<OnTeam>,
-            AsDerefMut<IsTapped>,
-            AsDerefMut<MovesTaken>,
-        ),
-        With<NodePiece>,
-    >,
+    mut params: ParamSet<(
+        NodeOpPreview,
+        Query<(AsDerefMut<CurrentTurn>, AsDerefMut<ActiveCurio>), With<Node>>,
+        Query<
+            (
+                AsDerefCopied<OnTeam>,
+                AsDerefMut<IsTapped>,
+                AsDerefMut<MovesTaken>,
+                Option<&mut StatusEffects>,
+                Option<&mut ActionHistory>,
+            ),
+            With<NodePiece>,
+        >,
+        Query<AsDerefMut<TurnsEnded>, With<Team>>,
+    )>,
 ) -> OpImplResult {
     if !matches!(node_op, NodeOp::EndTurn) {
         Err(OpError::MismatchedOpSystem)?;
     }
 
-    let (player_team, node) = players.get(player).critical()?;
-    let (mut current_turn, mut active_curio, teams, team_status) =
-        nodes.get_mut(node).critical()?;
-    if team_status.is_decided(player_team) {
-        Err("Cannot do any more".invalid())?;
+    let plan = params.p0().plan_end_turn(player)?;
+    {
+        let mut nodes = params.p1();
+        let (mut current_turn, mut active_curio) = nodes.get_mut(plan.node_id).critical()?;
+        active_curio.set_if_neq(None);
+        // Teams that have already won or lost are skipped, so that a free-for-all
+        // can continue after a team is knocked out
+        *current_turn = plan.next_team;
     }
-
-    if *current_turn.as_ref() != player_team {
-        Err("Not this player's turn")?;
+    if let Ok(mut turns_ended) = params.p3().get_mut(plan.team_id) {
+        *turns_ended += 1;
     }
-    let mut metadata = Metadata::new();
-    if let Some(id) = *active_curio {
-        metadata.put(key::CURIO, id).critical()?;
+
+    let mut pieces = params.p2();
+    // Cooldowns count down at the end of their team's turn
+    for (team, _, _, _, action_history) in pieces.iter_mut() {
+        if team != plan.team_id {
+            continue;
+        }
+        if let Some(mut action_history) = action_history {
+            action_history.tick();
+        }
     }
-    active_curio.set_if_neq(None);
-    let team_index = teams
-        .iter()
-        .position(|team_id| *team_id == player_team)
-        .ok_or("Can't find this team".critical())?;
-    *current_turn = teams[(team_index + 1) % teams.len()];
 
     // Gotta untap all player things
-    let moved_pieces: HashMap<Entity, u32> = pieces
-        .iter_mut()
-        .filter_map(|(id, team, mut is_tapped, mut moves_taken)| {
-            if team == player_team && (*is_tapped || *moves_taken > 0) {
-                let old_moves_taken = *moves_taken;
-                *moves_taken = 0;
-                *is_tapped = false;
-                Some((id, old_moves_taken))
-            } else {
-                None
-            }
-        })
-        .collect();
-    metadata.put(key::MOVED_PIECES, moved_pieces).critical()?;
-    Ok(metadata)
+    for &piece in plan.moved_pieces.iter() {
+        let (_, mut is_tapped, mut moves_taken, ..) = pieces.get_mut(piece).critical()?;
+        *moves_taken = 0;
+        *is_tapped = false;
+    }
+
+    // Statuses count down at the start of their team's turn, so that a status
+    // applied during the team's own turn still affects its next one. Stunned
+    // curios lose the turn that's starting.
+    for (team, mut is_tapped, _, status_effects, _) in pieces.iter_mut() {
+        if team != plan.next_team {
+            continue;
+        }
+        let Some(mut status_effects) = status_effects else {
+            continue;
+        };
+        if !status_effects.is_empty() {
+            status_effects.tick();
+        }
+        if status_effects.has_status(Status::Stunned) {
+            *is_tapped = true;
+        }
+    }
+    Ok(plan.metadata)
 }
 
-fn opsys_telegraph_action(In((_, op)): In<(Entity, NodeOp)>) -> OpImplResult {
-    if let NodeOp::TelegraphAction { .. } = op {
-        let metadata = Metadata::new();
-        Ok(metadata)
-    } else {
-        Err(OpError::MismatchedOpSystem)
+/// Checks that a team can end its turn, returning the team whose turn is next
+fn next_turn(
+    team_id: Entity,
+    current_turn: Entity,
+    teams: &Teams,
+    team_status: &TeamStatus,
+) -> Result<Entity, OpError> {
+    // A team that has just lost, such as by running out of time, still ends
+    // the turn it was in the middle of
+    if team_status.is_decided(team_id) && current_turn != team_id {
+        Err("Cannot do any more".invalid())?;
+    }
+    if current_turn != team_id {
+        Err("Not this player's turn")?;
+    }
+    match teams.next_undecided(team_id, team_status) {
+        Some(next_team) => Ok(next_team),
+        None if team_status.is_decided(team_id) => Err("Cannot do any more".invalid()),
+        None => Err("Can't find this team".critical()),
     }
 }
 
+fn opsys_telegraph_action(
+    In((player, op)): In<(Entity, NodeOp)>,
+    node_op_preview: NodeOpPreview,
+) -> OpImplResult {
+    let NodeOp::TelegraphAction { action_id, target } = op else {
+        Err(OpError::MismatchedOpSystem)?
+    };
+    node_op_preview.plan_telegraph(player, &action_id, target)
+}
+
 fn opsys_node_enter_battle(
     In((player_id, node_op)): In<(Entity, NodeOp)>,
     mut commands: Commands,
@@ -827,104 +913,323 @@ fn opsys_node_quit_battle(
 fn opsys_node_undo(
     In((player_id, node_op)): In<(Entity, NodeOp)>,
     mut commands: Commands,
-    q_player: Query<(&OnTeam, &InNode), With<Player>>,
-    mut q_node: Query<(&mut EntityGrid, AsDerefMut<ActiveCurio>, &TeamStatus), With<Node>>,
-    mut q_team: Query<AsDerefMut<NodeUndoStack>, With<Team>>,
-    mut q_curio: Query<(AsDerefMut<MovesTaken>, AsDerefMut<IsTapped>), With<Curio>>,
-    mut q_curio_effects: Query<(AsDerefMut<MaximumSize>, AsDerefMut<MovementSpeed>), With<Curio>>,
+    mut params: ParamSet<(
+        NodeOpPreview,
+        Query<&mut NodeUndoStack, With<Team>>,
+        Query<(&mut EntityGrid, AsDerefMut<ActiveCurio>), With<Node>>,
+        Query<
+            (
+                AsDerefMut<MovesTaken>,
+                AsDerefMut<IsTapped>,
+                Option<&mut ActionHistory>,
+            ),
+            With<Curio>,
+        >,
+        Query<CurioEffectQ, With<Curio>>,
+        Query<&mut Inventory, With<Player>>,
+    )>,
 ) -> OpImplResult {
     if !matches!(node_op, NodeOp::Undo) {
         return Err(OpError::MismatchedOpSystem);
     }
-    let (&OnTeam(team_id), &InNode(node_id)) = q_player.get(player_id).invalid()?;
-    let mut undo_queue = q_team.get_mut(team_id).invalid()?;
-    if undo_queue.len() == 0 {
-        Err("Not able to undo any more".invalid())?;
+    let plan = params.p0().plan_undo(player_id)?;
+    {
+        let mut undo_stacks = params.p1();
+        let mut undo_stack = undo_stacks.get_mut(plan.team_id).critical()?;
+        let undo_group = undo_stack.pop_undo_group();
+        undo_stack.push_redo_group(undo_group);
     }
-    let (mut grid, mut active_curio, team_status) = q_node.get_mut(node_id).critical()?;
-    if team_status.is_decided(team_id) {
-        Err("Cannot do any more".invalid())?;
+    {
+        let mut nodes = params.p2();
+        let (mut grid, mut active_curio) = nodes.get_mut(plan.node_id).critical()?;
+        *grid = plan.grid;
+        active_curio.set_if_neq(plan.active_curio);
     }
-    let mut undo_metadata = Metadata::new();
-    undo_metadata.put(key::NODE_ID, node_id).invalid()?;
-    for op_to_undo in undo_queue.drain(..).rev() {
-        if let Ok(metadata) = op_to_undo.result() {
-            match op_to_undo.op() {
-                NodeOp::ActivateCurio { .. } => {
-                    if let Some(curio_id) = *active_curio {
-                        undo_metadata.put(key::CURIO, curio_id).critical()?;
-                        *active_curio = None;
-                    }
-                },
-                NodeOp::MoveActiveCurio { .. } => {
-                    let curio_id = metadata.get_required(key::CURIO).critical()?;
-                    let (mut moves_taken, mut is_tapped) = q_curio.get_mut(curio_id).critical()?;
-                    if let Some(dropped_square) =
-                        metadata.get_optional(key::DROPPED_SQUARE).critical()?
-                    {
-                        grid.push_back(dropped_square, curio_id);
-                    }
-                    undo_metadata.put(key::CURIO, curio_id).critical()?;
-                    grid.pop_front(curio_id);
-                    let target_pt = metadata.get_required(key::TARGET_POINT).critical()?;
-                    if metadata
-                        .get_optional(key::REPLACED_SQUARE)
-                        .critical()?
-                        .unwrap_or(false)
-                    {
-                        let replaced_square_next = metadata
-                            .get_optional(key::REPLACED_SQUARE_NEXT)
-                            .critical()?;
-                        grid.insert_square_before(curio_id, target_pt, replaced_square_next);
-                    }
-
-                    if let Some(pickup_id) = metadata.get_optional(key::PICKUP_ID).critical()? {
-                        // TODO Configurable return pick-up (cq should, nf should not)
-                        grid.put_item(target_pt, pickup_id);
-                        commands.entity(pickup_id).remove::<Claimed>();
-                    }
-                    active_curio.set_if_neq(Some(curio_id)); // In case something goes wrong before full undo can occur
-                    is_tapped.set_if_neq(false);
-                    *moves_taken -= 1;
-                },
-                NodeOp::PerformCurioAction { .. } => {
-                    let curio_id = metadata.get_required(key::CURIO).critical()?;
-                    let (_, mut is_tapped) = q_curio.get_mut(curio_id).critical()?;
-                    let skipped_activation =
-                        metadata.get_required(key::SKIPPED_ACTIVATION).critical()?;
-                    undo_metadata.put(key::CURIO, curio_id).critical()?;
-                    if !skipped_activation {
-                        active_curio.set_if_neq(Some(curio_id));
-                    } else {
-                        active_curio.set_if_neq(None);
-                    }
-                    is_tapped.set_if_neq(false);
-
-                    if let Some(effects) = metadata.get_optional(key::EFFECTS).critical()? {
-                        // More than invalid, but not critical
-                        ActionEffect::revert_effects(
-                            effects,
-                            &mut grid,
-                            q_curio_effects.as_query_lens(),
-                        )
-                        .critical()?;
-                    }
-                    if let Some(self_effects) =
-                        metadata.get_optional(key::SELF_EFFECTS).invalid()?
-                    {
-                        ActionEffect::revert_effects(
-                            self_effects,
-                            &mut grid,
-                            q_curio_effects.as_query_lens(),
-                        )
-                        .critical()?;
-                    }
-                },
-                _ => {
-                    log::error!("Invalid op in undo queue: {op_to_undo:?}");
+    let mut curios = params.p3();
+    for (curio_id, curio_turn) in plan.curio_turns {
+        let (mut moves_taken, mut is_tapped, action_history) =
+            curios.get_mut(curio_id).critical()?;
+        moves_taken.set_if_neq(curio_turn.moves_taken);
+        is_tapped.set_if_neq(curio_turn.tapped);
+        if let (Some(mut action_history), Some(old_action_history)) =
+            (action_history, curio_turn.action_history)
+        {
+            *action_history = old_action_history;
+        }
+    }
+    commit_curio_effects(plan.curio_effects, &mut params.p4());
+    let mut inventories = params.p5();
+    for (player_id, item_id) in plan.refunds {
+        inventories.get_mut(player_id).critical()?.add(&item_id, 1);
+    }
+    for pickup_id in plan.returned_pickups {
+        commands.entity(pickup_id).remove::<Claimed>();
+    }
+    Ok(plan.metadata)
+}
+
+/// Reverts the effects [`apply_action_effects`] applied, in reverse order
+fn revert_action_effects<C: CurioEffectAccess>(
+    metadata: &Metadata,
+    grid: &mut EntityGrid,
+    curio_effects: &mut C,
+) -> Result<(), OpError> {
+    if let Some(self_effects) = metadata.get_optional(key::SELF_EFFECTS).invalid()? {
+        ActionEffect::revert_effects(self_effects, grid, curio_effects).critical()?;
+    }
+    if let Some(area_effects) = metadata.get_optional(key::AREA_EFFECTS).critical()? {
+        for effects in area_effects.into_iter().rev() {
+            ActionEffect::revert_effects(effects, grid, curio_effects).critical()?;
+        }
+    }
+    if let Some(effects) = metadata.get_optional(key::EFFECTS).critical()? {
+        // More than invalid, but not critical
+        ActionEffect::revert_effects(effects, grid, curio_effects).critical()?;
+    }
+    Ok(())
+}
+
+fn opsys_node_redo(
+    In((player_id, node_op)): In<(Entity, NodeOp)>,
+    mut res_core_ops: ResMut<CoreOps>,
+    q_player: Query<AsDerefCopied<OnTeam>, With<Player>>,
+    mut params: ParamSet<(NodeOpPreview, Query<&mut NodeUndoStack, With<Team>>)>,
+) -> OpImplResult {
+    if !matches!(node_op, NodeOp::Redo) {
+        return Err(OpError::MismatchedOpSystem);
+    }
+    let redo_metadata = params.p0().plan_redo(player_id)?;
+    let team_id = q_player.get(player_id).critical()?;
+    let (source, first_op) = params
+        .p1()
+        .get_mut(team_id)
+        .critical()?
+        .pop_redo_group()
+        .ok_or("Not able to redo any more".critical())?;
+    // The ops are performed again as new ops, so that their results can be
+    // undone again. Each is performed by whoever performed it the first time.
+    res_core_ops.request(source, first_op);
+    Ok(redo_metadata)
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::event::Events;
+
+    use super::*;
+    use crate::card::{ActionRange, ActionTarget};
+    use crate::item::ItemDefinition;
+    use crate::node::node_testing::TestBattle;
+    use crate::op::{
+        OpClient, OpExecutor, OpExecutorPlugin, OpPlugin, OpResult, OpServer, OpServerPlugin,
+    };
+    use crate::registry::Reg;
+
+    /// A battle where the first team has an active curio next to an enemy,
+    /// and an item of each kind that damages enemies
+    fn item_battle() -> (TestBattle, Entity) {
+        let mut battle = TestBattle::new(4, 4).with_undo();
+        let bomb = battle.add_action(Action {
+            id: "bomb".to_owned(),
+            range: Some(ActionRange::new(1)),
+            effects: vec![ActionEffect::Damage(1)],
+            target: ActionTarget::Enemies,
+            ..default()
+        });
+        let player = battle.players[0];
+        let world = battle.app.world_mut();
+        for (item_id, kind) in [
+            ("item:bomb", ItemKind::Consumable),
+            ("item:key", ItemKind::Key),
+        ] {
+            world.resource_mut::<Reg<ItemDefinitions>>().insert(
+                item_id,
+                ItemDefinition {
+                    kind,
+                    description: String::new(),
+                    action: None,
                 },
+            );
+            world
+                .resource_mut::<ItemActions>()
+                .insert(item_id, bomb.clone());
+            world.get_mut::<Inventory>(player).unwrap().add(item_id, 1);
+        }
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[]);
+        let enemy = battle.spawn_curio(1, &[UVec2::new(1, 0), UVec2::new(2, 0)], 0, &[]);
+        battle.perform(0, NodeOp::ActivateCurio { curio_id: curio });
+        (battle, enemy)
+    }
+
+    fn use_item(item_id: &str) -> NodeOp {
+        NodeOp::UseItem {
+            item_id: item_id.to_owned(),
+            target: UVec2::new(1, 0),
+        }
+    }
+
+    fn item_count(battle: &TestBattle, item_id: &str) -> u32 {
+        battle.get::<Inventory>(battle.players[0]).count(item_id)
+    }
+
+    #[test]
+    fn test_use_item_and_undo() {
+        let (mut battle, enemy) = item_battle();
+        let results = battle.perform(0, use_item("item:bomb"));
+        assert!(results[0].result().is_ok(), "{results:?}");
+        assert_eq!(battle.grid().len_of(enemy), 1);
+        assert_eq!(item_count(&battle, "item:bomb"), 0);
+
+        let results = battle.perform(0, use_item("item:bomb"));
+        assert!(results[0].result().is_err(), "{results:?}");
+
+        let results = battle.perform(0, NodeOp::Undo);
+        assert!(results[0].result().is_ok(), "{results:?}");
+        assert_eq!(battle.grid().len_of(enemy), 2);
+        assert_eq!(item_count(&battle, "item:bomb"), 1);
+    }
+
+    /// Runs the client and server until the client has `count` more results
+    #[test]
+    fn test_undo_teammates_item_use() {
+        let (mut battle, enemy) = item_battle();
+        let world = battle.app.world_mut();
+        let mut inventory = Inventory::default();
+        inventory.add("item:bomb", 1);
+        let teammate = world
+            .spawn((
+                Player,
+                OnTeam(battle.teams[0]),
+                InNode(battle.node),
+                inventory,
+                PlayedCards::default(),
+            ))
+            .id();
+        battle.players.push(teammate);
+
+        let results = battle.perform(2, use_item("item:bomb"));
+        assert!(results[0].result().is_ok(), "{results:?}");
+        assert_eq!(battle.grid().len_of(enemy), 1);
+        assert_eq!(battle.get::<Inventory>(teammate).count("item:bomb"), 0);
+
+        // The item goes back to the teammate who used it
+        let results = battle.perform(0, NodeOp::Undo);
+        assert!(results[0].result().is_ok(), "{results:?}");
+        assert_eq!(battle.grid().len_of(enemy), 2);
+        assert_eq!(battle.get::<Inventory>(teammate).count("item:bomb"), 1);
+        assert_eq!(item_count(&battle, "item:bomb"), 1);
+    }
+
+    fn exchange_results(
+        client: &mut App,
+        battle: &mut TestBattle,
+        count: usize,
+    ) -> Vec<OpResult<NodeOp>> {
+        let mut results = Vec::new();
+        for _ in 0..200 {
+            client.update();
+            battle.update();
+            results.extend(
+                client
+                    .world_mut()
+                    .resource_mut::<Events<OpResult<NodeOp>>>()
+                    .drain(),
+            );
+            if results.len() >= count {
+                break;
             }
+            std::thread::sleep(std::time::Duration::from_millis(10));
         }
+        results
+    }
+
+    #[test]
+    fn test_activate_curio_over_network() {
+        let mut battle = TestBattle::new(4, 4);
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 1, &[]);
+        battle
+            .app
+            .add_plugins(OpServerPlugin::<CoreOps>::new("127.0.0.1:0").allow_op::<NodeOp>());
+        let player = battle.players[0];
+        let mut server = battle.app.world_mut().resource_mut::<OpServer>();
+        server.host_player(player);
+        let address = server.local_addr();
+
+        // The client has its own ids, like a client that loaded the same node
+        let mut client = App::new();
+        let local_player = client.world_mut().spawn_empty().id();
+        let local_curio = client.world_mut().spawn_empty().id();
+        let unknown_curio = client.world_mut().spawn_empty().id();
+        let mut op_client =
+            OpClient::connect(address).expect("should be able to connect to local server");
+        op_client.map_entity(local_curio, curio);
+        client
+            .insert_resource(CoreOps(OpExecutor::Network(op_client)))
+            .add_plugins((
+                OpExecutorPlugin::<CoreOps>::default(),
+                OpPlugin::<NodeOp>::default(),
+            ));
+        client.update();
+
+        let mut ops = client.world_mut().resource_mut::<CoreOps>();
+        ops.request(
+            local_player,
+            NodeOp::ActivateCurio {
+                curio_id: unknown_curio,
+            },
+        );
+        ops.request(
+            local_player,
+            NodeOp::ActivateCurio {
+                curio_id: local_curio,
+            },
+        );
+        let results = exchange_results(&mut client, &mut battle, 2);
+        assert_eq!(2, results.len(), "{results:?}");
+        assert!(
+            results[0].result().is_err(),
+            "Ops with unmapped entities should be rejected: {results:?}"
+        );
+        assert!(results[1].result().is_ok(), "{results:?}");
+        assert_eq!(local_player, results[1].source());
+        assert!(matches!(
+            results[1].op(),
+            NodeOp::ActivateCurio { curio_id } if curio_id == &local_curio
+        ));
+        assert_eq!(Some(curio), **battle.get::<ActiveCurio>(battle.node));
+
+        client.world_mut().resource_mut::<CoreOps>().request(
+            local_player,
+            NodeOp::MoveActiveCurio {
+                dir: Compass::South,
+            },
+        );
+        // Results of players on the server are sent to the client too
+        battle.request(1, NodeOp::EndTurn);
+        let results = exchange_results(&mut client, &mut battle, 2);
+        let moved = results
+            .iter()
+            .find(|result| result.source() == local_player)
+            .expect("client should get the result of its move");
+        let metadata = moved.result().as_ref().expect("move should succeed");
+        assert_eq!(local_curio, metadata.get_required(key::CURIO).unwrap());
+        let node_id = metadata.get_required(key::NODE_ID).unwrap();
+        assert_ne!(battle.node, node_id, "Server ids should be mapped");
+        assert!(client.world().get_entity(node_id).is_some());
+        let other_player = results
+            .iter()
+            .find(|result| result.source() != local_player)
+            .expect("client should get the result of the other player's op")
+            .source();
+        assert!(client.world().get_entity(other_player).is_some());
+    }
+
+    #[test]
+    fn test_only_consumable_items_can_be_used() {
+        let (mut battle, enemy) = item_battle();
+        let results = battle.perform(0, use_item("item:key"));
+        assert!(results[0].result().is_err(), "{results:?}");
+        assert_eq!(battle.grid().len_of(enemy), 2);
+        assert_eq!(item_count(&battle, "item:key"), 1);
     }
-    Ok(undo_metadata)
 }
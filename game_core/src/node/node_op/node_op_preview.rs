@@ -0,0 +1,1190 @@
+use bevy::ecs::query::QueryData;
+use bevy::ecs::system::SystemParam;
+
+use super::node_op_undo::NodeUndoStack;
+use super::{
+    apply_action_effects, check_action_target, check_can_activate, check_team_can_play,
+    find_action, find_item_action, move_curio_on_grid, moves_left, next_turn,
+    put_activation_metadata, revert_action_effects, NodeOp, ACCESS_POINT_DISPLAY_ID,
+};
+use crate::card::{
+    Action, ActionHistory, Actions, CardQuery, CurioEffectAccess, CurioEffectProps, Deck,
+    Description, MaximumSize, MovementSpeed, PrereqQ, StatusEffects, Tags,
+};
+use crate::item::{self, Inventory, ItemActions, ItemDefinitions};
+use crate::node::{
+    key, AccessPoint, AccessPointLoadingRule, ActiveCurio, Alliances, Curio, CurrentTurn, InNode,
+    IsReadyToGo, IsTapped, LostSquares, MovesTaken, Node, NodePiece, OnTeam, Pickup, PlayedCards,
+    Team, TeamPhase, TeamStatus, Teams,
+};
+use crate::op::{OpError, OpErrorUtils, OpImplResult};
+use crate::player::Player;
+use crate::prelude::*;
+use crate::registry::Reg;
+
+/// Works out what a [`NodeOp`] would do without doing it, so that UIs can
+/// show the results of an op before it is committed.
+///
+/// Each op system plans its op here and then commits the plan, so a preview
+/// goes through exactly the same checks as the op and produces the same
+/// metadata, including what effects would be applied. Ops that change more
+/// than the battle itself, like entering a node, can't be previewed.
+#[derive(SystemParam)]
+pub struct NodeOpPreview<'w, 's> {
+    ast_action: Res<'w, Assets<Action>>,
+    res_item_definitions: Res<'w, Reg<ItemDefinitions>>,
+    res_item_actions: Res<'w, ItemActions>,
+    nodes: Query<
+        'w,
+        's,
+        (
+            &'static EntityGrid,
+            AsDerefCopied<CurrentTurn>,
+            AsDerefCopied<ActiveCurio>,
+            &'static TeamStatus,
+            &'static Teams,
+        ),
+        With<Node>,
+    >,
+    setup_nodes: Query<
+        'w,
+        's,
+        (
+            &'static AccessPointLoadingRule,
+            &'static EntityGrid,
+            &'static Teams,
+        ),
+        With<Node>,
+    >,
+    players: Query<'w, 's, (AsDerefCopied<OnTeam>, AsDerefCopied<InNode>), With<Player>>,
+    ready_players: Query<
+        'w,
+        's,
+        (
+            Entity,
+            AsDerefCopied<OnTeam>,
+            AsDerefCopied<InNode>,
+            AsDerefCopied<IsReadyToGo>,
+        ),
+        With<Player>,
+    >,
+    player_cards:
+        Query<'w, 's, (&'static PlayedCards, &'static Deck, AsDerefCopied<InNode>), With<Player>>,
+    inventories: Query<'w, 's, &'static Inventory, With<Player>>,
+    team_phases: Query<'w, 's, &'static TeamPhase, With<Team>>,
+    team_alliances: Query<'w, 's, &'static Alliances, With<Team>>,
+    undo_stacks: Query<'w, 's, &'static NodeUndoStack, With<Team>>,
+    curios: Query<'w, 's, PreviewCurioQ, With<Curio>>,
+    curio_teams: Query<'w, 's, AsDerefCopied<OnTeam>, With<Curio>>,
+    prereqs: Query<'w, 's, PrereqQ, With<Curio>>,
+    pieces: Query<
+        'w,
+        's,
+        (
+            Entity,
+            AsDerefCopied<OnTeam>,
+            AsDerefCopied<IsTapped>,
+            AsDerefCopied<MovesTaken>,
+        ),
+        With<NodePiece>,
+    >,
+    access_points: Query<
+        'w,
+        's,
+        (Entity, Option<AsDerefCopied<OnTeam>>, &'static AccessPoint),
+        With<NodePiece>,
+    >,
+    cards: Query<'w, 's, CardQuery>,
+    pickups: Query<'w, 's, &'static Pickup>,
+}
+
+#[derive(Debug, QueryData)]
+pub struct PreviewCurioQ {
+    id: Entity,
+    node: Option<AsDerefCopied<Parent>>,
+    team: AsDerefCopied<OnTeam>,
+    tapped: AsDerefCopied<IsTapped>,
+    moves_taken: AsDerefCopied<MovesTaken>,
+    movement_speed: Option<AsDerefCopied<MovementSpeed>>,
+    max_size: Option<AsDerefCopied<MaximumSize>>,
+    lost_squares: Option<&'static LostSquares>,
+    tags: Option<&'static Tags>,
+    status_effects: Option<&'static StatusEffects>,
+    actions: Option<&'static Actions>,
+    action_history: Option<&'static ActionHistory>,
+}
+
+/// Copy of the curio state that action effects can change
+#[derive(Debug)]
+pub(crate) struct PreviewCurio {
+    max_size: u32,
+    movement_speed: u32,
+    lost_squares: Option<Vec<UVec2>>,
+    tags: Option<Tags>,
+    status_effects: Option<StatusEffects>,
+}
+
+impl PreviewCurio {
+    /// Writes the copy over the curio it was made from, leaving alone what
+    /// effects didn't change
+    fn commit(self, curio_props: CurioEffectProps<'_>) {
+        let CurioEffectProps {
+            mut max_size,
+            mut movement_speed,
+            lost_squares,
+            tags,
+            status_effects,
+        } = curio_props;
+        if *max_size != self.max_size {
+            *max_size = self.max_size;
+        }
+        if *movement_speed != self.movement_speed {
+            *movement_speed = self.movement_speed;
+        }
+        if let (Some(mut lost_squares), Some(new_lost_squares)) = (lost_squares, self.lost_squares)
+        {
+            if *lost_squares != new_lost_squares {
+                *lost_squares = new_lost_squares;
+            }
+        }
+        if let (Some(mut tags), Some(new_tags)) = (tags, self.tags) {
+            if tags.iter().ne(new_tags.iter()) {
+                *tags = new_tags;
+            }
+        }
+        if let (Some(mut status_effects), Some(new_status_effects)) =
+            (status_effects, self.status_effects)
+        {
+            if status_effects.0 != new_status_effects.0 {
+                *status_effects = new_status_effects;
+            }
+        }
+    }
+}
+
+impl CurioEffectAccess for HashMap<Entity, PreviewCurio> {
+    fn effect_props(&mut self, curio: Entity) -> Option<CurioEffectProps<'_>> {
+        let preview_curio = self.get_mut(&curio)?;
+        Some(CurioEffectProps {
+            max_size: (&mut preview_curio.max_size).into(),
+            movement_speed: (&mut preview_curio.movement_speed).into(),
+            lost_squares: preview_curio.lost_squares.as_mut().map(Into::into),
+            tags: preview_curio.tags.as_mut().map(Into::into),
+            status_effects: preview_curio.status_effects.as_mut().map(Into::into),
+        })
+    }
+}
+
+/// Writes planned curio effects over the curios they were copied from
+pub(crate) fn commit_curio_effects<C: CurioEffectAccess>(
+    curio_effects: HashMap<Entity, PreviewCurio>,
+    curios: &mut C,
+) {
+    for (curio_id, preview_curio) in curio_effects {
+        if let Some(curio_props) = curios.effect_props(curio_id) {
+            preview_curio.commit(curio_props);
+        }
+    }
+}
+
+/// What moving the active curio would do
+#[derive(Debug)]
+pub(crate) struct MovementPlan {
+    pub(crate) metadata: Metadata,
+    pub(crate) node_id: Entity,
+    pub(crate) curio_id: Entity,
+    pub(crate) grid: EntityGrid,
+    pub(crate) remaining_moves: u32,
+}
+
+/// What performing an action or using an item would do
+#[derive(Debug)]
+pub(crate) struct ActionPlan {
+    pub(crate) metadata: Metadata,
+    pub(crate) node_id: Entity,
+    pub(crate) curio_id: Entity,
+    pub(crate) grid: EntityGrid,
+    pub(crate) curio_effects: HashMap<Entity, PreviewCurio>,
+    /// The curio's action history once the action is recorded in it, if
+    /// it keeps one. Using an item isn't recorded.
+    pub(crate) action_history: Option<ActionHistory>,
+}
+
+/// What loading or unloading an access point would do
+#[derive(Debug)]
+pub(crate) struct AccessPointPlan {
+    pub(crate) metadata: Metadata,
+    pub(crate) played_cards: PlayedCards,
+    pub(crate) display_id: String,
+    /// The components the access point takes from the card loaded into it
+    pub(crate) card_props: Option<(Description, MovementSpeed, MaximumSize, Actions)>,
+}
+
+/// What marking a player as ready would do
+#[derive(Debug)]
+pub(crate) struct ReadyPlan {
+    pub(crate) metadata: Metadata,
+    pub(crate) node_id: Entity,
+    /// The teams that start playing, if every player they were waiting on is
+    /// ready
+    pub(crate) ready_teams: Option<Vec<Entity>>,
+    /// The team's access points and the cards loaded into them, which become
+    /// curios once the teams start playing
+    pub(crate) access_points: Vec<(Entity, Option<Entity>)>,
+}
+
+/// What ending the turn would do
+#[derive(Debug)]
+pub(crate) struct EndTurnPlan {
+    pub(crate) metadata: Metadata,
+    pub(crate) node_id: Entity,
+    pub(crate) team_id: Entity,
+    pub(crate) next_team: Entity,
+    /// Pieces that moved or were tapped this turn, and get untapped
+    pub(crate) moved_pieces: Vec<Entity>,
+}
+
+/// What undoing the last group of ops would do
+#[derive(Debug)]
+pub(crate) struct UndoPlan {
+    pub(crate) metadata: Metadata,
+    pub(crate) node_id: Entity,
+    pub(crate) team_id: Entity,
+    pub(crate) grid: EntityGrid,
+    pub(crate) active_curio: Option<Entity>,
+    pub(crate) curio_effects: HashMap<Entity, PreviewCurio>,
+    pub(crate) curio_turns: HashMap<Entity, CurioTurn>,
+    /// Items given back to the players who used them
+    pub(crate) refunds: Vec<(Entity, String)>,
+    /// Pickups put back in the grid, which are no longer claimed
+    pub(crate) returned_pickups: Vec<Entity>,
+}
+
+/// How far through its turn a curio is, which undoing rolls back
+#[derive(Debug)]
+pub(crate) struct CurioTurn {
+    pub(crate) moves_taken: u32,
+    pub(crate) tapped: bool,
+    pub(crate) action_history: Option<ActionHistory>,
+}
+
+impl std::fmt::Debug for NodeOpPreview<'_, '_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeOpPreview").finish_non_exhaustive()
+    }
+}
+
+impl NodeOpPreview<'_, '_> {
+    /// The result `node_op` would have if `player` requested it right now
+    pub fn preview(&self, player: Entity, node_op: &NodeOp) -> OpImplResult {
+        match node_op {
+            NodeOp::MoveActiveCurio { dir } => {
+                self.plan_movement(player, *dir).map(|plan| plan.metadata)
+            },
+            NodeOp::PerformCurioAction {
+                action_id,
+                curio,
+                target,
+            } => self
+                .plan_action(player, action_id, *curio, *target)
+                .map(|plan| plan.metadata),
+            NodeOp::ActivateCurio { curio_id } => self.plan_activate(player, *curio_id),
+            NodeOp::LoadAccessPoint {
+                access_point_id,
+                card_id,
+            } => self
+                .plan_access_point(player, *access_point_id, Some(*card_id))
+                .map(|plan| plan.metadata),
+            NodeOp::UnloadAccessPoint { access_point_id } => self
+                .plan_access_point(player, *access_point_id, None)
+                .map(|plan| plan.metadata),
+            NodeOp::ReadyToGo => self.plan_ready(player).map(|plan| plan.metadata),
+            NodeOp::EndTurn => self.plan_end_turn(player).map(|plan| plan.metadata),
+            NodeOp::TelegraphAction { action_id, target } => {
+                self.plan_telegraph(player, action_id, *target)
+            },
+            NodeOp::Undo => self.plan_undo(player).map(|plan| plan.metadata),
+            NodeOp::Redo => self.plan_redo(player),
+            NodeOp::UseItem { item_id, target } => self
+                .plan_use_item(player, item_id, *target)
+                .map(|plan| plan.metadata),
+            NodeOp::EnterNode(_) | NodeOp::QuitNode(_) => {
+                Err("That op can't be previewed".invalid())
+            },
+        }
+    }
+
+    pub(crate) fn plan_movement(
+        &self,
+        player: Entity,
+        dir: Compass,
+    ) -> Result<MovementPlan, OpError> {
+        let mut metadata = Metadata::default();
+        let (player_team_id, node_id) = self.players.get(player).critical()?;
+        let (grid, current_turn, active_curio, team_status, _) =
+            self.nodes.get(node_id).critical()?;
+
+        check_team_can_play(
+            player_team_id,
+            current_turn,
+            team_status,
+            self.team_phases.get(player_team_id).critical()?,
+            "Can't move pieces during setup phase",
+        )?;
+        let active_curio_id = active_curio.ok_or("No active curio to move")?;
+
+        metadata.put(key::NODE_ID, node_id).critical()?;
+        metadata.put(key::CURIO, active_curio_id).critical()?;
+        let curio_q = self.curios.get(active_curio_id).critical()?;
+        debug_assert!(!curio_q.tapped, "a tapped curio was active");
+        let moves_left = moves_left(
+            curio_q.movement_speed,
+            curio_q.status_effects,
+            curio_q.moves_taken,
+        )?;
+        let mut grid = grid.clone();
+        metadata.extend(move_curio_on_grid(
+            &mut grid,
+            active_curio_id,
+            dir,
+            curio_q.max_size.unwrap_or(1),
+            &self.pickups,
+        )?);
+        let remaining_moves = moves_left - 1;
+        metadata
+            .put(key::REMAINING_MOVES, remaining_moves)
+            .critical()?;
+        Ok(MovementPlan {
+            metadata,
+            node_id,
+            curio_id: active_curio_id,
+            grid,
+            remaining_moves,
+        })
+    }
+
+    pub(crate) fn plan_action(
+        &self,
+        player: Entity,
+        action_id: &str,
+        curio: Option<Entity>,
+        target: UVec2,
+    ) -> Result<ActionPlan, OpError> {
+        let (player_team_id, node_id) = self.players.get(player).critical()?;
+        let (grid, current_turn, active_curio, team_status, _) =
+            self.nodes.get(node_id).critical()?;
+
+        check_team_can_play(
+            player_team_id,
+            current_turn,
+            team_status,
+            self.team_phases.get(player_team_id).critical()?,
+            "Can't perform actions during setup phase",
+        )?;
+        if active_curio.is_some() && curio.is_some() && active_curio != curio {
+            Err("There's already an active curio and it's not that one".invalid())?;
+        }
+        let curio_id = active_curio
+            .or(curio)
+            .ok_or("No curio to perform that action".invalid())?;
+        let curio_q = self
+            .curios
+            .get(curio_id)
+            .map_err(|_| "Calling curio action on entity that is not a curio".critical())?;
+        if curio_q.tapped {
+            Err("Curio is tapped".invalid())?;
+        }
+        let action_def = find_action(&self.ast_action, curio_q.actions, action_id)?;
+
+        let team_check = |id| self.curio_teams.get(id).ok();
+        let alliances = self.team_alliances.get(player_team_id).ok();
+        check_action_target(
+            action_def,
+            grid,
+            curio_id,
+            target,
+            &self.prereqs,
+            team_check,
+            alliances,
+        )?;
+        let mut metadata = Metadata::new();
+        metadata.put(key::CURIO, curio_id).critical()?;
+        put_activation_metadata(&mut metadata, active_curio, curio_id)?;
+        let mut curio_effects = self.preview_curios(node_id, grid);
+        let mut grid = grid.clone();
+        apply_action_effects(
+            &mut metadata,
+            &mut grid,
+            curio_id,
+            action_def,
+            target,
+            team_check,
+            alliances,
+            &mut curio_effects,
+        )?;
+        metadata.put(key::NODE_ID, node_id).critical()?;
+        let action_history = if let Some(action_history) = curio_q.action_history {
+            metadata
+                .put(key::OLD_ACTION_HISTORY, action_history)
+                .critical()?;
+            let mut action_history = action_history.clone();
+            action_history.record_use(action_def);
+            Some(action_history)
+        } else {
+            None
+        };
+        Ok(ActionPlan {
+            metadata,
+            node_id,
+            curio_id,
+            grid,
+            curio_effects,
+            action_history,
+        })
+    }
+
+    pub(crate) fn plan_activate(&self, player: Entity, curio_id: Entity) -> OpImplResult {
+        let mut metadata = Metadata::default();
+        let (player_team_id, node_id) = self.players.get(player).critical()?;
+        let (_, current_turn, active_curio, team_status, _) = self.nodes.get(node_id).critical()?;
+
+        check_team_can_play(
+            player_team_id,
+            current_turn,
+            team_status,
+            self.team_phases.get(player_team_id).critical()?,
+            "Can't activate pieces during setup phase",
+        )?;
+        let target_curio = self
+            .curios
+            .get(curio_id)
+            .map_err(|_| "Target curio not found".invalid())?;
+        check_can_activate(
+            player_team_id,
+            curio_id,
+            target_curio.team,
+            target_curio.tapped,
+            active_curio,
+        )?;
+        if let Some(last_active) = active_curio {
+            metadata
+                .put(key::DEACTIVATED_CURIO, last_active)
+                .critical()?; // Recoverable?
+        }
+        Ok(metadata)
+    }
+
+    /// Plans loading `next_card_id` into an access point, or unloading it if
+    /// there is no card
+    pub(crate) fn plan_access_point(
+        &self,
+        player: Entity,
+        access_point_id: Entity,
+        next_card_id: Option<Entity>,
+    ) -> Result<AccessPointPlan, OpError> {
+        let mut metadata = Metadata::new();
+        let (_, _, access_point) = self
+            .access_points
+            .get(access_point_id)
+            .map_err(|_| "No such access point".invalid())?;
+        let (played_cards, deck, node_id) = self.player_cards.get(player).critical()?;
+        metadata.put(key::NODE_ID, node_id).invalid()?;
+
+        if access_point.card.is_none() && next_card_id.is_none() {
+            Err("Access point is already unloaded".invalid())?;
+        }
+        if access_point.card == next_card_id {
+            Err("That is already loaded".invalid())?;
+        }
+        let next_card = if let Some(next_card_id) = next_card_id {
+            if !played_cards.can_be_played(deck, next_card_id) {
+                Err("Already played all of those".invalid())?;
+            }
+            let card_q = self
+                .cards
+                .get(next_card_id)
+                .map_err(|_| "Cannot find that card or it is not loaded".invalid())?;
+            metadata.put(key::CARD, next_card_id).critical()?;
+            Some((next_card_id, card_q))
+        } else {
+            None
+        };
+
+        let mut played_cards = played_cards.clone();
+        if let Some(old_card_id) = access_point.card {
+            let withdrawn_successfully = played_cards.withdraw_card_from(old_card_id, node_id);
+            if !withdrawn_successfully {
+                Err("Attempting to unload card that wasn't played here".critical())?;
+            }
+        }
+        let (display_id, card_props) = if let Some((next_card_id, card_q)) = next_card {
+            played_cards.play_card_to(deck, next_card_id, node_id);
+            let card_props = (
+                Description::new(card_q.description.to_owned()),
+                MovementSpeed(card_q.movement_speed),
+                MaximumSize(card_q.max_size),
+                Actions(card_q.actions.clone()),
+            );
+            (card_q.base_name.clone(), Some(card_props))
+        } else {
+            (ACCESS_POINT_DISPLAY_ID.to_owned(), None)
+        };
+        Ok(AccessPointPlan {
+            metadata,
+            played_cards,
+            display_id,
+            card_props,
+        })
+    }
+
+    pub(crate) fn plan_ready(&self, player: Entity) -> Result<ReadyPlan, OpError> {
+        let mut metadata = Metadata::new();
+
+        let (_, player_team, node_id, is_ready_to_go) =
+            self.ready_players.get(player).critical()?;
+        if is_ready_to_go {
+            Err("You are already marked as ready".invalid())?;
+        }
+        let (access_point_loading_rule, grid, teams) = self.setup_nodes.get(node_id).critical()?;
+        let relevant_teams = match access_point_loading_rule {
+            AccessPointLoadingRule::Staggered => vec![player_team],
+            AccessPointLoadingRule::Simultaneous => teams.0.clone(),
+        };
+        let has_loaded_access_point = self.access_points.iter().any(|(id, team, access_point)| {
+            grid.contains_key(id) && team == Some(player_team) && access_point.card.is_some()
+        });
+        if !has_loaded_access_point {
+            Err("Must load an access point first".invalid())?;
+        }
+
+        let relevant_teams_are_ready =
+            self.ready_players
+                .iter()
+                .all(|(iter_player, team, _, ready_to_go)| {
+                    !relevant_teams.contains(&team) || ready_to_go || iter_player == player
+                });
+        metadata
+            .put(key::ALL_TEAM_MEMBERS_READY, relevant_teams_are_ready)
+            .critical()?;
+
+        let access_points = if relevant_teams_are_ready {
+            self.access_points
+                .iter()
+                .filter(|&(id, team, _)| team == Some(player_team) && grid.contains_key(id))
+                .map(|(id, _, access_point)| (id, access_point.card))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(ReadyPlan {
+            metadata,
+            node_id,
+            ready_teams: relevant_teams_are_ready.then_some(relevant_teams),
+            access_points,
+        })
+    }
+
+    pub(crate) fn plan_end_turn(&self, player: Entity) -> Result<EndTurnPlan, OpError> {
+        let (player_team_id, node_id) = self.players.get(player).critical()?;
+        let (_, current_turn, active_curio, team_status, teams) =
+            self.nodes.get(node_id).critical()?;
+        let next_team = next_turn(player_team_id, current_turn, teams, team_status)?;
+
+        let mut metadata = Metadata::new();
+        if let Some(id) = active_curio {
+            metadata.put(key::CURIO, id).critical()?;
+        }
+        let moved_pieces: HashMap<Entity, u32> = self
+            .pieces
+            .iter()
+            .filter(|&(_, team, is_tapped, moves_taken)| {
+                team == player_team_id && (is_tapped || moves_taken > 0)
+            })
+            .map(|(id, _, _, moves_taken)| (id, moves_taken))
+            .collect();
+        metadata.put(key::MOVED_PIECES, &moved_pieces).critical()?;
+        Ok(EndTurnPlan {
+            metadata,
+            node_id,
+            team_id: player_team_id,
+            next_team,
+            moved_pieces: moved_pieces.into_keys().collect(),
+        })
+    }
+
+    /// Plans telegraphing an action, which has the results that performing
+    /// it on `target` would have
+    pub(crate) fn plan_telegraph(
+        &self,
+        player: Entity,
+        action_id: &str,
+        target: UVec2,
+    ) -> OpImplResult {
+        let (player_team_id, node_id) = self.players.get(player).critical()?;
+        let (_, current_turn, active_curio, team_status, _) = self.nodes.get(node_id).critical()?;
+
+        check_team_can_play(
+            player_team_id,
+            current_turn,
+            team_status,
+            self.team_phases.get(player_team_id).critical()?,
+            "Can't telegraph actions during setup phase",
+        )?;
+        active_curio.ok_or("No active curio to telegraph an action".invalid())?;
+        self.plan_action(player, action_id, None, target)
+            .map(|plan| plan.metadata)
+    }
+
+    pub(crate) fn plan_undo(&self, player: Entity) -> Result<UndoPlan, OpError> {
+        let (team_id, node_id) = self.players.get(player).invalid()?;
+        let undo_stack = self.undo_stacks.get(team_id).invalid()?;
+        if !undo_stack.can_undo() {
+            Err("Not able to undo any more".invalid())?;
+        }
+        let (grid, _, active_curio, team_status, _) = self.nodes.get(node_id).critical()?;
+        if team_status.is_decided(team_id) {
+            Err("Cannot do any more".invalid())?;
+        }
+        let mut plan = UndoPlan {
+            metadata: Metadata::new(),
+            node_id,
+            team_id,
+            grid: grid.clone(),
+            active_curio,
+            curio_effects: self.preview_curios(node_id, grid),
+            curio_turns: HashMap::new(),
+            refunds: Vec::new(),
+            returned_pickups: Vec::new(),
+        };
+        plan.metadata.put(key::NODE_ID, node_id).invalid()?;
+        for op_to_undo in undo_stack.undo_group().iter().rev() {
+            let Ok(metadata) = op_to_undo.result() else {
+                continue;
+            };
+            match op_to_undo.op() {
+                NodeOp::ActivateCurio { .. } => {
+                    if let Some(curio_id) = plan.active_curio {
+                        plan.metadata.put(key::CURIO, curio_id).critical()?;
+                    }
+                    // Activating a curio taps the one that was active before
+                    let deactivated_curio =
+                        metadata.get_optional(key::DEACTIVATED_CURIO).critical()?;
+                    if let Some(deactivated_id) = deactivated_curio {
+                        self.curio_turn(&mut plan.curio_turns, deactivated_id)?
+                            .tapped = false;
+                    }
+                    plan.active_curio = deactivated_curio;
+                },
+                NodeOp::MoveActiveCurio { .. } => {
+                    let curio_id = metadata.get_required(key::CURIO).critical()?;
+                    let curio_turn = self.curio_turn(&mut plan.curio_turns, curio_id)?;
+                    if let Some(dropped_square) =
+                        metadata.get_optional(key::DROPPED_SQUARE).critical()?
+                    {
+                        plan.grid.push_back(dropped_square, curio_id);
+                    }
+                    plan.metadata.put(key::CURIO, curio_id).critical()?;
+                    plan.grid.pop_front(curio_id);
+                    let target_pt = metadata.get_required(key::TARGET_POINT).critical()?;
+                    if metadata
+                        .get_optional(key::REPLACED_SQUARE)
+                        .critical()?
+                        .unwrap_or(false)
+                    {
+                        let replaced_square_next = metadata
+                            .get_optional(key::REPLACED_SQUARE_NEXT)
+                            .critical()?;
+                        plan.grid
+                            .insert_square_before(curio_id, target_pt, replaced_square_next);
+                    }
+
+                    if let Some(pickup_id) = metadata.get_optional(key::PICKUP_ID).critical()? {
+                        // TODO Configurable return pick-up (cq should, nf should not)
+                        plan.grid.put_item(target_pt, pickup_id);
+                        plan.returned_pickups.push(pickup_id);
+                    }
+                    plan.active_curio = Some(curio_id);
+                    curio_turn.tapped = false;
+                    curio_turn.moves_taken -= 1;
+                },
+                NodeOp::PerformCurioAction { .. } => {
+                    let curio_id = metadata.get_required(key::CURIO).critical()?;
+                    let curio_turn = self.curio_turn(&mut plan.curio_turns, curio_id)?;
+                    let skipped_activation =
+                        metadata.get_required(key::SKIPPED_ACTIVATION).critical()?;
+                    plan.metadata.put(key::CURIO, curio_id).critical()?;
+                    plan.active_curio = if !skipped_activation {
+                        Some(curio_id)
+                    } else {
+                        metadata.get_optional(key::DEACTIVATED_CURIO).critical()?
+                    };
+                    curio_turn.tapped = false;
+                    if let (Some(action_history), Some(old_action_history)) = (
+                        curio_turn.action_history.as_mut(),
+                        metadata.get_optional(key::OLD_ACTION_HISTORY).critical()?,
+                    ) {
+                        *action_history = old_action_history;
+                    }
+
+                    revert_action_effects(metadata, &mut plan.grid, &mut plan.curio_effects)?;
+                },
+                NodeOp::UseItem { item_id, .. } => {
+                    let curio_id = metadata.get_required(key::CURIO).critical()?;
+                    plan.metadata.put(key::CURIO, curio_id).critical()?;
+                    plan.active_curio = Some(curio_id);
+                    // Teammates share an undo stack, so the item might not have
+                    // been this player's
+                    plan.refunds.push((op_to_undo.source(), item_id.clone()));
+                    revert_action_effects(metadata, &mut plan.grid, &mut plan.curio_effects)?;
+                },
+                _ => {
+                    log::error!("Invalid op in undo queue: {op_to_undo:?}");
+                },
+            }
+        }
+        Ok(plan)
+    }
+
+    pub(crate) fn plan_redo(&self, player: Entity) -> OpImplResult {
+        let (team_id, node_id) = self.players.get(player).invalid()?;
+        let (_, current_turn, _, team_status, _) = self.nodes.get(node_id).critical()?;
+        if team_status.is_decided(team_id) {
+            Err("Cannot do any more".invalid())?;
+        }
+        if current_turn != team_id {
+            Err("Not this player's turn".invalid())?;
+        }
+        let undo_stack = self.undo_stacks.get(team_id).invalid()?;
+        if undo_stack.is_redoing() {
+            Err("Still redoing".invalid())?;
+        }
+        if !undo_stack.can_redo() {
+            Err("Not able to redo any more".invalid())?;
+        }
+        let mut metadata = Metadata::new();
+        metadata.put(key::NODE_ID, node_id).invalid()?;
+        Ok(metadata)
+    }
+
+    pub(crate) fn plan_use_item(
+        &self,
+        player: Entity,
+        item_id: &str,
+        target: UVec2,
+    ) -> Result<ActionPlan, OpError> {
+        let (player_team_id, node_id) = self.players.get(player).critical()?;
+        let (grid, current_turn, active_curio, team_status, _) =
+            self.nodes.get(node_id).critical()?;
+
+        check_team_can_play(
+            player_team_id,
+            current_turn,
+            team_status,
+            self.team_phases.get(player_team_id).critical()?,
+            "Can't use items during setup phase",
+        )?;
+        let curio_id = active_curio.ok_or("Need an active curio to use items".invalid())?;
+        let item_count = self.inventories.get(player).critical()?.count(item_id);
+        if item_count == 0 {
+            Err("You don't have that item".invalid())?;
+        }
+        let action_def = find_item_action(
+            &self.ast_action,
+            &self.res_item_definitions,
+            &self.res_item_actions,
+            item_id,
+        )?;
+
+        let team_check = |id| self.curio_teams.get(id).ok();
+        let alliances = self.team_alliances.get(player_team_id).ok();
+        check_action_target(
+            action_def,
+            grid,
+            curio_id,
+            target,
+            &self.prereqs,
+            team_check,
+            alliances,
+        )?;
+        let mut metadata = Metadata::new();
+        metadata.put(key::CURIO, curio_id).critical()?;
+        let mut curio_effects = self.preview_curios(node_id, grid);
+        let mut grid = grid.clone();
+        apply_action_effects(
+            &mut metadata,
+            &mut grid,
+            curio_id,
+            action_def,
+            target,
+            team_check,
+            alliances,
+            &mut curio_effects,
+        )?;
+        metadata.put(key::NODE_ID, node_id).critical()?;
+        metadata
+            .put(item::key::ITEM_COUNT, item_count - 1)
+            .critical()?;
+        Ok(ActionPlan {
+            metadata,
+            node_id,
+            curio_id,
+            grid,
+            curio_effects,
+            action_history: None,
+        })
+    }
+
+    /// Copies of the curios in a node, for effects to be applied to. Curios
+    /// that have been knocked out of the grid are included, so that undoing
+    /// can bring them back.
+    fn preview_curios(&self, node_id: Entity, grid: &EntityGrid) -> HashMap<Entity, PreviewCurio> {
+        self.curios
+            .iter()
+            .filter(|curio_q| curio_q.node == Some(node_id) || grid.contains_key(curio_q.id))
+            .filter_map(|curio_q| {
+                let preview_curio = PreviewCurio {
+                    max_size: curio_q.max_size?,
+                    movement_speed: curio_q.movement_speed?,
+                    lost_squares: curio_q
+                        .lost_squares
+                        .map(|lost_squares| lost_squares.0.clone()),
+                    tags: curio_q.tags.cloned(),
+                    status_effects: curio_q.status_effects.cloned(),
+                };
+                Some((curio_q.id, preview_curio))
+            })
+            .collect()
+    }
+
+    /// A curio's turn as an undo has left it so far, starting from how it is
+    /// now
+    fn curio_turn<'a>(
+        &self,
+        curio_turns: &'a mut HashMap<Entity, CurioTurn>,
+        curio_id: Entity,
+    ) -> Result<&'a mut CurioTurn, OpError> {
+        if !curio_turns.contains_key(&curio_id) {
+            let curio_q = self.curios.get(curio_id).critical()?;
+            curio_turns.insert(
+                curio_id,
+                CurioTurn {
+                    moves_taken: curio_q.moves_taken,
+                    tapped: curio_q.tapped,
+                    action_history: curio_q.action_history.cloned(),
+                },
+            );
+        }
+        Ok(curio_turns
+            .get_mut(&curio_id)
+            .expect("curio turn was just inserted"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::hierarchy::BuildWorldChildren;
+
+    use super::*;
+    use crate::card::{ActionEffect, ActionRange, ActionTarget, BaseName, Card};
+    use crate::node::node_testing::TestBattle;
+
+    fn preview(battle: &mut TestBattle, player: usize, op: &NodeOp) -> OpImplResult {
+        let player = battle.players[player];
+        battle.app.world_mut().run_system_once_with(
+            (player, op.clone()),
+            |In((player, op)): In<(Entity, NodeOp)>, node_op_preview: NodeOpPreview| {
+                node_op_preview.preview(player, &op)
+            },
+        )
+    }
+
+    /// Previews an op, then performs it, checking that the preview had the
+    /// same result
+    fn assert_preview_matches(battle: &mut TestBattle, player: usize, op: NodeOp) {
+        let preview = preview(battle, player, &op);
+        let results = battle.perform(player, op);
+        let result = results[0].result();
+        assert_eq!(
+            preview.is_ok(),
+            result.is_ok(),
+            "preview: {preview:?}, result: {result:?}"
+        );
+        assert_eq!(
+            preview.ok().map(metadata_json),
+            result.clone().ok().map(metadata_json)
+        );
+    }
+
+    /// Metadata as JSON, with values that are JSON themselves parsed, so
+    /// that it can be compared regardless of the order maps were written in
+    fn metadata_json(metadata: Metadata) -> serde_json::Value {
+        fn parse_nested(value: serde_json::Value) -> serde_json::Value {
+            use serde_json::Value;
+            match value {
+                Value::String(string) => match serde_json::from_str(&string) {
+                    Ok(nested @ (Value::Object(_) | Value::Array(_))) => parse_nested(nested),
+                    _ => Value::String(string),
+                },
+                Value::Array(values) => {
+                    Value::Array(values.into_iter().map(parse_nested).collect())
+                },
+                Value::Object(values) => Value::Object(
+                    values
+                        .into_iter()
+                        .map(|(key, value)| (key, parse_nested(value)))
+                        .collect(),
+                ),
+                value => value,
+            }
+        }
+        parse_nested(serde_json::to_value(metadata).expect("metadata should serialize"))
+    }
+
+    fn hit_battle(damage: usize) -> (TestBattle, Entity) {
+        let mut battle = TestBattle::new(4, 4);
+        let hit = battle.add_action(Action {
+            id: "hit".to_owned(),
+            range: Some(ActionRange::new(1)),
+            effects: vec![ActionEffect::Damage(damage)],
+            target: ActionTarget::Enemies,
+            ..default()
+        });
+        let hitter = battle.spawn_curio(0, &[UVec2::new(0, 0)], 2, &[hit]);
+        battle.spawn_curio(1, &[UVec2::new(1, 0), UVec2::new(2, 0)], 0, &[]);
+        (battle, hitter)
+    }
+
+    fn hit_op(target: UVec2) -> NodeOp {
+        NodeOp::PerformCurioAction {
+            action_id: "hit".to_owned().into(),
+            curio: None,
+            target,
+        }
+    }
+
+    #[test]
+    fn test_preview_movement() {
+        let (mut battle, hitter) = hit_battle(1);
+        assert_preview_matches(&mut battle, 0, NodeOp::ActivateCurio { curio_id: hitter });
+        assert_preview_matches(
+            &mut battle,
+            0,
+            NodeOp::MoveActiveCurio {
+                dir: Compass::South,
+            },
+        );
+        // Blocked by the other curio
+        assert_preview_matches(
+            &mut battle,
+            0,
+            NodeOp::MoveActiveCurio { dir: Compass::East },
+        );
+        assert_preview_matches(
+            &mut battle,
+            0,
+            NodeOp::MoveActiveCurio {
+                dir: Compass::North,
+            },
+        );
+        // Out of moves
+        assert_preview_matches(
+            &mut battle,
+            0,
+            NodeOp::MoveActiveCurio {
+                dir: Compass::South,
+            },
+        );
+    }
+
+    #[test]
+    fn test_preview_action() {
+        let (mut battle, hitter) = hit_battle(1);
+        assert_preview_matches(&mut battle, 0, NodeOp::ActivateCurio { curio_id: hitter });
+        // Out of range
+        assert_preview_matches(&mut battle, 0, hit_op(UVec2::new(2, 0)));
+        assert_preview_matches(&mut battle, 0, hit_op(UVec2::new(1, 0)));
+        assert_eq!(
+            battle
+                .grid()
+                .len_of(battle.grid().item_at(UVec2::new(1, 0)).unwrap()),
+            1
+        );
+    }
+
+    #[test]
+    fn test_preview_fatal_hit() {
+        let (mut battle, hitter) = hit_battle(2);
+        let target = battle.grid().item_at(UVec2::new(1, 0)).unwrap();
+        assert_preview_matches(&mut battle, 0, NodeOp::ActivateCurio { curio_id: hitter });
+        assert_preview_matches(&mut battle, 0, hit_op(UVec2::new(1, 0)));
+        assert!(!battle.grid().contains_key(target));
+    }
+
+    #[test]
+    fn test_preview_pickup() {
+        let (mut battle, hitter) = hit_battle(1);
+        let node = battle.node;
+        let pickup = battle
+            .app
+            .world_mut()
+            .spawn(Pickup::Item("item:test".to_owned()))
+            .set_parent(node)
+            .id();
+        battle
+            .app
+            .world_mut()
+            .get_mut::<EntityGrid>(node)
+            .unwrap()
+            .put_item(UVec2::new(0, 1), pickup);
+
+        assert_preview_matches(&mut battle, 0, NodeOp::ActivateCurio { curio_id: hitter });
+        let preview = preview(
+            &mut battle,
+            0,
+            &NodeOp::MoveActiveCurio {
+                dir: Compass::South,
+            },
+        );
+        assert_eq!(
+            preview.unwrap().get_optional(key::PICKUP_ID).unwrap(),
+            Some(pickup)
+        );
+        assert_preview_matches(
+            &mut battle,
+            0,
+            NodeOp::MoveActiveCurio {
+                dir: Compass::South,
+            },
+        );
+    }
+
+    fn telegraph_hit(target: UVec2) -> NodeOp {
+        NodeOp::TelegraphAction {
+            action_id: "hit".into(),
+            target,
+        }
+    }
+
+    #[test]
+    fn test_preview_telegraph_and_end_turn() {
+        let (mut battle, hitter) = hit_battle(1);
+        // No active curio
+        assert_preview_matches(&mut battle, 0, telegraph_hit(UVec2::new(1, 0)));
+        assert_preview_matches(&mut battle, 0, NodeOp::ActivateCurio { curio_id: hitter });
+        assert_preview_matches(
+            &mut battle,
+            0,
+            NodeOp::TelegraphAction {
+                action_id: "missing".into(),
+                target: UVec2::new(1, 0),
+            },
+        );
+        // Telegraphing shows what the action would do
+        let action_preview = preview(&mut battle, 0, &hit_op(UVec2::new(1, 0)));
+        let results = battle.perform(0, telegraph_hit(UVec2::new(1, 0)));
+        assert_eq!(
+            results[0].result().clone().ok().map(metadata_json),
+            action_preview.ok().map(metadata_json)
+        );
+        // without doing it
+        let enemy = battle.grid().item_at(UVec2::new(1, 0)).unwrap();
+        assert_eq!(battle.grid().len_of(enemy), 2);
+        assert_preview_matches(&mut battle, 0, telegraph_hit(UVec2::new(1, 0)));
+        assert_preview_matches(
+            &mut battle,
+            0,
+            NodeOp::MoveActiveCurio {
+                dir: Compass::South,
+            },
+        );
+        // Out of range now
+        assert_preview_matches(&mut battle, 0, telegraph_hit(UVec2::new(1, 0)));
+        // Not their turn
+        assert_preview_matches(&mut battle, 1, NodeOp::EndTurn);
+        assert_preview_matches(&mut battle, 0, NodeOp::EndTurn);
+    }
+
+    #[test]
+    fn test_preview_undo_and_redo() {
+        let (battle, hitter) = hit_battle(2);
+        let mut battle = battle.with_undo();
+        let target = battle.grid().item_at(UVec2::new(1, 0)).unwrap();
+        // Nothing to undo or redo yet
+        assert_preview_matches(&mut battle, 0, NodeOp::Undo);
+        assert_preview_matches(&mut battle, 0, NodeOp::Redo);
+        assert_preview_matches(&mut battle, 0, NodeOp::ActivateCurio { curio_id: hitter });
+        assert_preview_matches(&mut battle, 0, hit_op(UVec2::new(1, 0)));
+        assert!(!battle.grid().contains_key(target));
+
+        // Undoing brings back the curio that was knocked out
+        assert_preview_matches(&mut battle, 0, NodeOp::Undo);
+        assert_eq!(battle.grid().len_of(target), 2);
+        assert!(battle.get::<LostSquares>(target).is_empty());
+        assert!(!battle.get::<IsTapped>(hitter).0);
+
+        assert_preview_matches(&mut battle, 0, NodeOp::Redo);
+        // The activation and hit are redone over the next frames
+        battle.update();
+        battle.update();
+        assert!(!battle.grid().contains_key(target));
+        assert_preview_matches(&mut battle, 0, NodeOp::Redo);
+    }
+
+    #[test]
+    fn test_preview_access_points_and_ready() {
+        let mut battle = TestBattle::new(4, 4);
+        let (node, team, player) = (battle.node, battle.teams[0], battle.players[0]);
+        let world = battle.app.world_mut();
+        *world.get_mut::<TeamPhase>(team).unwrap() = TeamPhase::Setup;
+        world
+            .entity_mut(node)
+            .insert(AccessPointLoadingRule::Staggered);
+        let card = world
+            .spawn((
+                Card,
+                BaseName::new("card"),
+                Description::new("A card"),
+                Actions::default(),
+                MovementSpeed(1),
+                MaximumSize(2),
+            ))
+            .id();
+        let mut deck = Deck::new();
+        deck.add_card(card);
+        world.entity_mut(player).insert((deck, IsReadyToGo(false)));
+        let access_point = world
+            .spawn((
+                AccessPoint::default(),
+                NodePiece::new("access_point"),
+                OnTeam(team),
+            ))
+            .set_parent(node)
+            .id();
+        world
+            .get_mut::<EntityGrid>(node)
+            .unwrap()
+            .put_item(UVec2::new(0, 0), access_point);
+        let load = NodeOp::LoadAccessPoint {
+            access_point_id: access_point,
+            card_id: card,
+        };
+        let unload = NodeOp::UnloadAccessPoint {
+            access_point_id: access_point,
+        };
+
+        // Nothing loaded
+        assert_preview_matches(&mut battle, 0, NodeOp::ReadyToGo);
+        assert_preview_matches(&mut battle, 0, unload.clone());
+        assert_preview_matches(&mut battle, 0, load.clone());
+        assert_eq!(battle.get::<AccessPoint>(access_point).card, Some(card));
+        // Already loaded
+        assert_preview_matches(&mut battle, 0, load.clone());
+        assert_preview_matches(&mut battle, 0, unload);
+        assert_eq!(battle.get::<AccessPoint>(access_point).card, None);
+        assert_preview_matches(&mut battle, 0, load);
+        assert_preview_matches(&mut battle, 0, NodeOp::ReadyToGo);
+        assert_eq!(*battle.get::<TeamPhase>(team), TeamPhase::Play);
+        assert!(battle.app.world().get::<Curio>(access_point).is_some());
+    }
+}
@@ -1,6 +1,8 @@
+use std::collections::VecDeque;
+
 use super::NodeOp;
 use crate::node::{OnTeam, Team};
-use crate::op::OpResult;
+use crate::op::{CoreOps, OpResult};
 use crate::player::Player;
 use crate::prelude::*;
 use crate::NDitCoreSet;
@@ -13,6 +15,7 @@ pub struct NodeOpUndoPlugin {
 impl Plugin for NodeOpUndoPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(self.default_allowed_undo_depth)
+            .register_type::<UndoDepth>()
             .add_systems(
                 Update,
                 (
@@ -23,17 +26,98 @@ impl Plugin for NodeOpUndoPlugin {
     }
 }
 
+/// How far back a team can undo. Can be put on a team to override the
+/// default resource.
 #[derive(Clone, Copy, Component, Debug, Default, Resource, Reflect)]
 #[reflect(Component)]
 pub enum UndoDepth {
+    /// Only the movement of the active curio
     OnlyMovement,
+    /// The movement and action of the active curio
     #[default]
     ActionAndMovement,
+    /// Everything done this turn, one curio at a time
     WholeTurn,
 }
 
+/// Team component with the ops the team can undo, and the ops it has undone
+/// that it can redo. Undoing and redoing happen a curio activation at a time.
 #[derive(Component, Debug, Default, Deref, DerefMut)]
-pub struct NodeUndoStack(Vec<OpResult<NodeOp>>);
+pub struct NodeUndoStack {
+    #[deref]
+    undo_stack: Vec<OpResult<NodeOp>>,
+    /// Groups of undone ops with the players who performed them, most
+    /// recently undone last
+    redo_stack: Vec<Vec<(Entity, NodeOp)>>,
+    /// Ops left to redo from the group being redone. Each is requested once
+    /// the one before it succeeds, and recording them doesn't clear the redo
+    /// stack.
+    pending_redos: Option<VecDeque<(Entity, NodeOp)>>,
+}
+
+impl NodeUndoStack {
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// The ops since the last curio activation, or all of them if there
+    /// isn't one, which are what the next undo reverts
+    pub(crate) fn undo_group(&self) -> &[OpResult<NodeOp>] {
+        let group_start = self
+            .undo_stack
+            .iter()
+            .rposition(|op_result| matches!(op_result.op(), NodeOp::ActivateCurio { .. }))
+            .unwrap_or(0);
+        &self.undo_stack[group_start..]
+    }
+
+    /// Removes the ops in the [undo group](Self::undo_group)
+    pub(crate) fn pop_undo_group(&mut self) -> Vec<OpResult<NodeOp>> {
+        let group_start = self.undo_stack.len() - self.undo_group().len();
+        self.undo_stack.drain(group_start..).collect()
+    }
+
+    /// Keeps undone ops so that they can be redone. Teammates share an undo
+    /// stack, so each op is kept with whoever performed it.
+    pub(crate) fn push_redo_group(&mut self, undo_group: Vec<OpResult<NodeOp>>) {
+        self.redo_stack.push(
+            undo_group
+                .into_iter()
+                .map(|op_result| (op_result.source(), op_result.op))
+                .collect(),
+        );
+    }
+
+    pub(crate) fn is_redoing(&self) -> bool {
+        self.pending_redos.is_some()
+    }
+
+    /// Starts redoing the most recently undone group of ops, returning the
+    /// first op to request and who to request it as. The rest are requested
+    /// as each op before them succeeds.
+    pub(crate) fn pop_redo_group(&mut self) -> Option<(Entity, NodeOp)> {
+        let mut ops = VecDeque::from(self.redo_stack.pop()?);
+        let first_op = ops.pop_front()?;
+        self.pending_redos = Some(ops);
+        Some(first_op)
+    }
+
+    /// Requests the next op of the group being redone, if there is one left
+    fn continue_redo(&mut self, res_core_ops: &mut CoreOps) {
+        let Some(pending_redos) = self.pending_redos.as_mut() else {
+            return;
+        };
+        if let Some((source, op)) = pending_redos.pop_front() {
+            res_core_ops.request(source, op);
+        } else {
+            self.pending_redos = None;
+        }
+    }
+}
 
 pub fn sys_add_undo_queue(
     mut commands: Commands,
@@ -45,29 +129,223 @@ pub fn sys_add_undo_queue(
 }
 
 pub fn sys_record_node_ops(
+    res_undo_depth: Res<UndoDepth>,
+    mut res_core_ops: ResMut<CoreOps>,
     mut evr_node_op: EventReader<OpResult<NodeOp>>,
     q_player: Query<&OnTeam, With<Player>>,
-    mut q_team: Query<(DebugName, &mut NodeUndoStack), With<Team>>,
+    mut q_team: Query<(&mut NodeUndoStack, Option<&UndoDepth>), With<Team>>,
 ) {
     for op_result in evr_node_op.read() {
         (|| {
             // try
             let &OnTeam(team_id) = q_player.get(op_result.source()).ok()?;
-            let (_, mut undo_queue) = q_team.get_mut(team_id).ok()?;
-            match op_result.op() {
-                NodeOp::ActivateCurio { .. } => {
-                    undo_queue.0.clear();
-                    undo_queue.0.push(op_result.clone());
+            let (mut undo_queue, undo_depth) = q_team.get_mut(team_id).ok()?;
+            let undo_depth = undo_depth.copied().unwrap_or(*res_undo_depth);
+            if matches!(
+                op_result.op(),
+                NodeOp::EnterNode(_) | NodeOp::TelegraphAction { .. } | NodeOp::Undo | NodeOp::Redo
+            ) {
+                return Some(());
+            }
+            let is_redo = undo_queue.is_redoing();
+            if op_result.result().is_err() {
+                // The rest of the group relies on this op, such as moves
+                // relying on which curio was activated
+                if is_redo {
+                    undo_queue.pending_redos = None;
+                }
+                return Some(());
+            }
+            if is_redo {
+                undo_queue.continue_redo(&mut res_core_ops);
+            } else {
+                undo_queue.redo_stack.clear();
+            }
+            match (op_result.op(), undo_depth) {
+                (NodeOp::ActivateCurio { .. }, UndoDepth::WholeTurn) => {
+                    undo_queue.undo_stack.push(op_result.clone());
+                },
+                (NodeOp::ActivateCurio { .. }, _) => {
+                    undo_queue.undo_stack.clear();
+                    undo_queue.undo_stack.push(op_result.clone());
                 },
-                NodeOp::MoveActiveCurio { .. } | NodeOp::PerformCurioAction { .. } => {
-                    undo_queue.0.push(op_result.clone())
+                (
+                    NodeOp::PerformCurioAction { .. } | NodeOp::UseItem { .. },
+                    UndoDepth::OnlyMovement,
+                ) => {
+                    undo_queue.undo_stack.clear();
                 },
-                NodeOp::EnterNode(_) => {},
+                (
+                    NodeOp::MoveActiveCurio { .. }
+                    | NodeOp::PerformCurioAction { .. }
+                    | NodeOp::UseItem { .. },
+                    _,
+                ) => undo_queue.undo_stack.push(op_result.clone()),
                 _ => {
-                    undo_queue.0.clear();
+                    undo_queue.undo_stack.clear();
+                    undo_queue.redo_stack.clear();
                 },
             }
             Some(())
         })();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::node::node_testing::TestBattle;
+    use crate::node::{ActiveCurio, InNode, IsTapped, MovesTaken};
+
+    fn perform_ok(battle: &mut TestBattle, op: NodeOp) {
+        let results = battle.perform(0, op);
+        assert!(results[0].result().is_ok(), "{results:?}");
+    }
+
+    fn move_east(battle: &mut TestBattle, curio: Entity) {
+        perform_ok(battle, NodeOp::ActivateCurio { curio_id: curio });
+        perform_ok(battle, NodeOp::MoveActiveCurio { dir: Compass::East });
+    }
+
+    /// Redoes the last undone group, running frames until its ops are done
+    fn redo(battle: &mut TestBattle) -> Vec<OpResult<NodeOp>> {
+        let mut results = battle.perform(0, NodeOp::Redo);
+        assert!(results[0].result().is_ok(), "{results:?}");
+        for _ in 0..4 {
+            results.extend(battle.update());
+        }
+        results
+    }
+
+    fn undo_stack(battle: &TestBattle) -> &NodeUndoStack {
+        battle.get::<NodeUndoStack>(battle.teams[0])
+    }
+
+    #[test]
+    fn test_redo_undone_moves() {
+        let mut battle = TestBattle::new(4, 4).with_undo();
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 3, &[]);
+        move_east(&mut battle, curio);
+        perform_ok(&mut battle, NodeOp::MoveActiveCurio { dir: Compass::East });
+
+        perform_ok(&mut battle, NodeOp::Undo);
+        assert_eq!(battle.grid().head(curio), Some(UVec2::new(0, 0)));
+        assert!(battle.get::<ActiveCurio>(battle.node).is_none());
+        assert!(undo_stack(&battle).can_redo());
+
+        redo(&mut battle);
+        assert_eq!(battle.grid().head(curio), Some(UVec2::new(2, 0)));
+        assert_eq!(**battle.get::<MovesTaken>(curio), 2);
+        assert!(undo_stack(&battle).can_undo());
+        assert!(!undo_stack(&battle).can_redo());
+    }
+
+    #[test]
+    fn test_redo_teammates_moves() {
+        let mut battle = TestBattle::new(4, 4).with_undo();
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 2, &[]);
+        let teammate = battle
+            .app
+            .world_mut()
+            .spawn((Player, OnTeam(battle.teams[0]), InNode(battle.node)))
+            .id();
+        battle.players.push(teammate);
+        for op in [
+            NodeOp::ActivateCurio { curio_id: curio },
+            NodeOp::MoveActiveCurio { dir: Compass::East },
+        ] {
+            let results = battle.perform(2, op);
+            assert!(results[0].result().is_ok(), "{results:?}");
+        }
+        perform_ok(&mut battle, NodeOp::Undo);
+
+        // The teammate's ops are redone as theirs, not as whoever redid them
+        let results = redo(&mut battle);
+        let redone: Vec<_> = results
+            .iter()
+            .filter(|result| !matches!(result.op(), NodeOp::Redo))
+            .collect();
+        assert_eq!(redone.len(), 2, "{results:?}");
+        assert!(
+            redone
+                .iter()
+                .all(|result| result.result().is_ok() && result.source() == teammate),
+            "{results:?}"
+        );
+        assert_eq!(battle.grid().head(curio), Some(UVec2::new(1, 0)));
+    }
+
+    #[test]
+    fn test_whole_turn_undo_across_curios() {
+        let mut battle = TestBattle::new(4, 4).with_undo();
+        let team = battle.teams[0];
+        battle
+            .app
+            .world_mut()
+            .entity_mut(team)
+            .insert(UndoDepth::WholeTurn);
+        let first = battle.spawn_curio(0, &[UVec2::new(0, 0)], 2, &[]);
+        let second = battle.spawn_curio(0, &[UVec2::new(0, 2)], 2, &[]);
+        move_east(&mut battle, first);
+        move_east(&mut battle, second);
+
+        perform_ok(&mut battle, NodeOp::Undo);
+        assert_eq!(battle.grid().head(second), Some(UVec2::new(0, 2)));
+        assert_eq!(battle.grid().head(first), Some(UVec2::new(1, 0)));
+        assert_eq!(**battle.get::<ActiveCurio>(battle.node), Some(first));
+        assert!(!**battle.get::<IsTapped>(first));
+
+        perform_ok(&mut battle, NodeOp::Undo);
+        assert_eq!(battle.grid().head(first), Some(UVec2::new(0, 0)));
+        assert!(!undo_stack(&battle).can_undo());
+
+        redo(&mut battle);
+        redo(&mut battle);
+        assert_eq!(battle.grid().head(first), Some(UVec2::new(1, 0)));
+        assert_eq!(battle.grid().head(second), Some(UVec2::new(1, 2)));
+    }
+
+    #[test]
+    fn test_new_op_clears_redo_stack() {
+        let mut battle = TestBattle::new(4, 4).with_undo();
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 2, &[]);
+        move_east(&mut battle, curio);
+        perform_ok(&mut battle, NodeOp::Undo);
+        assert!(undo_stack(&battle).can_redo());
+
+        move_east(&mut battle, curio);
+        assert!(!undo_stack(&battle).can_redo());
+        let results = battle.perform(0, NodeOp::Redo);
+        assert!(results[0].result().is_err(), "{results:?}");
+    }
+
+    #[test]
+    fn test_redo_stops_when_activation_fails() {
+        let mut battle = TestBattle::new(4, 4).with_undo();
+        let team = battle.teams[0];
+        battle
+            .app
+            .world_mut()
+            .entity_mut(team)
+            .insert(UndoDepth::WholeTurn);
+        let first = battle.spawn_curio(0, &[UVec2::new(0, 0)], 2, &[]);
+        let second = battle.spawn_curio(0, &[UVec2::new(0, 2)], 2, &[]);
+        perform_ok(&mut battle, NodeOp::ActivateCurio { curio_id: first });
+        move_east(&mut battle, second);
+        perform_ok(&mut battle, NodeOp::Undo);
+        assert_eq!(**battle.get::<ActiveCurio>(battle.node), Some(first));
+
+        // The second curio can no longer be activated, so the move that was
+        // made with it must not be made with the first
+        **battle.app.world_mut().get_mut::<IsTapped>(second).unwrap() = true;
+        let results = redo(&mut battle);
+        assert!(
+            !results
+                .iter()
+                .any(|result| matches!(result.op, NodeOp::MoveActiveCurio { .. })),
+            "{results:?}"
+        );
+        assert_eq!(battle.grid().head(first), Some(UVec2::new(0, 0)));
+        assert!(!undo_stack(&battle).is_redoing());
+    }
+}
@@ -0,0 +1,545 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use bevy::ecs::entity::{EntityMapper, MapEntities};
+use bevy::ecs::schedule::common_conditions::resource_exists;
+use bevy::reflect::serde::{ReflectDeserializer, ReflectSerializer};
+use bevy::reflect::TypeRegistry;
+use serde::de::DeserializeSeed;
+use serde::{Deserialize, Serialize};
+
+use super::{key, InNode, NodeBattleIntelligence, NodeOp};
+use crate::card::{BaseName, Card, Deck};
+use crate::op::{CoreOps, OpResult};
+use crate::player::Player;
+use crate::prelude::*;
+use crate::NDitCoreSet;
+
+/// Records node ops to a replay file when [`NodeOpRecorder`] is present,
+/// and feeds them back through [`CoreOps`] when [`NodeReplay`] is present.
+#[derive(Debug, Default)]
+pub struct NodeReplayPlugin;
+
+impl Plugin for NodeReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (sys_disable_ai_for_replay, sys_replay_node_ops)
+                .chain()
+                .in_set(NDitCoreSet::ProcessInputs)
+                .run_if(resource_exists::<NodeReplay>),
+        )
+        .add_systems(
+            Update,
+            sys_record_node_ops
+                .in_set(NDitCoreSet::PostProcessCommands)
+                .run_if(resource_exists::<NodeOpRecorder>),
+        );
+    }
+}
+
+/// Since entity ids are not stable between runs, entities in a replay are
+/// stored as an index into a table of these descriptions.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ReplayEntity {
+    /// A player, identified by name
+    Player(String),
+    /// A player without a name, by their index among all players in the
+    /// order they were spawned
+    UnnamedPlayer(usize),
+    /// A child of the node the source player is in, by index
+    NodePiece(usize),
+    /// A card in the source player's deck, by base name
+    Card(String),
+}
+
+/// A replay file is one of these per line. Entities referenced by an op are
+/// declared before the op, and are referred to by the order they are declared.
+#[derive(Debug, Deserialize, Serialize)]
+enum ReplayLine {
+    Entity(ReplayEntity),
+    Op {
+        source: Entity,
+        op: serde_json::Value,
+        metadata: Metadata,
+    },
+}
+
+/// Ops are buffered, and written to the file when the player leaves the node
+/// or the recorder is dropped
+#[derive(Debug, Resource)]
+pub struct NodeOpRecorder {
+    writer: BufWriter<File>,
+    entities: HashMap<Entity, Entity>,
+}
+
+impl NodeOpRecorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(NodeOpRecorder {
+            writer: BufWriter::new(File::create(path)?),
+            entities: default(),
+        })
+    }
+
+    fn write_line(&mut self, line: &ReplayLine) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.writer, line)?;
+        writeln!(self.writer)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn replay_entity<F: FnOnce() -> Option<ReplayEntity>>(
+        &mut self,
+        entity: Entity,
+        describe: F,
+    ) -> Option<Entity> {
+        if let Some(replay_entity) = self.entities.get(&entity) {
+            return Some(*replay_entity);
+        }
+        let description = describe()?;
+        let replay_entity = Entity::from_raw(self.entities.len() as u32);
+        if let Err(e) = self.write_line(&ReplayLine::Entity(description)) {
+            log::error!("Unable to write to replay file: {e}");
+        }
+        self.entities.insert(entity, replay_entity);
+        Some(replay_entity)
+    }
+}
+
+impl Drop for NodeOpRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!("Unable to write to replay file: {e}");
+        }
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct NodeReplay {
+    entities: Vec<ReplayEntity>,
+    ops: VecDeque<(Entity, serde_json::Value, Metadata)>,
+    /// Source of the op last replayed, and the metadata it was recorded with
+    awaiting_result: Option<(Entity, Metadata)>,
+    ops_replayed: usize,
+    divergence: Option<String>,
+    finished: bool,
+}
+
+impl NodeReplay {
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut entities = Vec::new();
+        let mut ops = VecDeque::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            match serde_json::from_str(&line?)? {
+                ReplayLine::Entity(replay_entity) => entities.push(replay_entity),
+                ReplayLine::Op {
+                    source,
+                    op,
+                    metadata,
+                } => ops.push_back((source, op, metadata)),
+            }
+        }
+        Ok(NodeReplay {
+            entities,
+            ops,
+            awaiting_result: None,
+            ops_replayed: 0,
+            divergence: None,
+            finished: false,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Describes the first op whose result differed from the recording, if
+    /// any has
+    pub fn divergence(&self) -> Option<&str> {
+        self.divergence.as_deref()
+    }
+}
+
+/// Entity mapper that tracks if any entity could not be mapped
+struct ReplayEntityMapper<F: FnMut(Entity) -> Option<Entity>> {
+    map: F,
+    failed: bool,
+}
+
+impl<F: FnMut(Entity) -> Option<Entity>> EntityMapper for ReplayEntityMapper<F> {
+    fn map_entity(&mut self, entity: Entity) -> Entity {
+        (self.map)(entity).unwrap_or_else(|| {
+            self.failed = true;
+            Entity::PLACEHOLDER
+        })
+    }
+}
+
+fn sys_record_node_ops(
+    mut res_recorder: ResMut<NodeOpRecorder>,
+    res_registry: Res<AppTypeRegistry>,
+    mut evr_node_op: EventReader<OpResult<NodeOp>>,
+    q_player: Query<(Entity, Option<&Name>, Option<&InNode>), With<Player>>,
+    q_children: Query<&Children>,
+    q_card: Query<AsDeref<BaseName>, With<Card>>,
+) {
+    let registry = res_registry.read();
+    let mut node_exited = false;
+    for op_result in evr_node_op.read() {
+        let Ok(metadata) = op_result.result() else {
+            continue;
+        };
+        node_exited |= matches!(op_result.op(), NodeOp::QuitNode(_));
+        let source = op_result.source();
+        let node = q_player
+            .get(source)
+            .ok()
+            .and_then(|(_, _, in_node)| in_node.copied());
+        let mut mapper = ReplayEntityMapper {
+            map: |entity| {
+                res_recorder.replay_entity(entity, || {
+                    if let Ok((_, name, _)) = q_player.get(entity) {
+                        return match name {
+                            Some(name) => Some(ReplayEntity::Player(name.to_string())),
+                            None => players_in_spawn_order(q_player.iter().map(|(id, ..)| id))
+                                .iter()
+                                .position(|id| *id == entity)
+                                .map(ReplayEntity::UnnamedPlayer),
+                        };
+                    }
+                    if let Ok(base_name) = q_card.get(entity) {
+                        return Some(ReplayEntity::Card(base_name.clone()));
+                    }
+                    q_children
+                        .get(*node?)
+                        .ok()?
+                        .iter()
+                        .position(|child| *child == entity)
+                        .map(ReplayEntity::NodePiece)
+                })
+            },
+            failed: false,
+        };
+        let replay_source = mapper.map_entity(source);
+        let mut op = op_result.op().clone();
+        op.map_entities(&mut mapper);
+        if mapper.failed {
+            log::warn!("Unable to record op, could not identify entities: {op_result:?}");
+            continue;
+        }
+        let line =
+            serde_json::to_value(ReflectSerializer::new(&op, &registry)).map(|op| ReplayLine::Op {
+                source: replay_source,
+                op,
+                metadata: metadata.clone(),
+            });
+        let result = line
+            .map_err(std::io::Error::from)
+            .and_then(|line| res_recorder.write_line(&line));
+        if let Err(e) = result {
+            log::error!("Unable to record op {op_result:?}: {e}");
+        }
+    }
+    if node_exited {
+        if let Err(e) = res_recorder.flush() {
+            log::error!("Unable to write to replay file: {e}");
+        }
+    }
+}
+
+/// Players that don't have a name to tell them apart are told apart by where
+/// they are in this order instead
+fn players_in_spawn_order<I: Iterator<Item = Entity>>(players: I) -> Vec<Entity> {
+    let mut players: Vec<Entity> = players.collect();
+    players.sort();
+    players
+}
+
+/// AI players would otherwise perform their ops a second time
+fn sys_disable_ai_for_replay(
+    mut commands: Commands,
+    q_ai_player: Query<Entity, Added<NodeBattleIntelligence>>,
+) {
+    for id in q_ai_player.iter() {
+        commands.entity(id).remove::<NodeBattleIntelligence>();
+    }
+}
+
+fn sys_replay_node_ops(
+    mut res_replay: ResMut<NodeReplay>,
+    mut res_core_ops: ResMut<CoreOps>,
+    res_registry: Res<AppTypeRegistry>,
+    mut evr_node_op: EventReader<OpResult<NodeOp>>,
+    q_player: Query<(Entity, Option<&Name>, Option<&InNode>, Option<&Deck>), With<Player>>,
+    q_children: Query<&Children>,
+    q_card: Query<AsDeref<BaseName>, With<Card>>,
+) {
+    for op_result in evr_node_op.read() {
+        let is_awaited = matches!(
+            res_replay.awaiting_result,
+            Some((source, _)) if source == op_result.source()
+        );
+        if !is_awaited {
+            continue;
+        }
+        let Some((_, recorded_metadata)) = res_replay.awaiting_result.take() else {
+            continue;
+        };
+        let mismatch = match op_result.result() {
+            Ok(metadata) => metadata_mismatch(&recorded_metadata, metadata),
+            Err(e) => Some(format!("op failed: {e}")),
+        };
+        if let Some(mismatch) = mismatch {
+            let divergence = format!(
+                "op {} {:?}, {mismatch}",
+                res_replay.ops_replayed,
+                op_result.op()
+            );
+            log::error!("Replay diverged at {divergence}");
+            res_replay.divergence.get_or_insert(divergence);
+        }
+    }
+    if res_replay.awaiting_result.is_some() || res_replay.finished {
+        return;
+    }
+    let Some((replay_source, op, recorded_metadata)) = res_replay
+        .ops
+        .front()
+        .map(|(source, op, metadata)| (*source, op.clone(), metadata.clone()))
+    else {
+        log::info!("Node replay finished");
+        res_replay.finished = true;
+        return;
+    };
+    let registry = res_registry.read();
+    let mut op = match deserialize_node_op(op, &registry) {
+        Ok(op) => op,
+        Err(e) => {
+            log::error!("Unable to deserialize replay op, skipping: {e}");
+            res_replay.ops.pop_front();
+            return;
+        },
+    };
+    let players = players_in_spawn_order(q_player.iter().map(|(id, ..)| id));
+    let find_player = |replay_entity: &ReplayEntity| match replay_entity {
+        ReplayEntity::Player(name) => q_player
+            .iter()
+            .find(|(_, player_name, _, _)| player_name.map(|n| n.as_str()) == Some(name)),
+        ReplayEntity::UnnamedPlayer(index) => q_player.get(*players.get(*index)?).ok(),
+        _ => None,
+    };
+    let source_entity = res_replay
+        .entities
+        .get(replay_source.index() as usize)
+        .cloned();
+    let Some(source_entity @ (ReplayEntity::Player(_) | ReplayEntity::UnnamedPlayer(_))) =
+        source_entity
+    else {
+        log::error!("Replay op source is not a player, skipping");
+        res_replay.ops.pop_front();
+        return;
+    };
+    // The player might not be spawned yet
+    let Some((source, _, in_node, deck)) = find_player(&source_entity) else {
+        return;
+    };
+    let mut mapper = ReplayEntityMapper {
+        map: |replay_entity: Entity| match res_replay.entities.get(replay_entity.index() as usize)?
+        {
+            player @ (ReplayEntity::Player(_) | ReplayEntity::UnnamedPlayer(_)) => {
+                find_player(player).map(|(id, ..)| id)
+            },
+            ReplayEntity::NodePiece(index) => q_children.get(**in_node?).ok()?.get(*index).copied(),
+            ReplayEntity::Card(name) => deck?
+                .cards_iter()
+                .find(|card| q_card.get(*card).ok() == Some(name)),
+        },
+        failed: false,
+    };
+    op.map_entities(&mut mapper);
+    // Entities might still be loading, try again next frame
+    if mapper.failed {
+        return;
+    }
+    res_replay.ops.pop_front();
+    res_replay.ops_replayed += 1;
+    res_replay.awaiting_result = Some((source, recorded_metadata));
+    res_core_ops.request(source, op);
+}
+
+/// Finds the first field that differs between the metadata an op was
+/// recorded with and the metadata it had when replayed
+fn metadata_mismatch(recorded: &Metadata, replayed: &Metadata) -> Option<String> {
+    let recorded = serde_json::to_value(recorded).ok()?;
+    let replayed = serde_json::to_value(replayed).ok()?;
+    value_mismatch("metadata", &recorded, &replayed)
+}
+
+fn value_mismatch(
+    path: &str,
+    recorded: &serde_json::Value,
+    replayed: &serde_json::Value,
+) -> Option<String> {
+    use serde_json::Value;
+    match (recorded, replayed) {
+        (Value::Object(recorded), Value::Object(replayed)) => {
+            let mut fields: Vec<&String> = recorded.keys().chain(replayed.keys()).collect();
+            fields.sort();
+            fields.dedup();
+            fields.into_iter().find_map(|field| {
+                let path = format!("{path}.{field}");
+                match (recorded.get(field), replayed.get(field)) {
+                    (Some(_), Some(_)) if key::is_entity_field(field) => None,
+                    (Some(recorded), Some(replayed)) => value_mismatch(&path, recorded, replayed),
+                    (Some(_), None) => Some(format!("[{path}] is missing")),
+                    (None, _) => Some(format!("[{path}] was not recorded")),
+                }
+            })
+        },
+        (Value::Array(recorded), Value::Array(replayed)) if recorded.len() == replayed.len() => {
+            recorded
+                .iter()
+                .zip(replayed)
+                .enumerate()
+                .find_map(|(i, (recorded, replayed))| {
+                    value_mismatch(&format!("{path}[{i}]"), recorded, replayed)
+                })
+        },
+        _ if recorded == replayed => None,
+        _ => Some(format!(
+            "[{path}] was recorded as {recorded} but is {replayed}"
+        )),
+    }
+}
+
+fn deserialize_node_op(op: serde_json::Value, registry: &TypeRegistry) -> Result<NodeOp, String> {
+    let op = ReflectDeserializer::new(registry)
+        .deserialize(op)
+        .map_err(|e| e.to_string())?;
+    NodeOp::from_reflect(&*op).ok_or_else(|| format!("Replay op is not a NodeOp: {op:?}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::card::{Action, ActionEffect, ActionRange, ActionTarget};
+    use crate::node::node_testing::TestBattle;
+
+    /// A battle where the first team's curio can move next to the second
+    /// team's curio and hit it for 2 damage
+    fn hit_battle(enemy_len: u32) -> (TestBattle, Entity) {
+        let mut battle = TestBattle::new(6, 6);
+        battle.app.add_plugins(NodeReplayPlugin);
+        let hit = battle.add_action(Action {
+            id: "hit".to_owned(),
+            range: Some(ActionRange::new(1)),
+            effects: vec![ActionEffect::Damage(2)],
+            target: ActionTarget::Enemies,
+            ..default()
+        });
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 2, &[hit]);
+        let enemy_pts: Vec<UVec2> = (0..enemy_len).map(|x| UVec2::new(x, 2)).collect();
+        battle.spawn_curio(1, &enemy_pts, 2, &[]);
+        (battle, curio)
+    }
+
+    /// Players' names are how they are usually told apart in replays
+    fn remove_player_names((mut battle, curio): (TestBattle, Entity)) -> (TestBattle, Entity) {
+        for player in battle.players.clone() {
+            battle.app.world_mut().entity_mut(player).remove::<Name>();
+        }
+        (battle, curio)
+    }
+
+    fn record_hit_battle((mut battle, curio): (TestBattle, Entity), path: &Path) -> EntityGrid {
+        battle
+            .app
+            .insert_resource(NodeOpRecorder::create(path).unwrap());
+        for op in [
+            NodeOp::ActivateCurio { curio_id: curio },
+            NodeOp::MoveActiveCurio {
+                dir: Compass::South,
+            },
+            NodeOp::PerformCurioAction {
+                action_id: "hit".into(),
+                curio: None,
+                target: UVec2::new(0, 2),
+            },
+            NodeOp::EndTurn,
+        ] {
+            let results = battle.perform(0, op);
+            assert!(results.iter().all(|result| result.result().is_ok()));
+        }
+        // Ops are only written once the recorder is done with
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "");
+        battle.app.world_mut().remove_resource::<NodeOpRecorder>();
+        // The player and their curio, and then the ops
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 6);
+        battle.grid().clone()
+    }
+
+    fn replay(mut battle: TestBattle, path: &Path) -> (TestBattle, NodeReplay) {
+        battle.app.insert_resource(NodeReplay::open(path).unwrap());
+        for _ in 0..20 {
+            battle.app.update();
+            if battle.app.world().resource::<NodeReplay>().is_finished() {
+                break;
+            }
+        }
+        let replay = battle.app.world_mut().remove_resource::<NodeReplay>();
+        (battle, replay.unwrap())
+    }
+
+    #[test]
+    fn test_replay_recorded_battle() {
+        let path =
+            std::env::temp_dir().join(format!("nf-replay-test-{}-same.jsonl", std::process::id()));
+        let recorded_grid = record_hit_battle(hit_battle(3), &path);
+
+        let (battle, replay) = replay(hit_battle(3).0, &path);
+        assert!(replay.is_finished());
+        assert_eq!(replay.divergence(), None);
+        assert_eq!(battle.grid(), &recorded_grid);
+        assert_eq!(battle.current_turn(), battle.teams[1]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_reports_divergence() {
+        let path = std::env::temp_dir().join(format!(
+            "nf-replay-test-{}-diverged.jsonl",
+            std::process::id()
+        ));
+        record_hit_battle(hit_battle(3), &path);
+
+        // The hit takes different squares from a shorter enemy, and is fatal
+        let (_, replay) = replay(hit_battle(2).0, &path);
+        assert!(replay.is_finished());
+        let divergence = replay.divergence().expect("replay should diverge");
+        assert!(
+            divergence.contains("PerformCurioAction")
+                && divergence.contains("[metadata.effects.damages"),
+            "unexpected divergence: {divergence}"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_players_without_names() {
+        let path = std::env::temp_dir().join(format!(
+            "nf-replay-test-{}-unnamed.jsonl",
+            std::process::id()
+        ));
+        let recorded_grid = record_hit_battle(remove_player_names(hit_battle(3)), &path);
+
+        let (battle, replay) = replay(remove_player_names(hit_battle(3)).0, &path);
+        assert!(replay.is_finished());
+        assert_eq!(replay.divergence(), None);
+        assert_eq!(battle.grid(), &recorded_grid);
+        std::fs::remove_file(path).unwrap();
+    }
+}
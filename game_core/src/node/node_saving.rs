@@ -0,0 +1,533 @@
+use std::time::Duration;
+
+use bevy::ecs::entity::{EntityMapper, MapEntities};
+use bevy::ecs::query::QuerySingleError;
+use bevy::hierarchy::DespawnRecursiveExt;
+use bevy::scene::SceneFilter;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    AccessPoint, AccessPointLoadingRule, ActiveCurio, Alliances, Claimed, Curio, CurrentTurn,
+    InNode, IsReadyToGo, IsTapped, LostSquares, MovesTaken, NoOpAction, Node,
+    NodeBattleIntelligence, NodePiece, OnTeam, Pickup, PlayedCards, PreventNoOp,
+    SimpleAiCurioOrder, Team, TeamClock, TeamColor, TeamPhase, TeamStatus, Teams, TurnTimeLimit,
+    TurnsEnded, VictoryAward, VictoryRule, VictoryRules,
+};
+use crate::card::{
+    ActionHistory, Actions, Description, MaximumSize, MovementSpeed, StatusEffect, StatusEffects,
+    Tags,
+};
+use crate::common::daddy::Daddy;
+use crate::player::{Ncp, Player};
+use crate::prelude::*;
+use crate::saving::{LoadData, LoadSchedule, SaveData, SaveSchedule};
+
+mod save_key {
+    use typed_key::{typed_key, Key};
+
+    use super::SavedBattle;
+
+    pub const BATTLE: Key<SavedBattle> = typed_key!("battle");
+}
+
+/// Saves the battle a player is in along with the rest of the game, and puts
+/// them back into it when the save is loaded.
+///
+/// Like the rest of the save, this is for the one local ([`Ncp`]) player, so
+/// a save holds at most one battle. Battles aren't saved or loaded when there
+/// is more than one local player, since there's no telling whose it would be.
+#[derive(Debug, Default)]
+pub struct NodeSavingPlugin;
+
+impl Plugin for NodeSavingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BattleSaveFilter>()
+            // Internal collection types need to be registered too
+            .register_type::<HashMap<String, u32>>()
+            .register_type::<Option<Duration>>()
+            .register_type::<Vec<StatusEffect>>()
+            .register_type::<Vec<String>>()
+            .register_type::<Vec<UVec2>>()
+            .register_type::<Vec<VictoryRule>>()
+            .add_systems(SaveSchedule, sys_save_battle)
+            .add_systems(LoadSchedule, sys_load_battle);
+    }
+}
+
+/// Components saved on the entities of a battle in progress. Kept apart from
+/// [`SaveFilter`](crate::saving::SaveFilter) so that other saved entities,
+/// like cards, don't save these too.
+#[derive(Debug, Deref, DerefMut, Resource)]
+pub struct BattleSaveFilter(SceneFilter);
+
+impl Default for BattleSaveFilter {
+    fn default() -> Self {
+        Self(
+            SceneFilter::deny_all()
+                .allow::<Name>()
+                // Node
+                .allow::<AccessPointLoadingRule>()
+                .allow::<ActiveCurio>()
+                .allow::<CurrentTurn>()
+                .allow::<EntityGrid>()
+                .allow::<Node>()
+                .allow::<TeamStatus>()
+                .allow::<Teams>()
+                .allow::<TurnTimeLimit>()
+                .allow::<VictoryRules>()
+                // Teams
+                .allow::<Alliances>()
+                .allow::<Team>()
+                .allow::<TeamClock>()
+                .allow::<TeamColor>()
+                .allow::<TeamPhase>()
+                .allow::<TurnsEnded>()
+                // Computer players
+                .allow::<InNode>()
+                .allow::<IsReadyToGo>()
+                .allow::<NodeBattleIntelligence>()
+                .allow::<OnTeam>()
+                .allow::<Player>()
+                // Pieces
+                .allow::<AccessPoint>()
+                .allow::<ActionHistory>()
+                .allow::<Curio>()
+                .allow::<Description>()
+                .allow::<IsTapped>()
+                .allow::<LostSquares>()
+                .allow::<MaximumSize>()
+                .allow::<MovementSpeed>()
+                .allow::<MovesTaken>()
+                .allow::<NodePiece>()
+                .allow::<Pickup>()
+                .allow::<PreventNoOp>()
+                .allow::<SimpleAiCurioOrder>()
+                .allow::<StatusEffects>()
+                .allow::<Tags>()
+                .allow::<VictoryAward>(),
+        )
+    }
+}
+
+/// What is needed to put a player back into the battle they saved in, besides
+/// the node's entities in the saved scene.
+///
+/// Hierarchy isn't saved in the scene, since the node's scene entity isn't
+/// saved, so it is kept here instead.
+#[derive(Debug, Deserialize, Serialize)]
+struct SavedBattle {
+    node: Entity,
+    team: Entity,
+    is_ready_to_go: Option<bool>,
+    played_cards: PlayedCards,
+    /// Entities that the node scene was spawned with, including the node
+    scene_roots: Vec<Entity>,
+    children: HashMap<Entity, Vec<Entity>>,
+    /// Asset paths of each curio's actions, since handles can't be saved.
+    /// `None` is the no-op action.
+    curio_actions: HashMap<Entity, Vec<Option<String>>>,
+    /// Pickups the player has claimed in the node
+    claimed_pickups: Vec<Entity>,
+}
+
+impl MapEntities for SavedBattle {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        self.node = entity_mapper.map_entity(self.node);
+        self.team = entity_mapper.map_entity(self.team);
+        self.played_cards.map_entities(entity_mapper);
+        for id in self
+            .scene_roots
+            .iter_mut()
+            .chain(self.claimed_pickups.iter_mut())
+        {
+            *id = entity_mapper.map_entity(*id);
+        }
+        self.children = self
+            .children
+            .drain()
+            .map(|(parent, children)| {
+                (
+                    entity_mapper.map_entity(parent),
+                    children
+                        .into_iter()
+                        .map(|child| entity_mapper.map_entity(child))
+                        .collect(),
+                )
+            })
+            .collect();
+        self.curio_actions = self
+            .curio_actions
+            .drain()
+            .map(|(curio, actions)| (entity_mapper.map_entity(curio), actions))
+            .collect();
+    }
+}
+
+fn sys_save_battle(
+    res_save_data: Res<SaveData>,
+    res_battle_save_filter: Res<BattleSaveFilter>,
+    res_no_op_action: Res<NoOpAction>,
+    q_player: Query<
+        (
+            Entity,
+            AsDerefCopied<InNode>,
+            AsDerefCopied<OnTeam>,
+            Option<AsDerefCopied<IsReadyToGo>>,
+            &PlayedCards,
+        ),
+        (With<Player>, With<Ncp>),
+    >,
+    q_node: Query<AsDerefCopied<Parent>, With<Node>>,
+    q_children: Query<&Children>,
+    q_curio: Query<(Entity, &Actions), With<Curio>>,
+    q_claimed: Query<(Entity, &Claimed)>,
+) {
+    if !res_save_data.include_battle() {
+        return;
+    }
+    // Saves have a single battle key, so only one local player's battle fits
+    let (player_id, node_id, team_id, is_ready_to_go, played_cards) = match q_player.get_single() {
+        Ok(player) => player,
+        Err(QuerySingleError::NoEntities(_)) => return,
+        Err(QuerySingleError::MultipleEntities(_)) => {
+            log::warn!("Unable to save battle, there is more than one local player in a node");
+            return;
+        },
+    };
+    // ASSUMES THAT THE NODE HAS NO PARENTS OTHER THAN THE SCENE
+    let Ok(node_scene_id) = q_node.get(node_id) else {
+        log::warn!("Unable to save battle for {player_id:?}, node {node_id:?} has no scene");
+        return;
+    };
+    let entities: Vec<Entity> = q_children.iter_descendants(node_scene_id).collect();
+    let children = entities
+        .iter()
+        .filter_map(|&id| Some((id, q_children.get(id).ok()?.to_vec())))
+        .collect();
+    let curio_actions = q_curio
+        .iter_many(entities.iter())
+        .map(|(curio_id, actions)| {
+            let action_paths = actions
+                .iter()
+                .filter_map(|action| match action.path() {
+                    Some(path) => Some(Some(path.to_string())),
+                    None if *action == **res_no_op_action => Some(None),
+                    None => {
+                        log::warn!("Unable to save action of {curio_id:?}, it has no path");
+                        None
+                    },
+                })
+                .collect();
+            (curio_id, action_paths)
+        })
+        .collect();
+    let claimed_pickups = q_claimed
+        .iter()
+        .filter(|(_, claimed)| claimed.player == player_id && claimed.node_id == node_id)
+        .map(|(pickup_id, _)| pickup_id)
+        .collect();
+
+    res_save_data.add_entities_with_filter(&entities, res_battle_save_filter.clone());
+    res_save_data
+        .put(
+            save_key::BATTLE,
+            SavedBattle {
+                node: node_id,
+                team: team_id,
+                is_ready_to_go,
+                played_cards: played_cards.clone(),
+                scene_roots: q_children
+                    .get(node_scene_id)
+                    .map(|scene_roots| scene_roots.to_vec())
+                    .unwrap_or_default(),
+                children,
+                curio_actions,
+                claimed_pickups,
+            },
+        )
+        .expect("Should be able to save!");
+}
+
+fn sys_load_battle(
+    mut commands: Commands,
+    res_asset_server: Res<AssetServer>,
+    res_daddy_node: Res<Daddy<Node>>,
+    res_load_data: Res<LoadData>,
+    res_no_op_action: Res<NoOpAction>,
+    mut q_player: Query<
+        (Entity, Option<AsDerefCopied<InNode>>, &mut PlayedCards),
+        (With<Player>, With<Ncp>),
+    >,
+    q_node: Query<AsDerefCopied<Parent>, With<Node>>,
+) {
+    // The saved battle belongs to the one local player, see sys_save_battle
+    let (player_id, current_node_id, mut played_cards) = match q_player.get_single_mut() {
+        Ok(player) => player,
+        Err(QuerySingleError::NoEntities(_)) => return,
+        Err(QuerySingleError::MultipleEntities(_)) => {
+            log::warn!("Unable to load saved battle, there is more than one local player");
+            return;
+        },
+    };
+    let mut saved_battle = match res_load_data.get_optional(save_key::BATTLE) {
+        Ok(Some(saved_battle)) => saved_battle,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("Unable to load saved battle: {e}");
+            return;
+        },
+    };
+    res_load_data.map_entities(&mut saved_battle);
+    // The saved battle replaces the one the player is in
+    if let Some(node_scene_id) = current_node_id.and_then(|node_id| q_node.get(node_id).ok()) {
+        commands.entity(node_scene_id).despawn_recursive();
+    }
+
+    commands
+        .spawn(Name::new("Saved node scene"))
+        .set_parent(**res_daddy_node)
+        .push_children(&saved_battle.scene_roots);
+    for (parent_id, children) in saved_battle.children.iter() {
+        commands.entity(*parent_id).push_children(children);
+    }
+    for (curio_id, action_paths) in saved_battle.curio_actions {
+        let actions = action_paths
+            .into_iter()
+            .map(|path| match path {
+                Some(path) => res_asset_server.load(path),
+                None => (**res_no_op_action).clone(),
+            })
+            .collect();
+        commands.entity(curio_id).insert(Actions(actions));
+    }
+    for pickup_id in saved_battle.claimed_pickups {
+        commands.entity(pickup_id).insert(Claimed {
+            node_id: saved_battle.node,
+            player: player_id,
+        });
+    }
+
+    let mut player = commands.entity(player_id);
+    player.insert((InNode(saved_battle.node), OnTeam(saved_battle.team)));
+    match saved_battle.is_ready_to_go {
+        Some(is_ready_to_go) => player.insert(IsReadyToGo(is_ready_to_go)),
+        None => player.remove::<IsReadyToGo>(),
+    };
+    *played_cards = saved_battle.played_cards;
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bevy::ecs::event::Events;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::hierarchy::BuildWorldChildren;
+
+    use super::*;
+    use crate::card::{Card, Deck};
+    use crate::node::node_testing::TestBattle;
+    use crate::node::NodeOp;
+    use crate::saving::{opsys_load_op, opsys_save_op, SaveOp, SaveWritten};
+
+    /// What the battle looks like to the first player, with entities
+    /// described by name since they are respawned when the save is loaded
+    #[derive(Debug, PartialEq)]
+    struct BattleSnapshot {
+        shape: String,
+        /// Name, points, whether it is tapped and moves taken of each piece
+        pieces: Vec<(String, Vec<UVec2>, Option<bool>, Option<u32>)>,
+        current_turn: usize,
+        played_cards: Vec<(String, Vec<String>)>,
+    }
+
+    fn name_of(battle: &TestBattle, id: Entity) -> String {
+        let world = battle.app.world();
+        world
+            .get::<Name>(id)
+            .map(|name| name.to_string())
+            .or_else(|| world.get::<Curio>(id).map(|curio| curio.name().to_owned()))
+            .unwrap_or_else(|| format!("{id:?}"))
+    }
+
+    fn snapshot(battle: &TestBattle) -> BattleSnapshot {
+        let world = battle.app.world();
+        let player = battle.players[0];
+        let node = **battle.get::<InNode>(player);
+        let grid = world
+            .get::<EntityGrid>(node)
+            .expect("node should have a grid");
+        let mut pieces: Vec<_> = grid
+            .entities()
+            .into_iter()
+            .map(|id| {
+                (
+                    name_of(battle, id),
+                    grid.points(id),
+                    world.get::<IsTapped>(id).map(|is_tapped| **is_tapped),
+                    world.get::<MovesTaken>(id).map(|moves_taken| **moves_taken),
+                )
+            })
+            .collect();
+        // Curios on the same team share a name
+        pieces.sort_by_key(|(name, pts, ..)| {
+            let pts: Vec<_> = pts.iter().map(|pt| (pt.x, pt.y)).collect();
+            (name.clone(), pts)
+        });
+        let current_turn = **world.get::<CurrentTurn>(node).unwrap();
+        let mut played_cards: Vec<_> = battle
+            .get::<PlayedCards>(player)
+            .0
+            .iter()
+            .map(|(&card, locations)| {
+                (
+                    name_of(battle, card),
+                    locations
+                        .iter()
+                        .map(|&location| name_of(battle, location))
+                        .collect(),
+                )
+            })
+            .collect();
+        played_cards.sort();
+        BattleSnapshot {
+            shape: grid.shape_string_base64(),
+            pieces,
+            current_turn: world
+                .get::<Teams>(node)
+                .unwrap()
+                .iter()
+                .position(|&team| team == current_turn)
+                .expect("current turn should be one of the teams"),
+            played_cards,
+        }
+    }
+
+    fn perform_save_op(battle: &mut TestBattle, op: SaveOp) {
+        let player = battle.players[0];
+        let result = match op {
+            SaveOp::Save { .. } => battle
+                .app
+                .world_mut()
+                .run_system_once_with((player, op), opsys_save_op),
+            _ => battle
+                .app
+                .world_mut()
+                .run_system_once_with((player, op), opsys_load_op),
+        };
+        result.expect("save op should work");
+    }
+
+    fn save(battle: &mut TestBattle) {
+        perform_save_op(
+            battle,
+            SaveOp::Save {
+                include_battle: true,
+            },
+        );
+        // Saves are written in the background
+        for _ in 0..200 {
+            battle.update();
+            let written = battle
+                .app
+                .world_mut()
+                .resource_mut::<Events<SaveWritten>>()
+                .drain()
+                .next();
+            if let Some(written) = written {
+                assert!(written.result().is_ok(), "{written:?}");
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("save was never written");
+    }
+
+    #[test]
+    fn test_save_and_load_mid_battle() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("nf-battle-save-test-{}", std::process::id()));
+        let mut battle = TestBattle::new(4, 4).with_saving(dir.clone());
+        let mover = battle.spawn_curio(0, &[UVec2::new(0, 0)], 3, &[]);
+        battle.spawn_curio(0, &[UVec2::new(3, 0)], 3, &[]);
+        battle.spawn_curio(1, &[UVec2::new(3, 3), UVec2::new(2, 3)], 3, &[]);
+        let world = battle.app.world_mut();
+        let card = world.spawn((Card, Name::new("Card"))).id();
+        let access_point = world
+            .spawn((
+                Name::new("Access point"),
+                AccessPoint::default(),
+                NodePiece::new("access_point"),
+                OnTeam(battle.teams[0]),
+            ))
+            .set_parent(battle.node)
+            .id();
+        world
+            .get_mut::<EntityGrid>(battle.node)
+            .unwrap()
+            .put_item(UVec2::new(1, 3), access_point);
+        let player = battle.players[0];
+        let mut deck = Deck::new();
+        deck.add_card(card);
+        world
+            .get_mut::<PlayedCards>(player)
+            .unwrap()
+            .play_card_to(&deck, card, access_point);
+        *world.get_mut::<Deck>(player).unwrap() = deck;
+
+        battle.perform(0, NodeOp::ActivateCurio { curio_id: mover });
+        let results = battle.perform(0, NodeOp::MoveActiveCurio { dir: Compass::East });
+        assert!(results.iter().all(|result| result.result().is_ok()));
+        let saved = snapshot(&battle);
+        assert!(
+            saved.pieces.iter().any(|piece| piece.3 == Some(1)),
+            "{saved:?}"
+        );
+        save(&mut battle);
+
+        // Play on after saving, and then load the save
+        battle.perform(0, NodeOp::EndTurn);
+        assert_ne!(snapshot(&battle), saved);
+        let old_node = battle.node;
+        perform_save_op(&mut battle, SaveOp::Load);
+        battle.update();
+
+        assert_eq!(snapshot(&battle), saved);
+        let world = battle.app.world_mut();
+        assert!(
+            world.get_entity(old_node).is_none(),
+            "the battle the player was in should be despawned"
+        );
+        assert_eq!(world.query::<&Node>().iter(world).count(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_battle_not_saved_for_more_than_one_local_player() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("nf-battle-two-players-test-{}", std::process::id()));
+        let mut battle = TestBattle::new(4, 4).with_saving(dir.clone());
+        let mover = battle.spawn_curio(0, &[UVec2::new(0, 0)], 3, &[]);
+        battle.spawn_curio(1, &[UVec2::new(3, 3)], 3, &[]);
+        let other_player = battle.players[1];
+        battle.app.world_mut().entity_mut(other_player).insert(Ncp);
+        save(&mut battle);
+
+        battle
+            .app
+            .world_mut()
+            .entity_mut(other_player)
+            .remove::<Ncp>();
+        battle.perform(0, NodeOp::ActivateCurio { curio_id: mover });
+        battle.perform(0, NodeOp::MoveActiveCurio { dir: Compass::East });
+        let played_on = snapshot(&battle);
+        let node = battle.node;
+        perform_save_op(&mut battle, SaveOp::Load);
+        battle.update();
+
+        // There was no telling whose battle to save, so there's none to load
+        assert_eq!(snapshot(&battle), played_on);
+        assert!(battle.app.world().get_entity(node).is_some());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+//! Small battles for testing node ops, built directly in a world so that no
+//! assets or registries need to be loaded
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bevy::asset::AssetPlugin;
+use bevy::ecs::entity::EntityHashMap;
+use bevy::ecs::event::Events;
+use bevy::hierarchy::BuildWorldChildren;
+use bevy::tasks::{IoTaskPool, TaskPool};
+use bevy::time::Time;
+
+use super::node_saving::NodeSavingPlugin;
+use super::rule::{self, TurnsEnded};
+use super::*;
+use crate::card::{
+    self, Action, ActionHistory, Actions, Card, Deck, MaximumSize, MovementSpeed, Status,
+    StatusEffect, StatusEffects, Tags,
+};
+use crate::common::daddy::Daddy;
+use crate::entity_grid::EntityGridSupportPlugin;
+use crate::item::{Inventory, ItemActions, ItemDefinitions, ItemOp};
+use crate::op::OpExecutorPlugin;
+use crate::player::{Ncp, Player};
+use crate::registry::Reg;
+use crate::saving::{LoadSchedule, SaveDirectory, SavePlugin, SaveSchedule};
+
+/// A battle between teams with one player each, already in the play phase.
+/// It is the first team's turn.
+pub(crate) struct TestBattle {
+    pub app: App,
+    pub node: Entity,
+    pub teams: Vec<Entity>,
+    pub players: Vec<Entity>,
+}
+
+impl TestBattle {
+    /// Creates a battle between two teams on a grid with every square open
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::with_team_count(2, width, height)
+    }
+
+    pub fn with_team_count(team_count: usize, width: u32, height: u32) -> Self {
+        let mut app = App::new();
+        app.configure_sets(
+            Update,
+            (
+                NDitCoreSet::ProcessCommands,
+                NDitCoreSet::ProcessCommandsFlush,
+                NDitCoreSet::PostProcessCommands,
+            )
+                .chain(),
+        )
+        .init_resource::<Assets<Action>>()
+        .init_resource::<NoOpAction>()
+        .init_resource::<ItemActions>()
+        .init_resource::<Reg<ItemDefinitions>>()
+        .init_resource::<Daddy<Card>>()
+        .add_plugins((
+            OpExecutorPlugin::<CoreOps>::new(Update, Some(NDitCoreSet::ProcessCommands)),
+            OpPlugin::<NodeOp>::default(),
+            OpPlugin::<ItemOp>::default(),
+        ));
+        let world = app.world_mut();
+        let teams: Vec<Entity> = (0..team_count)
+            .map(|_| {
+                world
+                    .spawn((Team, TeamPhase::Play, TurnsEnded::default()))
+                    .id()
+            })
+            .collect();
+        let grid = EntityGrid::from(vec![vec![true; height as usize]; width as usize]);
+        let node = world
+            .spawn((
+                Node(NodeId::new("node:test", 0)),
+                grid,
+                CurrentTurn(teams[0]),
+                ActiveCurio::default(),
+                Teams(teams.clone()),
+                TeamStatus(
+                    teams
+                        .iter()
+                        .map(|team| (*team, VictoryStatus::Undecided))
+                        .collect::<EntityHashMap<_>>(),
+                ),
+            ))
+            .id();
+        let players = teams
+            .iter()
+            .enumerate()
+            .map(|(i, team)| {
+                world
+                    .spawn((
+                        Player,
+                        Name::new(format!("Player {i}")),
+                        OnTeam(*team),
+                        InNode(node),
+                        Inventory::default(),
+                        PlayedCards::default(),
+                    ))
+                    .id()
+            })
+            .collect();
+        // Registers the op systems
+        app.update();
+        TestBattle {
+            app,
+            node,
+            teams,
+            players,
+        }
+    }
+
+    /// Adds undo and redo support, with each team's undo stack in place
+    pub fn with_undo(mut self) -> Self {
+        self.app
+            .add_plugins(super::node_op::node_op_undo::NodeOpUndoPlugin::default());
+        self.app.update();
+        self
+    }
+
+    /// Adds the node's victory and time limit rules, which take effect from
+    /// the next frame. Time only passes in [`TestBattle::advance_time`].
+    pub fn with_rules(mut self) -> Self {
+        self.app
+            .init_resource::<Time>()
+            .add_systems(PreUpdate, rule::sys_tick_team_clocks)
+            .add_systems(
+                Update,
+                (rule::sys_reset_turn_clocks, rule::sys_apply_victory_rules)
+                    .in_set(NDitCoreSet::PostProcessCommands),
+            )
+            .add_systems(
+                PostUpdate,
+                (rule::sys_setup_turns_ended, rule::sys_setup_team_clocks),
+            );
+        self
+    }
+
+    /// Adds saving to slots in `save_directory`. The node and teams are put
+    /// in a node scene like a loaded node's, and the first player saves their
+    /// deck along with the battle.
+    pub fn with_saving(mut self, save_directory: PathBuf) -> Self {
+        IoTaskPool::get_or_init(TaskPool::new);
+        self.app
+            .add_plugins((
+                AssetPlugin {
+                    watch_for_changes_override: Some(false),
+                    ..default()
+                },
+                EntityGridSupportPlugin,
+                NodeSavingPlugin,
+                SavePlugin,
+            ))
+            .insert_resource(SaveDirectory::new(save_directory))
+            .init_resource::<Daddy<Node>>()
+            .register_type::<AccessPoint>()
+            .register_type::<ActionHistory>()
+            .register_type::<ActiveCurio>()
+            .register_type::<Curio>()
+            .register_type::<CurrentTurn>()
+            .register_type::<InNode>()
+            .register_type::<IsTapped>()
+            .register_type::<LostSquares>()
+            .register_type::<MaximumSize>()
+            .register_type::<MovementSpeed>()
+            .register_type::<MovesTaken>()
+            .register_type::<Name>()
+            .register_type::<Node>()
+            .register_type::<NodeId>()
+            .register_type::<NodePiece>()
+            .register_type::<OnTeam>()
+            .register_type::<Player>()
+            .register_type::<Status>()
+            .register_type::<StatusEffect>()
+            .register_type::<StatusEffects>()
+            .register_type::<Tags>()
+            .register_type::<Team>()
+            .register_type::<TeamPhase>()
+            .register_type::<TeamStatus>()
+            .register_type::<Teams>()
+            .register_type::<TurnsEnded>()
+            .register_type::<VictoryStatus>()
+            .register_type::<EntityHashMap<VictoryStatus>>()
+            .register_type::<Option<Entity>>()
+            .register_type::<Vec<Entity>>()
+            .add_systems(SaveSchedule, card::sys_save_deck)
+            .add_systems(LoadSchedule, card::sys_loadsave_deck);
+        let world = self.app.world_mut();
+        let daddy = **world.resource::<Daddy<Node>>();
+        let node_scene = world.spawn(Name::new("Node scene")).set_parent(daddy).id();
+        world.entity_mut(self.node).set_parent(node_scene);
+        for &team in self.teams.iter() {
+            world.entity_mut(team).set_parent(node_scene);
+        }
+        world.entity_mut(self.players[0]).insert((Ncp, Deck::new()));
+        self
+    }
+
+    /// Runs a frame that takes `delta` of time, returning the results of
+    /// every op performed during it. Other frames take no time.
+    pub fn advance_time(&mut self, delta: Duration) -> Vec<OpResult<NodeOp>> {
+        self.app
+            .world_mut()
+            .resource_mut::<Time>()
+            .advance_by(delta);
+        let results = self.update();
+        self.app
+            .world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::ZERO);
+        results
+    }
+
+    /// Allies two teams with each other
+    pub fn ally(&mut self, team: usize, other_team: usize) {
+        for (team, other_team) in [(team, other_team), (other_team, team)] {
+            let [team, other_team] = [self.teams[team], self.teams[other_team]];
+            let world = self.app.world_mut();
+            let mut team = world.entity_mut(team);
+            if let Some(mut alliances) = team.get_mut::<Alliances>() {
+                alliances.push(other_team);
+            } else {
+                team.insert(Alliances(vec![other_team]));
+            }
+        }
+    }
+
+    pub fn add_action(&mut self, action: Action) -> Handle<Action> {
+        self.app
+            .world_mut()
+            .resource_mut::<Assets<Action>>()
+            .add(action)
+    }
+
+    /// Spawns a curio for a team, head first, able to move `speed` squares
+    pub fn spawn_curio(
+        &mut self,
+        team: usize,
+        pts: &[UVec2],
+        speed: u32,
+        actions: &[Handle<Action>],
+    ) -> Entity {
+        let node = self.node;
+        let world = self.app.world_mut();
+        let curio = world
+            .spawn((
+                Curio::new(format!("Curio {team}")),
+                NodePiece::new("curio"),
+                OnTeam(self.teams[team]),
+                IsTapped::default(),
+                MovesTaken::default(),
+                MovementSpeed(speed),
+                MaximumSize(pts.len().max(1) as u32 + 2),
+                Actions(actions.to_vec()),
+                ActionHistory::default(),
+                LostSquares::default(),
+                StatusEffects::default(),
+                Tags::default(),
+            ))
+            .set_parent(node)
+            .id();
+        world
+            .get_mut::<EntityGrid>(node)
+            .expect("test node should have a grid")
+            .put_entries([(curio, pts.iter().copied())]);
+        curio
+    }
+
+    pub fn request(&mut self, player: usize, op: NodeOp) {
+        let player = self.players[player];
+        self.app
+            .world_mut()
+            .resource_mut::<CoreOps>()
+            .request(player, op);
+    }
+
+    /// Requests an op, runs a frame and returns the results of every op
+    /// performed during it
+    pub fn perform(&mut self, player: usize, op: NodeOp) -> Vec<OpResult<NodeOp>> {
+        self.request(player, op);
+        self.update()
+    }
+
+    /// Runs a frame and returns the results of every op performed during it
+    pub fn update(&mut self) -> Vec<OpResult<NodeOp>> {
+        self.app.update();
+        self.app
+            .world_mut()
+            .resource_mut::<Events<OpResult<NodeOp>>>()
+            .drain()
+            .collect()
+    }
+
+    pub fn grid(&self) -> &EntityGrid {
+        self.app
+            .world()
+            .get::<EntityGrid>(self.node)
+            .expect("test node should have a grid")
+    }
+
+    pub fn get<C: Component>(&self, entity: Entity) -> &C {
+        self.app
+            .world()
+            .get::<C>(entity)
+            .expect("test entity should have component")
+    }
+
+    pub fn current_turn(&self) -> Entity {
+        **self.get::<CurrentTurn>(self.node)
+    }
+}
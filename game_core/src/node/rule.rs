@@ -1,3 +1,16 @@
+use std::time::Duration;
+
+use bevy::ecs::entity::{EntityMapper, MapEntities};
+use bevy::ecs::reflect::ReflectMapEntities;
+use bevy::time::Time;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Alliances, Claimed, Curio, CurrentTurn, InNode, Node, NodeOp, OnTeam, Pickup, Team, TeamPhase,
+    TeamStatus, Teams, VictoryStatus,
+};
+use crate::op::{CoreOps, OpResult};
+use crate::player::Player;
 use crate::prelude::*;
 
 #[derive(Clone, Component, Copy, Debug, Default, Eq, PartialEq, Reflect)]
@@ -7,3 +20,597 @@ pub enum AccessPointLoadingRule {
     #[default]
     Staggered,
 }
+
+/// Node component listing how the battle can be won. Rules are checked after
+/// every node op once all teams are out of setup. When a team wins, the teams
+/// it is allied with win alongside it.
+///
+/// Nodes without any rules use [`VictoryRule::Elimination`].
+#[derive(Clone, Component, Debug, Default, Deref, Reflect)]
+#[reflect(Component, MapEntities)]
+pub struct VictoryRules(pub Vec<VictoryRule>);
+
+#[derive(Clone, Debug, Reflect)]
+pub enum VictoryRule {
+    /// Teams with no curios left in the grid lose, and the last team standing
+    /// wins, along with its allies if they are the only others left
+    Elimination,
+    /// The team of whoever picks up a [`Pickup::MacGuffin`] wins
+    CollectMacGuffin,
+    /// The team wins once it has ended this many turns
+    SurviveTurns { team: Entity, turns: u32 },
+    /// The team wins once the piece is no longer in the grid
+    DestroyPiece { team: Entity, piece: Entity },
+    /// The team wins once one of its curios is on the square
+    ReachSquare { team: Entity, pt: UVec2 },
+}
+
+/// Node component limiting how long each team can take, tracked in each
+/// team's [`TeamClock`]. Either limit can be left out.
+#[derive(Clone, Component, Copy, Debug, Default, Deserialize, Reflect, Serialize)]
+#[reflect(Component, Deserialize, Serialize)]
+pub struct TurnTimeLimit {
+    /// Time a team has for each of its turns
+    pub per_turn: Option<Duration>,
+    /// Time a team has for all of its turns put together, like a chess clock
+    pub total: Option<Duration>,
+    pub on_timeout: TimeoutRule,
+}
+
+/// What happens to a team that runs out of time
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Reflect, Serialize)]
+pub enum TimeoutRule {
+    /// Their turn is ended for them
+    #[default]
+    EndTurn,
+    /// They lose the battle
+    Loss,
+}
+
+/// Team component with how much time the team has left under the node's
+/// [`TurnTimeLimit`]
+#[derive(Clone, Component, Copy, Debug, Default, Deserialize, Reflect, Serialize)]
+#[reflect(Component, Deserialize, Serialize)]
+pub struct TeamClock {
+    pub turn_remaining: Option<Duration>,
+    pub total_remaining: Option<Duration>,
+}
+
+impl TeamClock {
+    pub fn is_out_of_time(&self) -> bool {
+        self.turn_remaining == Some(Duration::ZERO) || self.total_remaining == Some(Duration::ZERO)
+    }
+}
+
+/// Team component counting how many turns the team has ended
+#[derive(Clone, Component, Copy, Debug, Default, Deref, DerefMut, Reflect)]
+#[reflect(Component)]
+pub struct TurnsEnded(pub u32);
+
+impl MapEntities for VictoryRules {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        for rule in self.0.iter_mut() {
+            match rule {
+                VictoryRule::SurviveTurns { team, .. } | VictoryRule::ReachSquare { team, .. } => {
+                    *team = entity_mapper.map_entity(*team);
+                },
+                VictoryRule::DestroyPiece { team, piece } => {
+                    *team = entity_mapper.map_entity(*team);
+                    *piece = entity_mapper.map_entity(*piece);
+                },
+                VictoryRule::Elimination | VictoryRule::CollectMacGuffin => {},
+            }
+        }
+    }
+}
+
+pub(super) fn sys_setup_turns_ended(
+    mut commands: Commands,
+    teams: Query<Entity, (With<Team>, Without<TurnsEnded>)>,
+) {
+    for id in teams.iter() {
+        commands.entity(id).insert(TurnsEnded::default());
+    }
+}
+
+pub(super) fn sys_setup_team_clocks(
+    mut commands: Commands,
+    nodes: Query<(AsDeref<Teams>, &TurnTimeLimit), With<Node>>,
+    teams: Query<(), (With<Team>, Without<TeamClock>)>,
+) {
+    for (node_teams, time_limit) in nodes.iter() {
+        for team in node_teams.iter() {
+            if teams.contains(*team) {
+                commands.entity(*team).insert(TeamClock {
+                    turn_remaining: time_limit.per_turn,
+                    total_remaining: time_limit.total,
+                });
+            }
+        }
+    }
+}
+
+/// Resets the turn time of the team whose turn just started
+pub(super) fn sys_reset_turn_clocks(
+    nodes: Query<(AsDerefCopied<CurrentTurn>, &TurnTimeLimit), Changed<CurrentTurn>>,
+    mut team_clocks: Query<&mut TeamClock, With<Team>>,
+) {
+    for (current_turn, time_limit) in nodes.iter() {
+        if let Ok(mut team_clock) = team_clocks.get_mut(current_turn) {
+            team_clock.turn_remaining = time_limit.per_turn;
+        }
+    }
+}
+
+/// Counts down the clock of the team whose turn it is. A team that is out of
+/// time has its turn ended for it once per turn, having first lost the battle
+/// if the node's [`TimeoutRule`] says so.
+pub(super) fn sys_tick_team_clocks(
+    time: Res<Time>,
+    mut res_core_ops: ResMut<CoreOps>,
+    players: Query<(Entity, AsDerefCopied<OnTeam>, AsDerefCopied<InNode>), With<Player>>,
+    mut nodes: Query<
+        (
+            Entity,
+            &Teams,
+            &TurnTimeLimit,
+            Ref<CurrentTurn>,
+            &mut TeamStatus,
+        ),
+        With<Node>,
+    >,
+    mut teams: Query<(&TeamPhase, &mut TeamClock), With<Team>>,
+) {
+    for (node_id, node_teams, time_limit, current_turn, mut team_status) in nodes.iter_mut() {
+        let in_setup = node_teams.iter().any(|team| {
+            teams
+                .get(*team)
+                .map(|(phase, _)| *phase == TeamPhase::Setup)
+                .unwrap_or(false)
+        });
+        let team = **current_turn;
+        if in_setup || team_status.is_decided(team) {
+            continue;
+        }
+        let Ok((_, mut team_clock)) = teams.get_mut(team) else {
+            continue;
+        };
+        let was_out_of_time = team_clock.is_out_of_time();
+        let delta = time.delta();
+        if let Some(turn_remaining) = team_clock.turn_remaining.as_mut() {
+            *turn_remaining = turn_remaining.saturating_sub(delta);
+        }
+        if let Some(total_remaining) = team_clock.total_remaining.as_mut() {
+            *total_remaining = total_remaining.saturating_sub(delta);
+        }
+        // A team that was already out of time only needs its turn ended again
+        // once a new turn of theirs has started
+        if !team_clock.is_out_of_time() || (was_out_of_time && !current_turn.is_changed()) {
+            continue;
+        }
+        if time_limit.on_timeout == TimeoutRule::Loss {
+            team_status.insert(team, VictoryStatus::Loss);
+        }
+        let player = players.iter().find_map(|(player, player_team, in_node)| {
+            (player_team == team && in_node == node_id).then_some(player)
+        });
+        if let Some(player) = player {
+            res_core_ops.request(player, NodeOp::EndTurn);
+        } else {
+            log::error!("No player on team {team:?} to end their turn when time ran out");
+        }
+    }
+}
+
+pub(super) fn sys_apply_victory_rules(
+    mut evr_node_op: EventReader<OpResult<NodeOp>>,
+    players: Query<(AsDerefCopied<OnTeam>, AsDerefCopied<InNode>), With<Player>>,
+    mut nodes: Query<
+        (
+            Entity,
+            &EntityGrid,
+            &Teams,
+            &mut TeamStatus,
+            Option<&VictoryRules>,
+        ),
+        With<Node>,
+    >,
+    teams: Query<(&TeamPhase, Option<AsDerefCopied<TurnsEnded>>), With<Team>>,
+    team_alliances: Query<&Alliances, With<Team>>,
+    curios: Query<(Entity, AsDerefCopied<OnTeam>, AsDerefCopied<Parent>), With<Curio>>,
+    pickups: Query<(&Pickup, &Claimed)>,
+) {
+    let mut changed_nodes: HashSet<Entity> = evr_node_op
+        .read()
+        .filter(|op_result| op_result.result.is_ok())
+        .filter_map(|op_result| Some(players.get(op_result.source).ok()?.1))
+        .collect();
+    // Teams can also lose outside of ops, such as by running out of time
+    changed_nodes.extend(
+        nodes
+            .iter_mut()
+            .filter(|(_, _, _, team_status, _)| team_status.is_changed())
+            .map(|(node_id, ..)| node_id),
+    );
+    for node_id in changed_nodes {
+        let Ok((_, grid, node_teams, mut team_status, rules)) = nodes.get_mut(node_id) else {
+            continue;
+        };
+        let in_setup = node_teams.iter().any(|team| {
+            teams
+                .get(*team)
+                .map(|(phase, _)| *phase == TeamPhase::Setup)
+                .unwrap_or(false)
+        });
+        if in_setup {
+            continue;
+        }
+        let is_allied = |team: Entity, other_team: Entity| {
+            team == other_team
+                || team_alliances
+                    .get(team)
+                    .is_ok_and(|alliances| alliances.is_allied_with(other_team))
+        };
+        let curio_in_grid = |team: Entity| {
+            curios
+                .iter()
+                .any(|(id, curio_team, _)| curio_team == team && grid.contains_key(id))
+        };
+        let rules = rules
+            .map(|rules| rules.as_slice())
+            .filter(|rules| !rules.is_empty())
+            .unwrap_or(&[VictoryRule::Elimination]);
+        let mut winner = None;
+        for rule in rules {
+            let rule_winner = match rule {
+                VictoryRule::Elimination => {
+                    for team in node_teams.iter() {
+                        if is_undecided(&team_status, *team) && !curio_in_grid(*team) {
+                            team_status.insert(*team, VictoryStatus::Loss);
+                        }
+                    }
+                    let remaining_teams: Vec<Entity> = node_teams
+                        .iter()
+                        .filter(|team| is_undecided(&team_status, **team))
+                        .copied()
+                        .collect();
+                    remaining_teams.first().copied().filter(|first_team| {
+                        remaining_teams
+                            .iter()
+                            .all(|team| is_allied(*first_team, *team))
+                    })
+                },
+                VictoryRule::CollectMacGuffin => pickups.iter().find_map(|(pickup, claimed)| {
+                    if !matches!(pickup, Pickup::MacGuffin) || claimed.node_id() != node_id {
+                        return None;
+                    }
+                    Some(players.get(claimed.player()).ok()?.0)
+                }),
+                VictoryRule::SurviveTurns { team, turns } => teams
+                    .get(*team)
+                    .ok()
+                    .and_then(|(_, turns_ended)| turns_ended)
+                    .filter(|turns_ended| turns_ended >= turns)
+                    .map(|_| *team),
+                VictoryRule::DestroyPiece { team, piece } => {
+                    (!grid.contains_key(*piece)).then_some(*team)
+                },
+                VictoryRule::ReachSquare { team, pt } => grid
+                    .item_at(*pt)
+                    .and_then(|id| curios.get(id).ok())
+                    .filter(|(_, curio_team, _)| curio_team == team)
+                    .map(|_| *team),
+            };
+            winner = winner.or(rule_winner);
+        }
+        let Some(winner) = winner.filter(|winner| is_undecided(&team_status, *winner)) else {
+            continue;
+        };
+        let winners: Vec<Entity> = node_teams
+            .iter()
+            .filter(|team| is_allied(winner, **team))
+            .filter(|team| is_undecided(&team_status, **team))
+            .copied()
+            .collect();
+        for winning_team in winners {
+            let is_victory_flawed = curios.iter().any(|(id, team, in_node)| {
+                in_node == node_id && team == winning_team && !grid.contains_key(id)
+            });
+            let victory_status = if is_victory_flawed {
+                VictoryStatus::Victory
+            } else {
+                VictoryStatus::PerfectVictory
+            };
+            team_status.insert(winning_team, victory_status);
+        }
+        for team in node_teams.iter() {
+            if is_undecided(&team_status, *team) {
+                team_status.insert(*team, VictoryStatus::Loss);
+            }
+        }
+    }
+}
+
+fn is_undecided(team_status: &TeamStatus, team: Entity) -> bool {
+    team_status
+        .get(&team)
+        .map(VictoryStatus::is_undecided)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::hierarchy::BuildWorldChildren;
+
+    use super::*;
+    use crate::card::{Action, ActionEffect, ActionRange, ActionTarget};
+    use crate::node::node_testing::TestBattle;
+    use crate::node::{ActiveCurio, MovesTaken};
+
+    fn hit_action() -> Action {
+        Action {
+            id: "hit".to_owned(),
+            range: Some(ActionRange::new(1)),
+            effects: vec![ActionEffect::Damage(2)],
+            target: ActionTarget::Curios,
+            ..default()
+        }
+    }
+
+    fn hit(battle: &mut TestBattle, player: usize, curio: Entity, target: UVec2) {
+        let results = battle.perform(
+            player,
+            NodeOp::PerformCurioAction {
+                action_id: "hit".to_owned().into(),
+                curio: Some(curio),
+                target,
+            },
+        );
+        assert!(results[0].result().is_ok(), "{results:?}");
+    }
+
+    fn end_turn(battle: &mut TestBattle, player: usize) {
+        let results = battle.perform(player, NodeOp::EndTurn);
+        assert!(results[0].result().is_ok(), "{results:?}");
+    }
+
+    fn set_rules(battle: &mut TestBattle, rules: Vec<VictoryRule>) {
+        let node = battle.node;
+        battle
+            .app
+            .world_mut()
+            .entity_mut(node)
+            .insert(VictoryRules(rules));
+    }
+
+    fn status(battle: &TestBattle, team: usize) -> VictoryStatus {
+        battle.get::<TeamStatus>(battle.node)[&battle.teams[team]]
+    }
+
+    #[test]
+    fn test_elimination_by_default() {
+        let mut battle = TestBattle::new(4, 4).with_rules();
+        let hit_action = battle.add_action(hit_action());
+        let hitter = battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[hit_action]);
+        battle.spawn_curio(1, &[UVec2::new(1, 0)], 0, &[]);
+
+        hit(&mut battle, 0, hitter, UVec2::new(1, 0));
+        assert!(matches!(status(&battle, 0), VictoryStatus::PerfectVictory));
+        assert!(matches!(status(&battle, 1), VictoryStatus::Loss));
+    }
+
+    #[test]
+    fn test_victory_is_flawed_by_lost_curios() {
+        let mut battle = TestBattle::new(4, 4).with_rules();
+        let hit_action = battle.add_action(hit_action());
+        let hitter =
+            battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, std::slice::from_ref(&hit_action));
+        battle.spawn_curio(0, &[UVec2::new(2, 0)], 0, &[]);
+        let enemy = battle.spawn_curio(1, &[UVec2::new(1, 0)], 0, &[hit_action]);
+
+        end_turn(&mut battle, 0);
+        hit(&mut battle, 1, enemy, UVec2::new(2, 0));
+        assert!(status(&battle, 0).is_undecided());
+        end_turn(&mut battle, 1);
+        hit(&mut battle, 0, hitter, UVec2::new(1, 0));
+        assert!(matches!(status(&battle, 0), VictoryStatus::Victory));
+        assert!(matches!(status(&battle, 1), VictoryStatus::Loss));
+    }
+
+    #[test]
+    fn test_survive_turns() {
+        let mut battle = TestBattle::new(4, 4).with_rules();
+        battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[]);
+        battle.spawn_curio(1, &[UVec2::new(3, 3)], 0, &[]);
+        let survivor = battle.teams[1];
+        set_rules(
+            &mut battle,
+            vec![VictoryRule::SurviveTurns {
+                team: survivor,
+                turns: 2,
+            }],
+        );
+
+        for _ in 0..2 {
+            assert!(status(&battle, 1).is_undecided());
+            end_turn(&mut battle, 0);
+            end_turn(&mut battle, 1);
+        }
+        assert!(status(&battle, 1).is_victorious());
+        assert!(matches!(status(&battle, 0), VictoryStatus::Loss));
+    }
+
+    #[test]
+    fn test_destroy_piece_wins_before_elimination() {
+        let mut battle = TestBattle::new(4, 4).with_rules();
+        let hit_action = battle.add_action(hit_action());
+        let hitter = battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[hit_action]);
+        let target = battle.spawn_curio(1, &[UVec2::new(1, 0)], 0, &[]);
+        battle.spawn_curio(1, &[UVec2::new(3, 3)], 0, &[]);
+        let attacker = battle.teams[0];
+        set_rules(
+            &mut battle,
+            vec![VictoryRule::DestroyPiece {
+                team: attacker,
+                piece: target,
+            }],
+        );
+
+        hit(&mut battle, 0, hitter, UVec2::new(1, 0));
+        assert!(status(&battle, 0).is_victorious());
+        assert!(matches!(status(&battle, 1), VictoryStatus::Loss));
+    }
+
+    #[test]
+    fn test_reach_square() {
+        let mut battle = TestBattle::new(4, 4).with_rules();
+        let runner = battle.spawn_curio(0, &[UVec2::new(0, 0)], 2, &[]);
+        battle.spawn_curio(1, &[UVec2::new(3, 3)], 0, &[]);
+        let team = battle.teams[0];
+        set_rules(
+            &mut battle,
+            vec![VictoryRule::ReachSquare {
+                team,
+                pt: UVec2::new(2, 0),
+            }],
+        );
+
+        battle.perform(0, NodeOp::ActivateCurio { curio_id: runner });
+        for _ in 0..2 {
+            assert!(status(&battle, 0).is_undecided());
+            let results = battle.perform(0, NodeOp::MoveActiveCurio { dir: Compass::East });
+            assert!(results[0].result().is_ok(), "{results:?}");
+        }
+        assert!(status(&battle, 0).is_victorious());
+    }
+
+    #[test]
+    fn test_collect_macguffin() {
+        let mut battle = TestBattle::new(4, 4).with_rules();
+        let runner = battle.spawn_curio(0, &[UVec2::new(0, 0)], 1, &[]);
+        battle.spawn_curio(1, &[UVec2::new(3, 3)], 0, &[]);
+        set_rules(&mut battle, vec![VictoryRule::CollectMacGuffin]);
+        let node = battle.node;
+        let world = battle.app.world_mut();
+        let macguffin = world.spawn(Pickup::MacGuffin).set_parent(node).id();
+        world
+            .get_mut::<EntityGrid>(node)
+            .unwrap()
+            .put_item(UVec2::new(1, 0), macguffin);
+
+        battle.perform(0, NodeOp::ActivateCurio { curio_id: runner });
+        assert!(status(&battle, 0).is_undecided());
+        // Claimed is inserted with commands, which are applied before
+        // PostProcessCommands, so the rules see it in the same update
+        let results = battle.perform(0, NodeOp::MoveActiveCurio { dir: Compass::East });
+        assert!(results[0].result().is_ok(), "{results:?}");
+        assert!(battle.app.world().get::<Claimed>(macguffin).is_some());
+        assert!(status(&battle, 0).is_victorious());
+        assert!(matches!(status(&battle, 1), VictoryStatus::Loss));
+    }
+
+    #[test]
+    fn test_allies_win_together() {
+        let mut battle = TestBattle::with_team_count(3, 4, 4).with_rules();
+        battle.ally(0, 2);
+        let hit_action = battle.add_action(hit_action());
+        let hitter = battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[hit_action]);
+        battle.spawn_curio(1, &[UVec2::new(1, 0)], 0, &[]);
+        battle.spawn_curio(2, &[UVec2::new(3, 3)], 0, &[]);
+
+        hit(&mut battle, 0, hitter, UVec2::new(1, 0));
+        assert!(status(&battle, 0).is_victorious());
+        assert!(matches!(status(&battle, 1), VictoryStatus::Loss));
+        assert!(status(&battle, 2).is_victorious());
+    }
+
+    #[test]
+    fn test_free_for_all_continues_past_eliminated_team() {
+        let mut battle = TestBattle::with_team_count(3, 4, 4).with_rules();
+        let hit_action = battle.add_action(hit_action());
+        let hitter = battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[hit_action]);
+        battle.spawn_curio(1, &[UVec2::new(1, 0)], 0, &[]);
+        battle.spawn_curio(2, &[UVec2::new(3, 3)], 0, &[]);
+
+        hit(&mut battle, 0, hitter, UVec2::new(1, 0));
+        assert!(matches!(status(&battle, 1), VictoryStatus::Loss));
+        assert!(status(&battle, 0).is_undecided());
+        assert!(status(&battle, 2).is_undecided());
+
+        end_turn(&mut battle, 0);
+        assert_eq!(battle.current_turn(), battle.teams[2]);
+        end_turn(&mut battle, 2);
+        assert_eq!(battle.current_turn(), battle.teams[0]);
+    }
+
+    fn set_time_limit(battle: &mut TestBattle, time_limit: TurnTimeLimit) {
+        let node = battle.node;
+        battle.app.world_mut().entity_mut(node).insert(time_limit);
+        // Gives each team its clock
+        battle.update();
+    }
+
+    fn ended_turns(results: &[OpResult<NodeOp>]) -> usize {
+        results
+            .iter()
+            .filter(|result| matches!(result.op, NodeOp::EndTurn) && result.result().is_ok())
+            .count()
+    }
+
+    #[test]
+    fn test_out_of_time_team_has_each_turn_ended_once() {
+        let mut battle = TestBattle::new(4, 4).with_rules();
+        battle.spawn_curio(0, &[UVec2::new(0, 0)], 0, &[]);
+        battle.spawn_curio(1, &[UVec2::new(3, 3)], 0, &[]);
+        set_time_limit(
+            &mut battle,
+            TurnTimeLimit {
+                per_turn: None,
+                total: Some(Duration::from_secs(1)),
+                on_timeout: TimeoutRule::EndTurn,
+            },
+        );
+
+        let results = battle.advance_time(Duration::from_secs(1));
+        assert_eq!(ended_turns(&results), 1, "{results:?}");
+        assert_eq!(battle.current_turn(), battle.teams[1]);
+
+        for _ in 0..2 {
+            end_turn(&mut battle, 1);
+            assert_eq!(battle.current_turn(), battle.teams[0]);
+            let results = battle.advance_time(Duration::from_secs(1));
+            assert_eq!(ended_turns(&results), 1, "{results:?}");
+            assert_eq!(battle.current_turn(), battle.teams[1]);
+            assert_eq!(ended_turns(&battle.update()), 0);
+        }
+        assert!(status(&battle, 0).is_undecided());
+    }
+
+    #[test]
+    fn test_timeout_loss_ends_the_turn() {
+        let mut battle = TestBattle::with_team_count(3, 4, 4).with_rules();
+        let curio = battle.spawn_curio(0, &[UVec2::new(0, 0)], 2, &[]);
+        battle.spawn_curio(1, &[UVec2::new(3, 3)], 0, &[]);
+        battle.spawn_curio(2, &[UVec2::new(3, 0)], 0, &[]);
+        set_time_limit(
+            &mut battle,
+            TurnTimeLimit {
+                per_turn: Some(Duration::from_secs(1)),
+                total: None,
+                on_timeout: TimeoutRule::Loss,
+            },
+        );
+        battle.perform(0, NodeOp::ActivateCurio { curio_id: curio });
+        let results = battle.perform(0, NodeOp::MoveActiveCurio { dir: Compass::East });
+        assert!(results[0].result().is_ok(), "{results:?}");
+
+        let results = battle.advance_time(Duration::from_secs(1));
+        assert_eq!(ended_turns(&results), 1, "{results:?}");
+        assert!(matches!(status(&battle, 0), VictoryStatus::Loss));
+        assert!(status(&battle, 1).is_undecided());
+        assert_eq!(battle.current_turn(), battle.teams[1]);
+        assert_eq!(**battle.get::<MovesTaken>(curio), 0);
+        assert!(battle.get::<ActiveCurio>(battle.node).is_none());
+        assert_eq!(**battle.get::<TurnsEnded>(battle.teams[0]), 1);
+    }
+}
@@ -1,10 +1,15 @@
 mod executor;
+mod network;
 
 use std::marker::PhantomData;
 
+use bevy::ecs::entity::EntityMapper;
+use bevy::ecs::schedule::common_conditions::resource_exists;
 use bevy::ecs::system::{ExclusiveSystemParamFunction, StaticSystemParam, SystemId};
-use bevy::reflect::TypePath;
+use bevy::reflect::{GetTypeRegistration, TypePath};
 pub use executor::{OpExecutor, OpExecutorPlugin, OpExecutorResource};
+pub use network::{OpClient, OpServer, OpServerPlugin, ReflectOp};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::prelude::*;
@@ -12,7 +17,7 @@ use crate::prelude::*;
 // TODO refactor most of this into a module "op_sys" which will be more generic,
 // then the more game-specific stuff defined somewhere else
 #[derive(Debug, Default, Deref, DerefMut, Resource)]
-pub struct CoreOps(OpExecutor);
+pub struct CoreOps(pub OpExecutor);
 
 pub trait OpErrorUtils {
     type Error;
@@ -47,17 +52,23 @@ impl<T, E: std::error::Error + Send + Sync + 'static> OpErrorUtils for Result<T,
 pub type OpImplResult = Result<Metadata, OpError>;
 
 #[derive(Debug)]
-pub struct OpPlugin<T: Op + TypePath + FromReflect>(PhantomData<T>);
+pub struct OpPlugin<T: Op + TypePath + FromReflect + GetTypeRegistration>(PhantomData<T>);
 
-impl<T: Op + TypePath + FromReflect> Default for OpPlugin<T> {
+impl<T: Op + TypePath + FromReflect + GetTypeRegistration> Default for OpPlugin<T> {
     fn default() -> Self {
         OpPlugin(default())
     }
 }
 
-impl<T: Op + TypePath + FromReflect> Plugin for OpPlugin<T> {
+impl<T: Op + TypePath + FromReflect + GetTypeRegistration> Plugin for OpPlugin<T> {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, sys_register_op::<T>)
+        app.register_type::<T>()
+            .register_type_data::<T, ReflectOp>()
+            .add_systems(Startup, sys_register_op::<T>)
+            .add_systems(
+                Last,
+                network::sys_broadcast_op_results::<T>.run_if(resource_exists::<OpServer>),
+            )
             .add_event::<OpResult<T>>();
     }
 }
@@ -189,9 +200,56 @@ pub trait Op: std::fmt::Debug + Sync + Send + Reflect + 'static {
             op: Box::new(self),
         }
     }
+
+    /// Maps the entities this op refers to, so that it can be sent to a world
+    /// where they have different ids. Ops that refer to entities must
+    /// implement this to be sent over the network.
+    fn map_op_entities(&mut self, _entity_mapper: &mut OpEntityMapper) {}
+
+    /// Whether a field in this op's result metadata holds entities, to be
+    /// mapped along with the op
+    fn is_entity_field(_field: &str) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+}
+
+/// Maps the entities in an op, noting whether any entity had no mapping
+pub struct OpEntityMapper<'a> {
+    map: &'a mut dyn FnMut(Entity) -> Option<Entity>,
+    failed: bool,
+}
+
+impl<'a> OpEntityMapper<'a> {
+    pub fn new(map: &'a mut dyn FnMut(Entity) -> Option<Entity>) -> Self {
+        OpEntityMapper { map, failed: false }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+impl std::fmt::Debug for OpEntityMapper<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpEntityMapper")
+            .field("failed", &self.failed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EntityMapper for OpEntityMapper<'_> {
+    fn map_entity(&mut self, entity: Entity) -> Entity {
+        (self.map)(entity).unwrap_or_else(|| {
+            self.failed = true;
+            Entity::PLACEHOLDER
+        })
+    }
 }
 
-#[derive(Clone, Debug, Error)]
+#[derive(Clone, Debug, Deserialize, Error, Serialize)]
 pub enum OpError {
     /// This error usually means a user or system was trying to perform an OP but it is unsuccessful
     #[error("Invalid op: {0}")]
@@ -234,6 +292,8 @@ pub struct OpResult<O> {
 
 #[cfg(test)]
 mod test {
+    use bevy::ecs::event::Events;
+
     use super::*;
 
     #[derive(Debug, Default, Deref, DerefMut, Resource)]
@@ -342,4 +402,65 @@ mod test {
         });
         app.update();
     }
+
+    #[test]
+    fn test_network_op_system() {
+        let mut server = App::new();
+        server.add_plugins((
+            OpExecutorPlugin::<ExampleExecutor>::default(),
+            OpServerPlugin::<ExampleExecutor>::new("127.0.0.1:0").allow_op::<ExampleOp>(),
+            OpPlugin::<ExampleOp>::default(),
+        ));
+        let server_player = server.world_mut().spawn_empty().id();
+        server
+            .world_mut()
+            .resource_mut::<OpServer>()
+            .host_player(server_player);
+        let address = server.world().resource::<OpServer>().local_addr();
+
+        let mut client = App::new();
+        client
+            .insert_resource(ExampleExecutor(OpExecutor::Network(
+                OpClient::connect(address).expect("should be able to connect to local server"),
+            )))
+            .add_plugins((
+                OpExecutorPlugin::<ExampleExecutor>::default(),
+                OpPlugin::<ExampleOp>::default(),
+            ))
+            .add_systems(Startup, |mut ops: ResMut<ExampleExecutor>| {
+                ops.request(Entity::PLACEHOLDER, ExampleOp::ExampleOne);
+            });
+
+        let mut results = Vec::new();
+        let mut server_results = Vec::new();
+        for _ in 0..200 {
+            client.update();
+            server.update();
+            server_results.extend(
+                server
+                    .world_mut()
+                    .resource_mut::<Events<OpResult<ExampleOp>>>()
+                    .drain(),
+            );
+            results.extend(
+                client
+                    .world_mut()
+                    .resource_mut::<Events<OpResult<ExampleOp>>>()
+                    .drain(),
+            );
+            if !results.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(1, results.len(), "Client should receive one op result");
+        assert_eq!(ExampleOp::ExampleOne, results[0].op);
+        assert_eq!(Entity::PLACEHOLDER, results[0].source());
+        assert!(results[0].result().is_ok());
+        assert_eq!(
+            server_player,
+            server_results[0].source(),
+            "Server should perform the op as the player bound to the client"
+        );
+    }
 }
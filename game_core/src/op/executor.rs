@@ -2,6 +2,7 @@ use std::marker::PhantomData;
 
 use bevy::ecs::schedule::ScheduleLabel;
 
+use super::network::{self, OpClient};
 use super::{Op, OpRegistry, OpRequest};
 use crate::prelude::*;
 
@@ -71,7 +72,8 @@ where
 #[derive(Debug)]
 pub enum OpExecutor {
     Local(Vec<OpRequest>),
-    Network,
+    /// Sends ops to an `OpServer` to be performed, emitting the results it broadcasts back
+    Network(OpClient),
 }
 
 impl Default for OpExecutor {
@@ -88,7 +90,7 @@ impl OpExecutor {
     pub fn accept_request(&mut self, op_request: OpRequest) {
         match self {
             Self::Local(ref mut queue) => queue.push(op_request),
-            Self::Network => todo!("TODO network support"),
+            Self::Network(ref mut client) => client.queue().push(op_request),
         }
     }
     pub fn accept_requests<E: Iterator<Item = OpRequest>>(&mut self, events: E) {
@@ -96,7 +98,9 @@ impl OpExecutor {
             Self::Local(ref mut queue) => {
                 queue.extend(events);
             },
-            Self::Network => todo!("TODO Network support"),
+            Self::Network(ref mut client) => {
+                client.queue().extend(events);
+            },
         }
     }
 
@@ -107,7 +111,7 @@ impl OpExecutor {
                 // In the future, might only implement X ops per frame
                 std::mem::replace(queue, new_queue)
             },
-            Self::Network => todo!("TODO Network support"),
+            Self::Network(ref mut client) => std::mem::take(client.queue()),
         }
     }
 }
@@ -121,6 +125,11 @@ pub fn sys_perform_ops<E: Resource + std::ops::DerefMut + std::ops::Deref<Target
         .expect("Can't find executor")
         .take_ops();
 
+    if matches!(**world.resource::<E>(), OpExecutor::Network(_)) {
+        network::exchange_ops::<E>(world, ops);
+        return;
+    }
+
     let ops = world.get_resource::<OpRegistry>().map(|op_registry| {
         ops.into_iter()
             .map(|op| {
@@ -0,0 +1,675 @@
+use std::io::{BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use bevy::ecs::entity::EntityHashMap;
+use bevy::reflect::serde::{ReflectDeserializer, ReflectSerializer};
+use bevy::reflect::{FromType, TypeRegistry};
+use serde::de::DeserializeSeed;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    Op, OpEntityMapper, OpError, OpErrorUtils, OpExecutor, OpExecutorResource, OpRequest, OpResult,
+};
+use crate::prelude::*;
+
+/// Messages sent over an op connection, one JSON object per line.
+///
+/// Ops are serialized with the reflect serializer, so both ends need the
+/// op type registered (`OpPlugin` takes care of this).
+#[derive(Debug, Deserialize, Serialize)]
+enum OpMessage {
+    Request {
+        source: Entity,
+        op: serde_json::Value,
+    },
+    /// Sent to a client when its connection is bound to a player, before the
+    /// result of its first request
+    AssignedPlayer { source: Entity, player: Entity },
+    Result {
+        source: Entity,
+        op: serde_json::Value,
+        result: Result<Metadata, OpError>,
+    },
+}
+
+/// Type data registered for every op type by `OpPlugin`, used to turn
+/// deserialized reflect values back into concrete ops and results.
+#[derive(Clone, Debug)]
+pub struct ReflectOp {
+    into_op: fn(&dyn Reflect) -> Option<Box<dyn Op>>,
+    is_entity_field: fn(&str) -> bool,
+    send_result: fn(&mut World, Entity, Box<dyn Op>, Result<Metadata, OpError>) -> bool,
+}
+
+impl<O: Op + FromReflect> FromType<O> for ReflectOp {
+    fn from_type() -> Self {
+        ReflectOp {
+            into_op: |op| O::from_reflect(op).map(|op| Box::new(op) as Box<dyn Op>),
+            is_entity_field: O::is_entity_field,
+            send_result: |world, source, op, result| {
+                op.into_reflect()
+                    .downcast::<O>()
+                    .map(|op| {
+                        world.send_event(OpResult {
+                            source,
+                            op: *op,
+                            result,
+                        });
+                    })
+                    .is_ok()
+            },
+        }
+    }
+}
+
+/// The threads reading and writing one end of an op connection. Dropping
+/// this closes the connection and waits for both threads, so the sender
+/// feeding the writer must be dropped first.
+#[derive(Debug)]
+struct ConnectionThreads {
+    stream: TcpStream,
+    reader: Option<JoinHandle<()>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl ConnectionThreads {
+    fn spawn<T: Send + 'static>(
+        stream: TcpStream,
+        incoming: Sender<T>,
+        wrap: impl Fn(OpMessage) -> T + Send + 'static,
+        outgoing: Receiver<String>,
+    ) -> std::io::Result<Self> {
+        let reader = spawn_reader(stream.try_clone()?, incoming, wrap);
+        let writer = spawn_writer(stream.try_clone()?, outgoing);
+        Ok(ConnectionThreads {
+            stream,
+            reader: Some(reader),
+            writer: Some(writer),
+        })
+    }
+}
+
+impl Drop for ConnectionThreads {
+    fn drop(&mut self) {
+        // Unblocks the reader. The connection might already be closed.
+        let _ = self.stream.shutdown(Shutdown::Both);
+        for thread in [self.reader.take(), self.writer.take()]
+            .into_iter()
+            .flatten()
+        {
+            if thread.join().is_err() {
+                log::error!("Op connection thread panicked");
+            }
+        }
+    }
+}
+
+/// Client side of an op connection, held by `OpExecutor::Network`.
+///
+/// Requests are queued like in a local executor, but `sys_perform_ops`
+/// sends them to the server instead of running them, and emits the
+/// `OpResult` events the server broadcasts back.
+///
+/// Entities have different ids on the server, so the client keeps a map
+/// between the two. Entities in requests are mapped to the server's ids, and
+/// requests that refer to entities the server hasn't been paired with are
+/// rejected. Entities in results are mapped back, with a new local entity
+/// spawned for each server entity the client hasn't seen before.
+#[derive(Debug)]
+pub struct OpClient {
+    queue: Vec<OpRequest>,
+    /// Server ids of local entities
+    server_entities: EntityHashMap<Entity>,
+    /// Local ids of server entities
+    local_entities: EntityHashMap<Entity>,
+    outgoing: Sender<String>,
+    incoming: Mutex<Receiver<OpMessage>>,
+    // Declared after `outgoing` so that it is dropped after it
+    _threads: ConnectionThreads,
+}
+
+impl OpClient {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        let (incoming_sender, incoming) = channel();
+        let (outgoing, outgoing_receiver) = channel();
+        let threads = ConnectionThreads::spawn(
+            stream,
+            incoming_sender,
+            |message| message,
+            outgoing_receiver,
+        )?;
+        Ok(OpClient {
+            queue: Vec::new(),
+            server_entities: default(),
+            local_entities: default(),
+            outgoing,
+            incoming: Mutex::new(incoming),
+            _threads: threads,
+        })
+    }
+
+    /// Pairs a local entity with the server's id for the same entity, such as
+    /// for entities loaded from the same scene on both ends
+    pub fn map_entity(&mut self, local: Entity, server: Entity) {
+        self.server_entities.insert(local, server);
+        self.local_entities.insert(server, local);
+    }
+
+    pub(super) fn queue(&mut self) -> &mut Vec<OpRequest> {
+        &mut self.queue
+    }
+
+    /// Returns the local entity for a server entity, spawning one if the
+    /// client hasn't seen it before
+    fn local_entity(&mut self, server: Entity, world: &mut World) -> Entity {
+        if let Some(local) = self.local_entities.get(&server) {
+            return *local;
+        }
+        let local = world.spawn_empty().id();
+        self.map_entity(local, server);
+        local
+    }
+
+    /// Sends a request to the server, giving it back if it refers to
+    /// entities the server's ids aren't known for
+    fn send_request(&self, request: OpRequest, registry: &TypeRegistry) -> Result<(), OpRequest> {
+        let OpRequest { source, mut op } = request;
+        // Checked before mapping so that a rejected op is left unchanged
+        let mut is_mapped = |entity| self.server_entities.get(&entity).map(|_| entity);
+        let mut checker = OpEntityMapper::new(&mut is_mapped);
+        op.map_op_entities(&mut checker);
+        if checker.failed() {
+            return Err(OpRequest { source, op });
+        }
+        let mut to_server = |entity| self.server_entities.get(&entity).copied();
+        op.map_op_entities(&mut OpEntityMapper::new(&mut to_server));
+        let message = serde_json::to_value(ReflectSerializer::new(op.as_reflect(), registry))
+            .and_then(|op| serde_json::to_string(&OpMessage::Request { source, op }));
+        match message {
+            Ok(message) => {
+                if self.outgoing.send(message).is_err() {
+                    log::error!("Op connection closed, dropping op {op:?}");
+                }
+            },
+            Err(e) => log::error!("Unable to serialize op {op:?}: {e}"),
+        }
+        Ok(())
+    }
+
+    /// Emits a result the server sent, with its entities mapped to local ones
+    fn receive_result(
+        &mut self,
+        world: &mut World,
+        registry: &TypeRegistry,
+        source: Entity,
+        op: serde_json::Value,
+        result: Result<Metadata, OpError>,
+    ) -> Result<(), String> {
+        let (op, reflect_op) = deserialize_op(op, registry)?;
+        let mut op =
+            (reflect_op.into_op)(&*op).ok_or_else(|| format!("Unable to convert op: {op:?}"))?;
+        let source = self.local_entity(source, world);
+        let mut to_local = |entity| Some(self.local_entity(entity, world));
+        op.map_op_entities(&mut OpEntityMapper::new(&mut to_local));
+        let result = match result {
+            Ok(metadata) => Ok(map_metadata_entities(
+                metadata,
+                reflect_op.is_entity_field,
+                &mut |entity| self.local_entity(entity, world),
+            )
+            .map_err(|e| format!("Unable to map op result metadata: {e}"))?),
+            Err(e) => Err(e),
+        };
+        if !(reflect_op.send_result)(world, source, op, result) {
+            Err("Op result type does not match its op".to_owned())?;
+        }
+        Ok(())
+    }
+
+    fn take_messages(&self) -> Vec<OpMessage> {
+        self.incoming
+            .lock()
+            .map(|incoming| incoming.try_iter().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A client connected to an `OpServer`
+#[derive(Debug)]
+struct OpConnection {
+    id: usize,
+    sender: Sender<String>,
+    /// Server-side player this client performs ops as, assigned on its first request
+    player: Option<Entity>,
+    // Declared after `sender` so that it is dropped after it
+    _threads: ConnectionThreads,
+}
+
+/// An authoritative op server. Requests from connected clients are fed into
+/// the executor chosen in `OpServerPlugin`, and the results of every op type
+/// with an `OpPlugin` are broadcast to all clients.
+///
+/// Clients never choose the source of their ops: each connection is bound to
+/// one of the players made available with `host_player`, and only op types
+/// allowed with `allow_op` are accepted from it.
+#[derive(Debug, Resource)]
+pub struct OpServer {
+    local_addr: SocketAddr,
+    connections: Arc<Mutex<Vec<OpConnection>>>,
+    requests: Mutex<Receiver<(usize, OpMessage)>>,
+    hosted_players: Vec<Entity>,
+    remote_ops: HashSet<&'static str>,
+    shutdown: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl OpServer {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let connections: Arc<Mutex<Vec<OpConnection>>> = default();
+        let (request_sender, requests) = channel();
+        let shutdown: Arc<AtomicBool> = default();
+        let listener_connections = connections.clone();
+        let listener_shutdown = shutdown.clone();
+        let listener = std::thread::spawn(move || {
+            for (id, stream) in listener.incoming().enumerate() {
+                if listener_shutdown.load(Ordering::Acquire) {
+                    break;
+                }
+                let (sender, receiver) = channel();
+                let connection = stream.and_then(|stream| {
+                    stream.set_nodelay(true)?;
+                    log::info!("Op client connected: {:?}", stream.peer_addr());
+                    ConnectionThreads::spawn(
+                        stream,
+                        request_sender.clone(),
+                        move |message| (id, message),
+                        receiver,
+                    )
+                });
+                match connection {
+                    Ok(threads) => {
+                        if let Ok(mut connections) = listener_connections.lock() {
+                            connections.push(OpConnection {
+                                id,
+                                sender,
+                                player: None,
+                                _threads: threads,
+                            });
+                        }
+                    },
+                    Err(e) => log::error!("Error accepting op client: {e}"),
+                }
+            }
+        });
+        Ok(OpServer {
+            local_addr,
+            connections,
+            requests: Mutex::new(requests),
+            hosted_players: Vec::new(),
+            remote_ops: HashSet::default(),
+            shutdown,
+            listener: Some(listener),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Makes a player available to be controlled by a client. Players are
+    /// assigned to clients in the order both arrive.
+    pub fn host_player(&mut self, player: Entity) {
+        self.hosted_players.push(player);
+    }
+
+    /// Allows clients to request ops of this type
+    pub fn allow_op<O: Op + TypePath>(&mut self) {
+        self.remote_ops.insert(O::type_path());
+    }
+
+    /// Returns the player bound to a connection, binding the next hosted
+    /// player if it doesn't have one yet. The client is told which player it
+    /// was bound to, so that it can pair it with the source it sent.
+    fn player_for_connection(&mut self, id: usize, remote_source: Entity) -> Option<Entity> {
+        let mut connections = self.connections.lock().ok()?;
+        let connection = connections
+            .iter_mut()
+            .find(|connection| connection.id == id)?;
+        if connection.player.is_none() && !self.hosted_players.is_empty() {
+            let player = self.hosted_players.remove(0);
+            log::info!("Op client {id} assigned player {player:?}");
+            connection.player = Some(player);
+            let message = serde_json::to_string(&OpMessage::AssignedPlayer {
+                source: remote_source,
+                player,
+            });
+            match message {
+                Ok(message) => {
+                    if connection.sender.send(message).is_err() {
+                        log::error!("Op client {id} disconnected before being assigned a player");
+                    }
+                },
+                Err(e) => log::error!("Unable to serialize player assignment: {e}"),
+            }
+        }
+        connection.player
+    }
+
+    /// Sends an op result to every client. Its entities are the server's,
+    /// each client maps them to its own.
+    fn send_result(
+        &self,
+        source: Entity,
+        op: serde_json::Value,
+        result: Result<Metadata, OpError>,
+    ) {
+        let message = match serde_json::to_string(&OpMessage::Result { source, op, result }) {
+            Ok(message) => message,
+            Err(e) => {
+                log::error!("Unable to serialize op result: {e}");
+                return;
+            },
+        };
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.retain(|connection| connection.sender.send(message.clone()).is_ok());
+        }
+    }
+
+    fn take_requests(&self) -> Vec<(usize, OpMessage)> {
+        self.requests
+            .lock()
+            .map(|requests| requests.try_iter().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for OpServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Release);
+        // The listener only sees the shutdown once another client connects
+        let mut wake_addr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(if wake_addr.is_ipv4() {
+                Ipv4Addr::LOCALHOST.into()
+            } else {
+                Ipv6Addr::LOCALHOST.into()
+            });
+        }
+        if let Err(e) = TcpStream::connect(wake_addr) {
+            log::error!("Unable to wake op server listener, it will be left running: {e}");
+        } else if let Some(listener) = self.listener.take() {
+            if listener.join().is_err() {
+                log::error!("Op server listener panicked");
+            }
+        }
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.clear();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OpServerPlugin<E> {
+    address: String,
+    remote_ops: Vec<&'static str>,
+    phantom_data: PhantomData<E>,
+}
+
+impl<E> OpServerPlugin<E> {
+    pub fn new<S: Into<String>>(address: S) -> Self {
+        OpServerPlugin {
+            address: address.into(),
+            remote_ops: Vec::new(),
+            phantom_data: PhantomData,
+        }
+    }
+
+    /// Allows clients to request ops of this type. Requests for any other op
+    /// type are dropped.
+    pub fn allow_op<O: Op + TypePath>(mut self) -> Self {
+        self.remote_ops.push(O::type_path());
+        self
+    }
+}
+
+impl<E: OpExecutorResource> Plugin for OpServerPlugin<E> {
+    fn build(&self, app: &mut App) {
+        match OpServer::bind(self.address.as_str()) {
+            Ok(mut server) => {
+                log::info!("Op server listening on {}", server.local_addr());
+                server.remote_ops.extend(self.remote_ops.iter().copied());
+                app.insert_resource(server)
+                    .add_systems(PreUpdate, sys_accept_network_requests::<E>);
+            },
+            Err(e) => log::error!("Unable to start op server on [{}]: {e}", self.address),
+        }
+    }
+}
+
+fn spawn_reader<T: Send + 'static>(
+    stream: TcpStream,
+    sender: Sender<T>,
+    wrap: impl Fn(OpMessage) -> T + Send + 'static,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    log::info!("Op connection closed: {e}");
+                    break;
+                },
+            };
+            match serde_json::from_str(&line) {
+                Ok(message) => {
+                    if sender.send(wrap(message)).is_err() {
+                        break;
+                    }
+                },
+                Err(e) => log::error!("Unable to parse op message [{line}]: {e}"),
+            }
+        }
+    })
+}
+
+fn spawn_writer(mut stream: TcpStream, receiver: Receiver<String>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        for message in receiver.iter() {
+            if let Err(e) = writeln!(stream, "{message}").and_then(|_| stream.flush()) {
+                log::info!("Op connection closed: {e}");
+                break;
+            }
+        }
+    })
+}
+
+fn deserialize_op(
+    op: serde_json::Value,
+    registry: &TypeRegistry,
+) -> Result<(Box<dyn Reflect>, ReflectOp), String> {
+    let op = ReflectDeserializer::new(registry)
+        .deserialize(op)
+        .map_err(|e| e.to_string())?;
+    let type_path = op
+        .get_represented_type_info()
+        .ok_or("Op has no type info")?
+        .type_path();
+    let reflect_op = registry
+        .get_with_type_path(type_path)
+        .ok_or_else(|| format!("Op type [{type_path}] is not registered"))?
+        .data::<ReflectOp>()
+        .ok_or_else(|| format!("Type [{type_path}] is not registered as an op"))?
+        .clone();
+    Ok((op, reflect_op))
+}
+
+/// Called by `sys_perform_ops` for network executors
+pub(super) fn exchange_ops<E: OpExecutorResource>(world: &mut World, ops: Vec<OpRequest>) {
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+    world.resource_scope::<E, _>(|world, mut executor| {
+        let OpExecutor::Network(client) = &mut **executor else {
+            return;
+        };
+        for op in ops {
+            if let Err(OpRequest { source, op }) = client.send_request(op, &registry) {
+                log::warn!("Op refers to entities unknown to the server: {op:?}");
+                let reflect_op = op
+                    .get_represented_type_info()
+                    .and_then(|type_info| registry.get_type_data::<ReflectOp>(type_info.type_id()));
+                let result = Err("Op refers to entities unknown to the server".invalid());
+                if !reflect_op
+                    .is_some_and(|reflect_op| (reflect_op.send_result)(world, source, op, result))
+                {
+                    log::error!("Unable to send result for rejected op");
+                }
+            }
+        }
+        for message in client.take_messages() {
+            match message {
+                OpMessage::AssignedPlayer { source, player } => client.map_entity(source, player),
+                OpMessage::Result { source, op, result } => {
+                    if let Err(e) = client.receive_result(world, &registry, source, op, result) {
+                        log::error!("Unable to receive op result: {e}");
+                    }
+                },
+                OpMessage::Request { .. } => {
+                    log::warn!("Op client received unexpected message: {message:?}")
+                },
+            }
+        }
+    });
+}
+
+/// Maps the entities in a metadata's entity fields, including in metadata
+/// nested in its other fields
+fn map_metadata_entities(
+    metadata: Metadata,
+    is_entity_field: fn(&str) -> bool,
+    map: &mut impl FnMut(Entity) -> Entity,
+) -> serde_json::Result<Metadata> {
+    let mut metadata = serde_json::to_value(metadata)?;
+    map_json_fields(&mut metadata, is_entity_field, map);
+    serde_json::from_value(metadata)
+}
+
+fn map_json_fields(
+    value: &mut Value,
+    is_entity_field: fn(&str) -> bool,
+    map: &mut impl FnMut(Entity) -> Entity,
+) {
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields.iter_mut() {
+                if is_entity_field(field) {
+                    map_json_entities(value, map);
+                } else {
+                    map_json_fields(value, is_entity_field, map);
+                }
+            }
+        },
+        Value::Array(values) => {
+            for value in values.iter_mut() {
+                map_json_fields(value, is_entity_field, map);
+            }
+        },
+        _ => {},
+    }
+}
+
+/// Entities are serialized as numbers, or as strings when they are map keys
+fn map_json_entities(value: &mut Value, map: &mut impl FnMut(Entity) -> Entity) {
+    match value {
+        Value::Number(bits) => {
+            let entity = bits
+                .as_u64()
+                .and_then(|bits| Entity::try_from_bits(bits).ok());
+            if let Some(entity) = entity {
+                *value = map(entity).to_bits().into();
+            }
+        },
+        Value::Array(values) => {
+            for value in values.iter_mut() {
+                map_json_entities(value, map);
+            }
+        },
+        Value::Object(entries) => {
+            *entries = std::mem::take(entries)
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = key
+                        .parse()
+                        .ok()
+                        .and_then(|bits| Entity::try_from_bits(bits).ok())
+                        .map(|entity| map(entity).to_bits().to_string())
+                        .unwrap_or(key);
+                    (key, value)
+                })
+                .collect();
+        },
+        _ => {},
+    }
+}
+
+/// Queues requests from clients as their assigned player. The source sent
+/// over the wire is only used to tell the client which player it was
+/// assigned. Clients map the other entities in their requests to the
+/// server's before sending them.
+fn sys_accept_network_requests<E: OpExecutorResource>(
+    mut server: ResMut<OpServer>,
+    registry: Res<AppTypeRegistry>,
+    mut executor: ResMut<E>,
+) {
+    let registry = registry.read();
+    for (id, message) in server.take_requests() {
+        let OpMessage::Request { source, op } = message else {
+            log::warn!("Op server received unexpected message: {message:?}");
+            continue;
+        };
+        let op = deserialize_op(op, &registry).and_then(|(op, reflect_op)| {
+            let type_path = op
+                .get_represented_type_info()
+                .map(|type_info| type_info.type_path())
+                .unwrap_or_default();
+            if !server.remote_ops.contains(type_path) {
+                return Err(format!("Clients may not request [{type_path}] ops"));
+            }
+            (reflect_op.into_op)(&*op).ok_or_else(|| format!("Unable to convert op: {op:?}"))
+        });
+        let op = match op {
+            Ok(op) => op,
+            Err(e) => {
+                log::error!("Rejected op request from client {id}: {e}");
+                continue;
+            },
+        };
+        match server.player_for_connection(id, source) {
+            Some(player) => executor.accept_request(OpRequest { source: player, op }),
+            None => log::warn!("No player available for op client {id}, dropping op {op:?}"),
+        }
+    }
+}
+
+pub(super) fn sys_broadcast_op_results<O: Op + TypePath>(
+    server: Res<OpServer>,
+    registry: Res<AppTypeRegistry>,
+    mut evr_op_results: EventReader<OpResult<O>>,
+) {
+    let registry = registry.read();
+    for OpResult { source, op, result } in evr_op_results.read() {
+        match serde_json::to_value(ReflectSerializer::new(op, &registry)) {
+            Ok(op_value) => server.send_result(*source, op_value, result.clone()),
+            Err(e) => log::error!("Unable to serialize op result for {op:?}: {e}"),
+        }
+    }
+}
@@ -1,29 +1,58 @@
+use bevy_yarnspinner::events::NodeCompleteEvent;
+use getset::CopyGetters;
 use serde::{Deserialize, Serialize};
 
 use super::node::NodeId;
+use crate::common::SetId;
+use crate::item::{Item, ItemOp};
+use crate::op::{
+    CoreOps, Op, OpError, OpErrorUtils, OpImplResult, OpPlugin, OpRegistrar, OpResult,
+};
 use crate::player::{Ncp, Player};
 use crate::prelude::*;
+use crate::registry::{Reg, Registry};
 use crate::saving::{LoadData, LoadSchedule, SaveData, SaveSchedule};
+use crate::shop::{self, ShopOp};
+use crate::NDitCoreSet;
 
 #[derive(Debug)]
 pub struct QuestPlugin;
 
-mod key {
-    use typed_key::*;
+pub mod key {
+    use typed_key::{typed_key, Key};
 
-    pub const SAVE_QUEST_STATUS: Key<super::QuestStatus> = typed_key!("quest_status");
+    pub const QUEST_FINISHED: Key<bool> = typed_key!("quest_finished");
+    pub const QUEST_ID: Key<String> = typed_key!("quest_id");
+    pub const STAGE: Key<usize> = typed_key!("stage");
+    pub const UPDATED_QUESTS: Key<Vec<String>> = typed_key!("updated_quests");
+    pub mod save {
+        use super::*;
+        use crate::quest::{QuestLog, QuestStatus};
+
+        pub const QUEST_LOG: Key<QuestLog> = typed_key!("quest_log");
+        pub const QUEST_STATUS: Key<QuestStatus> = typed_key!("quest_status");
+    }
 }
 
 impl Plugin for QuestPlugin {
     fn build(&self, app: &mut App) {
-        app.register_type::<QuestStatus>()
-            .add_systems(SaveSchedule, sys_save_quest_status)
-            .add_systems(LoadSchedule, sys_load_quest_status);
+        app.register_type::<QuestLog>()
+            .register_type::<QuestStatus>()
+            .add_plugins((OpPlugin::<QuestOp>::default(), Reg::<Quests>::default()))
+            .add_systems(
+                Update,
+                (sys_track_quest_objectives, sys_advance_quests)
+                    .in_set(NDitCoreSet::PostProcessCommands),
+            )
+            .add_systems(SaveSchedule, (sys_save_quest_status, sys_save_quest_log))
+            .add_systems(LoadSchedule, (sys_load_quest_status, sys_load_quest_log));
     }
 }
 /// Indicates status of nodes and quests
 /// Indicates which nodes have been completed. Would love to fit this into a more comprehensive player
 /// metadata/save data/progress flag/score system later.
+///
+/// Progress on quests themselves is kept in the [`QuestLog`].
 #[derive(Component, Debug, Default, Deserialize, Eq, PartialEq, Reflect, Serialize)]
 #[reflect(Component)]
 #[serde(transparent)]
@@ -36,6 +65,11 @@ impl QuestStatus {
         *self.flags.entry(node_id.set().to_string()).or_default() |= node_id.num_flag();
     }
 
+    /// How many nodes have been completed in total
+    pub fn nodes_done(&self) -> u32 {
+        self.flags.values().map(|flags| flags.count_ones()).sum()
+    }
+
     pub fn is_node_done(&self, node_id: &NodeId) -> bool {
         self.flags
             .get(node_id.set())
@@ -44,14 +78,428 @@ impl QuestStatus {
     }
 }
 
+/// Quests players can take on, by quest id
+#[derive(Debug)]
+pub struct Quests;
+
+impl Registry for Quests {
+    const REGISTRY_NAME: &'static str = "core:quests";
+    type Value = QuestDefinition;
+
+    fn detect_change(old_value: &Self::Value, new_value: &Self::Value) -> bool {
+        old_value != new_value
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct QuestDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Have to be met before the quest can be started
+    #[serde(default)]
+    pub prerequisites: Vec<QuestPrerequisite>,
+    pub stages: Vec<QuestStage>,
+    /// Given when the last stage is finished, after that stage's rewards
+    #[serde(default)]
+    pub rewards: Vec<QuestReward>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct QuestStage {
+    #[serde(default)]
+    pub description: String,
+    /// All of these have to be met to move on to the next stage
+    pub objectives: Vec<QuestObjective>,
+    #[serde(default)]
+    pub rewards: Vec<QuestReward>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Reflect, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestObjective {
+    /// Win the node with this node id, such as `node:area1:0`. Nodes won
+    /// before the quest was started count too.
+    BeatNode(String),
+    /// Buy the card with this id from a shop
+    BuyCard(String),
+    /// Finish the dialog node with this title, such as by talking to
+    /// whoever it belongs to
+    TalkTo(String),
+}
+
+impl std::fmt::Display for QuestObjective {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BeatNode(node_id) => write!(f, "Beat {node_id}"),
+            Self::BuyCard(card_id) => write!(f, "Buy {card_id}"),
+            Self::TalkTo(dialog_node) => write!(f, "Talk to {dialog_node}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestPrerequisite {
+    /// Another quest has to be finished first, by quest id
+    Quest(String),
+    /// A node has to be won first, by node id
+    Node(String),
+}
+
+impl QuestPrerequisite {
+    pub fn is_met(&self, quest_status: &QuestStatus, quest_log: &QuestLog) -> bool {
+        match self {
+            Self::Quest(quest_id) => quest_log.is_finished(quest_id),
+            Self::Node(node_id) => is_node_done(quest_status, node_id),
+        }
+    }
+}
+
+impl std::fmt::Display for QuestPrerequisite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Quest(quest_id) => write!(f, "Finish quest {quest_id}"),
+            Self::Node(node_id) => write!(f, "Beat {node_id}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestReward {
+    Mon(u32),
+    /// Asset path of a card definition
+    Card(String),
+    /// An inventory item, by item id
+    Item(String),
+}
+
+impl QuestReward {
+    pub fn to_item(&self, asset_server: &AssetServer) -> Item {
+        match self {
+            Self::Mon(mon) => Item::Mon(*mon),
+            Self::Card(path) => Item::Card(asset_server.load(path)),
+            Self::Item(item_id) => Item::Inventory(item_id.clone()),
+        }
+    }
+}
+
+/// Player component with the quests they have started and finished
+#[derive(Clone, Component, Debug, Default, Deserialize, PartialEq, Reflect, Serialize)]
+#[reflect(Component)]
+pub struct QuestLog {
+    /// Quests in progress, by quest id
+    active: HashMap<String, QuestProgress>,
+    /// Ids of finished quests, in the order they were finished
+    finished: Vec<String>,
+}
+
+impl QuestLog {
+    pub fn is_active(&self, quest_id: &str) -> bool {
+        self.active.contains_key(quest_id)
+    }
+
+    pub fn is_finished(&self, quest_id: &str) -> bool {
+        self.finished
+            .iter()
+            .any(|finished_id| finished_id == quest_id)
+    }
+
+    pub fn progress(&self, quest_id: &str) -> Option<&QuestProgress> {
+        self.active.get(quest_id)
+    }
+
+    /// Quests in progress, ordered by quest id
+    pub fn active(&self) -> Vec<(&str, &QuestProgress)> {
+        let mut active: Vec<_> = self
+            .active
+            .iter()
+            .map(|(quest_id, progress)| (quest_id.as_str(), progress))
+            .collect();
+        active.sort_by_key(|(quest_id, _)| *quest_id);
+        active
+    }
+
+    pub fn finished(&self) -> &[String] {
+        &self.finished
+    }
+}
+
+#[derive(Clone, CopyGetters, Debug, Default, Deserialize, PartialEq, Reflect, Serialize)]
+pub struct QuestProgress {
+    /// Index of the stage the quest is on
+    #[getset(get_copy = "pub")]
+    stage: usize,
+    /// Objectives of the current stage that have been done
+    completed_objectives: Vec<QuestObjective>,
+}
+
+impl QuestProgress {
+    pub fn is_objective_met(&self, objective: &QuestObjective, quest_status: &QuestStatus) -> bool {
+        match objective {
+            QuestObjective::BeatNode(node_id) if is_node_done(quest_status, node_id) => true,
+            _ => self.completed_objectives.contains(objective),
+        }
+    }
+
+    /// If all objectives of the current stage have been met
+    pub fn is_stage_complete(&self, quest: &QuestDefinition, quest_status: &QuestStatus) -> bool {
+        quest
+            .stages
+            .get(self.stage)
+            .map(|stage| {
+                stage
+                    .objectives
+                    .iter()
+                    .all(|objective| self.is_objective_met(objective, quest_status))
+            })
+            .unwrap_or(false)
+    }
+}
+
+fn is_node_done(quest_status: &QuestStatus, node_id: &str) -> bool {
+    match node_id.parse::<SetId>() {
+        Ok(node_sid) => quest_status.is_node_done(&node_sid.into()),
+        Err(e) => {
+            log::warn!("Quest refers to invalid node id [{node_id}]: {e:?}");
+            false
+        },
+    }
+}
+
+#[derive(Debug, Reflect)]
+pub enum QuestOp {
+    /// Starts a quest, by quest id
+    Start(String),
+    /// Moves a quest on to its next stage once the current stage's objectives
+    /// are met, handing out that stage's rewards. Finishing the last stage
+    /// finishes the quest.
+    Advance(String),
+    /// Marks an objective done for each active quest whose current stage has it
+    CompleteObjective(QuestObjective),
+    /// Drops a quest in progress, it can be started again later
+    Abandon(String),
+}
+
+impl Op for QuestOp {
+    fn register_systems(mut registrar: OpRegistrar<Self>) {
+        registrar
+            .register_op(opsys_start_quest)
+            .register_op(opsys_advance_quest)
+            .register_op(opsys_complete_objective)
+            .register_op(opsys_abandon_quest);
+    }
+
+    fn system_index(&self) -> usize {
+        match self {
+            Self::Start(_) => 0,
+            Self::Advance(_) => 1,
+            Self::CompleteObjective(_) => 2,
+            Self::Abandon(_) => 3,
+        }
+    }
+}
+
+pub fn opsys_start_quest(
+    In((player_id, op)): In<(Entity, QuestOp)>,
+    res_quests: Res<Reg<Quests>>,
+    mut q_player: Query<(&QuestStatus, &mut QuestLog), With<Player>>,
+) -> OpImplResult {
+    let QuestOp::Start(quest_id) = op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    let (quest_status, mut quest_log) = q_player.get_mut(player_id).invalid()?;
+    let quest = res_quests.get(&quest_id).ok_or("No such quest".invalid())?;
+    if quest.stages.is_empty() {
+        Err("Quest has no stages".critical())?;
+    }
+    if quest_log.is_active(&quest_id) {
+        Err("Quest already started".invalid())?;
+    }
+    if quest_log.is_finished(&quest_id) {
+        Err("Quest already finished".invalid())?;
+    }
+    if let Some(prerequisite) = quest
+        .prerequisites
+        .iter()
+        .find(|prerequisite| !prerequisite.is_met(quest_status, &quest_log))
+    {
+        Err(format!("Can't start {} yet: {prerequisite}", quest.name))?;
+    }
+    quest_log.active.insert(quest_id.clone(), default());
+    let mut metadata = Metadata::default();
+    metadata.put(key::QUEST_ID, quest_id).critical()?;
+    Ok(metadata)
+}
+
+pub fn opsys_advance_quest(
+    In((player_id, op)): In<(Entity, QuestOp)>,
+    mut res_core_ops: ResMut<CoreOps>,
+    res_asset_server: Res<AssetServer>,
+    res_quests: Res<Reg<Quests>>,
+    mut q_player: Query<(&QuestStatus, &mut QuestLog), With<Player>>,
+) -> OpImplResult {
+    let QuestOp::Advance(quest_id) = op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    let (quest_status, mut quest_log) = q_player.get_mut(player_id).invalid()?;
+    let quest = res_quests.get(&quest_id).ok_or("No such quest".invalid())?;
+    let progress = quest_log
+        .active
+        .get_mut(&quest_id)
+        .ok_or("Quest not in progress".invalid())?;
+    let stage = quest
+        .stages
+        .get(progress.stage)
+        .ok_or("Quest has no such stage".critical())?;
+    if !progress.is_stage_complete(quest, quest_status) {
+        Err("Objectives not met yet".invalid())?;
+    }
+    let mut rewards = stage.rewards.clone();
+    progress.stage += 1;
+    progress.completed_objectives.clear();
+    let next_stage = progress.stage;
+    let quest_finished = next_stage >= quest.stages.len();
+    if quest_finished {
+        quest_log.active.remove(&quest_id);
+        quest_log.finished.push(quest_id.clone());
+        rewards.extend(quest.rewards.iter().cloned());
+    }
+    for reward in rewards.iter() {
+        res_core_ops.request(
+            player_id,
+            ItemOp::AddItem {
+                item: reward.to_item(&res_asset_server),
+                refund: 0,
+            },
+        );
+    }
+    let mut metadata = Metadata::default();
+    metadata.put(key::QUEST_ID, quest_id).critical()?;
+    metadata.put(key::STAGE, next_stage).critical()?;
+    metadata
+        .put(key::QUEST_FINISHED, quest_finished)
+        .critical()?;
+    Ok(metadata)
+}
+
+pub fn opsys_complete_objective(
+    In((player_id, op)): In<(Entity, QuestOp)>,
+    res_quests: Res<Reg<Quests>>,
+    mut q_player: Query<&mut QuestLog, With<Player>>,
+) -> OpImplResult {
+    let QuestOp::CompleteObjective(objective) = op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    let mut quest_log = q_player.get_mut(player_id).invalid()?;
+    // Checked before mutating so that the log is only marked changed when
+    // there is actually progress
+    let mut updated_quests: Vec<String> = quest_log
+        .active
+        .iter()
+        .filter(|(quest_id, progress)| {
+            !progress.completed_objectives.contains(&objective)
+                && res_quests
+                    .get(quest_id)
+                    .and_then(|quest| quest.stages.get(progress.stage))
+                    .map(|stage| stage.objectives.contains(&objective))
+                    .unwrap_or(false)
+        })
+        .map(|(quest_id, _)| quest_id.clone())
+        .collect();
+    updated_quests.sort();
+    for quest_id in updated_quests.iter() {
+        if let Some(progress) = quest_log.active.get_mut(quest_id) {
+            progress.completed_objectives.push(objective.clone());
+        }
+    }
+    let mut metadata = Metadata::default();
+    metadata
+        .put_nonempty(key::UPDATED_QUESTS, updated_quests)
+        .critical()?;
+    Ok(metadata)
+}
+
+pub fn opsys_abandon_quest(
+    In((player_id, op)): In<(Entity, QuestOp)>,
+    mut q_player: Query<&mut QuestLog, With<Player>>,
+) -> OpImplResult {
+    let QuestOp::Abandon(quest_id) = op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    let mut quest_log = q_player.get_mut(player_id).invalid()?;
+    if quest_log.active.remove(&quest_id).is_none() {
+        Err("Quest not in progress".invalid())?;
+    }
+    let mut metadata = Metadata::default();
+    metadata.put(key::QUEST_ID, quest_id).critical()?;
+    Ok(metadata)
+}
+
+/// Completes objectives for things players do elsewhere, like buying cards
+/// and talking to people. Nodes won are checked against [`QuestStatus`]
+/// instead.
+pub fn sys_track_quest_objectives(
+    mut res_core_ops: ResMut<CoreOps>,
+    mut evr_shop_op: EventReader<OpResult<ShopOp>>,
+    mut evr_dialog_node_complete: EventReader<NodeCompleteEvent>,
+    q_player: Query<(), (With<Player>, With<QuestLog>)>,
+) {
+    for shop_op_result in evr_shop_op.read() {
+        let (ShopOp::BuyItem(_) | ShopOp::BuyItemByName(_), Ok(metadata)) =
+            (shop_op_result.op(), shop_op_result.result())
+        else {
+            continue;
+        };
+        if let Ok(card_id) = metadata.get_required(shop::key::CARD_NAME) {
+            res_core_ops.request(
+                shop_op_result.source(),
+                QuestOp::CompleteObjective(QuestObjective::BuyCard(card_id)),
+            );
+        }
+    }
+    for NodeCompleteEvent { node_name, source } in evr_dialog_node_complete.read() {
+        if q_player.contains(*source) {
+            res_core_ops.request(
+                *source,
+                QuestOp::CompleteObjective(QuestObjective::TalkTo(node_name.clone())),
+            );
+        }
+    }
+}
+
+/// Advances quests whose current stage has all its objectives met
+pub fn sys_advance_quests(
+    mut res_core_ops: ResMut<CoreOps>,
+    res_quests: Res<Reg<Quests>>,
+    q_player: Query<
+        (Entity, &QuestStatus, &QuestLog),
+        (With<Player>, Or<(Changed<QuestStatus>, Changed<QuestLog>)>),
+    >,
+) {
+    for (player_id, quest_status, quest_log) in q_player.iter() {
+        for (quest_id, progress) in quest_log.active() {
+            let Some(quest) = res_quests.get(quest_id) else {
+                continue;
+            };
+            if progress.is_stage_complete(quest, quest_status) {
+                res_core_ops.request(player_id, QuestOp::Advance(quest_id.to_owned()));
+            }
+        }
+    }
+}
+
 pub fn sys_save_quest_status(
     res_save_data: Res<SaveData>,
     q_player: Query<&QuestStatus, (With<Player>, With<Ncp>)>,
 ) {
     for quest_status in q_player.iter() {
         res_save_data
-            .put(key::SAVE_QUEST_STATUS, quest_status)
+            .put(key::save::QUEST_STATUS, quest_status)
             .expect("TODO figure out how to handle save issues");
+        let completed_nodes = quest_status.nodes_done();
+        res_save_data.summarize(move |summary| summary.completed_nodes = completed_nodes);
     }
 }
 
@@ -60,8 +508,415 @@ pub fn sys_load_quest_status(
     mut q_player: Query<&mut QuestStatus, (With<Player>, With<Ncp>)>,
 ) {
     for mut quest_status in q_player.iter_mut() {
-        if let Ok(Some(load_qs)) = res_save_data.get_optional(key::SAVE_QUEST_STATUS) {
+        if let Ok(Some(load_qs)) = res_save_data.get_optional(key::save::QUEST_STATUS) {
             quest_status.set_if_neq(load_qs);
         }
     }
 }
+
+pub fn sys_save_quest_log(
+    res_save_data: Res<SaveData>,
+    q_player: Query<&QuestLog, (With<Player>, With<Ncp>)>,
+) {
+    for quest_log in q_player.iter() {
+        res_save_data
+            .put(key::save::QUEST_LOG, quest_log)
+            .expect("TODO figure out how to handle save issues");
+    }
+}
+
+/// Saves from before quest logs were saved have no quest log, so players
+/// start with an empty one
+pub fn sys_load_quest_log(
+    res_save_data: Res<LoadData>,
+    mut q_player: Query<&mut QuestLog, (With<Player>, With<Ncp>)>,
+) {
+    for mut quest_log in q_player.iter_mut() {
+        match res_save_data.get_optional(key::save::QUEST_LOG) {
+            Ok(Some(load_quest_log)) => {
+                quest_log.set_if_neq(load_quest_log);
+            },
+            Ok(None) => {
+                quest_log.set_if_neq(default());
+            },
+            Err(e) => log::error!("Unable to load quest log: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bevy::asset::AssetPlugin;
+    use bevy::ecs::event::Events;
+    use bevy::tasks::{IoTaskPool, TaskPool};
+
+    use super::*;
+    use crate::card::{Card, CardDefinition, Deck};
+    use crate::common::daddy::Daddy;
+    use crate::item::{Inventory, ItemDefinition, ItemDefinitions, ItemKind, Wallet};
+    use crate::op::OpExecutorPlugin;
+    use crate::saving::{SaveDirectory, SaveOp, SavePlugin, SaveWritten};
+
+    const QUEST: &str = r#"
+        name = "Test run"
+        prerequisites = [{ node = "node:tutorial:0" }]
+        rewards = [{ mon = 500 }, { card = "nightfall/lvl1.cards.json#Bug" }]
+
+        [[stages]]
+        description = "Get ready"
+        objectives = [{ talk_to = "warez_0" }, { buy_card = "Bit Man" }]
+
+        [[stages]]
+        objectives = [{ beat_node = "node:area1:0" }]
+        rewards = [{ item = "Patch Kit" }]
+    "#;
+
+    #[test]
+    fn test_quest_definition_from_toml() {
+        let quest: QuestDefinition = toml::from_str(QUEST).unwrap();
+        assert_eq!(quest.stages.len(), 2);
+        assert_eq!(
+            quest.stages[0].objectives,
+            vec![
+                QuestObjective::TalkTo("warez_0".to_owned()),
+                QuestObjective::BuyCard("Bit Man".to_owned()),
+            ]
+        );
+        assert_eq!(
+            quest.prerequisites,
+            vec![QuestPrerequisite::Node("node:tutorial:0".to_owned())]
+        );
+        assert_eq!(
+            quest.stages[1].rewards,
+            vec![QuestReward::Item("Patch Kit".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_beat_node_uses_quest_status() {
+        let quest: QuestDefinition = toml::from_str(QUEST).unwrap();
+        let mut quest_status = QuestStatus::default();
+        let progress = QuestProgress {
+            stage: 1,
+            ..default()
+        };
+        assert!(!progress.is_stage_complete(&quest, &quest_status));
+        quest_status.record_node_done(&NodeId::new("node:area1", 0));
+        assert!(progress.is_stage_complete(&quest, &quest_status));
+        assert!(QuestPrerequisite::Node("node:area1:0".to_owned())
+            .is_met(&quest_status, &QuestLog::default()));
+    }
+
+    const TEST_RUN: &str = "quest:test_run";
+    const SEQUEL: &str = "quest:sequel";
+    const PATCH_KIT: &str = "Patch Kit";
+    const BUG: &str = "nightfall/lvl1.cards.json#Bug";
+
+    /// A player with the test quests registered but none started. Saving
+    /// needs a [`SaveDirectory`] to be inserted first.
+    fn quest_app() -> (App, Entity) {
+        IoTaskPool::get_or_init(TaskPool::new);
+        let mut app = App::new();
+        app.configure_sets(
+            Update,
+            (
+                NDitCoreSet::ProcessCommands,
+                NDitCoreSet::ProcessCommandsFlush,
+                NDitCoreSet::PostProcessCommands,
+            )
+                .chain(),
+        )
+        .add_plugins((
+            AssetPlugin {
+                watch_for_changes_override: Some(false),
+                ..default()
+            },
+            OpExecutorPlugin::<CoreOps>::new(Update, Some(NDitCoreSet::ProcessCommands)),
+            OpPlugin::<ItemOp>::default(),
+            OpPlugin::<QuestOp>::default(),
+            SavePlugin,
+        ))
+        .init_asset::<CardDefinition>()
+        .init_resource::<Reg<ItemDefinitions>>()
+        .init_resource::<Reg<Quests>>()
+        .init_resource::<Daddy<Card>>()
+        .add_event::<OpResult<ShopOp>>()
+        .add_event::<NodeCompleteEvent>()
+        .add_systems(
+            Update,
+            sys_track_quest_objectives.in_set(NDitCoreSet::PostProcessCommands),
+        )
+        .add_systems(SaveSchedule, sys_save_quest_log)
+        .add_systems(LoadSchedule, sys_load_quest_log);
+        let world = app.world_mut();
+        world.resource_mut::<Reg<ItemDefinitions>>().insert(
+            PATCH_KIT,
+            ItemDefinition {
+                kind: ItemKind::Consumable,
+                description: String::new(),
+                action: None,
+            },
+        );
+        let sequel: QuestDefinition = toml::from_str(
+            r#"
+            name = "Sequel"
+            prerequisites = [{ quest = "quest:test_run" }]
+
+            [[stages]]
+            objectives = [{ talk_to = "warez_1" }]
+            "#,
+        )
+        .unwrap();
+        let mut quests = world.resource_mut::<Reg<Quests>>();
+        quests.insert(TEST_RUN, toml::from_str(QUEST).unwrap());
+        quests.insert(SEQUEL, sequel);
+        let player = world
+            .spawn((
+                Player,
+                Ncp,
+                QuestStatus::default(),
+                QuestLog::default(),
+                Wallet::new(),
+                Inventory::default(),
+                Deck::new(),
+            ))
+            .id();
+        // Registers the op systems
+        app.update();
+        (app, player)
+    }
+
+    fn perform(app: &mut App, player: Entity, op: QuestOp) -> OpImplResult {
+        app.world_mut()
+            .resource_mut::<CoreOps>()
+            .request(player, op);
+        app.update();
+        let mut results: Vec<_> = app
+            .world_mut()
+            .resource_mut::<Events<OpResult<QuestOp>>>()
+            .drain()
+            .collect();
+        assert_eq!(results.len(), 1, "{results:?}");
+        results.remove(0).result
+    }
+
+    /// Items requested since the last time this was called
+    fn added_items(app: &mut App) -> Vec<Item> {
+        app.update();
+        app.world_mut()
+            .resource_mut::<Events<OpResult<ItemOp>>>()
+            .drain()
+            .filter_map(|item_op_result| match item_op_result.op {
+                ItemOp::AddItem { item, .. } => Some(item),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn quest_log(app: &App, player: Entity) -> &QuestLog {
+        app.world().get::<QuestLog>(player).unwrap()
+    }
+
+    fn record_node_done(app: &mut App, player: Entity, node_id: NodeId) {
+        app.world_mut()
+            .get_mut::<QuestStatus>(player)
+            .unwrap()
+            .record_node_done(&node_id);
+    }
+
+    /// Completes the first stage of the test run the way a player would
+    fn talk_and_buy(app: &mut App, player: Entity) {
+        app.world_mut().send_event(NodeCompleteEvent {
+            node_name: "warez_0".to_owned(),
+            source: player,
+        });
+        let mut metadata = Metadata::default();
+        metadata
+            .put(shop::key::ITEM_NAME, "Bit Man".to_owned())
+            .unwrap();
+        metadata
+            .put(shop::key::CARD_NAME, "Bit Man".to_owned())
+            .unwrap();
+        app.world_mut().send_event(OpResult {
+            source: player,
+            op: ShopOp::BuyItemByName("Bit Man".to_owned()),
+            result: Ok(metadata),
+        });
+        app.update();
+        app.update();
+        app.world_mut()
+            .resource_mut::<Events<OpResult<QuestOp>>>()
+            .clear();
+    }
+
+    fn start_test_run(app: &mut App, player: Entity) {
+        record_node_done(app, player, NodeId::new("node:tutorial", 0));
+        perform(app, player, QuestOp::Start(TEST_RUN.to_owned())).expect("quest should start");
+    }
+
+    #[test]
+    fn test_start_needs_prerequisites() {
+        let (mut app, player) = quest_app();
+        assert!(perform(&mut app, player, QuestOp::Start("quest:missing".to_owned())).is_err());
+        assert!(perform(&mut app, player, QuestOp::Start(TEST_RUN.to_owned())).is_err());
+        assert!(!quest_log(&app, player).is_active(TEST_RUN));
+
+        record_node_done(&mut app, player, NodeId::new("node:tutorial", 0));
+        let metadata = perform(&mut app, player, QuestOp::Start(TEST_RUN.to_owned()))
+            .expect("prerequisite node was won");
+        assert_eq!(metadata.get_required(key::QUEST_ID).unwrap(), TEST_RUN);
+        assert_eq!(
+            quest_log(&app, player).progress(TEST_RUN).unwrap().stage(),
+            0
+        );
+        assert!(perform(&mut app, player, QuestOp::Start(TEST_RUN.to_owned())).is_err());
+        // Needs the test run to be finished, not just started
+        assert!(perform(&mut app, player, QuestOp::Start(SEQUEL.to_owned())).is_err());
+    }
+
+    #[test]
+    fn test_objectives_from_shop_and_dialog() {
+        let (mut app, player) = quest_app();
+        start_test_run(&mut app, player);
+
+        // Buying an item that shares a card's name isn't buying the card
+        let mut metadata = Metadata::default();
+        metadata
+            .put(shop::key::ITEM_NAME, "Bit Man".to_owned())
+            .unwrap();
+        app.world_mut().send_event(OpResult {
+            source: player,
+            op: ShopOp::BuyItem(0),
+            result: Ok(metadata),
+        });
+        app.update();
+        app.update();
+        let progress = quest_log(&app, player).progress(TEST_RUN).unwrap();
+        assert!(progress.completed_objectives.is_empty());
+
+        talk_and_buy(&mut app, player);
+        let progress = quest_log(&app, player).progress(TEST_RUN).unwrap();
+        assert_eq!(
+            progress.completed_objectives,
+            vec![
+                QuestObjective::BuyCard("Bit Man".to_owned()),
+                QuestObjective::TalkTo("warez_0".to_owned()),
+            ]
+        );
+
+        // Objectives aren't counted twice, and quests not on them are left alone
+        let metadata = perform(
+            &mut app,
+            player,
+            QuestOp::CompleteObjective(QuestObjective::TalkTo("warez_0".to_owned())),
+        )
+        .unwrap();
+        assert_eq!(metadata.get_optional(key::UPDATED_QUESTS).unwrap(), None);
+    }
+
+    #[test]
+    fn test_advance_gives_rewards() {
+        let (mut app, player) = quest_app();
+        start_test_run(&mut app, player);
+        assert!(perform(&mut app, player, QuestOp::Advance(TEST_RUN.to_owned())).is_err());
+
+        talk_and_buy(&mut app, player);
+        let metadata = perform(&mut app, player, QuestOp::Advance(TEST_RUN.to_owned()))
+            .expect("first stage is done");
+        assert_eq!(metadata.get_required(key::STAGE).unwrap(), 1);
+        assert!(!metadata.get_required(key::QUEST_FINISHED).unwrap());
+        assert_eq!(added_items(&mut app), vec![]);
+
+        record_node_done(&mut app, player, NodeId::new("node:area1", 0));
+        let metadata =
+            perform(&mut app, player, QuestOp::Advance(TEST_RUN.to_owned())).expect("node was won");
+        assert!(metadata.get_required(key::QUEST_FINISHED).unwrap());
+        let bug = app.world().resource::<AssetServer>().load(BUG);
+        assert_eq!(
+            added_items(&mut app),
+            vec![
+                Item::Inventory(PATCH_KIT.to_owned()),
+                Item::Mon(500),
+                Item::Card(bug),
+            ]
+        );
+        assert_eq!(app.world().get::<Wallet>(player).unwrap().mon(), 500);
+        assert_eq!(
+            app.world()
+                .get::<Inventory>(player)
+                .unwrap()
+                .count(PATCH_KIT),
+            1
+        );
+        let quest_log = quest_log(&app, player);
+        assert!(!quest_log.is_active(TEST_RUN));
+        assert_eq!(quest_log.finished(), [TEST_RUN.to_owned()]);
+
+        assert!(perform(&mut app, player, QuestOp::Start(TEST_RUN.to_owned())).is_err());
+        assert!(perform(&mut app, player, QuestOp::Start(SEQUEL.to_owned())).is_ok());
+    }
+
+    #[test]
+    fn test_abandon() {
+        let (mut app, player) = quest_app();
+        assert!(perform(&mut app, player, QuestOp::Abandon(TEST_RUN.to_owned())).is_err());
+        start_test_run(&mut app, player);
+        talk_and_buy(&mut app, player);
+
+        let metadata = perform(&mut app, player, QuestOp::Abandon(TEST_RUN.to_owned()))
+            .expect("quest is in progress");
+        assert_eq!(metadata.get_required(key::QUEST_ID).unwrap(), TEST_RUN);
+        assert!(!quest_log(&app, player).is_active(TEST_RUN));
+        assert!(!quest_log(&app, player).is_finished(TEST_RUN));
+
+        // Starting again starts from scratch
+        perform(&mut app, player, QuestOp::Start(TEST_RUN.to_owned())).unwrap();
+        let progress = quest_log(&app, player).progress(TEST_RUN).unwrap();
+        assert!(progress.completed_objectives.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load_quest_log() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("nf-quest-save-test-{}", std::process::id()));
+        let (mut app, player) = quest_app();
+        app.insert_resource(SaveDirectory::new(dir.clone()));
+        start_test_run(&mut app, player);
+        talk_and_buy(&mut app, player);
+        let saved_log = quest_log(&app, player).clone();
+
+        app.world_mut().resource_mut::<CoreOps>().request(
+            player,
+            SaveOp::Save {
+                include_battle: false,
+            },
+        );
+        // Saves are written in the background
+        let written = (0..200)
+            .find_map(|_| {
+                app.update();
+                let written = app
+                    .world_mut()
+                    .resource_mut::<Events<SaveWritten>>()
+                    .drain()
+                    .next();
+                if written.is_none() {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                written
+            })
+            .expect("save was never written");
+        assert!(written.result().is_ok(), "{written:?}");
+
+        perform(&mut app, player, QuestOp::Abandon(TEST_RUN.to_owned())).unwrap();
+        assert_ne!(quest_log(&app, player), &saved_log);
+        app.world_mut()
+            .resource_mut::<CoreOps>()
+            .request(player, SaveOp::Load);
+        app.update();
+        assert_eq!(quest_log(&app, player), &saved_log);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
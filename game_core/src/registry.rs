@@ -74,6 +74,11 @@ impl<R: Registry> Reg<R> {
         self.values.get(key).map(|(_, v)| v)
     }
 
+    #[cfg(test)]
+    pub(crate) fn insert(&mut self, key: &str, value: R::Value) {
+        self.values.insert(key.to_owned(), (0, value));
+    }
+
     fn add(
         &mut self,
         key: String,
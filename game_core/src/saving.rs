@@ -1,64 +1,96 @@
 use std::borrow::{Borrow, Cow};
 use std::fs::File;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use bevy::ecs::entity::{EntityHashMap, EntityHashSet, MapEntities};
 use bevy::ecs::reflect::AppTypeRegistry;
 use bevy::ecs::schedule::ScheduleLabel;
 use bevy::scene::serde::SceneDeserializer;
 use bevy::scene::{ron, SceneFilter};
+use bevy::tasks::{IoTaskPool, Task};
 use freeform::SerdeScheme;
 use serde::de::DeserializeSeed;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use typed_key::Key;
 
+pub use self::save_migration::{SaveMigrationFn, SaveMigrations, SAVE_FORMAT_VERSION};
 use crate::op::{Op, OpError, OpErrorUtils, OpImplResult, OpPlugin};
 use crate::prelude::*;
+use crate::NDitCoreSet;
 
-mod key {
+mod save_migration;
+
+pub mod key {
     use typed_key::{typed_key, Key};
 
-    pub const SCENE: Key<String> = typed_key!("scene");
+    use super::SaveSlotSummary;
+
+    pub(super) const SCENE: Key<String> = typed_key!("scene");
+    pub(super) const VERSION: Key<u32> = typed_key!("version");
+    pub const SLOT: Key<String> = typed_key!("slot");
+    pub const SLOTS: Key<Vec<SaveSlotSummary>> = typed_key!("slots");
+    pub const SUMMARY: Key<SaveSlotSummary> = typed_key!("summary");
 }
 
+const SAVE_FILE_EXTENSION: &str = ".sav.json";
+const BACKUP_SUFFIX: &str = ".bak";
+const TEMP_SUFFIX: &str = ".tmp";
+
 #[derive(Debug)]
 pub struct SavePlugin;
 
 impl Plugin for SavePlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<CurrentSaveFile>()
+            .init_resource::<SaveDirectory>()
+            .init_resource::<SaveMigrations>()
             .init_resource::<SaveFilter>()
+            .init_resource::<PendingSaves>()
             .init_schedule(SaveSchedule)
             .init_schedule(LoadSchedule)
-            .add_plugins(OpPlugin::<SaveOp>::default());
+            .add_event::<SaveWritten>()
+            .add_plugins(OpPlugin::<SaveOp>::default())
+            .add_systems(
+                Update,
+                sys_finish_pending_saves.in_set(NDitCoreSet::PostProcessCommands),
+            );
     }
 }
 
+/// Directory that save slots are kept in. If it is a bare name, it is put in
+/// the OS data directory. Each game should insert its own so that their saves
+/// don't get mixed up.
 #[derive(Clone, Debug, Resource)]
-pub struct CurrentSaveFile(Cow<'static, Path>);
+pub struct SaveDirectory(Cow<'static, Path>);
 
-impl Default for CurrentSaveFile {
+impl Default for SaveDirectory {
     fn default() -> Self {
-        Self(Cow::Borrowed(Path::new("default.sav.json")))
+        Self::new("nf")
     }
 }
 
-impl CurrentSaveFile {
-    fn get_path(&self) -> std::io::Result<Cow<'static, Path>> {
+impl SaveDirectory {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self(Cow::Owned(path.into()))
+    }
+
+    fn get_path(&self) -> std::io::Result<PathBuf> {
         if self.0.parent() == Some(Path::new("")) {
-            let mut path_buf = self.get_os_save_directory()?;
+            let mut path_buf = Self::get_os_save_directory()?;
             path_buf.push(self.0.clone());
-            Ok(Cow::Owned(path_buf))
+            Ok(path_buf)
         } else {
-            Ok(self.0.clone())
+            Ok(self.0.to_path_buf())
         }
     }
 
-    fn get_os_save_directory(&self) -> std::io::Result<PathBuf> {
+    fn get_os_save_directory() -> std::io::Result<PathBuf> {
         // TODO actually change based on compiled OS.
-        let mut path = std::env::var("XDG_DATA_HOME")
+        std::env::var("XDG_DATA_HOME")
             .map(PathBuf::from)
             .or_else(|_| {
                 let mut pathbuf = PathBuf::new();
@@ -73,16 +105,24 @@ impl CurrentSaveFile {
                 pathbuf.push(".local");
                 pathbuf.push("share");
                 Ok(pathbuf)
-            })?;
-        path.push("nf"); // TODO make this configurable
+            })
+    }
+
+    fn slot_path(&self, slot: &str) -> std::io::Result<PathBuf> {
+        self.slot_file_path(slot, "")
+    }
+
+    fn slot_file_path(&self, slot: &str, suffix: &str) -> std::io::Result<PathBuf> {
+        let mut path = self.get_path()?;
+        path.push(format!("{slot}{SAVE_FILE_EXTENSION}{suffix}"));
         Ok(path)
     }
 
-    fn create(&self) -> std::io::Result<File> {
-        let path = self.get_path()?;
-        if path.exists() {
-            return File::create(path);
-        }
+    /// Writes a save slot without ever leaving it half written. The data is
+    /// written to a temporary file that then replaces the slot, and the
+    /// previous save is kept as a backup.
+    fn write(&self, slot: &str, contents: &[u8]) -> std::io::Result<()> {
+        let path = self.slot_path(slot)?;
         let parent = path
             .parent()
             .expect("There should always be a parent in the expanded path");
@@ -90,16 +130,156 @@ impl CurrentSaveFile {
             log::info!("Creating parent directory {parent:?}");
             std::fs::create_dir_all(parent)?;
         }
-        log::info!("Creating save file {path:?}");
-        File::create(path)
-        // Err(std::io::Error::new(std::io::ErrorKind::Other, "This was just test"))
+        let temp_path = self.slot_file_path(slot, TEMP_SUFFIX)?;
+        let mut temp_file = File::create(&temp_path)?;
+        temp_file.write_all(contents)?;
+        temp_file.sync_all()?;
+        drop(temp_file);
+        if path.exists() {
+            std::fs::copy(&path, self.slot_file_path(slot, BACKUP_SUFFIX)?)?;
+        } else {
+            log::info!("Creating save file {path:?}");
+        }
+        std::fs::rename(temp_path, path)
+    }
+
+    fn read(&self, slot: &str) -> Result<Metadata, String> {
+        read_save_file(&self.slot_path(slot).map_err(|e| e.to_string())?)
+    }
+
+    fn read_backup(&self, slot: &str) -> Result<Metadata, String> {
+        read_save_file(
+            &self
+                .slot_file_path(slot, BACKUP_SUFFIX)
+                .map_err(|e| e.to_string())?,
+        )
+    }
+
+    fn delete(&self, slot: &str) -> std::io::Result<()> {
+        std::fs::remove_file(self.slot_path(slot)?)?;
+        match std::fs::remove_file(self.slot_file_path(slot, BACKUP_SUFFIX)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Summaries of all the save slots in the directory, most recent first
+    fn list_slots(&self, migrations: &SaveMigrations) -> std::io::Result<Vec<SaveSlotSummary>> {
+        let path = self.get_path()?;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut summaries = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(slot) = file_name
+                .to_str()
+                .and_then(|file_name| file_name.strip_suffix(SAVE_FILE_EXTENSION))
+            else {
+                continue;
+            };
+            let summary = read_save_file(&entry.path()).and_then(|mut data| {
+                migrations.migrate(&mut data)?;
+                data.get_or_default(key::SUMMARY).map_err(|e| e.to_string())
+            });
+            match summary {
+                Ok(mut summary) => {
+                    // Slots are loaded by file name, so that is what to show
+                    summary.slot = slot.to_owned();
+                    if summary.saved_at == 0 {
+                        // Migrated saves don't know when they were made
+                        summary.saved_at = entry
+                            .metadata()
+                            .and_then(|file_metadata| file_metadata.modified())
+                            .ok()
+                            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                            .map(|since_epoch| since_epoch.as_secs())
+                            .unwrap_or_default();
+                    }
+                    summaries.push(summary);
+                },
+                Err(e) => log::warn!("Unable to read save slot [{slot}]: {e}"),
+            }
+        }
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.saved_at));
+        Ok(summaries)
+    }
+}
+
+fn read_save_file(path: &Path) -> Result<Metadata, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    serde_json::from_reader(file).map_err(|e| e.to_string())
+}
+
+/// The save slot that saving and loading use
+#[derive(Clone, Debug, Resource)]
+pub struct CurrentSaveFile(String);
+
+impl Default for CurrentSaveFile {
+    fn default() -> Self {
+        Self("default".to_owned())
+    }
+}
+
+impl CurrentSaveFile {
+    pub fn slot(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+/// Overview of a save slot, so players can tell them apart without loading
+/// them. Filled in by save systems through [`SaveData::summarize`].
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Reflect, Serialize)]
+pub struct SaveSlotSummary {
+    pub slot: String,
+    /// Seconds since the unix epoch
+    pub saved_at: u64,
+    pub completed_nodes: u32,
+    pub mon: u32,
+    pub deck_size: u32,
+}
+
+/// Saves that are still being written to disk
+#[derive(Debug, Default, Resource)]
+pub struct PendingSaves(Vec<PendingSave>);
+
+#[derive(Debug)]
+struct PendingSave {
+    source: Entity,
+    slot: String,
+    task: Task<std::io::Result<()>>,
+}
+
+impl PendingSaves {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 
-    fn open(&self) -> std::io::Result<File> {
-        File::open(self.get_path()?)
+    pub fn is_saving(&self, slot: &str) -> bool {
+        self.0.iter().any(|pending| pending.slot == slot)
     }
 }
 
+/// Sent once a save from [`SaveOp::Save`] has been written to disk, or
+/// failed to be. The op itself finishes before the save is written.
+#[derive(Clone, Debug, Event, getset::CopyGetters, getset::Getters)]
+pub struct SaveWritten {
+    #[getset(get_copy = "pub")]
+    source: Entity,
+    #[getset(get = "pub")]
+    slot: String,
+    #[getset(get = "pub")]
+    result: Result<(), OpError>,
+}
+
+fn validate_slot_name(slot: &str) -> Result<(), OpError> {
+    if slot.is_empty() || slot.contains(|c: char| std::path::is_separator(c) || c == '.') {
+        Err(format!("Invalid save slot name [{slot}]"))?;
+    }
+    Ok(())
+}
+
 /// Contains data from loaded save file, to provide as a resource
 /// to the bevy LoadSchedule.
 ///
@@ -140,6 +320,7 @@ pub struct LoadSchedule;
 
 #[derive(Debug, Resource)]
 pub struct SaveData {
+    include_battle: bool,
     send: Sender<SaveEvent>,
     recv: Mutex<Receiver<SaveEvent>>, // Mutex to be replaced with std::sync::Exclusive once that is no longer nightly exclusive
 }
@@ -148,6 +329,7 @@ impl Default for SaveData {
     fn default() -> Self {
         let (send, recv) = std::sync::mpsc::channel();
         Self {
+            include_battle: false,
             send,
             recv: Mutex::new(recv),
         }
@@ -155,13 +337,33 @@ impl Default for SaveData {
 }
 
 impl SaveData {
+    /// Whether this save should capture the battle the player is in
+    pub fn include_battle(&self) -> bool {
+        self.include_battle
+    }
+
     fn send(&self, event: SaveEvent) {
         self.send.send(event).expect("the receiver should never be disconnected as the SaveData API should not allow it except in consuming methods");
     }
 
+    /// Fills in part of the save slot summary
+    pub fn summarize<F: FnOnce(&mut SaveSlotSummary) + Send + 'static>(&self, func: F) {
+        self.send(SaveEvent::AlterSummary(Box::new(func)));
+    }
+
     pub fn add_entities(&self, entities: &[Entity]) {
         self.send(SaveEvent::AddEntitiesToScene(entities.to_owned()))
     }
+
+    /// Adds entities to the scene using their own filter instead of
+    /// [`SaveFilter`], for entities that need components saved that other
+    /// entities shouldn't
+    pub fn add_entities_with_filter(&self, entities: &[Entity], filter: SceneFilter) {
+        self.send(SaveEvent::AddFilteredEntitiesToScene(
+            entities.to_owned(),
+            filter,
+        ))
+    }
     /**
      * This function will pass the whole save data metadata to it, which can be potentially unsafe.
      *
@@ -187,21 +389,43 @@ impl SaveData {
     }
 
     pub fn process(self, world: &mut World) -> Result<Metadata, ron::Error> {
-        let SaveData { send: _, mut recv } = self;
+        let SaveData { mut recv, .. } = self;
         let mut metadata = Metadata::new();
+        let mut summary = SaveSlotSummary::default();
         let mut entity_set = EntityHashSet::default();
+        let mut filtered_entities = Vec::new();
         let recv = recv
             .get_mut()
             .expect("this should be the only place where the mutex is accessed");
         for event in recv.try_iter() {
-            event.apply(&mut metadata, &mut entity_set)
+            event.apply(
+                &mut metadata,
+                &mut summary,
+                &mut entity_set,
+                &mut filtered_entities,
+            )
         }
+        summary.saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or_default();
+        metadata
+            .put(key::SUMMARY, summary)
+            .expect("summary should be easy to serialize");
+        metadata
+            .put(key::VERSION, SAVE_FORMAT_VERSION)
+            .expect("should be easy to serialize a number");
         let save_filter = world.resource::<SaveFilter>().deref().clone();
-        let scene = bevy::scene::DynamicSceneBuilder::from_world(world)
+        let mut scene_builder = bevy::scene::DynamicSceneBuilder::from_world(world)
             .with_filter(save_filter.clone())
             .with_resource_filter(save_filter)
-            .extract_entities(entity_set.into_iter())
-            .build();
+            .extract_entities(entity_set.into_iter());
+        for (entities, filter) in filtered_entities {
+            scene_builder = scene_builder
+                .with_filter(filter)
+                .extract_entities(entities.into_iter());
+        }
+        let scene = scene_builder.build();
         let registry = world.resource::<AppTypeRegistry>();
         let save_data = scene.serialize(&registry.read())?;
         metadata
@@ -214,16 +438,27 @@ impl SaveData {
 
 enum SaveEvent {
     AddEntitiesToScene(Vec<Entity>),
+    AddFilteredEntitiesToScene(Vec<Entity>, SceneFilter),
     Put(&'static str, String),
     AlterSave(Box<dyn FnOnce(&mut Metadata) + Send>),
+    AlterSummary(Box<dyn FnOnce(&mut SaveSlotSummary) + Send>),
 }
 
 impl SaveEvent {
-    fn apply(self, metadata: &mut Metadata, entity_hash_set: &mut EntityHashSet) {
+    fn apply(
+        self,
+        metadata: &mut Metadata,
+        summary: &mut SaveSlotSummary,
+        entity_hash_set: &mut EntityHashSet,
+        filtered_entities: &mut Vec<(Vec<Entity>, SceneFilter)>,
+    ) {
         match self {
             Self::AlterSave(f) => {
                 f(metadata);
             },
+            Self::AlterSummary(f) => {
+                f(summary);
+            },
             Self::Put(key, value) => {
                 unsafe {
                     // Event is only populated with valid JSON
@@ -233,6 +468,9 @@ impl SaveEvent {
             Self::AddEntitiesToScene(entities) => {
                 entity_hash_set.extend(entities);
             },
+            Self::AddFilteredEntitiesToScene(entities, filter) => {
+                filtered_entities.push((entities, filter));
+            },
         }
     }
 }
@@ -243,12 +481,21 @@ impl std::fmt::Debug for SaveEvent {
             Self::AddEntitiesToScene(entities) => {
                 write!(f, "SaveEvent::AddEntitiesToScene({entities:?})")
             },
+            Self::AddFilteredEntitiesToScene(entities, filter) => {
+                write!(
+                    f,
+                    "SaveEvent::AddFilteredEntitiesToScene({entities:?}, {filter:?})"
+                )
+            },
             Self::Put(key, value) => {
                 write!(f, "SaveEvent::Put({key:?}, {value:?})")
             },
             Self::AlterSave(_) => {
                 write!(f, "SaveEvent::AlterSave(?)")
             },
+            Self::AlterSummary(_) => {
+                write!(f, "SaveEvent::AlterSummary(?)")
+            },
         }
     }
 }
@@ -269,44 +516,88 @@ pub struct SaveSchedule;
 // memory) and PerformLoad
 #[derive(Debug, Clone, Reflect)]
 pub enum SaveOp {
-    Save,
+    /// Saves to the current slot. The save is written in the background, and
+    /// [`SaveWritten`] is sent when it is done.
+    Save {
+        /// Also save the battle the player is in, so that loading puts them
+        /// back into it
+        include_battle: bool,
+    },
     Load,
+    /// Lists the summaries of the save slots, under [`key::SLOTS`]
+    ListSlots,
+    Delete(String),
+    /// Changes which slot is saved to and loaded from
+    SetSlot(String),
 }
 
 impl Op for SaveOp {
     fn register_systems(mut registrar: crate::op::OpRegistrar<Self>) {
         registrar
             .register_op_exclusive(opsys_save_op)
-            .register_op_exclusive(opsys_load_op);
+            .register_op_exclusive(opsys_load_op)
+            .register_op(opsys_list_slots_op)
+            .register_op(opsys_delete_op)
+            .register_op(opsys_set_slot_op);
     }
 
     fn system_index(&self) -> usize {
         match self {
-            Self::Save => 0,
+            Self::Save { .. } => 0,
             Self::Load => 1,
+            Self::ListSlots => 2,
+            Self::Delete(_) => 3,
+            Self::SetSlot(_) => 4,
         }
     }
 }
 
-pub fn opsys_save_op(In((_source, op)): In<(Entity, SaveOp)>, world: &mut World) -> OpImplResult {
-    if !matches!(op, SaveOp::Save) {
+pub fn opsys_save_op(In((source, op)): In<(Entity, SaveOp)>, world: &mut World) -> OpImplResult {
+    let SaveOp::Save { include_battle } = op else {
         return Err(OpError::MismatchedOpSystem);
-    }
-    world.insert_resource(SaveData::default());
+    };
     let current_save_file = world
         .get_resource::<CurrentSaveFile>()
         .cloned()
         .ok_or_else(|| "No save file configured".critical())?;
-    let file = current_save_file.create().critical()?;
+    let save_directory = world
+        .get_resource::<SaveDirectory>()
+        .cloned()
+        .ok_or_else(|| "No save directory configured".critical())?;
+    let slot = current_save_file.slot().to_owned();
+    if world.resource::<PendingSaves>().is_saving(&slot) {
+        Err(format!("Already saving to slot [{slot}]"))?;
+    }
+    let save_data = SaveData {
+        include_battle,
+        ..default()
+    };
+    let summary_slot = slot.clone();
+    save_data.summarize(move |summary| summary.slot = summary_slot);
+    world.insert_resource(save_data);
     world.run_schedule(SaveSchedule);
     if let Some(save_data) = world.remove_resource::<SaveData>() {
-        // TODO return different metadata
-        // TODO more importantly, this writing should be async instead of in-frame
         let save_metadata = save_data
             .process(world)
             .map_err(|e| format!("Problem serializing save file: {e:?}").critical())?;
-        serde_json::to_writer(file, &save_metadata).critical()?;
-        Ok(save_metadata)
+        let contents = serde_json::to_vec(&save_metadata).critical()?;
+        // The save itself can be large, so just describe it
+        let mut metadata = Metadata::new();
+        metadata.put(key::SLOT, &slot).critical()?;
+        metadata
+            .put(
+                key::SUMMARY,
+                save_metadata.get_required(key::SUMMARY).critical()?,
+            )
+            .critical()?;
+        let task_slot = slot.clone();
+        let task =
+            IoTaskPool::get().spawn(async move { save_directory.write(&task_slot, &contents) });
+        world
+            .resource_mut::<PendingSaves>()
+            .0
+            .push(PendingSave { source, slot, task });
+        Ok(metadata)
     } else {
         Err("Something went wrong, unable to save".critical())
     }
@@ -320,8 +611,24 @@ pub fn opsys_load_op(In((_source, op)): In<(Entity, SaveOp)>, world: &mut World)
         .get_resource::<CurrentSaveFile>()
         .cloned()
         .ok_or_else(|| "No save file configured".critical())?;
-    let file = current_save_file.open().critical()?;
-    let data: Metadata = serde_json::from_reader(file).critical()?;
+    let save_directory = world
+        .get_resource::<SaveDirectory>()
+        .cloned()
+        .ok_or_else(|| "No save directory configured".critical())?;
+    let slot = current_save_file.slot();
+    validate_slot_name(slot)?;
+    if world.resource::<PendingSaves>().is_saving(slot) {
+        Err(format!(
+            "Still saving slot [{slot}], try again once it is saved"
+        ))?;
+    }
+    let mut data = save_directory.read(slot).or_else(|e| {
+        log::warn!("Unable to read save slot [{slot}], trying its backup: {e}");
+        save_directory
+            .read_backup(slot)
+            .map_err(|_| format!("Unable to read save slot [{slot}]: {e}"))
+    })?;
+    world.resource::<SaveMigrations>().migrate(&mut data)?;
     let scene_ron = data.get_required(key::SCENE).critical()?;
 
     let registry = world.resource::<AppTypeRegistry>();
@@ -341,3 +648,333 @@ pub fn opsys_load_op(In((_source, op)): In<(Entity, SaveOp)>, world: &mut World)
     world.remove_resource::<LoadData>();
     Ok(default())
 }
+
+pub fn opsys_list_slots_op(
+    In((_source, op)): In<(Entity, SaveOp)>,
+    res_save_directory: Res<SaveDirectory>,
+    res_save_migrations: Res<SaveMigrations>,
+) -> OpImplResult {
+    if !matches!(op, SaveOp::ListSlots) {
+        return Err(OpError::MismatchedOpSystem);
+    }
+    let slots = res_save_directory
+        .list_slots(&res_save_migrations)
+        .critical()?;
+    let mut metadata = Metadata::default();
+    metadata.put(key::SLOTS, slots).critical()?;
+    Ok(metadata)
+}
+
+pub fn opsys_delete_op(
+    In((_source, op)): In<(Entity, SaveOp)>,
+    res_save_directory: Res<SaveDirectory>,
+    res_pending_saves: Res<PendingSaves>,
+) -> OpImplResult {
+    let SaveOp::Delete(slot) = op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    validate_slot_name(&slot)?;
+    if res_pending_saves.is_saving(&slot) {
+        Err(format!(
+            "Still saving slot [{slot}], try again once it is saved"
+        ))?;
+    }
+    res_save_directory
+        .delete(&slot)
+        .map_err(|e| format!("Unable to delete save slot [{slot}]: {e}"))?;
+    Ok(default())
+}
+
+pub fn opsys_set_slot_op(
+    In((_source, op)): In<(Entity, SaveOp)>,
+    mut res_current_save_file: ResMut<CurrentSaveFile>,
+) -> OpImplResult {
+    let SaveOp::SetSlot(slot) = op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    validate_slot_name(&slot)?;
+    res_current_save_file.0 = slot;
+    Ok(default())
+}
+
+fn sys_finish_pending_saves(
+    mut res_pending_saves: ResMut<PendingSaves>,
+    mut evw_save_written: EventWriter<SaveWritten>,
+) {
+    if res_pending_saves.is_empty() {
+        return;
+    }
+    let (finished, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut res_pending_saves.0)
+        .into_iter()
+        .partition(|pending| pending.task.is_finished());
+    res_pending_saves.0 = pending;
+    for PendingSave { source, slot, task } in finished {
+        let result = bevy::tasks::block_on(task)
+            .map_err(|e| format!("Unable to write save slot [{slot}]: {e}").critical());
+        match &result {
+            Ok(()) => log::info!("Saved slot [{slot}]"),
+            Err(e) => log::error!("{e}"),
+        }
+        evw_save_written.send(SaveWritten {
+            source,
+            slot,
+            result,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slot_names() {
+        assert!(validate_slot_name("default").is_ok());
+        assert!(validate_slot_name("run 2").is_ok());
+        assert!(validate_slot_name("").is_err());
+        assert!(validate_slot_name("../default").is_err());
+        assert!(validate_slot_name("default.sav").is_err());
+    }
+
+    #[test]
+    fn test_list_slots() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("nf-save-slot-test-{}", std::process::id()));
+        let save_directory = SaveDirectory::new(dir.clone());
+        let migrations = SaveMigrations::default();
+        assert_eq!(save_directory.list_slots(&migrations).unwrap(), Vec::new());
+
+        for (slot, saved_at) in [("older", 10), ("newer", 20)] {
+            let mut data = Metadata::new();
+            data.put(
+                key::SUMMARY,
+                SaveSlotSummary {
+                    slot: slot.to_owned(),
+                    saved_at,
+                    ..default()
+                },
+            )
+            .unwrap();
+            save_directory
+                .write(slot, &serde_json::to_vec(&data).unwrap())
+                .unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "not a save").unwrap();
+
+        let slots: Vec<String> = save_directory
+            .list_slots(&migrations)
+            .unwrap()
+            .into_iter()
+            .map(|summary| summary.slot)
+            .collect();
+        assert_eq!(slots, vec!["newer".to_owned(), "older".to_owned()]);
+
+        save_directory.delete("older").unwrap();
+        assert_eq!(save_directory.list_slots(&migrations).unwrap().len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_op_describes_save() {
+        use bevy::ecs::schedule::Schedule;
+        use bevy::ecs::system::RunSystemOnce;
+        use bevy::tasks::{block_on, TaskPool};
+
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("nf-save-op-test-{}", std::process::id()));
+        IoTaskPool::get_or_init(TaskPool::new);
+        let mut world = World::new();
+        world.init_resource::<AppTypeRegistry>();
+        world.init_resource::<CurrentSaveFile>();
+        world.init_resource::<SaveFilter>();
+        world.init_resource::<PendingSaves>();
+        world.insert_resource(SaveDirectory::new(dir.clone()));
+        world.add_schedule(Schedule::new(SaveSchedule));
+        let player = world.spawn_empty().id();
+
+        let metadata = world
+            .run_system_once_with(
+                (
+                    player,
+                    SaveOp::Save {
+                        include_battle: false,
+                    },
+                ),
+                opsys_save_op,
+            )
+            .expect("save should start");
+        for pending_save in world.resource_mut::<PendingSaves>().0.drain(..) {
+            block_on(pending_save.task).expect("save should be written");
+        }
+
+        let summary = metadata.get_required(key::SUMMARY).unwrap();
+        assert_eq!(summary.slot, "default");
+        let mut expected = Metadata::new();
+        expected.put(key::SLOT, "default".to_owned()).unwrap();
+        expected.put(key::SUMMARY, summary).unwrap();
+        assert_eq!(metadata, expected);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Which run a save is from, so tests can tell which slot was loaded
+    #[derive(Debug, Resource)]
+    struct RunName(String);
+
+    const RUN_NAME: Key<String> = typed_key::typed_key!("run_name");
+
+    /// A world that saves and loads its [`RunName`]
+    fn save_op_world(dir: PathBuf) -> (World, Entity) {
+        use bevy::ecs::schedule::Schedule;
+        use bevy::tasks::TaskPool;
+
+        IoTaskPool::get_or_init(TaskPool::new);
+        let mut world = World::new();
+        world.init_resource::<AppTypeRegistry>();
+        world.init_resource::<CurrentSaveFile>();
+        world.init_resource::<SaveFilter>();
+        world.init_resource::<SaveMigrations>();
+        world.init_resource::<PendingSaves>();
+        world.insert_resource(SaveDirectory::new(dir));
+        let mut save_schedule = Schedule::new(SaveSchedule);
+        save_schedule.add_systems(|save_data: Res<SaveData>, run_name: Res<RunName>| {
+            save_data.put(RUN_NAME, &run_name.0).unwrap();
+        });
+        world.add_schedule(save_schedule);
+        let mut load_schedule = Schedule::new(LoadSchedule);
+        load_schedule.add_systems(|load_data: Res<LoadData>, mut run_name: ResMut<RunName>| {
+            run_name.0 = load_data.get_required(RUN_NAME).unwrap();
+        });
+        world.add_schedule(load_schedule);
+        let player = world.spawn_empty().id();
+        (world, player)
+    }
+
+    fn perform_save_op(world: &mut World, player: Entity, op: SaveOp) -> OpImplResult {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let result = match op {
+            SaveOp::Save { .. } => world.run_system_once_with((player, op), opsys_save_op),
+            SaveOp::Load => world.run_system_once_with((player, op), opsys_load_op),
+            SaveOp::ListSlots => world.run_system_once_with((player, op), opsys_list_slots_op),
+            SaveOp::Delete(_) => world.run_system_once_with((player, op), opsys_delete_op),
+            SaveOp::SetSlot(_) => world.run_system_once_with((player, op), opsys_set_slot_op),
+        };
+        for pending_save in world.resource_mut::<PendingSaves>().0.drain(..) {
+            bevy::tasks::block_on(pending_save.task).expect("save should be written");
+        }
+        result
+    }
+
+    fn save_run(world: &mut World, player: Entity, slot: &str, run_name: &str) {
+        perform_save_op(world, player, SaveOp::SetSlot(slot.to_owned())).unwrap();
+        world.insert_resource(RunName(run_name.to_owned()));
+        perform_save_op(
+            world,
+            player,
+            SaveOp::Save {
+                include_battle: false,
+            },
+        )
+        .expect("save should start");
+    }
+
+    #[test]
+    fn test_load_named_slot() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("nf-load-slot-test-{}", std::process::id()));
+        let (mut world, player) = save_op_world(dir.clone());
+        save_run(&mut world, player, "default", "first");
+        save_run(&mut world, player, "second run", "second");
+        world.insert_resource(RunName("unsaved".to_owned()));
+
+        perform_save_op(&mut world, player, SaveOp::Load).expect("slot should load");
+        assert_eq!(world.resource::<RunName>().0, "second");
+        perform_save_op(&mut world, player, SaveOp::SetSlot("default".to_owned())).unwrap();
+        perform_save_op(&mut world, player, SaveOp::Load).expect("slot should load");
+        assert_eq!(world.resource::<RunName>().0, "first");
+
+        assert!(perform_save_op(&mut world, player, SaveOp::SetSlot("missing".to_owned())).is_ok());
+        assert!(perform_save_op(&mut world, player, SaveOp::Load).is_err());
+        // Slots can only be set to valid names through ops, but the slot is
+        // checked again before it is read
+        world.insert_resource(CurrentSaveFile("../default".to_owned()));
+        assert!(perform_save_op(&mut world, player, SaveOp::Load).is_err());
+        assert_eq!(world.resource::<RunName>().0, "first");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_delete_op() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("nf-delete-op-test-{}", std::process::id()));
+        let (mut world, player) = save_op_world(dir.clone());
+        save_run(&mut world, player, "default", "first");
+        save_run(&mut world, player, "second run", "second");
+
+        assert!(
+            perform_save_op(&mut world, player, SaveOp::Delete("../default".to_owned())).is_err()
+        );
+        perform_save_op(&mut world, player, SaveOp::Delete("second run".to_owned()))
+            .expect("slot should be deleted");
+        let metadata = perform_save_op(&mut world, player, SaveOp::ListSlots).unwrap();
+        let slots: Vec<String> = metadata
+            .get_required(key::SLOTS)
+            .unwrap()
+            .into_iter()
+            .map(|summary| summary.slot)
+            .collect();
+        assert_eq!(slots, vec!["default".to_owned()]);
+        // The current slot was deleted, so there's nothing to load
+        assert!(perform_save_op(&mut world, player, SaveOp::Load).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_keeps_backup() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("nf-save-backup-test-{}", std::process::id()));
+        let save_directory = SaveDirectory::new(dir.clone());
+        let saves: Vec<Metadata> = [10, 20]
+            .into_iter()
+            .map(|saved_at| {
+                let mut data = Metadata::new();
+                data.put(
+                    key::SUMMARY,
+                    SaveSlotSummary {
+                        saved_at,
+                        ..default()
+                    },
+                )
+                .unwrap();
+                data
+            })
+            .collect();
+
+        save_directory
+            .write("slot", &serde_json::to_vec(&saves[0]).unwrap())
+            .unwrap();
+        assert!(save_directory.read_backup("slot").is_err());
+        save_directory
+            .write("slot", &serde_json::to_vec(&saves[1]).unwrap())
+            .unwrap();
+        let saved_at = |data: Metadata| data.get_required(key::SUMMARY).unwrap().saved_at;
+        assert_eq!(saved_at(save_directory.read("slot").unwrap()), 20);
+        assert_eq!(saved_at(save_directory.read_backup("slot").unwrap()), 10);
+        assert!(!save_directory
+            .slot_file_path("slot", TEMP_SUFFIX)
+            .unwrap()
+            .exists());
+        // Backups aren't listed as slots of their own
+        assert_eq!(
+            save_directory
+                .list_slots(&SaveMigrations::default())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        save_directory.delete("slot").unwrap();
+        assert!(save_directory.read_backup("slot").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
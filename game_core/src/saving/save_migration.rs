@@ -0,0 +1,367 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use super::{key, SaveSlotSummary};
+use crate::prelude::*;
+
+/// Version of the save format written by [`super::opsys_save_op`]. Saves
+/// written before saves were versioned are version 0.
+///
+/// Bump this whenever a change would break loading older saves, and add
+/// migrations from the old version to [`SaveMigrations`].
+pub const SAVE_FORMAT_VERSION: u32 = 1;
+
+/// Turns save data from one format version into the next
+pub type SaveMigrationFn = fn(&mut Metadata) -> Result<(), String>;
+
+#[derive(Clone, Debug)]
+enum SaveMigration {
+    Custom(SaveMigrationFn),
+    RenameField {
+        from: Cow<'static, str>,
+        to: Cow<'static, str>,
+    },
+    /// Renames a reflected type path in the saved scene, such as when a
+    /// component is renamed or moved to another module
+    RenameTypePath {
+        from: Cow<'static, str>,
+        to: Cow<'static, str>,
+    },
+}
+
+impl SaveMigration {
+    fn apply(&self, data: &mut Metadata) -> Result<(), String> {
+        match self {
+            Self::Custom(migrate) => migrate(data),
+            Self::RenameField { from, to } => {
+                data.rename_field(from, to);
+                Ok(())
+            },
+            Self::RenameTypePath { from, to } => {
+                let Some(scene) = data.get_optional(key::SCENE).map_err(|e| e.to_string())? else {
+                    return Ok(());
+                };
+                let scene = rename_scene_type_path(&scene, from, to);
+                data.put(key::SCENE, scene).map_err(|e| e.to_string())
+            },
+        }
+    }
+}
+
+/// Renames the type path keys of the resources and entity components in a
+/// serialized scene. Strings inside their values are left alone, even if they
+/// happen to match the type path.
+fn rename_scene_type_path(scene: &str, from: &str, to: &str) -> String {
+    let bytes = scene.as_bytes();
+    let end_of_quoted = |start: usize, quote: u8| -> usize {
+        let mut i = start + 1;
+        while i < bytes.len() && bytes[i] != quote {
+            i += if bytes[i] == b'\\' { 2 } else { 1 };
+        }
+        i.min(bytes.len() - 1)
+    };
+    let mut renamed = String::with_capacity(scene.len());
+    let mut copied_to = 0;
+    // Each open bracket, with the field name it is the value of if it is a map
+    let mut brackets: Vec<Option<&str>> = Vec::new();
+    let mut last_ident: Option<&str> = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let end = end_of_quoted(i, b'"');
+                let is_type_path_key = matches!(
+                    brackets.as_slice(),
+                    [None, Some("resources")] | [None, Some("entities"), None, Some("components")]
+                ) && scene[end + 1..].trim_start().starts_with(':');
+                if is_type_path_key && &scene[i + 1..end] == from {
+                    renamed.push_str(&scene[copied_to..i]);
+                    renamed.push_str(&format!("\"{to}\""));
+                    copied_to = end + 1;
+                }
+                last_ident = None;
+                i = end;
+            },
+            b'\'' => {
+                last_ident = None;
+                i = end_of_quoted(i, b'\'');
+            },
+            b'{' => brackets.push(last_ident.take()),
+            b'(' | b'[' => {
+                brackets.push(None);
+                last_ident = None;
+            },
+            b'}' | b')' | b']' => {
+                brackets.pop();
+                last_ident = None;
+            },
+            b':' => {},
+            c if c.is_ascii_whitespace() => {},
+            c if c.is_ascii_alphanumeric() || c == b'_' => {
+                let start = i;
+                while i + 1 < bytes.len()
+                    && (bytes[i + 1].is_ascii_alphanumeric() || bytes[i + 1] == b'_')
+                {
+                    i += 1;
+                }
+                last_ident = Some(&scene[start..=i]);
+            },
+            _ => last_ident = None,
+        }
+        i += 1;
+    }
+    renamed.push_str(&scene[copied_to..]);
+    renamed
+}
+
+/// Migrations applied to saves from older format versions when they are
+/// loaded, so that renaming things doesn't break existing saves.
+///
+/// Plugins that change how they save should add migrations from the version
+/// before [`SAVE_FORMAT_VERSION`] was bumped.
+#[derive(Debug, Resource)]
+pub struct SaveMigrations {
+    /// Migrations by the version they migrate from, in the order they are applied
+    migrations: BTreeMap<u32, Vec<SaveMigration>>,
+}
+
+impl Default for SaveMigrations {
+    fn default() -> Self {
+        let mut migrations = Self {
+            migrations: BTreeMap::new(),
+        };
+        migrations.add(0, migrate_v0_summary);
+        migrations
+    }
+}
+
+impl SaveMigrations {
+    pub fn add(&mut self, from_version: u32, migrate: SaveMigrationFn) -> &mut Self {
+        self.push(from_version, SaveMigration::Custom(migrate))
+    }
+
+    /// Moves a top level save field, such as `deck` or `wallet`
+    pub fn rename_field<S: Into<Cow<'static, str>>>(
+        &mut self,
+        from_version: u32,
+        from: S,
+        to: S,
+    ) -> &mut Self {
+        self.push(
+            from_version,
+            SaveMigration::RenameField {
+                from: from.into(),
+                to: to.into(),
+            },
+        )
+    }
+
+    /// Renames a type in the saved scene, such as `game_core::node::Node`
+    pub fn rename_type_path<S: Into<Cow<'static, str>>>(
+        &mut self,
+        from_version: u32,
+        from: S,
+        to: S,
+    ) -> &mut Self {
+        self.push(
+            from_version,
+            SaveMigration::RenameTypePath {
+                from: from.into(),
+                to: to.into(),
+            },
+        )
+    }
+
+    fn push(&mut self, from_version: u32, migration: SaveMigration) -> &mut Self {
+        debug_assert!(
+            from_version < SAVE_FORMAT_VERSION,
+            "migrations must be from an older version"
+        );
+        self.migrations
+            .entry(from_version)
+            .or_default()
+            .push(migration);
+        self
+    }
+
+    /// Brings save data up to the current format version. Returns the version
+    /// the save data was in before.
+    pub fn migrate(&self, data: &mut Metadata) -> Result<u32, String> {
+        let version = data
+            .get_optional(key::VERSION)
+            .map_err(|e| e.to_string())?
+            .unwrap_or(0);
+        if version > SAVE_FORMAT_VERSION {
+            return Err(format!(
+                "Save is from a newer version of the game (format {version}, \
+                 this version reads up to {SAVE_FORMAT_VERSION})"
+            ));
+        }
+        for (from_version, migrations) in self.migrations.range(version..SAVE_FORMAT_VERSION) {
+            log::info!("Migrating save from format version {from_version}");
+            for migration in migrations.iter() {
+                migration.apply(data)?;
+            }
+        }
+        data.put(key::VERSION, SAVE_FORMAT_VERSION)
+            .map_err(|e| e.to_string())?;
+        Ok(version)
+    }
+}
+
+/// Version 0 saves might not have a slot summary, so we work it out from the
+/// deck, wallet and quest status.
+///
+/// Fields are named directly rather than with their keys, since this should
+/// keep reading the version 0 format even if the keys change later.
+fn migrate_v0_summary(data: &mut Metadata) -> Result<(), String> {
+    if data
+        .get_optional(key::SUMMARY)
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {
+        return Ok(());
+    }
+    let field = |field: &str| -> Result<Value, String> {
+        Ok(data
+            .get_field_optional::<Value>(field)
+            .map_err(|e| format!("Unable to read [{field}] from save: {e}"))?
+            .unwrap_or_default())
+    };
+    let sum_counts = |counts: &Value, count_of: fn(u64) -> u64| -> u32 {
+        counts
+            .as_object()
+            .map(|counts| {
+                counts
+                    .values()
+                    .filter_map(Value::as_u64)
+                    .map(count_of)
+                    .sum::<u64>() as u32
+            })
+            .unwrap_or_default()
+    };
+    let summary = SaveSlotSummary {
+        completed_nodes: sum_counts(&field("quest_status")?, |flags| flags.count_ones() as u64),
+        mon: field("wallet")?.as_u64().unwrap_or_default() as u32,
+        deck_size: sum_counts(&field("deck")?["cards"], |count| count),
+        ..default()
+    };
+    data.put(key::SUMMARY, summary).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const V0_SAVE: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/saves/v0.sav.json"
+    ));
+
+    fn load_v0_save() -> Metadata {
+        serde_json::from_str(V0_SAVE).expect("fixture should be valid save data")
+    }
+
+    #[test]
+    fn test_v0_save_gets_summary() {
+        let mut data = load_v0_save();
+        assert_eq!(SaveMigrations::default().migrate(&mut data), Ok(0));
+        assert_eq!(
+            data.get_required(key::VERSION).unwrap(),
+            SAVE_FORMAT_VERSION
+        );
+        let summary = data.get_required(key::SUMMARY).unwrap();
+        assert_eq!(
+            summary,
+            SaveSlotSummary {
+                completed_nodes: 3,
+                mon: 1250,
+                deck_size: 4,
+                ..default()
+            }
+        );
+        // Everything else is left alone
+        assert_eq!(
+            data.get_field_required::<u32>("wallet").unwrap(),
+            summary.mon
+        );
+    }
+
+    #[test]
+    fn test_current_save_is_unchanged() {
+        let mut data = load_v0_save();
+        let migrations = SaveMigrations::default();
+        migrations.migrate(&mut data).unwrap();
+        let migrated = data.clone();
+        assert_eq!(migrations.migrate(&mut data), Ok(SAVE_FORMAT_VERSION));
+        assert_eq!(data, migrated);
+    }
+
+    #[test]
+    fn test_newer_save_is_rejected() {
+        let mut data = load_v0_save();
+        data.put(key::VERSION, SAVE_FORMAT_VERSION + 1).unwrap();
+        assert!(SaveMigrations::default().migrate(&mut data).is_err());
+    }
+
+    #[test]
+    fn test_renames() {
+        let mut data = load_v0_save();
+        let mut migrations = SaveMigrations::default();
+        migrations
+            .rename_field(0, "quest_status", "quest_log")
+            .rename_type_path(
+                0,
+                "game_core::card::Nickname",
+                "game_core::card::CardNickname",
+            );
+        migrations.migrate(&mut data).unwrap();
+
+        assert!(data
+            .get_field_optional::<Value>("quest_status")
+            .unwrap()
+            .is_none());
+        assert!(data
+            .get_field_optional::<Value>("quest_log")
+            .unwrap()
+            .is_some());
+        let scene = data.get_required(key::SCENE).unwrap();
+        assert!(scene.contains("\"game_core::card::CardNickname\""));
+        assert!(!scene.contains("\"game_core::card::Nickname\""));
+        assert!(scene.contains("\"game_core::card::CardHandle\""));
+    }
+
+    #[test]
+    fn test_rename_type_path_only_renames_keys() {
+        let scene = r#"(
+  resources: {
+    "game_core::Old": (),
+  },
+  entities: {
+    4294967301: (
+      components: {
+        "game_core::Old": ("game_core::Old"),
+        "game_core::Other": (
+          names: {
+            "game_core::Old": 1,
+          },
+          note: 'x',
+        ),
+      },
+    ),
+  },
+)"#;
+        let renamed = rename_scene_type_path(scene, "game_core::Old", "game_core::New");
+        assert_eq!(
+            renamed,
+            scene
+                .replace("\"game_core::Old\": ()", "\"game_core::New\": ()")
+                .replace(
+                    "\"game_core::Old\": (\"game_core::Old\")",
+                    "\"game_core::New\": (\"game_core::Old\")"
+                )
+        );
+    }
+}
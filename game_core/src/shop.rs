@@ -1,20 +1,28 @@
 use std::str::FromStr;
 
 use bevy::ecs::query::Has;
+use bevy::hierarchy::DespawnRecursiveExt;
 use getset::{CopyGetters, Getters};
 use serde::{Deserialize, Serialize};
 
 use super::item::Item;
-use crate::card::CardDefinition;
+use crate::card::{CardDefinition, CardQuery, Deck};
 use crate::item::{ItemOp, Wallet};
-use crate::op::{CoreOps, Op, OpError, OpErrorUtils, OpImplResult, OpPlugin, OpRegistrar};
+use crate::node::{self, NodeOp};
+use crate::op::{
+    CoreOps, Op, OpError, OpErrorUtils, OpImplResult, OpPlugin, OpRegistrar, OpResult,
+};
 use crate::player::Player;
 use crate::prelude::*;
+use crate::NDitCoreSet;
 
 pub mod key {
     use typed_key::{typed_key, Key};
 
+    /// Only put when the item bought is a card
+    pub const CARD_NAME: Key<String> = typed_key!("card_name");
     pub const ITEM_NAME: Key<String> = typed_key!("item_name");
+    pub const PRICE: Key<u32> = typed_key!("price");
 }
 
 #[derive(Debug, Default)]
@@ -22,7 +30,10 @@ pub struct ShopPlugin;
 
 impl Plugin for ShopPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(OpPlugin::<ShopOp>::default());
+        app.add_plugins(OpPlugin::<ShopOp>::default()).add_systems(
+            Update,
+            (sys_restock_shops, sys_restore_unsold_stock).in_set(NDitCoreSet::PostProcessCommands),
+        );
     }
 }
 
@@ -49,25 +60,102 @@ impl FromStr for ShopId {
 #[derive(Component, Debug, Default, Deref, DerefMut)]
 pub struct ShopInventory(pub Vec<ShopListing>);
 
+impl ShopInventory {
+    /// Finds the index of the listing for an item by name, ignoring case
+    pub fn index_of_name(&self, name: &str, cards: &Assets<CardDefinition>) -> Option<usize> {
+        self.iter()
+            .position(|listing| listing.item().name(cards).eq_ignore_ascii_case(name))
+    }
+
+    /// The listing that sells a given card, if there is one
+    pub fn listing_for_card(&self, card: &Handle<CardDefinition>) -> Option<&ShopListing> {
+        self.iter()
+            .find(|listing| matches!(listing.item(), Item::Card(handle) if handle == card))
+    }
+}
+
 #[derive(Debug, Getters, CopyGetters)]
 pub struct ShopListing {
     #[getset(get = "pub")]
     item: Item,
     #[getset(get_copy = "pub")]
     price: u32,
+    /// How many are left to buy, if the stock is limited
+    #[getset(get_copy = "pub")]
+    stock: Option<u32>,
+    /// What the stock is replenished to when the shop restocks
+    #[getset(get_copy = "pub")]
+    max_stock: Option<u32>,
 }
 
 impl ShopListing {
     pub fn new(price: u32, item: Item) -> Self {
-        ShopListing { item, price }
+        ShopListing {
+            item,
+            price,
+            stock: None,
+            max_stock: None,
+        }
+    }
+
+    /// Limits how many can be bought before the shop restocks
+    pub fn with_stock(mut self, stock: u32) -> Self {
+        self.stock = Some(stock);
+        self.max_stock = Some(stock);
+        self
+    }
+
+    pub fn in_stock(&self) -> bool {
+        self.stock != Some(0)
+    }
+
+    fn restock(&mut self) {
+        if self.stock != self.max_stock {
+            self.stock = self.max_stock;
+        }
+    }
+}
+
+/// How much a shop pays for cards that players sell to it. Shops without
+/// this component use the default rate.
+#[derive(Clone, Component, Copy, Debug, Reflect)]
+pub struct ShopSellRate {
+    /// Percent of the listing price paid for cards this shop sells
+    pub percent: u32,
+    /// Price paid for cards this shop doesn't sell
+    pub unlisted_price: u32,
+}
+
+impl Default for ShopSellRate {
+    fn default() -> Self {
+        Self {
+            percent: 50,
+            unlisted_price: 100,
+        }
+    }
+}
+
+impl ShopSellRate {
+    pub fn sell_price(&self, inventory: &ShopInventory, card: &Handle<CardDefinition>) -> u32 {
+        inventory
+            .listing_for_card(card)
+            .map(|listing| {
+                let price = u64::from(listing.price()) * u64::from(self.percent) / 100;
+                u32::try_from(price).unwrap_or(u32::MAX)
+            })
+            .unwrap_or(self.unlisted_price)
     }
 }
 
 #[derive(Clone, Debug, Reflect)]
 pub enum ShopOp {
-    BuyItem(usize), // TODO, perhaps also allow buying by name?
+    BuyItem(usize),
     Enter(ShopId),
     Leave,
+    /// Buys the listed item with this name
+    BuyItemByName(String),
+    /// Sells a card from the player's deck back to the shop
+    Sell(Entity),
 }
 
 impl Op for ShopOp {
@@ -75,14 +163,16 @@ impl Op for ShopOp {
         registrar
             .register_op(opsys_buy_item)
             .register_op(opsys_enter)
-            .register_op(opsys_leave);
+            .register_op(opsys_leave)
+            .register_op(opsys_sell);
     }
 
     fn system_index(&self) -> usize {
         match self {
-            Self::BuyItem(_) => 0,
+            Self::BuyItem(_) | Self::BuyItemByName(_) => 0,
             Self::Enter(_) => 1,
             Self::Leave => 2,
+            Self::Sell(_) => 3,
         }
     }
 }
@@ -92,24 +182,41 @@ pub fn opsys_buy_item(
     ast_card_def: Res<Assets<CardDefinition>>,
     mut res_core_ops: ResMut<CoreOps>,
     mut q_player: Query<(&InShop, &mut Wallet), With<Player>>,
-    q_shop: Query<&ShopInventory, With<ShopId>>,
+    mut q_shop: Query<&mut ShopInventory, With<ShopId>>,
 ) -> OpImplResult {
-    // TODO Add item to inventory
-    if let ShopOp::BuyItem(item_idx) = shop_op {
+    if let ShopOp::BuyItem(_) | ShopOp::BuyItemByName(_) = shop_op {
         let (&InShop(shop_id), mut wallet) = q_player.get_mut(player_id).invalid()?;
         let mut metadata = Metadata::default();
-        let shop_inventory = q_shop.get(shop_id).invalid()?;
+        let mut shop_inventory = q_shop.get_mut(shop_id).invalid()?;
+        let item_idx = match shop_op {
+            ShopOp::BuyItemByName(name) => shop_inventory
+                .index_of_name(&name, &ast_card_def)
+                .ok_or("No item listed with that name".invalid())?,
+            ShopOp::BuyItem(item_idx) => item_idx,
+            _ => unreachable!("checked above"),
+        };
         let listing = shop_inventory
-            .get(item_idx)
+            .get_mut(item_idx)
             .ok_or("No item listed for that index")?;
+        if !listing.in_stock() {
+            Err("That item is sold out".invalid())?;
+        }
         let can_pay = wallet.try_spend(listing.price());
         if !can_pay {
             Err("Cannot afford that item".invalid())?;
         }
+        if let Some(stock) = listing.stock.as_mut() {
+            *stock -= 1;
+        }
         let name = listing.item().name(&ast_card_def);
         metadata
             .put(key::ITEM_NAME, name.to_string())
             .expect("it would be crazy if you couldn't deserialize a string");
+        if let Item::Card(_) = listing.item() {
+            metadata
+                .put(key::CARD_NAME, name.to_string())
+                .expect("it would be crazy if you couldn't deserialize a string");
+        }
 
         res_core_ops.request(
             player_id,
@@ -128,6 +235,44 @@ pub fn opsys_buy_item(
     }
 }
 
+pub fn opsys_sell(
+    In((player_id, shop_op)): In<(Entity, ShopOp)>,
+    mut commands: Commands,
+    mut q_player: Query<(&InShop, &mut Wallet, &mut Deck), With<Player>>,
+    q_shop: Query<(&ShopInventory, Option<&ShopSellRate>), With<ShopId>>,
+    q_card: Query<(&Handle<CardDefinition>, CardQuery)>,
+) -> OpImplResult {
+    let ShopOp::Sell(card_id) = shop_op else {
+        return Err(OpError::MismatchedOpSystem);
+    };
+    let (&InShop(shop_id), mut wallet, mut deck) = q_player.get_mut(player_id).invalid()?;
+    if deck.count_of_card(card_id) == 0 {
+        Err("That card is not in your deck".invalid())?;
+    }
+    if deck.total_cards() <= 1 {
+        Err("Can't sell your last card".invalid())?;
+    }
+    let (shop_inventory, sell_rate) = q_shop.get(shop_id).critical()?;
+    let (card_handle, card) = q_card.get(card_id).critical()?;
+    let price = sell_rate
+        .copied()
+        .unwrap_or_default()
+        .sell_price(shop_inventory, card_handle);
+
+    let mut metadata = Metadata::default();
+    metadata
+        .put(key::ITEM_NAME, card.nickname_or_name().to_owned())
+        .critical()?;
+    metadata.put(key::PRICE, price).critical()?;
+    deck.remove_card(card_id);
+    if deck.count_of_card(card_id) == 0 {
+        commands.entity(card_id).despawn_recursive();
+    }
+    wallet.increase_mon(price);
+    log::debug!("Player sold [{card_id:?}] for [{price}] Mon [{wallet:?}]");
+    Ok(metadata)
+}
+
 pub fn opsys_enter(
     In((player_id, shop_op)): In<(Entity, ShopOp)>,
     mut commands: Commands,
@@ -165,3 +310,274 @@ pub fn opsys_leave(
         Err(OpError::MismatchedOpSystem)
     }
 }
+
+/// Shops restock their limited listings whenever a node is won
+pub fn sys_restock_shops(
+    mut evr_node_op: EventReader<OpResult<NodeOp>>,
+    mut q_shop: Query<&mut ShopInventory, With<ShopId>>,
+) {
+    let node_won = evr_node_op.read().any(|op_result| {
+        matches!(op_result.op(), NodeOp::QuitNode(_))
+            && op_result
+                .result()
+                .as_ref()
+                .ok()
+                .and_then(|metadata| metadata.get_required(node::key::VICTORY_STATUS).ok())
+                .map(|victory_status| victory_status.is_victorious())
+                .unwrap_or(false)
+    });
+    if node_won {
+        for mut shop_inventory in q_shop.iter_mut() {
+            for listing in shop_inventory.iter_mut() {
+                listing.restock();
+            }
+        }
+    }
+}
+
+/// Puts back the stock of purchases whose item couldn't be given to the
+/// player. The player gets their mon back from the item op.
+pub fn sys_restore_unsold_stock(
+    mut evr_item_op: EventReader<OpResult<ItemOp>>,
+    q_player: Query<&InShop, With<Player>>,
+    mut q_shop: Query<&mut ShopInventory, With<ShopId>>,
+) {
+    for op_result in evr_item_op.read() {
+        let ItemOp::AddItem { item, refund } = op_result.op() else {
+            continue;
+        };
+        if op_result.result().is_ok() {
+            continue;
+        }
+        let Ok(&InShop(shop_id)) = q_player.get(op_result.source()) else {
+            continue;
+        };
+        let Ok(mut shop_inventory) = q_shop.get_mut(shop_id) else {
+            continue;
+        };
+        let sold_listing = shop_inventory.iter_mut().find(|listing| {
+            listing.item() == item
+                && listing.price() == *refund
+                && listing.stock < listing.max_stock
+        });
+        if let Some(stock) = sold_listing.and_then(|listing| listing.stock.as_mut()) {
+            *stock += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::event::Events;
+
+    use super::*;
+    use crate::card::{Actions, BaseName, Card, Description};
+    use crate::common::daddy::Daddy;
+    use crate::item::{Inventory, ItemDefinition, ItemDefinitions, ItemKind};
+    use crate::node::VictoryStatus;
+    use crate::op::OpExecutorPlugin;
+    use crate::registry::Reg;
+
+    const POTION: &str = "item:potion";
+
+    /// A shop with the listings, and a player in it with some mon
+    fn shop_app(listings: Vec<ShopListing>) -> (App, Entity) {
+        let mut app = App::new();
+        app.configure_sets(
+            Update,
+            (
+                NDitCoreSet::ProcessCommands,
+                NDitCoreSet::ProcessCommandsFlush,
+                NDitCoreSet::PostProcessCommands,
+            )
+                .chain(),
+        )
+        .init_resource::<Assets<CardDefinition>>()
+        .init_resource::<Reg<ItemDefinitions>>()
+        .init_resource::<Daddy<Card>>()
+        .add_event::<OpResult<NodeOp>>()
+        .add_plugins((
+            OpExecutorPlugin::<CoreOps>::new(Update, Some(NDitCoreSet::ProcessCommands)),
+            OpPlugin::<ItemOp>::default(),
+            ShopPlugin,
+        ));
+        let world = app.world_mut();
+        world.resource_mut::<Reg<ItemDefinitions>>().insert(
+            POTION,
+            ItemDefinition {
+                kind: ItemKind::Consumable,
+                description: String::new(),
+                action: None,
+            },
+        );
+        let shop = world
+            .spawn((ShopId::default(), ShopInventory(listings)))
+            .id();
+        let player = world
+            .spawn((
+                Player,
+                InShop(shop),
+                Wallet::new().with_mon(100),
+                Inventory::default(),
+                Deck::new(),
+            ))
+            .id();
+        // Registers the op systems
+        app.update();
+        (app, player)
+    }
+
+    fn perform(app: &mut App, player: Entity, op: ShopOp) -> OpImplResult {
+        app.world_mut()
+            .resource_mut::<CoreOps>()
+            .request(player, op);
+        app.update();
+        let mut results: Vec<_> = app
+            .world_mut()
+            .resource_mut::<Events<OpResult<ShopOp>>>()
+            .drain()
+            .collect();
+        assert_eq!(results.len(), 1, "{results:?}");
+        results.remove(0).result
+    }
+
+    fn mon(app: &App, player: Entity) -> u32 {
+        app.world().get::<Wallet>(player).unwrap().mon()
+    }
+
+    fn stock(app: &mut App) -> Option<u32> {
+        let mut shops = app.world_mut().query::<&ShopInventory>();
+        shops.single(app.world())[0].stock()
+    }
+
+    fn finish_node(app: &mut App, player: Entity, victory_status: VictoryStatus) {
+        let mut metadata = Metadata::new();
+        metadata
+            .put(node::key::VICTORY_STATUS, victory_status)
+            .unwrap();
+        app.world_mut().send_event(OpResult {
+            source: player,
+            op: NodeOp::QuitNode(default()),
+            result: Ok(metadata),
+        });
+        app.update();
+    }
+
+    fn spawn_card(app: &mut App, card_handle: Handle<CardDefinition>) -> Entity {
+        app.world_mut()
+            .spawn((
+                Card,
+                card_handle,
+                Actions::default(),
+                Description::new(""),
+                BaseName::new("card:test"),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn test_sell_price_does_not_overflow() {
+        let card_handle = Handle::weak_from_u128(1);
+        let inventory = ShopInventory(vec![ShopListing::new(
+            u32::MAX,
+            Item::Card(card_handle.clone()),
+        )]);
+        let sell_rate = ShopSellRate {
+            percent: 50,
+            unlisted_price: 10,
+        };
+        assert_eq!(sell_rate.sell_price(&inventory, &card_handle), u32::MAX / 2);
+        let generous_rate = ShopSellRate {
+            percent: 300,
+            unlisted_price: 10,
+        };
+        assert_eq!(generous_rate.sell_price(&inventory, &card_handle), u32::MAX);
+        assert_eq!(
+            sell_rate.sell_price(&inventory, &Handle::weak_from_u128(2)),
+            10
+        );
+    }
+
+    #[test]
+    fn test_buy_limited_stock() {
+        let listing = ShopListing::new(30, Item::Inventory(POTION.to_owned())).with_stock(1);
+        let (mut app, player) = shop_app(vec![listing]);
+
+        let metadata = perform(
+            &mut app,
+            player,
+            ShopOp::BuyItemByName(POTION.to_uppercase()),
+        )
+        .expect("potion should be bought");
+        assert_eq!(metadata.get_required(key::ITEM_NAME).unwrap(), POTION);
+        assert_eq!(metadata.get_optional(key::CARD_NAME).unwrap(), None);
+        app.update();
+        assert_eq!(mon(&app, player), 70);
+        assert_eq!(
+            app.world().get::<Inventory>(player).unwrap().count(POTION),
+            1
+        );
+        assert_eq!(stock(&mut app), Some(0));
+
+        assert!(perform(&mut app, player, ShopOp::BuyItem(0)).is_err());
+        assert_eq!(mon(&app, player), 70);
+    }
+
+    #[test]
+    fn test_failed_purchase_restores_stock() {
+        let listing =
+            ShopListing::new(30, Item::Inventory("item:missing".to_owned())).with_stock(1);
+        let (mut app, player) = shop_app(vec![listing]);
+        assert!(perform(&mut app, player, ShopOp::BuyItem(0)).is_ok());
+        assert_eq!(stock(&mut app), Some(0));
+
+        // The item isn't defined, so it can't be added
+        app.update();
+        assert_eq!(mon(&app, player), 100);
+        assert_eq!(stock(&mut app), Some(1));
+    }
+
+    #[test]
+    fn test_restock_when_node_is_won() {
+        let listing = ShopListing::new(30, Item::Inventory(POTION.to_owned())).with_stock(2);
+        let (mut app, player) = shop_app(vec![listing]);
+        assert!(perform(&mut app, player, ShopOp::BuyItem(0)).is_ok());
+        assert_eq!(stock(&mut app), Some(1));
+
+        finish_node(&mut app, player, VictoryStatus::Loss);
+        assert_eq!(stock(&mut app), Some(1));
+        finish_node(&mut app, player, VictoryStatus::Victory);
+        assert_eq!(stock(&mut app), Some(2));
+    }
+
+    #[test]
+    fn test_sell_card() {
+        let card_handle = Handle::weak_from_u128(1);
+        let listing = ShopListing::new(40, Item::Card(card_handle.clone()));
+        let (mut app, player) = shop_app(vec![listing]);
+        let card = spawn_card(&mut app, card_handle);
+        let other_card = spawn_card(&mut app, Handle::weak_from_u128(2));
+        app.world_mut()
+            .get_mut::<Deck>(player)
+            .unwrap()
+            .add_card(card)
+            .add_card(card)
+            .add_card(other_card);
+
+        let metadata = perform(&mut app, player, ShopOp::Sell(card)).expect("sell should work");
+        assert_eq!(metadata.get_required(key::PRICE).unwrap(), 20);
+        assert_eq!(mon(&app, player), 120);
+        assert_eq!(
+            app.world().get::<Deck>(player).unwrap().count_of_card(card),
+            1
+        );
+
+        let metadata =
+            perform(&mut app, player, ShopOp::Sell(other_card)).expect("sell should work");
+        assert_eq!(metadata.get_required(key::PRICE).unwrap(), 100);
+        assert!(app.world().get_entity(other_card).is_none());
+
+        assert!(perform(&mut app, player, ShopOp::Sell(card)).is_err());
+        assert_eq!(mon(&app, player), 220);
+    }
+}
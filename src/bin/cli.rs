@@ -0,0 +1,638 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::scene::ScenePlugin;
+use clap::Parser;
+use game_core::card::{
+    Action, Actions, CardQuery, Deck, MaximumSize, MovementSpeed, PrereqQ, StatusEffects,
+};
+use game_core::common::{Compass, SetId};
+use game_core::entity_grid::EntityGrid;
+use game_core::item::{Inventory, Item, ItemOp, Wallet};
+use game_core::node::{
+    AccessPoint, ActiveCurio, Curio, CurrentTurn, InNode, IsTapped, MovesTaken, Node, NodeId,
+    NodeOp, NodePiece, NodeScene, OnTeam, PlayedCards, TeamColor,
+};
+use game_core::op::{CoreOps, OpResult};
+use game_core::player::{Ncp, PlayerBundle};
+use game_core::quest::{QuestLog, QuestStatus};
+use game_core::registry::Reg;
+use game_core::shop::{ShopId, ShopOp};
+use simplelog::{LevelFilter, WriteLogger};
+
+const HELP: &str = "\
+Commands:
+  show                    Print the node grid and pieces
+  cards                   List the cards in your deck
+  actions                 List the actions of the active curio
+  load <ap> <card>        Load a card (by number or name) into an access point
+  unload <ap>             Unload an access point
+  ready                   Finish loading access points
+  activate <curio>        Activate a curio (by label or name)
+  move <n|e|s|w>          Move the active curio
+  act <action> <x>,<y>    Have the active curio perform an action
+  use <item> <x>,<y>      Use an item from your inventory in range of the active curio
+  end                     End your turn
+  undo                    Undo the last move or action
+  redo                    Redo what was last undone
+  quit                    Leave the node
+  shop <shop id>          Enter a shop, such as warez:0
+  buy <listing>           Buy an item from the shop (by number or name)
+  sell <card>             Sell a card from your deck (by number or name)
+  leave                   Leave the shop
+  inventory               List the items in your inventory
+  drop <item>             Drop an item next to the active curio
+  trash <item>            Get rid of an item for good
+  help                    Print this message
+  exit                    Exit the game";
+
+const STARTING_CARDS: [&str; 4] = [
+    "nightfall/lvl1.cards.json#Hack",
+    "nightfall/lvl1.cards.json#Slingshot",
+    "nightfall/lvl1.cards.json#Bit Man",
+    "nightfall/lvl1.cards.json#Bug",
+];
+
+/// Plays the game through line-based commands on stdin, printing the
+/// node and op results as plain text.
+#[derive(Parser)]
+#[command(author, version, about)]
+struct CliGamePlugin {
+    /// Node to play, such as "node:area1:0"
+    #[arg(short, long, value_name = "NODE ID", default_value = "node:tutorial:0")]
+    node: String,
+    /// Activates logging and debuging to local file.
+    #[arg(short, long)]
+    debug: bool,
+}
+
+#[derive(Debug, Resource)]
+struct CliInput(Mutex<Receiver<String>>);
+
+#[derive(Debug, Resource)]
+struct CliPlayer(Entity);
+
+/// Node to enter once the node scene registry is ready
+#[derive(Debug, Resource)]
+struct CliNode(Option<NodeId>);
+
+impl Plugin for CliGamePlugin {
+    fn build(&self, app: &mut App) {
+        let node_id = match self.node.parse::<SetId>() {
+            Ok(set_id) => Some(NodeId::from(set_id)),
+            Err(e) => {
+                println!("Invalid node id [{}]: {e:?}", self.node);
+                None
+            },
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        app.insert_resource(CliInput(Mutex::new(receiver)))
+            .insert_resource(CliNode(node_id))
+            .add_systems(Startup, sys_cli_startup)
+            .add_systems(PreUpdate, (sys_enter_cli_node, sys_cli_commands))
+            .add_systems(PostUpdate, sys_print_op_results);
+    }
+}
+
+fn main() {
+    let cli_game = CliGamePlugin::parse();
+    if cli_game.debug {
+        WriteLogger::init(
+            LevelFilter::Debug,
+            simplelog::ConfigBuilder::new()
+                .set_target_level(LevelFilter::Error)
+                .build(),
+            File::create("debug.cli.log").unwrap(),
+        )
+        .unwrap()
+    }
+    App::new()
+        .add_plugins((
+            cli_game,
+            AssetPlugin { ..default() },
+            HierarchyPlugin,
+            bevy::core::TaskPoolPlugin::default(),
+            ScenePlugin,
+            TypeRegistrationPlugin,
+            bevy::time::TimePlugin,
+            bevy::app::ScheduleRunnerPlugin::run_loop(Duration::from_millis(25)),
+            FrameCountPlugin,
+            game_core::NDitCorePlugin,
+        ))
+        .run();
+}
+
+fn sys_cli_startup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut res_core_ops: ResMut<CoreOps>,
+) {
+    let player = commands
+        .spawn((
+            Deck::new(),
+            Inventory::default(),
+            Name::new("Player"),
+            Ncp,
+            PlayedCards::default(),
+            PlayerBundle::default(),
+            QuestLog::default(),
+            QuestStatus::default(),
+            Wallet::new(),
+        ))
+        .id();
+    for card_def_path in STARTING_CARDS.into_iter() {
+        res_core_ops.request(
+            player,
+            ItemOp::AddItem {
+                item: Item::Card(asset_server.load(card_def_path)),
+                refund: 0,
+            },
+        );
+    }
+    commands.insert_resource(CliPlayer(player));
+    println!("{HELP}");
+}
+
+fn sys_enter_cli_node(
+    mut res_cli_node: ResMut<CliNode>,
+    mut res_core_ops: ResMut<CoreOps>,
+    res_player: Res<CliPlayer>,
+    res_reg_nodes: Res<Reg<NodeScene>>,
+) {
+    let Some(node_id) = res_cli_node.0.as_ref() else {
+        return;
+    };
+    if res_reg_nodes.get(node_id.to_string().as_str()).is_some() {
+        println!("Entering node [{node_id}]");
+        res_core_ops.request(res_player.0, NodeOp::EnterNode(node_id.clone()));
+        res_cli_node.0 = None;
+    }
+}
+
+/// Text rendering of the node the player is in
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+struct NodeView<'w, 's> {
+    ast_actions: Res<'w, Assets<Action>>,
+    q_player: Query<'w, 's, (Option<&'static InNode>, &'static Deck)>,
+    q_node: Query<
+        'w,
+        's,
+        (
+            &'static EntityGrid,
+            &'static ActiveCurio,
+            &'static CurrentTurn,
+        ),
+        With<Node>,
+    >,
+    q_piece: Query<
+        'w,
+        's,
+        (
+            &'static NodePiece,
+            Option<&'static Curio>,
+            Option<&'static AccessPoint>,
+            Option<&'static OnTeam>,
+            Option<&'static Actions>,
+        ),
+    >,
+    q_curio_stats: Query<
+        'w,
+        's,
+        (
+            Option<&'static MaximumSize>,
+            Option<&'static MovementSpeed>,
+            Option<&'static MovesTaken>,
+            Option<&'static IsTapped>,
+            Option<&'static StatusEffects>,
+        ),
+    >,
+    q_prereq: Query<'w, 's, PrereqQ>,
+    q_node_id: Query<'w, 's, &'static Node>,
+    q_team: Query<'w, 's, &'static TeamColor>,
+    q_card: Query<'w, 's, CardQuery>,
+}
+
+impl NodeView<'_, '_> {
+    fn node(&self, player: Entity) -> Option<Entity> {
+        self.q_player.get(player).ok()?.0.map(|in_node| in_node.0)
+    }
+
+    /// Pieces in the node sorted so that labels are stable
+    fn pieces(&self, node: Entity) -> Vec<Entity> {
+        let Ok((grid, _, _)) = self.q_node.get(node) else {
+            return Vec::new();
+        };
+        let mut pieces = grid.entities();
+        pieces.sort();
+        pieces
+    }
+
+    fn label(index: usize) -> char {
+        if index < 26 {
+            char::from(b'A' + index as u8)
+        } else {
+            '?'
+        }
+    }
+
+    fn find_piece(&self, node: Entity, arg: &str) -> Option<Entity> {
+        let pieces = self.pieces(node);
+        if arg.len() == 1 {
+            let label = arg.to_ascii_uppercase();
+            if let Some(index) =
+                (0..pieces.len()).find(|index| label.starts_with(Self::label(*index)))
+            {
+                return Some(pieces[index]);
+            }
+        }
+        pieces.into_iter().find(|piece| {
+            self.q_piece
+                .get(*piece)
+                .ok()
+                .and_then(|(_, curio, ..)| curio)
+                .map(|curio| curio.name().eq_ignore_ascii_case(arg))
+                .unwrap_or(false)
+        })
+    }
+
+    fn find_card(&self, player: Entity, arg: &str) -> Option<Entity> {
+        let (_, deck) = self.q_player.get(player).ok()?;
+        if let Ok(index) = usize::from_str(arg) {
+            return deck.cards_iter().nth(index.checked_sub(1)?);
+        }
+        deck.cards_iter().find(|card| {
+            self.q_card
+                .get(*card)
+                .map(|card| card.nickname_or_name().eq_ignore_ascii_case(arg))
+                .unwrap_or(false)
+        })
+    }
+
+    fn render_cards(&self, player: Entity) -> String {
+        let mut output = String::new();
+        if let Ok((_, deck)) = self.q_player.get(player) {
+            for (index, (card, count)) in deck.cards_with_count().enumerate() {
+                if let Ok(card) = self.q_card.get(card) {
+                    let _ = writeln!(
+                        output,
+                        "{:>2}. {} x{count}",
+                        index + 1,
+                        card.nickname_or_name()
+                    );
+                }
+            }
+        }
+        output
+    }
+
+    fn render_actions(&self, player: Entity) -> String {
+        let mut output = String::new();
+        let Some((grid, active_curio, _)) = self
+            .node(player)
+            .and_then(|node| self.q_node.get(node).ok())
+        else {
+            return "Not in a node".to_string();
+        };
+        let Some((curio, actions)) = active_curio.0.and_then(|curio| {
+            let (.., actions) = self.q_piece.get(curio).ok()?;
+            Some((curio, actions?))
+        }) else {
+            return "No active curio".to_string();
+        };
+        for action in actions
+            .iter()
+            .filter_map(|action| self.ast_actions.get(action))
+        {
+            let _ = write!(output, "{}: {}", action.id(), action.description());
+            if let Some(prereq) = action.unsatisfied_prereq(grid, curio, None, &self.q_prereq) {
+                let _ = write!(output, " (unavailable: {prereq})");
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    fn render_node(&self, player: Entity) -> String {
+        let Some((node, (grid, active_curio, current_turn))) = self
+            .node(player)
+            .and_then(|node| Some((node, self.q_node.get(node).ok()?)))
+        else {
+            return "Not in a node".to_string();
+        };
+        let pieces = self.pieces(node);
+        let label_of = |entity: Entity| {
+            pieces
+                .iter()
+                .position(|piece| *piece == entity)
+                .map(Self::label)
+                .unwrap_or('?')
+        };
+        let mut output = String::from("   ");
+        for x in 0..grid.width() {
+            output.push(char::from_digit(x % 10, 10).unwrap_or(' '));
+        }
+        output.push('\n');
+        for y in 0..grid.height() {
+            let _ = write!(output, "{y:>2} ");
+            for x in 0..grid.width() {
+                let pt = UVec2 { x, y };
+                let c = match grid.item_at(pt) {
+                    Some(item) if grid.head(item) == Some(pt) => label_of(item),
+                    Some(item) => label_of(item).to_ascii_lowercase(),
+                    None if grid.square_is_free(pt) => '.',
+                    None => ' ',
+                };
+                output.push(c);
+            }
+            output.push('\n');
+        }
+        let current_team = self.q_team.get(current_turn.0).ok();
+        let _ = writeln!(output, "Current turn: {current_team:?}");
+        for (index, piece) in pieces.iter().enumerate() {
+            let Ok((node_piece, curio, access_point, team, _)) = self.q_piece.get(*piece) else {
+                continue;
+            };
+            let team = team.and_then(|team| self.q_team.get(team.0).ok());
+            let _ = write!(output, "{}: ", Self::label(index));
+            if let Some(curio) = curio {
+                let (max_size, speed, moves_taken, tapped, status_effects) =
+                    self.q_curio_stats.get(*piece).unwrap_or_default();
+                let _ = write!(
+                    output,
+                    "{} [{team:?}] size {}/{} moves {}/{}",
+                    curio.name(),
+                    grid.len_of(*piece),
+                    max_size.map(|size| size.0).unwrap_or_default(),
+                    moves_taken.map(|moves| moves.0).unwrap_or_default(),
+                    speed.map(|speed| speed.0).unwrap_or_default(),
+                );
+                if tapped.map(|tapped| tapped.0).unwrap_or_default() {
+                    output.push_str(" (tapped)");
+                }
+                if active_curio.0 == Some(*piece) {
+                    output.push_str(" (active)");
+                }
+                for status_effect in status_effects.iter().flat_map(|se| se.iter()) {
+                    let _ = write!(output, " ({status_effect})");
+                }
+            } else if let Some(access_point) = access_point {
+                let card = access_point
+                    .card()
+                    .and_then(|card| self.q_card.get(card).ok())
+                    .map(|card| card.nickname_or_name().to_string());
+                let _ = write!(output, "Access point [{team:?}] {card:?}");
+            } else {
+                output.push_str(node_piece.display_id());
+            }
+            output.push('\n');
+        }
+        output
+    }
+}
+
+fn parse_point(arg: &str) -> Option<UVec2> {
+    let (x, y) = arg.split_once(',')?;
+    Some(UVec2 {
+        x: x.trim().parse().ok()?,
+        y: y.trim().parse().ok()?,
+    })
+}
+
+fn parse_dir(arg: &str) -> Option<Compass> {
+    match arg.to_ascii_lowercase().as_str() {
+        "n" | "north" | "up" => Some(Compass::North),
+        "e" | "east" | "right" => Some(Compass::East),
+        "s" | "south" | "down" => Some(Compass::South),
+        "w" | "west" | "left" => Some(Compass::West),
+        _ => None,
+    }
+}
+
+fn sys_cli_commands(
+    res_input: Res<CliInput>,
+    res_player: Res<CliPlayer>,
+    mut res_core_ops: ResMut<CoreOps>,
+    mut evw_exit: EventWriter<AppExit>,
+    node_view: NodeView,
+    q_inventory: Query<&Inventory>,
+) {
+    let player = res_player.0;
+    let lines: Vec<String> = {
+        let Ok(input) = res_input.0.lock() else {
+            return;
+        };
+        let mut lines = Vec::new();
+        loop {
+            match input.try_recv() {
+                Ok(line) => lines.push(line),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    evw_exit.send(AppExit::Success);
+                    break;
+                },
+            }
+        }
+        lines
+    };
+    for line in lines {
+        let mut args = line.split_whitespace();
+        let Some(command) = args.next() else {
+            continue;
+        };
+        let args: Vec<&str> = args.collect();
+        let node = node_view.node(player);
+        let result: Result<(), String> = (|| {
+            match (command.to_ascii_lowercase().as_str(), args.as_slice()) {
+                ("help", _) => println!("{HELP}"),
+                ("show", _) => print!("{}", node_view.render_node(player)),
+                ("cards", _) => print!("{}", node_view.render_cards(player)),
+                ("actions", _) => println!("{}", node_view.render_actions(player)),
+                ("exit", _) => {
+                    evw_exit.send(AppExit::Success);
+                },
+                ("move", [dir]) => {
+                    let dir = parse_dir(dir).ok_or(format!("Unknown direction [{dir}]"))?;
+                    res_core_ops.request(player, NodeOp::MoveActiveCurio { dir });
+                },
+                ("activate", [curio]) => {
+                    let curio_id = node
+                        .and_then(|node| node_view.find_piece(node, curio))
+                        .ok_or(format!("Unknown curio [{curio}]"))?;
+                    res_core_ops.request(player, NodeOp::ActivateCurio { curio_id });
+                },
+                ("act", [action @ .., target]) if !action.is_empty() => {
+                    let target =
+                        parse_point(target).ok_or(format!("Invalid target point [{target}]"))?;
+                    res_core_ops.request(
+                        player,
+                        NodeOp::PerformCurioAction {
+                            action_id: action.join(" ").into(),
+                            curio: None,
+                            target,
+                        },
+                    );
+                },
+                ("use", [item @ .., target]) if !item.is_empty() => {
+                    let target =
+                        parse_point(target).ok_or(format!("Invalid target point [{target}]"))?;
+                    res_core_ops.request(
+                        player,
+                        NodeOp::UseItem {
+                            item_id: item.join(" "),
+                            target,
+                        },
+                    );
+                },
+                ("load", [access_point, card @ ..]) if !card.is_empty() => {
+                    let access_point_id = node
+                        .and_then(|node| node_view.find_piece(node, access_point))
+                        .ok_or(format!("Unknown access point [{access_point}]"))?;
+                    let card = card.join(" ");
+                    let card_id = node_view
+                        .find_card(player, &card)
+                        .ok_or(format!("Unknown card [{card}]"))?;
+                    res_core_ops.request(
+                        player,
+                        NodeOp::LoadAccessPoint {
+                            access_point_id,
+                            card_id,
+                        },
+                    );
+                },
+                ("unload", [access_point]) => {
+                    let access_point_id = node
+                        .and_then(|node| node_view.find_piece(node, access_point))
+                        .ok_or(format!("Unknown access point [{access_point}]"))?;
+                    res_core_ops.request(player, NodeOp::UnloadAccessPoint { access_point_id });
+                },
+                ("ready", []) => res_core_ops.request(player, NodeOp::ReadyToGo),
+                ("end", []) => res_core_ops.request(player, NodeOp::EndTurn),
+                ("undo", []) => res_core_ops.request(player, NodeOp::Undo),
+                ("redo", []) => res_core_ops.request(player, NodeOp::Redo),
+                ("quit", []) => {
+                    let Node(node_id) = node
+                        .and_then(|node| node_view.q_node_id.get(node).ok())
+                        .ok_or("Not in a node")?;
+                    res_core_ops.request(player, NodeOp::QuitNode(node_id.clone()));
+                },
+                ("shop", [shop_id]) => {
+                    let shop_id = shop_id
+                        .parse::<SetId>()
+                        .map_err(|e| format!("Invalid shop id [{shop_id}]: {e:?}"))?;
+                    res_core_ops.request(player, ShopOp::Enter(ShopId(shop_id)));
+                },
+                ("buy", listing) if !listing.is_empty() => {
+                    let listing = listing.join(" ");
+                    if let Ok(listing) = usize::from_str(&listing) {
+                        res_core_ops.request(player, ShopOp::BuyItem(listing));
+                    } else {
+                        res_core_ops.request(player, ShopOp::BuyItemByName(listing));
+                    }
+                },
+                ("sell", card) if !card.is_empty() => {
+                    let card = card.join(" ");
+                    let card_id = node_view
+                        .find_card(player, &card)
+                        .ok_or(format!("Unknown card [{card}]"))?;
+                    res_core_ops.request(player, ShopOp::Sell(card_id));
+                },
+                ("leave", []) => res_core_ops.request(player, ShopOp::Leave),
+                ("inventory", []) => {
+                    let inventory = q_inventory.get(player).map_err(|_| "No inventory")?;
+                    let items = inventory.items();
+                    if items.is_empty() {
+                        println!("Inventory is empty");
+                    }
+                    for (item_id, count) in items {
+                        println!("{item_id} x{count}");
+                    }
+                },
+                ("drop", item) if !item.is_empty() => {
+                    let item = Item::Inventory(item.join(" "));
+                    res_core_ops.request(player, ItemOp::DropItem { item });
+                },
+                ("trash", item) if !item.is_empty() => {
+                    let item = Item::Inventory(item.join(" "));
+                    res_core_ops.request(player, ItemOp::TrashItem { item });
+                },
+                _ => return Err(format!("Unknown command [{line}], try \"help\"")),
+            }
+            Ok(())
+        })();
+        if let Err(e) = result {
+            println!("{e}");
+        }
+    }
+}
+
+/// Prints the results of the local player's ops, and one line for each turn
+/// another player takes
+fn sys_print_op_results(
+    res_player: Res<CliPlayer>,
+    mut evr_node_op: EventReader<OpResult<NodeOp>>,
+    mut evr_item_op: EventReader<OpResult<ItemOp>>,
+    mut evr_shop_op: EventReader<OpResult<ShopOp>>,
+    mut ops_this_turn: Local<HashMap<Entity, usize>>,
+    q_player_team: Query<&OnTeam>,
+    node_view: NodeView,
+) {
+    let mut node_changed = false;
+    for op_result in evr_node_op.read() {
+        node_changed |= op_result.result().is_ok();
+        if op_result.source() == res_player.0 {
+            match op_result.result() {
+                Ok(_) => println!("{:?}: ok", op_result.op()),
+                Err(e) => println!("{:?}: {e}", op_result.op()),
+            }
+            continue;
+        }
+        if op_result.result().is_err() {
+            continue;
+        }
+        let ops = ops_this_turn.entry(op_result.source()).or_default();
+        *ops += 1;
+        if matches!(op_result.op(), NodeOp::EndTurn) {
+            let team = q_player_team
+                .get(op_result.source())
+                .ok()
+                .and_then(|team| node_view.q_team.get(team.0).ok());
+            println!("Team {team:?} took their turn ({ops} ops)");
+            ops_this_turn.remove(&op_result.source());
+        }
+    }
+    for op_result in evr_item_op.read() {
+        if op_result.source() != res_player.0 {
+            continue;
+        }
+        if let Err(e) = op_result.result() {
+            println!("{:?}: {e}", op_result.op());
+        }
+    }
+    for op_result in evr_shop_op.read() {
+        if op_result.source() != res_player.0 {
+            continue;
+        }
+        match op_result.result() {
+            Ok(_) => println!("{:?}: ok", op_result.op()),
+            Err(e) => println!("{:?}: {e}", op_result.op()),
+        }
+    }
+    if node_changed {
+        print!("{}", node_view.render_node(res_player.0));
+    }
+}
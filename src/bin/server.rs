@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::scene::ScenePlugin;
+use clap::Parser;
+use game_core::common::SetId;
+use game_core::item::{Inventory, ItemOp, Wallet};
+use game_core::node::{NodeId, NodeOp, NodeScene, PlayedCards};
+use game_core::op::{CoreOps, OpResult, OpServer, OpServerPlugin};
+use game_core::player::{Ncp, PlayerBundle};
+use game_core::quest::{QuestLog, QuestStatus};
+use game_core::registry::Reg;
+use game_core::saving::{SaveDirectory, SaveOp, SaveWritten};
+use game_core::shop::ShopOp;
+use simplelog::{LevelFilter, WriteLogger};
+
+/// Headless server that hosts nodes for remote players
+#[derive(Parser)]
+#[command(author, version, about)]
+struct ServerCliPlugin {
+    /// Address to listen for op clients on
+    #[arg(short, long, value_name = "ADDRESS", default_value = "127.0.0.1:7447")]
+    address: String,
+    /// Node to host, such as "node:area1:0". Can be specified multiple times
+    #[arg(short, long = "node", value_name = "NODE ID", required = true)]
+    nodes: Vec<String>,
+    /// Activates logging and debuging to local file.
+    #[arg(short, long)]
+    debug: bool,
+    /// Increases debug logging to next level
+    #[arg(short, long)]
+    trace: bool,
+}
+
+/// Nodes that still need to be loaded once the node scene registry is ready
+#[derive(Debug, Default, Resource)]
+struct HostedNodes(Vec<NodeId>);
+
+impl Plugin for ServerCliPlugin {
+    fn build(&self, app: &mut App) {
+        let nodes = self
+            .nodes
+            .iter()
+            .filter_map(|node| match node.parse::<SetId>() {
+                Ok(set_id) => Some(NodeId::from(set_id)),
+                Err(e) => {
+                    tracing::error!("Invalid node id [{node}]: {e:?}");
+                    None
+                },
+            })
+            .collect();
+        app.insert_resource(HostedNodes(nodes))
+            .insert_resource(SaveDirectory::new("nf-server"))
+            .add_plugins(
+                OpServerPlugin::<CoreOps>::new(self.address.as_str())
+                    .allow_op::<NodeOp>()
+                    .allow_op::<ShopOp>(),
+            )
+            .add_systems(Update, sys_host_nodes)
+            .add_systems(PostUpdate, log_op_results);
+    }
+}
+
+fn main() {
+    let server_cli = ServerCliPlugin::parse();
+    setup_logging(&server_cli);
+    App::new()
+        .add_plugins((
+            server_cli,
+            AssetPlugin { ..default() },
+            HierarchyPlugin,
+            bevy::core::TaskPoolPlugin::default(),
+            ScenePlugin,
+            TypeRegistrationPlugin,
+            bevy::time::TimePlugin,
+            bevy::app::ScheduleRunnerPlugin::run_loop(Duration::from_millis(25)),
+            FrameCountPlugin,
+            game_core::NDitCorePlugin,
+        ))
+        .run();
+}
+
+/// Spawns a player for each hosted node and has them enter it, once the node
+/// scene can be found in the registry. Each player is handed to the next
+/// remote client that connects.
+fn sys_host_nodes(
+    mut commands: Commands,
+    mut res_core_ops: ResMut<CoreOps>,
+    mut res_op_server: Option<ResMut<OpServer>>,
+    mut res_hosted_nodes: ResMut<HostedNodes>,
+    res_reg_nodes: Res<Reg<NodeScene>>,
+) {
+    res_hosted_nodes.0.retain(|node_id| {
+        if res_reg_nodes.get(node_id.to_string().as_str()).is_none() {
+            return true;
+        }
+        let player = commands
+            .spawn((
+                Inventory::default(),
+                Name::new(format!("Remote player [{node_id}]")),
+                Ncp,
+                PlayedCards::default(),
+                PlayerBundle::default(),
+                QuestLog::default(),
+                QuestStatus::default(),
+                Wallet::new(),
+            ))
+            .id();
+        tracing::info!("Hosting node [{node_id}] for player {player:?}");
+        res_core_ops.request(player, NodeOp::EnterNode(node_id.clone()));
+        if let Some(op_server) = res_op_server.as_mut() {
+            op_server.host_player(player);
+        }
+        false
+    });
+}
+
+fn log_op_results(
+    mut evr_node_op: EventReader<OpResult<NodeOp>>,
+    mut evr_item_op: EventReader<OpResult<ItemOp>>,
+    mut evr_shop_op: EventReader<OpResult<ShopOp>>,
+    mut evr_save_op: EventReader<OpResult<SaveOp>>,
+    mut evr_save_written: EventReader<SaveWritten>,
+) {
+    for op in evr_node_op.read() {
+        tracing::info!("NodeOp Result: {:?}", op)
+    }
+    for op in evr_item_op.read() {
+        tracing::info!("ItemOp Result: {:?}", op)
+    }
+    for op in evr_shop_op.read() {
+        tracing::info!("ShopOp Result: {:?}", op)
+    }
+    for op in evr_save_op.read() {
+        tracing::info!("SaveOp Result: {:?}", op)
+    }
+    for save_written in evr_save_written.read() {
+        tracing::info!("Save written: {:?}", save_written)
+    }
+}
+
+fn setup_logging(server_cli: &ServerCliPlugin) {
+    if server_cli.debug {
+        let log_level: LevelFilter = if server_cli.trace {
+            LevelFilter::Trace
+        } else {
+            LevelFilter::Debug
+        };
+        WriteLogger::init(
+            log_level,
+            simplelog::ConfigBuilder::new()
+                .set_target_level(LevelFilter::Error)
+                .build(),
+            File::create("debug.server.log").unwrap(),
+        )
+        .unwrap()
+    }
+}
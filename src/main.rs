@@ -1,4 +1,5 @@
 use std::fs::File;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use bevy::app::RunMode;
@@ -6,7 +7,9 @@ use bevy::prelude::*;
 use bevy::scene::ScenePlugin;
 use clap::Parser;
 use cq_term::demo::{DemoNodeId, UseDemoShader};
-use game_core::node::NodeId;
+use game_core::node::{NodeId, NodeOpRecorder, NodeReplay};
+use game_core::op::{CoreOps, OpClient, OpExecutor};
+use game_core::saving::SaveDirectory;
 use simplelog::{LevelFilter, WriteLogger};
 
 #[derive(Parser)]
@@ -21,15 +24,24 @@ struct CqCliPlugin {
     /// Increases debug logging to next leve
     #[arg(short, long)]
     trace: bool,
-    /// Specifies a server to connect to. Not currently implemented
+    /// Specifies a server to connect to. Core ops are sent to the server instead of being performed locally
     #[arg(short, long, value_name = "SERVER ADDRESS")]
     connect: Option<String>,
+    /// Records node ops to the specified file
+    #[arg(long, value_name = "REPLAY FILE")]
+    record: Option<PathBuf>,
+    /// Replays node ops from the specified file
+    #[arg(long, value_name = "REPLAY FILE", conflicts_with = "record")]
+    replay: Option<PathBuf>,
     /// Applies "demo shader" affect, a sliding rainbow
     #[arg(long = "rainbow", value_name = "RAINBOW HEIGHT")]
     demo_shader: Option<u32>,
     /// Runs game without a frame
     #[arg(short, long = "uncapped")]
     uncapped_fps: bool,
+    /// Directory to keep save slots in, instead of the default data directory
+    #[arg(long, value_name = "SAVE DIRECTORY")]
+    save_dir: Option<PathBuf>,
 }
 
 impl Plugin for CqCliPlugin {
@@ -43,6 +55,37 @@ impl Plugin for CqCliPlugin {
         }));
         app.insert_resource(UseDemoShader(self.demo_shader.unwrap_or(0)));
         app.insert_resource(demo_node_id);
+        if let Some(save_dir) = self.save_dir.as_ref() {
+            app.insert_resource(SaveDirectory::new(save_dir.clone()));
+        }
+        if let Some(address) = self.connect.as_ref() {
+            match OpClient::connect(address.as_str()) {
+                Ok(client) => {
+                    app.insert_resource(CoreOps(OpExecutor::Network(client)));
+                },
+                Err(e) => {
+                    tracing::error!("Unable to connect to server [{address}]: {e}");
+                    eprintln!("Unable to connect to server [{address}]: {e}");
+                    std::process::exit(1);
+                },
+            }
+        }
+        if let Some(path) = self.record.as_ref() {
+            match NodeOpRecorder::create(path) {
+                Ok(recorder) => {
+                    app.insert_resource(recorder);
+                },
+                Err(e) => tracing::error!("Unable to create replay file {path:?}: {e}"),
+            }
+        }
+        if let Some(path) = self.replay.as_ref() {
+            match NodeReplay::open(path) {
+                Ok(replay) => {
+                    app.insert_resource(replay);
+                },
+                Err(e) => tracing::error!("Unable to open replay file {path:?}: {e}"),
+            }
+        }
     }
 }
 